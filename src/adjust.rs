@@ -0,0 +1,508 @@
+use wasm_bindgen::prelude::*;
+
+use crate::canvas_source::CanvasSource;
+use crate::color::Rgba;
+
+/// Which channels a per-channel adjustment changes.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Channel {
+    /// Red, green and blue alike.
+    Rgb = 0,
+    Red = 1,
+    Green = 2,
+    Blue = 3,
+    Alpha = 4,
+}
+
+impl Channel {
+    fn indices(self) -> &'static [usize] {
+        match self {
+            Channel::Rgb => &[0, 1, 2],
+            Channel::Red => &[0],
+            Channel::Green => &[1],
+            Channel::Blue => &[2],
+            Channel::Alpha => &[3],
+        }
+    }
+}
+
+// one 256-entry table per channel (r, g, b, a)
+type Lut = [[u8; 256]; 4];
+
+fn identity() -> Lut {
+    let mut table = [0u8; 256];
+    for (i, entry) in table.iter_mut().enumerate() {
+        *entry = i as u8;
+    }
+    [table; 4]
+}
+
+// Rec. 601 luma weights in 8.8 fixed point, as per-value tables so the
+// weighted sum is three lookups and two adds
+pub(crate) fn luma_tables() -> [[u16; 256]; 3] {
+    let weights = [77u16, 150, 29];
+    let mut tables = [[0u16; 256]; 3];
+    for (table, weight) in tables.iter_mut().zip(weights.iter()) {
+        for (value, entry) in table.iter_mut().enumerate() {
+            *entry = value as u16 * weight;
+        }
+    }
+    tables
+}
+
+enum Step {
+    /// Independent per-channel remapping; consecutive ones are merged into
+    /// a single table.
+    Lut(Box<Lut>),
+    Grayscale,
+    Threshold(u8),
+    /// Hue rotation in degrees, then saturation and lightness shifts
+    /// (-1.0 to 1.0).
+    Hsl {
+        hue: f32,
+        saturation: f32,
+        lightness: f32,
+    },
+}
+
+/// A list of tonal and color adjustments applied in one go with
+/// `apply_adjustment`.
+///
+/// Adjustments that treat each channel on its own (brightness/contrast,
+/// levels, curves, invert) are folded into a single lookup table per
+/// channel, so stacking them costs nothing extra per pixel. Grayscale and
+/// threshold weigh the channels with luma tables. Hue/saturation/lightness
+/// is the exception: it mixes all three channels, so no per-channel table
+/// can express it, and it's worked out in floating point for each pixel.
+#[wasm_bindgen]
+pub struct Adjustment {
+    steps: Vec<Step>,
+}
+
+#[wasm_bindgen]
+impl Adjustment {
+    pub fn new() -> Adjustment {
+        Adjustment { steps: Vec::new() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.steps.clear();
+    }
+
+    /// `brightness` and `contrast` both run from -1.0 to 1.0, zero leaving
+    /// the image alone.
+    pub fn brightness_contrast(&mut self, brightness: f32, contrast: f32) {
+        let brightness = brightness.clamp(-1.0, 1.0);
+        let slope = ((contrast.clamp(-1.0, 1.0) + 1.0) * std::f32::consts::FRAC_PI_4).tan();
+        self.map(Channel::Rgb, |v| {
+            let v = if brightness < 0.0 {
+                v * (1.0 + brightness)
+            } else {
+                v + (1.0 - v) * brightness
+            };
+            (v - 0.5) * slope + 0.5
+        });
+    }
+
+    /// Stretch `black`..`white` to the full range, then apply `gamma`
+    /// (above 1.0 brightens the midtones).
+    pub fn levels(&mut self, channel: Channel, black: u8, white: u8, gamma: f32) {
+        let (black, white) = (black as f32 / 255.0, white as f32 / 255.0);
+        let range = (white - black).max(1.0 / 255.0);
+        let exponent = 1.0 / gamma.max(0.01);
+        self.map(channel, |v| {
+            ((v - black) / range).clamp(0.0, 1.0).powf(exponent)
+        });
+    }
+
+    /// Remap `channel` along a smooth curve through the control points,
+    /// given as flat `[in, out, in, out, ...]` pairs from 0 to 255. The
+    /// curve is a monotone cubic, so it never overshoots between points,
+    /// and is flat beyond the first and last point.
+    pub fn curves(&mut self, channel: Channel, points: &[f32]) {
+        let mut points: Vec<(f32, f32)> = points
+            .chunks_exact(2)
+            .map(|p| (p[0].clamp(0.0, 255.0), p[1].clamp(0.0, 255.0)))
+            .collect();
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        points.dedup_by(|a, b| a.0 == b.0);
+        if points.is_empty() {
+            return;
+        }
+        let curve = MonotoneCubic::new(&points);
+        self.map(channel, |v| curve.at(v * 255.0) / 255.0);
+    }
+
+    pub fn invert(&mut self) {
+        self.map(Channel::Rgb, |v| 1.0 - v);
+    }
+
+    /// Replace each color with its luma.
+    pub fn grayscale(&mut self) {
+        self.steps.push(Step::Grayscale);
+    }
+
+    /// Black where the luma is below `level`, white elsewhere.
+    pub fn threshold(&mut self, level: u8) {
+        self.steps.push(Step::Threshold(level));
+    }
+
+    /// Rotate hues by `hue` degrees and shift saturation and lightness by
+    /// -1.0 (gray, black) to 1.0 (fully saturated, white). Unlike the other
+    /// adjustments this isn't a lookup table, since each output channel
+    /// depends on all three inputs (a table over every RGB color would take
+    /// 48 MB), so it costs a round trip through HSL per pixel.
+    pub fn hue_saturation_lightness(&mut self, hue: f32, saturation: f32, lightness: f32) {
+        self.steps.push(Step::Hsl {
+            hue,
+            saturation: saturation.clamp(-1.0, 1.0),
+            lightness: lightness.clamp(-1.0, 1.0),
+        });
+    }
+}
+
+impl Default for Adjustment {
+    fn default() -> Self {
+        Adjustment::new()
+    }
+}
+
+impl Adjustment {
+    /// Add a per-channel remapping of values from 0.0 to 1.0, merging it
+    /// into the previous table if the last step was one.
+    fn map(&mut self, channel: Channel, f: impl Fn(f32) -> f32) {
+        let mut table = [0u8; 256];
+        for (i, entry) in table.iter_mut().enumerate() {
+            *entry = (f(i as f32 / 255.0).clamp(0.0, 1.0) * 255.0).round() as u8;
+        }
+
+        if !matches!(self.steps.last(), Some(Step::Lut(_))) {
+            self.steps.push(Step::Lut(Box::new(identity())));
+        }
+        if let Some(Step::Lut(lut)) = self.steps.last_mut() {
+            for &c in channel.indices() {
+                for entry in lut[c].iter_mut() {
+                    *entry = table[*entry as usize];
+                }
+            }
+        }
+    }
+
+    fn apply(&self, pixel: &mut [u8], luma: &[[u16; 256]; 3]) {
+        let luma_of = |p: &[u8]| {
+            ((luma[0][p[0] as usize] + luma[1][p[1] as usize] + luma[2][p[2] as usize]) >> 8) as u8
+        };
+        for step in self.steps.iter() {
+            match step {
+                Step::Lut(lut) => {
+                    for (c, value) in pixel.iter_mut().enumerate() {
+                        *value = lut[c][*value as usize];
+                    }
+                }
+                Step::Grayscale => {
+                    let y = luma_of(pixel);
+                    pixel[..3].copy_from_slice(&[y; 3]);
+                }
+                Step::Threshold(level) => {
+                    let y = if luma_of(pixel) < *level { 0 } else { 255 };
+                    pixel[..3].copy_from_slice(&[y; 3]);
+                }
+                Step::Hsl {
+                    hue,
+                    saturation,
+                    lightness,
+                } => {
+                    let (h, s, l) = rgb_to_hsl(pixel[0], pixel[1], pixel[2]);
+                    let h = (h + hue).rem_euclid(360.0);
+                    let s = (s * (1.0 + saturation)).clamp(0.0, 1.0);
+                    let l = if *lightness < 0.0 {
+                        l * (1.0 + lightness)
+                    } else {
+                        l + (1.0 - l) * lightness
+                    };
+                    pixel[..3].copy_from_slice(&hsl_to_rgb(h, s, l));
+                }
+            }
+        }
+    }
+}
+
+/// Fritsch-Carlson monotone cubic interpolation through sorted points.
+struct MonotoneCubic {
+    points: Vec<(f32, f32)>,
+    tangents: Vec<f32>,
+}
+
+impl MonotoneCubic {
+    fn new(points: &[(f32, f32)]) -> MonotoneCubic {
+        let n = points.len();
+        let slopes: Vec<f32> = points
+            .windows(2)
+            .map(|w| (w[1].1 - w[0].1) / (w[1].0 - w[0].0))
+            .collect();
+        let mut tangents = vec![0.0; n];
+        if n > 1 {
+            tangents[0] = slopes[0];
+            tangents[n - 1] = slopes[n - 2];
+        }
+        for i in 1..n.saturating_sub(1) {
+            tangents[i] = if slopes[i - 1] * slopes[i] <= 0.0 {
+                0.0
+            } else {
+                (slopes[i - 1] + slopes[i]) / 2.0
+            };
+        }
+        // keep each segment monotone
+        for (i, &slope) in slopes.iter().enumerate() {
+            if slope == 0.0 {
+                tangents[i] = 0.0;
+                tangents[i + 1] = 0.0;
+                continue;
+            }
+            let (a, b) = (tangents[i] / slope, tangents[i + 1] / slope);
+            let length = a.hypot(b);
+            if length > 3.0 {
+                tangents[i] = 3.0 * a / length * slope;
+                tangents[i + 1] = 3.0 * b / length * slope;
+            }
+        }
+        MonotoneCubic {
+            points: points.to_vec(),
+            tangents,
+        }
+    }
+
+    fn at(&self, x: f32) -> f32 {
+        let (first, last) = (self.points[0], self.points[self.points.len() - 1]);
+        if x <= first.0 {
+            return first.1;
+        }
+        if x >= last.0 {
+            return last.1;
+        }
+        let i = self.points.partition_point(|p| p.0 <= x) - 1;
+        let ((x0, y0), (x1, y1)) = (self.points[i], self.points[i + 1]);
+        let h = x1 - x0;
+        let t = (x - x0) / h;
+        let (t2, t3) = (t * t, t * t * t);
+        (2.0 * t3 - 3.0 * t2 + 1.0) * y0
+            + (t3 - 2.0 * t2 + t) * h * self.tangents[i]
+            + (-2.0 * t3 + 3.0 * t2) * y1
+            + (t3 - t2) * h * self.tangents[i + 1]
+    }
+}
+
+/// Hue in degrees, saturation and lightness 0.0 to 1.0.
+fn rgb_to_hsl(r: u8, g: u8, b: u8) -> (f32, f32, f32) {
+    let (r, g, b) = (r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let l = (max + min) / 2.0;
+    let d = max - min;
+    if d == 0.0 {
+        return (0.0, 0.0, l);
+    }
+    let s = d / (1.0 - (2.0 * l - 1.0).abs());
+    let h = if max == r {
+        ((g - b) / d).rem_euclid(6.0)
+    } else if max == g {
+        (b - r) / d + 2.0
+    } else {
+        (r - g) / d + 4.0
+    };
+    (h * 60.0, s, l)
+}
+
+fn hsl_to_rgb(h: f32, s: f32, l: f32) -> [u8; 3] {
+    let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+    let x = c * (1.0 - ((h / 60.0).rem_euclid(2.0) - 1.0).abs());
+    let m = l - c / 2.0;
+    let (r, g, b) = match (h / 60.0) as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    let to_byte = |v: f32| ((v + m).clamp(0.0, 1.0) * 255.0).round() as u8;
+    [to_byte(r), to_byte(g), to_byte(b)]
+}
+
+#[wasm_bindgen]
+impl CanvasSource {
+    /// Apply every step of `adjustment` to the whole canvas as one edit.
+    pub fn apply_adjustment(&mut self, adjustment: &Adjustment) {
+        self.apply_adjustment_to_region(adjustment, 0, 0, self.width, self.height);
+    }
+
+    /// Apply `adjustment` to a rectangle of the canvas only. Under a
+    /// selection, partly selected pixels get part of the change.
+    pub fn apply_adjustment_to_region(
+        &mut self,
+        adjustment: &Adjustment,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+    ) {
+        let (x0, y0, x1, y1) = match self
+            .clip_rect(x, y, width, height)
+            .and_then(|rect| self.selection_clip(rect))
+        {
+            Some(region) => region,
+            None => return,
+        };
+        if adjustment.steps.is_empty() {
+            return;
+        }
+
+        let luma = luma_tables();
+        self.edit(|canvas| {
+            canvas.touch(x0 as i32, y0 as i32, x1 as i32, y1 as i32);
+            for y in y0..y1 {
+                if canvas.selection.is_none() {
+                    let start = canvas.pixel_index(x0, y);
+                    let end = canvas.pixel_index(x1 - 1, y) + 4;
+                    for pixel in canvas.data[start..end].chunks_exact_mut(4) {
+                        adjustment.apply(pixel, &luma);
+                    }
+                    continue;
+                }
+                for x in x0..x1 {
+                    let mut pixel = canvas.pixel(x as i32, y as i32).unwrap().to_array();
+                    adjustment.apply(&mut pixel, &luma);
+                    canvas.replace_pixel(x, y, Rgba::from_slice(&pixel));
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn adjusted(adjustment: &Adjustment, pixel: [u8; 4]) -> [u8; 4] {
+        let mut pixel = pixel;
+        adjustment.apply(&mut pixel, &luma_tables());
+        pixel
+    }
+
+    #[test]
+    fn invert_leaves_alpha_alone() {
+        let mut adjustment = Adjustment::new();
+        adjustment.invert();
+        assert_eq!(adjusted(&adjustment, [10, 20, 30, 40]), [245, 235, 225, 40]);
+        // folded into the same table, undoing the first
+        adjustment.invert();
+        assert_eq!(adjustment.len(), 1);
+        assert_eq!(adjusted(&adjustment, [10, 20, 30, 40]), [10, 20, 30, 40]);
+    }
+
+    #[test]
+    fn levels_stretch_the_range() {
+        let mut adjustment = Adjustment::new();
+        adjustment.levels(Channel::Rgb, 50, 150, 1.0);
+        assert_eq!(
+            adjusted(&adjustment, [50, 125, 150, 200]),
+            [0, 191, 255, 200]
+        );
+        assert_eq!(adjusted(&adjustment, [0, 200, 255, 0])[..3], [0, 255, 255]);
+
+        let mut gamma = Adjustment::new();
+        gamma.levels(Channel::Red, 0, 255, 2.0);
+        assert_eq!(adjusted(&gamma, [64, 64, 64, 64]), [128, 64, 64, 64]);
+    }
+
+    #[test]
+    fn brightness_and_contrast() {
+        let mut neutral = Adjustment::new();
+        neutral.brightness_contrast(0.0, 0.0);
+        assert_eq!(adjusted(&neutral, [0, 77, 255, 9]), [0, 77, 255, 9]);
+
+        let mut brighter = Adjustment::new();
+        brighter.brightness_contrast(0.5, 0.0);
+        assert_eq!(adjusted(&brighter, [0, 255, 0, 255]), [128, 255, 128, 255]);
+
+        let mut flat = Adjustment::new();
+        flat.brightness_contrast(0.0, -1.0);
+        assert_eq!(adjusted(&flat, [0, 255, 30, 255]), [128, 128, 128, 255]);
+    }
+
+    #[test]
+    fn curves_pass_through_their_points() {
+        let mut adjustment = Adjustment::new();
+        adjustment.curves(Channel::Green, &[0.0, 0.0, 100.0, 200.0, 255.0, 255.0]);
+        assert_eq!(
+            adjusted(&adjustment, [100, 100, 100, 100]),
+            [100, 200, 100, 100]
+        );
+
+        let table: Vec<u8> = (0..=255)
+            .map(|v| adjusted(&adjustment, [0, v, 0, 0])[1])
+            .collect();
+        assert!(table.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!((table[0], table[255]), (0, 255));
+
+        let mut inverse = Adjustment::new();
+        inverse.curves(Channel::Rgb, &[255.0, 0.0, 0.0, 255.0]);
+        assert_eq!(adjusted(&inverse, [0, 55, 255, 1]), [255, 200, 0, 1]);
+    }
+
+    #[test]
+    fn grayscale_and_threshold_use_luma() {
+        let mut gray = Adjustment::new();
+        gray.grayscale();
+        assert_eq!(adjusted(&gray, [255, 0, 0, 7]), [76, 76, 76, 7]);
+
+        let mut threshold = Adjustment::new();
+        threshold.threshold(128);
+        assert_eq!(adjusted(&threshold, [127, 127, 127, 255]), [0, 0, 0, 255]);
+        assert_eq!(
+            adjusted(&threshold, [128, 128, 128, 255]),
+            [255, 255, 255, 255]
+        );
+    }
+
+    #[test]
+    fn hue_saturation_lightness() {
+        let hsl = |hue, saturation, lightness, pixel| {
+            let mut adjustment = Adjustment::new();
+            adjustment.hue_saturation_lightness(hue, saturation, lightness);
+            adjusted(&adjustment, pixel)
+        };
+        assert_eq!(hsl(120.0, 0.0, 0.0, [255, 0, 0, 255]), [0, 255, 0, 255]);
+        assert_eq!(hsl(-120.0, 0.0, 0.0, [255, 0, 0, 255]), [0, 0, 255, 255]);
+        assert_eq!(hsl(0.0, -1.0, 0.0, [255, 0, 0, 255]), [128, 128, 128, 255]);
+        assert_eq!(hsl(0.0, 0.0, 1.0, [255, 0, 0, 255]), [255, 255, 255, 255]);
+        assert_eq!(hsl(0.0, 0.0, -1.0, [255, 0, 0, 255]), [0, 0, 0, 255]);
+        assert_eq!(hsl(0.0, 0.0, 0.0, [12, 200, 99, 255]), [12, 200, 99, 255]);
+    }
+
+    #[test]
+    fn regions_limit_the_change() {
+        let mut canvas = CanvasSource::try_new(4, 1, [100, 100, 100, 255].repeat(4)).unwrap();
+        let original = canvas.data.clone();
+        let mut adjustment = Adjustment::new();
+        adjustment.invert();
+        canvas.apply_adjustment_to_region(&adjustment, 1, -5, 2, 10);
+        let row: Vec<u8> = canvas.data.chunks_exact(4).map(|p| p[0]).collect();
+        assert_eq!(row, [100, 155, 155, 100]);
+
+        assert!(canvas.undo());
+        assert_eq!(canvas.data, original);
+        // nothing to do is no undo step
+        canvas.apply_adjustment(&Adjustment::new());
+        assert!(!canvas.can_undo());
+    }
+}
@@ -0,0 +1,171 @@
+use wasm_bindgen::prelude::*;
+
+use crate::canvas_source::{CanvasSource, ImageDataError};
+use crate::color::Rgba;
+
+#[wasm_bindgen]
+impl CanvasSource {
+    /// Place a `width` by `height` RGBA image with its top left corner at
+    /// (x_offset, y_offset), clipping whatever falls outside the canvas.
+    /// Throws if `data` is shorter than `width * height * 4` bytes.
+    ///
+    /// With `blend` set the image is composited through the canvas's
+    /// compositor (source-over by default); otherwise its pixels, alpha and
+    /// all, replace what was there.
+    pub fn scale_to_source_from_offset(
+        &mut self,
+        data: &[u8],
+        width: u32,
+        height: u32,
+        x_offset: i32,
+        y_offset: i32,
+        blend: bool,
+    ) -> Result<(), JsError> {
+        Ok(self.try_scale_to_source_from_offset(data, width, height, x_offset, y_offset, blend)?)
+    }
+}
+
+// removed #[wasm_bindgen] - the Rust-side API for the native binary
+impl CanvasSource {
+    pub fn try_scale_to_source_from_offset(
+        &mut self,
+        data: &[u8],
+        width: u32,
+        height: u32,
+        x_offset: i32,
+        y_offset: i32,
+        blend: bool,
+    ) -> Result<(), ImageDataError> {
+        ImageDataError::check(data, width, height)?;
+        self.edit(|canvas| canvas.blit(data, width, height, x_offset, y_offset, blend));
+        Ok(())
+    }
+}
+
+// removed #[wasm_bindgen] - not sent to js
+impl CanvasSource {
+    pub(crate) fn blit(
+        &mut self,
+        data: &[u8],
+        width: u32,
+        height: u32,
+        x: i32,
+        y: i32,
+        blend: bool,
+    ) {
+        // the part of the source that lands on the canvas, in source space
+        let left = (-(x as i64)).max(0);
+        let top = (-(y as i64)).max(0);
+        let right = (self.width as i64 - x as i64).min(width as i64);
+        let bottom = (self.height as i64 - y as i64).min(height as i64);
+        if left >= right || top >= bottom {
+            return;
+        }
+
+        self.touch(
+            (x as i64 + left) as i32,
+            (y as i64 + top) as i32,
+            (x as i64 + right) as i32,
+            (y as i64 + bottom) as i32,
+        );
+        let run = (right - left) as usize * 4;
+        let compositor = self.compositor;
+        for source_y in top..bottom {
+            let source_start = ((source_y * width as i64 + left) * 4) as usize;
+            let source_row = &data[source_start..source_start + run];
+            let target_x = (x as i64 + left) as u32;
+            let target_y = (y as i64 + source_y) as u32;
+
+            // under a selection every pixel is faded in by how selected it is
+            if self.selection.is_some() {
+                for (i, src) in source_row.chunks_exact(4).enumerate() {
+                    let (px, src) = (target_x + i as u32, Rgba::from_slice(src));
+                    if blend {
+                        let coverage = self.selection_coverage(px, target_y);
+                        let idx = self.pixel_index(px, target_y);
+                        compositor.composite(&mut self.data[idx..idx + 4], src, coverage);
+                    } else {
+                        self.replace_pixel(px, target_y, src);
+                    }
+                }
+                continue;
+            }
+
+            let target = self.pixel_index(target_x, target_y);
+            let target_row = &mut self.data[target..target + run];
+            if blend {
+                for (dst, src) in target_row
+                    .chunks_exact_mut(4)
+                    .zip(source_row.chunks_exact(4))
+                {
+                    compositor.composite(dst, Rgba::from_slice(src), 1.0);
+                }
+            } else {
+                target_row.copy_from_slice(source_row);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::composite::Compositor;
+    use crate::pictures::coverage;
+
+    fn canvas(width: u32, height: u32) -> CanvasSource {
+        CanvasSource::try_new(width, height, Vec::new()).unwrap()
+    }
+
+    #[test]
+    fn images_are_clipped_at_every_edge() {
+        let image = vec![255; 3 * 3 * 4];
+        let mut c = canvas(5, 4);
+        c.try_scale_to_source_from_offset(&image, 3, 3, -1, 2, false)
+            .unwrap();
+        c.try_scale_to_source_from_offset(&image, 3, 3, 4, -2, false)
+            .unwrap();
+        c.try_scale_to_source_from_offset(&image, 3, 3, 40, -2, false)
+            .unwrap();
+        c.try_scale_to_source_from_offset(&image, 3, 3, i32::MIN, i32::MAX, false)
+            .unwrap();
+        assert_eq!(coverage(&c), ["....#", ".....", "##...", "##..."]);
+    }
+
+    #[test]
+    fn rows_land_in_order() {
+        let image: Vec<u8> = (1..=4).flat_map(|v| [v, v, v, 255]).collect();
+        let mut c = canvas(3, 3);
+        c.try_scale_to_source_from_offset(&image, 2, 2, 1, 1, false)
+            .unwrap();
+        assert_eq!(c.pixel(1, 1), Some(Rgba::new(1, 1, 1, 255)));
+        assert_eq!(c.pixel(2, 1), Some(Rgba::new(2, 2, 2, 255)));
+        assert_eq!(c.pixel(1, 2), Some(Rgba::new(3, 3, 3, 255)));
+        assert_eq!(c.pixel(2, 2), Some(Rgba::new(4, 4, 4, 255)));
+    }
+
+    #[test]
+    fn blending_composites_and_copying_replaces() {
+        let half_red = [255, 0, 0, 128];
+        let mut c = canvas(2, 1);
+        c.cover_in_blood();
+        let mut blended = c.data.clone();
+        Compositor::default().composite(&mut blended[..4], Rgba::from_slice(&half_red), 1.0);
+
+        c.try_scale_to_source_from_offset(&half_red, 1, 1, 0, 0, true)
+            .unwrap();
+        c.try_scale_to_source_from_offset(&half_red, 1, 1, 1, 0, false)
+            .unwrap();
+        assert_eq!(c.data[..4], blended[..4]);
+        assert_eq!(c.data[4..], half_red);
+    }
+
+    #[test]
+    fn short_data_is_an_error() {
+        let mut c = canvas(2, 2);
+        assert!(c
+            .try_scale_to_source_from_offset(&[255; 15], 2, 2, 0, 0, false)
+            .is_err());
+        assert!(c.data.iter().all(|&b| b == 0));
+    }
+}
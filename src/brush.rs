@@ -0,0 +1,524 @@
+use wasm_bindgen::prelude::*;
+
+use crate::canvas_source::{CanvasSource, ImageDataError};
+use crate::dirty::Rect;
+use crate::paint::Paint;
+
+/// Brush tip shape taken from an image's alpha channel.
+#[derive(Clone)]
+struct BrushTip {
+    width: u32,
+    height: u32,
+    alpha: Vec<f32>,
+}
+
+impl BrushTip {
+    /// Bilinearly sample the tip at (u, v), both 0.0 to 1.0 across the tip.
+    fn sample(&self, u: f32, v: f32) -> f32 {
+        if !(0.0..=1.0).contains(&u) || !(0.0..=1.0).contains(&v) {
+            return 0.0;
+        }
+        let x = (u * self.width as f32 - 0.5).clamp(0.0, (self.width - 1) as f32);
+        let y = (v * self.height as f32 - 0.5).clamp(0.0, (self.height - 1) as f32);
+        let (x0, y0) = (x.floor() as u32, y.floor() as u32);
+        let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+        let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+        let at = |x: u32, y: u32| self.alpha[y as usize * self.width as usize + x as usize];
+        let top = at(x0, y0) * (1.0 - fx) + at(x1, y0) * fx;
+        let bottom = at(x0, y1) * (1.0 - fx) + at(x1, y1) * fx;
+        top * (1.0 - fy) + bottom * fy
+    }
+}
+
+/// Settings for freehand painting with `begin_stroke` / `stroke_to`.
+///
+/// A stroke is built from dabs, copies of the brush tip stamped along the
+/// pointer's path. `flow` is how much paint each dab lays down, so
+/// overlapping dabs build up; `opacity` caps how opaque the whole stroke can
+/// get no matter how often it crosses itself.
+#[wasm_bindgen]
+#[derive(Clone)]
+pub struct Brush {
+    size: f32,
+    hardness: f32,
+    spacing: f32,
+    opacity: f32,
+    flow: f32,
+    paint: Paint,
+    tip: Option<BrushTip>,
+}
+
+#[wasm_bindgen]
+impl Brush {
+    /// A round brush `size` pixels across at full pressure.
+    pub fn new(size: f32, color: u32) -> Brush {
+        Brush {
+            size: size.max(0.0),
+            hardness: 0.8,
+            spacing: 0.1,
+            opacity: 1.0,
+            flow: 1.0,
+            paint: Paint::solid(color),
+            tip: None,
+        }
+    }
+
+    pub fn size(&self) -> f32 {
+        self.size
+    }
+
+    pub fn set_size(&mut self, size: f32) {
+        self.size = size.max(0.0);
+    }
+
+    pub fn hardness(&self) -> f32 {
+        self.hardness
+    }
+
+    /// How much of the round tip's radius is solid before it starts fading
+    /// out, from 0.0 (soft all the way from the center) to 1.0 (hard edged).
+    /// Ignored by image tips.
+    pub fn set_hardness(&mut self, hardness: f32) {
+        self.hardness = hardness.clamp(0.0, 1.0);
+    }
+
+    pub fn spacing(&self) -> f32 {
+        self.spacing
+    }
+
+    /// Distance between dabs as a fraction of the brush size.
+    pub fn set_spacing(&mut self, spacing: f32) {
+        self.spacing = spacing.max(0.01);
+    }
+
+    pub fn opacity(&self) -> f32 {
+        self.opacity
+    }
+
+    pub fn set_opacity(&mut self, opacity: f32) {
+        self.opacity = opacity.clamp(0.0, 1.0);
+    }
+
+    pub fn flow(&self) -> f32 {
+        self.flow
+    }
+
+    pub fn set_flow(&mut self, flow: f32) {
+        self.flow = flow.clamp(0.0, 1.0);
+    }
+
+    pub fn set_color(&mut self, color: u32) {
+        self.paint = Paint::solid(color);
+    }
+
+    pub fn set_paint(&mut self, paint: &Paint) {
+        self.paint = paint.clone();
+    }
+
+    /// Use the alpha channel of a `width` by `height` RGBA image as the tip,
+    /// stretched to the brush size. Throws if the image is empty or `data`
+    /// is shorter than `width * height * 4` bytes.
+    pub fn set_tip(&mut self, data: &[u8], width: u32, height: u32) -> Result<(), JsError> {
+        Ok(self.try_set_tip(data, width, height)?)
+    }
+
+    /// Go back to the round tip.
+    pub fn clear_tip(&mut self) {
+        self.tip = None;
+    }
+}
+
+// removed #[wasm_bindgen] - the Rust-side API for the native binary
+impl Brush {
+    pub fn try_set_tip(
+        &mut self,
+        data: &[u8],
+        width: u32,
+        height: u32,
+    ) -> Result<(), ImageDataError> {
+        let len = ImageDataError::check_non_empty(data, width, height)?;
+        let alpha = data[..len]
+            .chunks_exact(4)
+            .map(|pixel| pixel[3] as f32 / 255.0)
+            .collect();
+        self.tip = Some(BrushTip {
+            width,
+            height,
+            alpha,
+        });
+        Ok(())
+    }
+}
+
+impl Brush {
+    /// How strongly a dab of the given diameter covers the pixel center at
+    /// offset (dx, dy) from the dab's center, 0.0 to 1.0.
+    fn tip_alpha(&self, dx: f32, dy: f32, diameter: f32) -> f32 {
+        let radius = diameter / 2.0;
+        match &self.tip {
+            Some(tip) => tip.sample(dx / diameter + 0.5, dy / diameter + 0.5),
+            None => {
+                let distance = (dx * dx + dy * dy).sqrt();
+                // at least a pixel of falloff, so hard brushes are still
+                // anti-aliased
+                let falloff = (radius * (1.0 - self.hardness)).max(1.0);
+                let t = ((radius - distance) / falloff).clamp(0.0, 1.0);
+                t * t * (3.0 - 2.0 * t)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Sample {
+    x: f32,
+    y: f32,
+    pressure: f32,
+    time: f64,
+}
+
+// stroke state is kept in square tiles of this size, made the first time a
+// dab reaches them
+const STROKE_TILE: u32 = 64;
+
+struct StrokeTile {
+    // the tile as it was before the stroke first painted on it; every dab
+    // recomposites the stroke over these pixels so `opacity` caps the
+    // stroke as a whole
+    original: Vec<u8>,
+    // paint each pixel has received so far, 0.0 to 1.0
+    coverage: Vec<f32>,
+}
+
+/// The tiles of the canvas a stroke has painted on.
+struct StrokeTiles {
+    columns: u32,
+    tiles: Vec<Option<Box<StrokeTile>>>,
+}
+
+impl StrokeTiles {
+    fn new(width: u32, height: u32) -> StrokeTiles {
+        let columns = width.div_ceil(STROKE_TILE);
+        let rows = height.div_ceil(STROKE_TILE);
+        StrokeTiles {
+            columns,
+            tiles: (0..columns as usize * rows as usize)
+                .map(|_| None)
+                .collect(),
+        }
+    }
+
+    /// The tile holding canvas pixel (x, y), copied from the canvas the
+    /// first time the stroke gets there.
+    fn get(&mut self, canvas: (&[u8], u32, u32), x: u32, y: u32) -> &mut StrokeTile {
+        let (data, width, height) = canvas;
+        let (tile_x, tile_y) = (x / STROKE_TILE, y / STROKE_TILE);
+        let index = tile_y as usize * self.columns as usize + tile_x as usize;
+        self.tiles[index].get_or_insert_with(|| {
+            let side = STROKE_TILE as usize;
+            let mut original = vec![0; side * side * 4];
+            let (x0, y0) = (tile_x * STROKE_TILE, tile_y * STROKE_TILE);
+            let run = (width - x0).min(STROKE_TILE) as usize * 4;
+            for row in 0..(height - y0).min(STROKE_TILE) as usize {
+                let start = ((y0 as usize + row) * width as usize + x0 as usize) * 4;
+                original[row * side * 4..][..run].copy_from_slice(&data[start..start + run]);
+            }
+            Box::new(StrokeTile {
+                original,
+                coverage: vec![0.0; side * side],
+            })
+        })
+    }
+
+    /// Drop the tiles overlapping (x0, y0)..(x1, y1), so pixels written
+    /// there by something other than the stroke are painted over rather
+    /// than restored from before.
+    fn forget(&mut self, (x0, y0, x1, y1): Rect) {
+        if x0 >= x1 || y0 >= y1 {
+            return;
+        }
+        for tile_y in y0 / STROKE_TILE..=(y1 - 1) / STROKE_TILE {
+            for tile_x in x0 / STROKE_TILE..=(x1 - 1) / STROKE_TILE {
+                self.tiles[tile_y as usize * self.columns as usize + tile_x as usize] = None;
+            }
+        }
+    }
+}
+
+/// A stroke in progress.
+pub(crate) struct Stroke {
+    brush: Brush,
+    tiles: StrokeTiles,
+    last: Option<Sample>,
+    // distance along the path still to go before the next dab
+    to_next_dab: f32,
+}
+
+impl Stroke {
+    fn dab_spacing(&self, pressure: f32) -> f32 {
+        (self.brush.size * pressure * self.brush.spacing).max(0.5)
+    }
+
+    /// Called when something else writes to the canvas mid-stroke.
+    pub(crate) fn forget(&mut self, rect: Rect) {
+        self.tiles.forget(rect);
+    }
+}
+
+#[wasm_bindgen]
+impl CanvasSource {
+    /// Start a freehand stroke with a copy of `brush`. Everything up to
+    /// `end_stroke` is undone as one step.
+    pub fn begin_stroke(&mut self, brush: &Brush) {
+        self.end_stroke();
+        self.history.begin_group();
+        self.stroke = Some(Stroke {
+            brush: brush.clone(),
+            tiles: StrokeTiles::new(self.width, self.height),
+            last: None,
+            to_next_dab: 0.0,
+        });
+    }
+
+    /// Feed the next pointer sample. Dabs are stamped along the straight
+    /// path from the previous sample, with size following `pressure`
+    /// (0.0 to 1.0; pass 1.0 for devices without it). `time` is the sample's
+    /// timestamp in milliseconds, e.g. `PointerEvent.timeStamp`; samples
+    /// older than the previous one are dropped.
+    pub fn stroke_to(&mut self, x: f32, y: f32, pressure: f32, time: f64) {
+        let mut stroke = match self.stroke.take() {
+            Some(stroke) => stroke,
+            None => return,
+        };
+        let sample = Sample {
+            x,
+            y,
+            pressure: pressure.clamp(0.0, 1.0),
+            time,
+        };
+
+        match stroke.last {
+            None => {
+                self.dab(&mut stroke, sample.x, sample.y, sample.pressure);
+                stroke.to_next_dab = stroke.dab_spacing(sample.pressure);
+                stroke.last = Some(sample);
+            }
+            Some(last) if sample.time >= last.time => {
+                let length = ((sample.x - last.x).powi(2) + (sample.y - last.y).powi(2)).sqrt();
+                let mut along = stroke.to_next_dab;
+                while along <= length {
+                    let t = along / length;
+                    let pressure = last.pressure + (sample.pressure - last.pressure) * t;
+                    self.dab(
+                        &mut stroke,
+                        last.x + (sample.x - last.x) * t,
+                        last.y + (sample.y - last.y) * t,
+                        pressure,
+                    );
+                    along += stroke.dab_spacing(pressure);
+                }
+                stroke.to_next_dab = along - length;
+                stroke.last = Some(sample);
+            }
+            Some(_) => {}
+        }
+        self.stroke = Some(stroke);
+    }
+
+    /// Finish the current stroke, if any.
+    pub fn end_stroke(&mut self) {
+        if self.stroke.take().is_some() {
+            self.history.end_group(&self.data, self.width, self.height);
+        }
+    }
+
+    pub fn is_stroking(&self) -> bool {
+        self.stroke.is_some()
+    }
+}
+
+// removed #[wasm_bindgen] - not sent to js
+impl CanvasSource {
+    /// Stamp one dab centered on (x, y).
+    fn dab(&mut self, stroke: &mut Stroke, x: f32, y: f32, pressure: f32) {
+        let brush = &stroke.brush;
+        let diameter = brush.size * pressure;
+        if diameter <= 0.0 || brush.flow <= 0.0 {
+            return;
+        }
+        let radius = diameter / 2.0;
+        let x0 = ((x - radius).floor() as i32).max(0);
+        let y0 = ((y - radius).floor() as i32).max(0);
+        let x1 = ((x + radius).ceil() as i32).min(self.width as i32);
+        let y1 = ((y + radius).ceil() as i32).min(self.height as i32);
+        if x0 >= x1 || y0 >= y1 {
+            return;
+        }
+
+        self.touch(x0, y0, x1, y1);
+        let compositor = self.compositor;
+        for py in y0..y1 {
+            for px in x0..x1 {
+                let alpha = brush.tip_alpha(px as f32 + 0.5 - x, py as f32 + 0.5 - y, diameter)
+                    * brush.flow;
+                if alpha <= 0.0 {
+                    continue;
+                }
+                let (px_u, py_u) = (px as u32, py as u32);
+                let tile = stroke
+                    .tiles
+                    .get((&self.data, self.width, self.height), px_u, py_u);
+                let cell = ((py_u % STROKE_TILE) * STROKE_TILE + px_u % STROKE_TILE) as usize;
+                let coverage = &mut tile.coverage[cell];
+                *coverage += (1.0 - *coverage) * alpha;
+
+                let selected = self.selection_coverage(px_u, py_u);
+                let idx = self.pixel_index(px_u, py_u);
+                let pixel = &mut self.data[idx..idx + 4];
+                pixel.copy_from_slice(&tile.original[cell * 4..cell * 4 + 4]);
+                compositor.composite(
+                    pixel,
+                    brush.paint.color_at(px, py),
+                    *coverage * brush.opacity * selected,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raster::Rasterization;
+
+    const RED: u32 = 0xff0000ff;
+
+    fn canvas(width: u32, height: u32) -> CanvasSource {
+        CanvasSource::try_new(width, height, Vec::new()).unwrap()
+    }
+
+    fn hard_brush(size: f32) -> Brush {
+        let mut brush = Brush::new(size, RED);
+        brush.set_hardness(1.0);
+        brush
+    }
+
+    /// Stroke through the given points at full pressure, one sample per
+    /// millisecond.
+    fn stroke(canvas: &mut CanvasSource, brush: &Brush, points: &[(f32, f32)]) {
+        canvas.begin_stroke(brush);
+        for (time, &(x, y)) in points.iter().enumerate() {
+            canvas.stroke_to(x, y, 1.0, time as f64);
+        }
+        canvas.end_stroke();
+    }
+
+    fn alpha(canvas: &CanvasSource, x: i32, y: i32) -> u8 {
+        canvas.pixel(x, y).unwrap().a
+    }
+
+    #[test]
+    fn opacity_caps_the_whole_stroke() {
+        let mut canvas = canvas(40, 20);
+        let mut brush = hard_brush(6.0);
+        brush.set_opacity(0.5);
+        // back and forth over the same pixels, with dabs piling up
+        stroke(
+            &mut canvas,
+            &brush,
+            &[(5.0, 10.0), (35.0, 10.0), (5.0, 10.0)],
+        );
+        assert_eq!(alpha(&canvas, 20, 10), 128);
+
+        // a second stroke is capped on its own, so it builds on the first
+        stroke(&mut canvas, &brush, &[(20.0, 2.0), (20.0, 18.0)]);
+        assert_eq!(alpha(&canvas, 20, 10), 192);
+    }
+
+    #[test]
+    fn flow_builds_up_within_a_stroke() {
+        let mut canvas = canvas(40, 20);
+        let mut brush = hard_brush(6.0);
+        brush.set_flow(0.1);
+        stroke(&mut canvas, &brush, &[(20.0, 10.0)]);
+        let single = alpha(&canvas, 20, 10);
+        assert_eq!(single, 26);
+
+        stroke(&mut canvas, &brush, &[(5.0, 5.0), (35.0, 5.0)]);
+        // the dabs along the path overlap many times over
+        assert!(alpha(&canvas, 20, 5) > 4 * single);
+    }
+
+    #[test]
+    fn drawing_mid_stroke_is_painted_over_not_undone() {
+        let mut canvas = canvas(40, 20);
+        let mut brush = hard_brush(4.0);
+        brush.set_opacity(0.5);
+        canvas.begin_stroke(&brush);
+        canvas.stroke_to(2.0, 10.0, 1.0, 0.0);
+        canvas.stroke_to(10.0, 10.0, 1.0, 1.0);
+        canvas.fill_rect(20.0, 0.0, 10.0, 20.0, 0x0000ffff, Rasterization::Aliased);
+        canvas.stroke_to(38.0, 10.0, 1.0, 2.0);
+        canvas.end_stroke();
+
+        // the rest of the fill is untouched by the later dabs
+        assert_eq!(canvas.pixel(25, 2).unwrap().to_array(), [0, 0, 255, 255]);
+        // half-opaque red over the blue fill, not over the transparent
+        // pixels that were there when the stroke began
+        assert_eq!(canvas.pixel(25, 10).unwrap().to_array(), [128, 0, 128, 255]);
+        assert_eq!(alpha(&canvas, 5, 10), 128);
+    }
+
+    #[test]
+    fn a_stroke_is_one_undo_step() {
+        let mut canvas = canvas(40, 20);
+        let original = canvas.data.clone();
+        stroke(
+            &mut canvas,
+            &hard_brush(5.0),
+            &[(2.0, 2.0), (38.0, 18.0), (2.0, 18.0)],
+        );
+        assert!(canvas.undo());
+        assert_eq!(canvas.data, original);
+        assert!(!canvas.can_undo());
+    }
+
+    #[test]
+    fn late_samples_are_dropped() {
+        let mut canvas = canvas(40, 20);
+        canvas.begin_stroke(&hard_brush(4.0));
+        canvas.stroke_to(5.0, 10.0, 1.0, 10.0);
+        canvas.stroke_to(35.0, 10.0, 1.0, 5.0);
+        canvas.end_stroke();
+        assert_eq!(alpha(&canvas, 5, 10), 255);
+        assert_eq!(alpha(&canvas, 20, 10), 0);
+        assert!(!canvas.is_stroking());
+    }
+
+    #[test]
+    fn image_tips_shape_the_dab() {
+        // opaque on the left half only
+        let data: Vec<u8> = (0..8 * 8)
+            .flat_map(|i| [0, 0, 0, if i % 8 < 4 { 255 } else { 0 }])
+            .collect();
+        let mut brush = Brush::new(16.0, RED);
+        brush.try_set_tip(&data, 8, 8).unwrap();
+        let mut canvas = canvas(20, 20);
+        stroke(&mut canvas, &brush, &[(10.0, 10.0)]);
+        assert_eq!(alpha(&canvas, 5, 10), 255);
+        assert_eq!(alpha(&canvas, 15, 10), 0);
+    }
+
+    #[test]
+    fn tips_need_whole_non_empty_images() {
+        let mut brush = Brush::new(10.0, RED);
+        assert!(matches!(
+            brush.try_set_tip(&[], 0, 4),
+            Err(ImageDataError::Empty { .. })
+        ));
+        assert!(matches!(
+            brush.try_set_tip(&[255; 7], 2, 1),
+            Err(ImageDataError::Length { .. })
+        ));
+    }
+}
@@ -1,15 +1,130 @@
-#[cfg(web_sys_unstable_apis)]
 use wasm_bindgen::prelude::*;
 
 extern crate fixedbitset;
 extern crate web_sys;
 
+use std::convert::TryFrom;
+use std::fmt;
+
+use crate::brush::Stroke;
+use crate::clipboard::Floating;
+use crate::color::Rgba;
+use crate::composite::Compositor;
+use crate::dirty::{DirtyRegion, Rect};
+use crate::history::History;
+use crate::paint::{Paint, PaintKind};
+use crate::selection::Selection;
+
+// the most pixels a canvas can have, e.g. 16384 x 16384: a gigabyte of
+// RGBA, which keeps byte offsets well inside u32 on wasm32
+const MAX_PIXELS: u64 = 1 << 28;
+
+/// A canvas size over the limit of 2^28 pixels.
+#[derive(Debug)]
+pub struct SizeError {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl fmt::Display for SizeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "a {}x{} canvas is too large (at most {} pixels)",
+            self.width, self.height, MAX_PIXELS
+        )
+    }
+}
+
+impl std::error::Error for SizeError {}
+
+/// An RGBA image passed in as bytes that doesn't match its stated size.
+#[derive(Debug)]
+pub enum ImageDataError {
+    /// A zero width or height where an image can't be empty.
+    Empty { width: u32, height: u32 },
+    /// Fewer bytes than `width * height * 4`.
+    Length {
+        width: u32,
+        height: u32,
+        actual: usize,
+    },
+}
+
+impl ImageDataError {
+    /// Checks that `data` holds a `width` x `height` image, returning its
+    /// length in bytes.
+    pub(crate) fn check(data: &[u8], width: u32, height: u32) -> Result<usize, ImageDataError> {
+        let expected = width as u64 * height as u64 * 4;
+        if (data.len() as u64) < expected {
+            return Err(ImageDataError::Length {
+                width,
+                height,
+                actual: data.len(),
+            });
+        }
+        Ok(expected as usize)
+    }
+
+    /// `check`, for images that also need at least one pixel.
+    pub(crate) fn check_non_empty(
+        data: &[u8],
+        width: u32,
+        height: u32,
+    ) -> Result<usize, ImageDataError> {
+        if width == 0 || height == 0 {
+            return Err(ImageDataError::Empty { width, height });
+        }
+        ImageDataError::check(data, width, height)
+    }
+}
+
+impl fmt::Display for ImageDataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageDataError::Empty { width, height } => {
+                write!(f, "a {}x{} image has no pixels", width, height)
+            }
+            ImageDataError::Length {
+                width,
+                height,
+                actual,
+            } => write!(
+                f,
+                "expected {} bytes for a {}x{} image, got {}",
+                *width as u64 * *height as u64 * 4,
+                width,
+                height,
+                actual
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ImageDataError {}
+
+/// Bytes in a `width` x `height` RGBA buffer, or `None` if a canvas can't be
+/// that large.
+pub(crate) fn buffer_len(width: u32, height: u32) -> Option<usize> {
+    let pixels = width as u64 * height as u64;
+    if pixels > MAX_PIXELS {
+        return None;
+    }
+    usize::try_from(pixels * 4).ok()
+}
 
 #[wasm_bindgen]
 pub struct CanvasSource {
-    width: u32,
-    height: u32,
-    data: Vec<u8>,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) data: Vec<u8>,
+    pub(crate) compositor: Compositor,
+    pub(crate) history: History,
+    pub(crate) dirty: DirtyRegion,
+    pub(crate) stroke: Option<Stroke>,
+    pub(crate) selection: Option<Selection>,
+    pub(crate) clipboard: Option<Box<Floating>>,
+    pub(crate) floating: Option<Box<Floating>>,
 }
 
 #[wasm_bindgen]
@@ -27,27 +142,53 @@ impl CanvasSource {
         self.data.as_ptr()
     }
 
-    // take in data and start placing pixels from the top right
-    // pub fn scale_to_source(data: Vec<u8>) {}
-
-    // take in data and start placing pixels from a given location
-    // pub fn scale_to_source_from_offset(data: Vec<u8>, h_offset: u32, y_offset: u32) {}
-
     pub fn cover_in_blood(&mut self) {
-        let mut blood: Vec<u8> = vec![252, 3, 27, 255];
-        let pixel = blood.clone();
-
-        for _ in 0..self.width {
-            for _ in 0..self.height {
-                blood.extend(&pixel)
+        let (x0, y0, x1, y1) = match self.selection_clip((0, 0, self.width, self.height)) {
+            Some(rect) => rect,
+            None => return,
+        };
+        self.edit(|canvas| {
+            canvas.touch(x0 as i32, y0 as i32, x1 as i32, y1 as i32);
+            let blood = Rgba::new(252, 3, 27, 255);
+            if canvas.selection.is_none() {
+                for pixel in canvas.data.chunks_exact_mut(4) {
+                    pixel.copy_from_slice(&blood.to_array());
+                }
+                return;
             }
-        }
+            for y in y0..y1 {
+                for x in x0..x1 {
+                    canvas.replace_pixel(x, y, blood);
+                }
+            }
+        })
+    }
 
-        self.data = blood;
+    /// A canvas filled from `initial_data`, RGBA rows that are cut or
+    /// padded with transparency to fit. Throws if the canvas would have
+    /// more than 2^28 pixels.
+    pub fn new(width: u32, height: u32, initial_data: Vec<u8>) -> Result<CanvasSource, JsError> {
+        Ok(CanvasSource::try_new(width, height, initial_data)?)
     }
+}
 
-    pub fn new(width: u32, height: u32, initial_data: Vec<u8>) -> CanvasSource {
-        let data_size = (width * height) as usize;
+// removed #[wasm_bindgen] - the Rust-side API for the native binary
+impl CanvasSource {
+    pub fn try_new(
+        width: u32,
+        height: u32,
+        initial_data: Vec<u8>,
+    ) -> Result<CanvasSource, SizeError> {
+        match buffer_len(width, height) {
+            Some(_) => Ok(CanvasSource::from_parts(width, height, initial_data)),
+            None => Err(SizeError { width, height }),
+        }
+    }
+
+    /// `try_new` for sizes known to fit, like a copy of an existing canvas.
+    pub(crate) fn from_parts(width: u32, height: u32, initial_data: Vec<u8>) -> CanvasSource {
+        // four bytes (r, g, b, a) per pixel
+        let data_size = buffer_len(width, height).expect("canvas size already checked");
         let mut data = initial_data;
         data.resize(data_size, 0);
 
@@ -55,6 +196,197 @@ impl CanvasSource {
             width,
             height,
             data,
+            compositor: Compositor::default(),
+            history: History::default(),
+            dirty: DirtyRegion::new(width, height),
+            stroke: None,
+            selection: None,
+            clipboard: None,
+            floating: None,
+        }
+    }
+}
+
+// removed #[wasm_bindgen] - pixel access helpers shared by the drawing modules
+impl CanvasSource {
+    /// Run one user-visible operation. Everything it writes is undone
+    /// together; nested calls fold into the outermost one.
+    pub(crate) fn edit<R>(&mut self, operation: impl FnOnce(&mut CanvasSource) -> R) -> R {
+        self.history.begin_operation();
+        let result = operation(self);
+        self.history
+            .end_operation(&self.data, self.width, self.height);
+        result
+    }
+
+    /// Must be called before writing to the pixels in (x0, y0)..(x1, y1)
+    /// (exclusive end), so history can save what was there and the region
+    /// gets uploaded on the next frame. The rectangle is clipped to the
+    /// canvas.
+    pub(crate) fn touch(&mut self, x0: i32, y0: i32, x1: i32, y1: i32) {
+        let clip = |value: i32, max: u32| value.clamp(0, max as i32) as u32;
+        let rect = (
+            clip(x0, self.width),
+            clip(y0, self.height),
+            clip(x1, self.width),
+            clip(y1, self.height),
+        );
+        self.history
+            .record(&self.data, self.width, self.height, rect);
+        self.dirty.mark(rect);
+        // a stroke's own dabs run with it taken out, so this is some other
+        // drawing landing mid-stroke
+        if let Some(stroke) = &mut self.stroke {
+            stroke.forget(rect);
+        }
+    }
+
+    /// Clip the rectangle at (x, y) of the given size to the canvas, as
+    /// (x0, y0, x1, y1). `None` if nothing is left.
+    pub(crate) fn clip_rect(&self, x: i32, y: i32, width: u32, height: u32) -> Option<Rect> {
+        let clip = |value: i64, max: u32| value.clamp(0, max as i64) as u32;
+        let rect = (
+            clip(x as i64, self.width),
+            clip(y as i64, self.height),
+            clip(x as i64 + width as i64, self.width),
+            clip(y as i64 + height as i64, self.height),
+        );
+        Some(rect).filter(|&(x0, y0, x1, y1)| x0 < x1 && y0 < y1)
+    }
+
+    /// Byte offset of the pixel at (x, y). The caller is responsible for
+    /// bounds checking.
+    pub(crate) fn pixel_index(&self, x: u32, y: u32) -> usize {
+        (y as usize * self.width as usize + x as usize) * 4
+    }
+
+    pub(crate) fn contains(&self, x: i32, y: i32) -> bool {
+        x >= 0 && y >= 0 && (x as u32) < self.width && (y as u32) < self.height
+    }
+
+    /// Read a pixel, or `None` when (x, y) falls outside the buffer.
+    pub fn pixel(&self, x: i32, y: i32) -> Option<Rgba> {
+        if !self.contains(x, y) {
+            return None;
+        }
+        let idx = self.pixel_index(x as u32, y as u32);
+        Some(Rgba::from_slice(&self.data[idx..idx + 4]))
+    }
+
+    /// Composite a single pixel. Writes outside the buffer are dropped.
+    pub(crate) fn put_pixel(&mut self, x: i32, y: i32, color: Rgba) {
+        self.blend_pixel(x, y, color, 1.0);
+    }
+
+    /// `put_pixel` with the paint's color at (x, y).
+    pub(crate) fn paint_pixel(&mut self, x: i32, y: i32, paint: &Paint) {
+        self.blend_pixel(x, y, paint.color_at(x, y), 1.0);
+    }
+
+    /// Composite `color` onto the pixel at (x, y) with the active compositor,
+    /// `coverage` (0.0 to 1.0) being how much of the pixel the shape covers.
+    /// The selection, if any, scales the coverage further.
+    pub(crate) fn blend_pixel(&mut self, x: i32, y: i32, color: Rgba, coverage: f32) {
+        if !self.contains(x, y) {
+            return;
+        }
+        let coverage = coverage * self.selection_coverage(x as u32, y as u32);
+        if coverage <= 0.0 {
+            return;
+        }
+        self.touch(x, y, x + 1, y + 1);
+        let idx = self.pixel_index(x as u32, y as u32);
+        let compositor = self.compositor;
+        compositor.composite(&mut self.data[idx..idx + 4], color, coverage);
+    }
+
+    /// Composite a horizontal run of pixels from x0 to x1 (inclusive) on row
+    /// y, clipped to the buffer.
+    pub(crate) fn fill_span(&mut self, y: i32, x0: i32, x1: i32, color: Rgba) {
+        if y < 0 || y as u32 >= self.height || self.width == 0 {
+            return;
+        }
+        let x0 = x0.max(0);
+        let x1 = x1.min(self.width as i32 - 1);
+        if x0 > x1 {
+            return;
         }
+
+        self.touch(x0, y, x1 + 1, y + 1);
+        let compositor = self.compositor;
+        if self.selection.is_none() {
+            let start = self.pixel_index(x0 as u32, y as u32);
+            let end = self.pixel_index(x1 as u32, y as u32) + 4;
+            for chunk in self.data[start..end].chunks_exact_mut(4) {
+                compositor.composite(chunk, color, 1.0);
+            }
+            return;
+        }
+        for x in x0..=x1 {
+            let coverage = self.selection_coverage(x as u32, y as u32);
+            let idx = self.pixel_index(x as u32, y as u32);
+            compositor.composite(&mut self.data[idx..idx + 4], color, coverage);
+        }
+    }
+
+    /// `fill_span` with a paint that may vary from pixel to pixel.
+    pub(crate) fn paint_span(&mut self, y: i32, x0: i32, x1: i32, paint: &Paint) {
+        if let PaintKind::Solid(color) = paint.kind {
+            return self.fill_span(y, x0, x1, color);
+        }
+        if y < 0 || y as u32 >= self.height || self.width == 0 {
+            return;
+        }
+        let x0 = x0.max(0);
+        let x1 = x1.min(self.width as i32 - 1);
+        if x0 > x1 {
+            return;
+        }
+
+        self.touch(x0, y, x1 + 1, y + 1);
+        let compositor = self.compositor;
+        for x in x0..=x1 {
+            let coverage = self.selection_coverage(x as u32, y as u32);
+            let idx = self.pixel_index(x as u32, y as u32);
+            compositor.composite(&mut self.data[idx..idx + 4], paint.color_at(x, y), coverage);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_fits_the_data_to_the_size() {
+        let short = CanvasSource::try_new(2, 1, vec![1, 2, 3, 4]).unwrap();
+        assert_eq!(short.data, [1, 2, 3, 4, 0, 0, 0, 0]);
+        let long = CanvasSource::try_new(1, 1, vec![9; 12]).unwrap();
+        assert_eq!(long.data, [9; 4]);
+    }
+
+    #[test]
+    fn new_rejects_sizes_past_the_limit() {
+        assert_eq!(buffer_len(1 << 14, 1 << 14), Some(1 << 30));
+        assert!(CanvasSource::try_new((1 << 14) + 1, 1 << 14, Vec::new()).is_err());
+        // would overflow 32-bit size math
+        assert!(CanvasSource::try_new(u32::MAX, u32::MAX, Vec::new()).is_err());
+        assert!(buffer_len(u32::MAX, 2).is_none());
+    }
+
+    #[test]
+    fn cover_in_blood_fills_in_place() {
+        let mut canvas = CanvasSource::try_new(3, 2, Vec::new()).unwrap();
+        canvas.cover_in_blood();
+        assert_eq!(canvas.data.len(), 3 * 2 * 4);
+        assert!(canvas.data.chunks_exact(4).all(|p| p == [252, 3, 27, 255]));
+    }
+
+    #[test]
+    fn pixel_reads_stay_in_bounds() {
+        let canvas = CanvasSource::try_new(2, 2, vec![7; 16]).unwrap();
+        assert_eq!(canvas.pixel(1, 1), Some(Rgba::new(7, 7, 7, 7)));
+        assert_eq!(canvas.pixel(2, 0), None);
+        assert_eq!(canvas.pixel(0, -1), None);
     }
 }
@@ -0,0 +1,315 @@
+use wasm_bindgen::prelude::*;
+
+use crate::canvas_source::CanvasSource;
+use crate::resample::ResampleFilter;
+use crate::transform::Transform;
+
+/// Pixels lifted off the canvas, either sitting in the clipboard or
+/// floating above the canvas waiting to be committed.
+pub(crate) struct Floating {
+    image: CanvasSource,
+    // maps the image's pixels onto the canvas; pixel (i, j) covers the
+    // square from (i, j) to (i + 1, j + 1) before transforming
+    placement: Transform,
+    filter: ResampleFilter,
+}
+
+impl Floating {
+    fn duplicate(&self) -> Floating {
+        Floating {
+            image: CanvasSource::from_parts(
+                self.image.width,
+                self.image.height,
+                self.image.data.clone(),
+            ),
+            placement: self.placement,
+            filter: self.filter,
+        }
+    }
+
+    /// The canvas pixels the floating image reaches, as (x0, y0, x1, y1)
+    /// with an exclusive end. May extend past the canvas.
+    fn bounds(&self) -> (i32, i32, i32, i32) {
+        self.placement
+            .bounds(0.0, 0.0, self.image.width as f32, self.image.height as f32)
+    }
+}
+
+#[wasm_bindgen]
+impl CanvasSource {
+    /// Copy the selected pixels, or the whole canvas without a selection,
+    /// to this canvas's clipboard. Partly selected pixels are copied partly
+    /// transparent.
+    pub fn copy_selection(&mut self) {
+        let rect = match self.selection_clip((0, 0, self.width, self.height)) {
+            Some(rect) => rect,
+            None => return,
+        };
+        let mut placement = Transform::identity();
+        placement.translate(rect.0 as f32, rect.1 as f32);
+        self.clipboard = Some(Box::new(Floating {
+            image: self.lift(rect),
+            placement,
+            filter: ResampleFilter::Bilinear,
+        }));
+    }
+
+    /// `copy_selection`, then clear what was copied to transparent.
+    pub fn cut_selection(&mut self) {
+        let rect = match self.selection_clip((0, 0, self.width, self.height)) {
+            Some(rect) => rect,
+            None => return,
+        };
+        self.copy_selection();
+        self.edit(|canvas| canvas.clear_rect(rect));
+    }
+
+    /// Float a copy of the clipboard above the canvas where it was copied
+    /// from, committing anything already floating first. Returns false if
+    /// the clipboard is empty.
+    ///
+    /// The floating pixels aren't part of the canvas until
+    /// `commit_floating`; show them with `floating_bounds` and
+    /// `floating_data`.
+    pub fn paste(&mut self) -> bool {
+        let pasted = match &self.clipboard {
+            Some(clipboard) => clipboard.duplicate(),
+            None => return false,
+        };
+        self.commit_floating();
+        self.floating = Some(Box::new(pasted));
+        true
+    }
+
+    pub fn has_floating(&self) -> bool {
+        self.floating.is_some()
+    }
+
+    /// Drag the floating pixels by (dx, dy), e.g. (1, 0) to nudge them a
+    /// pixel right. Fractional offsets are resampled on commit.
+    pub fn move_floating(&mut self, dx: f32, dy: f32) {
+        let mut shift = Transform::identity();
+        shift.translate(dx, dy);
+        self.transform_floating(&shift);
+    }
+
+    /// Transform the floating pixels further. The matrix is in canvas
+    /// coordinates like `transform_region`'s, so rotating about the middle
+    /// of `floating_bounds` means translating there, rotating, and
+    /// translating back. Matrices that would flatten the pixels are
+    /// ignored.
+    pub fn transform_floating(&mut self, transform: &Transform) {
+        if let Some(floating) = &mut self.floating {
+            let mut placement = *transform;
+            placement.multiply(&floating.placement);
+            if placement.inverse().is_some() {
+                floating.placement = placement;
+            }
+        }
+    }
+
+    /// The filter used to resample the floating pixels once they're moved
+    /// off whole pixels or transformed. Defaults to bilinear.
+    pub fn set_floating_filter(&mut self, filter: ResampleFilter) {
+        if let Some(floating) = &mut self.floating {
+            floating.filter = filter;
+        }
+    }
+
+    /// `[x, y, width, height]` of the canvas area the floating pixels
+    /// cover, or empty if nothing is floating. Can reach past the canvas
+    /// edges.
+    pub fn floating_bounds(&self) -> Vec<i32> {
+        match &self.floating {
+            Some(floating) => {
+                let (x0, y0, x1, y1) = floating.bounds();
+                vec![x0, y0, x1 - x0, y1 - y0]
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// The floating pixels as they'll be committed, RGBA rows filling
+    /// `floating_bounds`. Empty if nothing is floating, or if it has been
+    /// scaled up past the largest size a canvas can be.
+    pub fn floating_data(&self) -> Vec<u8> {
+        self.floating
+            .as_ref()
+            .and_then(|floating| {
+                floating
+                    .image
+                    .transformed(&floating.placement, floating.filter, true)
+            })
+            .map_or_else(Vec::new, |image| image.data)
+    }
+
+    /// Composite the floating pixels onto the canvas as one undo step. The
+    /// active compositor applies; the selection doesn't.
+    pub fn commit_floating(&mut self) {
+        if let Some(floating) = self.floating.take() {
+            self.edit(|canvas| {
+                canvas.draw_transformed(&floating.image, &floating.placement, floating.filter)
+            });
+        }
+    }
+
+    /// Drop the floating pixels without drawing them. The clipboard keeps
+    /// its copy.
+    pub fn cancel_floating(&mut self) {
+        self.floating = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pictures::{digit_picture, digits};
+    use crate::selection::SelectionOp;
+
+    /// The floating pixels as a picture.
+    fn floating(canvas: &CanvasSource) -> Vec<String> {
+        let bounds = canvas.floating_bounds();
+        let image =
+            CanvasSource::try_new(bounds[2] as u32, bounds[3] as u32, canvas.floating_data())
+                .unwrap();
+        digit_picture(&image)
+    }
+
+    const DIGITS: [&str; 3] = ["1234", "5678", "9012"];
+
+    #[test]
+    fn without_a_selection_everything_is_copied() {
+        let mut canvas = digits(&DIGITS);
+        canvas.copy_selection();
+        assert!(!canvas.has_floating());
+        assert!(canvas.paste());
+        assert_eq!(canvas.floating_bounds(), vec![0, 0, 4, 3]);
+        assert_eq!(floating(&canvas), DIGITS);
+        // pasting alone doesn't touch the canvas
+        assert_eq!(digit_picture(&canvas), DIGITS);
+        assert!(!canvas.can_undo());
+    }
+
+    #[test]
+    fn pasted_pixels_move_and_commit_as_one_step() {
+        let mut canvas = digits(&DIGITS);
+        canvas.select_rect(1.0, 0.0, 2.0, 2.0, SelectionOp::Replace);
+        canvas.copy_selection();
+        assert!(canvas.paste());
+        assert_eq!(canvas.floating_bounds(), vec![1, 0, 2, 2]);
+        assert_eq!(floating(&canvas), ["23", "67"]);
+
+        canvas.move_floating(1.0, 1.0);
+        assert_eq!(canvas.floating_bounds(), vec![2, 1, 2, 2]);
+        assert_eq!(floating(&canvas), ["23", "67"]);
+        assert_eq!(digit_picture(&canvas), DIGITS);
+
+        // the selection doesn't clip the commit
+        canvas.commit_floating();
+        assert!(!canvas.has_floating());
+        assert_eq!(digit_picture(&canvas), ["1234", "5623", "9067"]);
+        assert!(canvas.undo());
+        assert_eq!(digit_picture(&canvas), DIGITS);
+        assert!(!canvas.can_undo());
+    }
+
+    #[test]
+    fn pasting_again_commits_what_was_floating() {
+        let mut canvas = digits(&DIGITS);
+        canvas.select_rect(0.0, 0.0, 1.0, 1.0, SelectionOp::Replace);
+        canvas.copy_selection();
+        canvas.paste();
+        canvas.move_floating(3.0, 2.0);
+        canvas.paste();
+        assert_eq!(digit_picture(&canvas), ["1234", "5678", "9011"]);
+        assert_eq!(canvas.floating_bounds(), vec![0, 0, 1, 1]);
+    }
+
+    #[test]
+    fn cancelling_drops_the_floating_pixels() {
+        let mut canvas = digits(&DIGITS);
+        canvas.copy_selection();
+        canvas.paste();
+        canvas.move_floating(1.0, 0.0);
+        canvas.cancel_floating();
+        assert!(!canvas.has_floating());
+        assert!(canvas.floating_bounds().is_empty());
+        assert!(canvas.floating_data().is_empty());
+        canvas.commit_floating();
+        assert_eq!(digit_picture(&canvas), DIGITS);
+        assert!(!canvas.can_undo());
+        // the clipboard keeps its copy, back where it came from
+        assert!(canvas.paste());
+        assert_eq!(canvas.floating_bounds(), vec![0, 0, 4, 3]);
+    }
+
+    #[test]
+    fn cutting_clears_what_was_copied() {
+        let mut canvas = digits(&DIGITS);
+        canvas.select_rect(1.0, 0.0, 2.0, 2.0, SelectionOp::Replace);
+        canvas.cut_selection();
+        assert_eq!(digit_picture(&canvas), ["1..4", "5..8", "9012"]);
+
+        canvas.paste();
+        canvas.commit_floating();
+        assert_eq!(digit_picture(&canvas), DIGITS);
+        canvas.undo();
+        canvas.undo();
+        assert_eq!(digit_picture(&canvas), DIGITS);
+        assert!(!canvas.can_undo());
+    }
+
+    #[test]
+    fn nothing_to_paste() {
+        let mut canvas = digits(&DIGITS);
+        assert!(!canvas.paste());
+        assert!(!canvas.has_floating());
+
+        // an empty selection copies nothing and cuts nothing
+        canvas.select_rect(0.0, 0.0, 1.0, 1.0, SelectionOp::Replace);
+        canvas.select_rect(0.0, 0.0, 1.0, 1.0, SelectionOp::Subtract);
+        canvas.copy_selection();
+        canvas.cut_selection();
+        assert!(!canvas.paste());
+        assert_eq!(digit_picture(&canvas), DIGITS);
+        assert!(!canvas.can_undo());
+    }
+
+    #[test]
+    fn partly_selected_pixels_copy_partly_transparent() {
+        let mut canvas = digits(&["55"]);
+        canvas.select_rect(0.5, 0.0, 1.0, 1.0, SelectionOp::Replace);
+        canvas.copy_selection();
+        canvas.paste();
+        assert_eq!(canvas.floating_bounds(), vec![0, 0, 2, 1]);
+        assert_eq!(canvas.floating_data(), vec![50, 0, 0, 128, 50, 0, 0, 128]);
+    }
+
+    #[test]
+    fn floating_pixels_transform_and_resample() {
+        let mut canvas = digits(&["12", "34", ".."]);
+        canvas.select_rect(0.0, 0.0, 2.0, 2.0, SelectionOp::Replace);
+        canvas.copy_selection();
+        canvas.paste();
+        canvas.set_floating_filter(ResampleFilter::Nearest);
+
+        let mut flatten = Transform::identity();
+        flatten.scale(0.0, 1.0);
+        canvas.transform_floating(&flatten);
+        assert_eq!(canvas.floating_bounds(), vec![0, 0, 2, 2]);
+
+        let mut stretch = Transform::identity();
+        stretch.scale(2.0, 1.0);
+        canvas.transform_floating(&stretch);
+        assert_eq!(canvas.floating_bounds(), vec![0, 0, 4, 2]);
+        assert_eq!(floating(&canvas), ["1122", "3344"]);
+
+        // half a pixel over, the bilinear filter blends neighbours
+        canvas.set_floating_filter(ResampleFilter::Bilinear);
+        canvas.move_floating(0.5, 0.0);
+        assert_eq!(canvas.floating_bounds(), vec![0, 0, 5, 2]);
+        let data = canvas.floating_data();
+        assert_eq!(data[4..8], [10, 0, 0, 255]);
+        assert_eq!(data[8..12], [15, 0, 0, 255]);
+    }
+}
@@ -0,0 +1,87 @@
+// colors cross the wasm boundary packed into a single u32 as 0xRRGGBBAA,
+// which is easy to write from JS (0xfc031bff) and cheap to pass around
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct Rgba {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Rgba {
+    pub const TRANSPARENT: Rgba = Rgba::new(0, 0, 0, 0);
+
+    pub const fn new(r: u8, g: u8, b: u8, a: u8) -> Rgba {
+        Rgba { r, g, b, a }
+    }
+
+    /// Unpack a color from the `0xRRGGBBAA` form used by the JS-facing API.
+    pub const fn from_u32(packed: u32) -> Rgba {
+        Rgba {
+            r: (packed >> 24) as u8,
+            g: (packed >> 16) as u8,
+            b: (packed >> 8) as u8,
+            a: packed as u8,
+        }
+    }
+
+    /// Pack the color back into `0xRRGGBBAA`.
+    pub const fn to_u32(self) -> u32 {
+        (self.r as u32) << 24 | (self.g as u32) << 16 | (self.b as u32) << 8 | self.a as u32
+    }
+
+    pub fn from_slice(pixel: &[u8]) -> Rgba {
+        Rgba::new(pixel[0], pixel[1], pixel[2], pixel[3])
+    }
+
+    pub fn to_array(self) -> [u8; 4] {
+        [self.r, self.g, self.b, self.a]
+    }
+
+    /// Euclidean distance between two colors over all four channels, scaled
+    /// so it runs from 0 (identical) to 255 (opposite corners of RGBA
+    /// space).
+    pub fn distance(self, other: Rgba) -> f32 {
+        let squared: f32 = self
+            .to_array()
+            .iter()
+            .zip(other.to_array().iter())
+            .map(|(&a, &b)| {
+                let d = a as f32 - b as f32;
+                d * d
+            })
+            .sum();
+        (squared / 4.0).sqrt()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packing_round_trips() {
+        let color = Rgba::from_u32(0xfc031bff);
+        assert_eq!(color, Rgba::new(0xfc, 0x03, 0x1b, 0xff));
+        assert_eq!(color.to_u32(), 0xfc031bff);
+        assert_eq!(color.to_array(), [0xfc, 0x03, 0x1b, 0xff]);
+        assert_eq!(Rgba::from_slice(&[0xfc, 0x03, 0x1b, 0xff, 9]), color);
+        assert_eq!(Rgba::from_u32(0), Rgba::TRANSPARENT);
+    }
+
+    #[test]
+    fn distance_runs_from_0_to_255() {
+        let black = Rgba::new(0, 0, 0, 255);
+        assert_eq!(black.distance(black), 0.0);
+        assert_eq!(
+            Rgba::TRANSPARENT.distance(Rgba::new(255, 255, 255, 255)),
+            255.0
+        );
+        // one channel all the way across is half the diagonal
+        assert_eq!(black.distance(Rgba::new(255, 0, 0, 255)), 127.5);
+        assert_eq!(
+            black.distance(Rgba::new(10, 0, 0, 255)),
+            Rgba::new(10, 0, 0, 255).distance(black)
+        );
+    }
+}
@@ -0,0 +1,360 @@
+use std::fmt;
+
+use wasm_bindgen::prelude::*;
+
+use crate::canvas_source::CanvasSource;
+use crate::color::Rgba;
+use crate::filters::gaussian_kernel;
+
+// what a pixel that differs by more than the tolerance is drawn as in the
+// diff image, and one that differs by no more than it
+const CHANGED: Rgba = Rgba::new(255, 0, 0, 255);
+const WITHIN_TOLERANCE: Rgba = Rgba::new(255, 200, 0, 255);
+
+// SSIM's Gaussian window and stabilizing constants, as in Wang et al. 2004
+const SSIM_SIGMA: f32 = 1.5;
+const SSIM_RADIUS: u32 = 5;
+const SSIM_C1: f32 = (0.01 * 255.0) * (0.01 * 255.0);
+const SSIM_C2: f32 = (0.03 * 255.0) * (0.03 * 255.0);
+
+#[derive(Debug)]
+pub enum CompareError {
+    /// The two canvases aren't the same size.
+    Size {
+        expected: (u32, u32),
+        actual: (u32, u32),
+    },
+}
+
+impl fmt::Display for CompareError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompareError::Size { expected, actual } => write!(
+                f,
+                "can't compare a {}x{} image with a {}x{} one",
+                expected.0, expected.1, actual.0, actual.1
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CompareError {}
+
+/// How two same-sized images differ, e.g. a render against its golden
+/// image.
+///
+/// Colors are compared premultiplied, so fully transparent pixels match
+/// whatever color they hold.
+#[wasm_bindgen]
+pub struct Comparison {
+    width: u32,
+    height: u32,
+    diff: Vec<u8>,
+    differing_pixels: u32,
+    mean_squared_error: f64,
+    ssim: f32,
+}
+
+#[wasm_bindgen]
+impl Comparison {
+    /// How many pixels have a channel more than the tolerance apart.
+    pub fn differing_pixels(&self) -> u32 {
+        self.differing_pixels
+    }
+
+    /// Peak signal-to-noise ratio in decibels over all four channels.
+    /// Higher is closer; identical images give infinity.
+    pub fn psnr(&self) -> f32 {
+        if self.mean_squared_error == 0.0 {
+            return f32::INFINITY;
+        }
+        (10.0 * (255.0 * 255.0 / self.mean_squared_error).log10()) as f32
+    }
+
+    /// Mean structural similarity of the two images' luma, from -1.0 to
+    /// 1.0 where 1.0 means identical. Unlike PSNR it tracks how different
+    /// the images look rather than how far apart the numbers are.
+    pub fn ssim(&self) -> f32 {
+        self.ssim
+    }
+
+    /// An image of where the two differ: unchanged pixels as a faded gray
+    /// copy of the first image, pixels past the tolerance in red and those
+    /// that changed by no more than it in orange.
+    pub fn diff_image(&self) -> CanvasSource {
+        CanvasSource::from_parts(self.width, self.height, self.diff.clone())
+    }
+}
+
+#[wasm_bindgen]
+impl CanvasSource {
+    /// Compare with another canvas of the same size. Pixels count as
+    /// differing when any channel is more than `tolerance` (0 to 255)
+    /// apart. Throws if the sizes differ.
+    pub fn compare(&self, other: &CanvasSource, tolerance: u8) -> Result<Comparison, JsError> {
+        Ok(self.compare_to(other, tolerance)?)
+    }
+}
+
+// removed #[wasm_bindgen] - the Rust-side API for the native binary
+impl CanvasSource {
+    pub fn compare_to(
+        &self,
+        other: &CanvasSource,
+        tolerance: u8,
+    ) -> Result<Comparison, CompareError> {
+        if (self.width, self.height) != (other.width, other.height) {
+            return Err(CompareError::Size {
+                expected: (self.width, self.height),
+                actual: (other.width, other.height),
+            });
+        }
+
+        let count = self.data.len() / 4;
+        let mut diff = Vec::with_capacity(count * 4);
+        let mut differing_pixels = 0;
+        let mut squared_error = 0.0f64;
+        let mut luma_a = Vec::with_capacity(count);
+        let mut luma_b = Vec::with_capacity(count);
+        for (a, b) in self.data.chunks_exact(4).zip(other.data.chunks_exact(4)) {
+            let (a, b) = (premultiply(a), premultiply(b));
+            let mut largest = 0;
+            for (&x, &y) in a.iter().zip(&b) {
+                let delta = (x as i32 - y as i32).unsigned_abs();
+                largest = largest.max(delta);
+                squared_error += (delta * delta) as f64;
+            }
+
+            let highlight = if largest > tolerance as u32 {
+                differing_pixels += 1;
+                CHANGED
+            } else if largest > 0 {
+                WITHIN_TOLERANCE
+            } else {
+                // composited over white, then faded to a quarter strength
+                let over_white = luma(&a) + (255 - a[3]) as f32;
+                let gray = 255 - (255 - over_white.round().min(255.0) as u32) / 4;
+                Rgba::new(gray as u8, gray as u8, gray as u8, 255)
+            };
+            diff.extend_from_slice(&highlight.to_array());
+            luma_a.push(luma(&a));
+            luma_b.push(luma(&b));
+        }
+
+        let ssim = mean_ssim(&luma_a, &luma_b, self.width, self.height);
+        Ok(Comparison {
+            width: self.width,
+            height: self.height,
+            diff,
+            differing_pixels,
+            mean_squared_error: squared_error / (count * 4).max(1) as f64,
+            ssim,
+        })
+    }
+}
+
+/// An RGBA pixel with its color scaled by its alpha.
+fn premultiply(p: &[u8]) -> [u8; 4] {
+    let scale = |c: u8| ((c as u32 * p[3] as u32 + 127) / 255) as u8;
+    [scale(p[0]), scale(p[1]), scale(p[2]), p[3]]
+}
+
+/// Rec. 601 luma of a premultiplied pixel, as if composited over black.
+fn luma(p: &[u8; 4]) -> f32 {
+    0.299 * p[0] as f32 + 0.587 * p[1] as f32 + 0.114 * p[2] as f32
+}
+
+/// The average over every pixel of SSIM in a Gaussian window around it.
+fn mean_ssim(a: &[f32], b: &[f32], width: u32, height: u32) -> f32 {
+    if a.is_empty() {
+        return 1.0;
+    }
+    let squares = |image: &[f32]| image.iter().map(|v| v * v).collect::<Vec<f32>>();
+    let products: Vec<f32> = a.iter().zip(b).map(|(x, y)| x * y).collect();
+    let mean_a = gaussian_blur(a, width, height);
+    let mean_b = gaussian_blur(b, width, height);
+    let mean_aa = gaussian_blur(&squares(a), width, height);
+    let mean_bb = gaussian_blur(&squares(b), width, height);
+    let mean_ab = gaussian_blur(&products, width, height);
+
+    let total: f64 = (0..a.len())
+        .map(|i| {
+            let (mu_a, mu_b) = (mean_a[i], mean_b[i]);
+            let var_a = mean_aa[i] - mu_a * mu_a;
+            let var_b = mean_bb[i] - mu_b * mu_b;
+            let covariance = mean_ab[i] - mu_a * mu_b;
+            let numerator = (2.0 * mu_a * mu_b + SSIM_C1) * (2.0 * covariance + SSIM_C2);
+            let denominator = (mu_a * mu_a + mu_b * mu_b + SSIM_C1) * (var_a + var_b + SSIM_C2);
+            (numerator / denominator) as f64
+        })
+        .sum();
+    (total / a.len() as f64) as f32
+}
+
+/// Separable Gaussian blur, with the edge pixels repeated past the edges.
+fn gaussian_blur(image: &[f32], width: u32, height: u32) -> Vec<f32> {
+    let kernel = gaussian_kernel(SSIM_SIGMA, SSIM_RADIUS);
+    let radius = (kernel.len() / 2) as i32;
+    let (w, h) = (width as i32, height as i32);
+    let blur = |sample: &dyn Fn(i32, i32, i32) -> f32| {
+        let mut output = vec![0.0f32; image.len()];
+        for y in 0..h {
+            for x in 0..w {
+                output[(y * w + x) as usize] = kernel
+                    .iter()
+                    .enumerate()
+                    .map(|(k, weight)| weight * sample(x, y, k as i32 - radius))
+                    .sum::<f32>();
+            }
+        }
+        output
+    };
+    let horizontal = blur(&|x, y, offset| image[(y * w + (x + offset).clamp(0, w - 1)) as usize]);
+    blur(&|x, y, offset| horizontal[((y + offset).clamp(0, h - 1) * w + x) as usize])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn canvas(width: u32, pixels: &[[u8; 4]]) -> CanvasSource {
+        let height = pixels.len() as u32 / width;
+        CanvasSource::try_new(width, height, pixels.concat()).unwrap()
+    }
+
+    /// A 16x16 gray ramp, with `f` applied to each level.
+    fn ramp(f: impl Fn(u32, u32, u8) -> u8) -> CanvasSource {
+        let pixels: Vec<[u8; 4]> = (0..256)
+            .map(|i| {
+                let (x, y) = (i % 16, i / 16);
+                let v = f(x, y, 50 + (x + y) as u8 * 5);
+                [v, v, v, 255]
+            })
+            .collect();
+        canvas(16, &pixels)
+    }
+
+    #[test]
+    fn identical_images_match_perfectly() {
+        let image = ramp(|_, _, v| v);
+        let comparison = image.compare_to(&ramp(|_, _, v| v), 0).unwrap();
+        assert_eq!(comparison.differing_pixels(), 0);
+        assert_eq!(comparison.psnr(), f32::INFINITY);
+        assert!((comparison.ssim() - 1.0).abs() < 1e-6);
+
+        let empty = CanvasSource::try_new(0, 0, Vec::new()).unwrap();
+        let comparison = empty.compare_to(&empty, 0).unwrap();
+        assert_eq!(comparison.psnr(), f32::INFINITY);
+        assert_eq!(comparison.ssim(), 1.0);
+    }
+
+    #[test]
+    fn psnr_follows_the_mean_squared_error() {
+        let a = canvas(1, &[[0, 0, 0, 255]]);
+        let b = canvas(1, &[[10, 0, 0, 255]]);
+        // one channel of four off by 10 is an MSE of 25
+        let expected = 10.0 * (255.0f32 * 255.0 / 25.0).log10();
+        let psnr = a.compare_to(&b, 0).unwrap().psnr();
+        assert!((psnr - expected).abs() < 1e-3, "{} != {}", psnr, expected);
+        assert!((psnr - 34.151).abs() < 1e-3);
+    }
+
+    #[test]
+    fn tolerance_decides_what_differs() {
+        let a = canvas(3, &[[100, 100, 100, 255]; 3]);
+        let b = canvas(
+            3,
+            &[
+                [100, 100, 100, 255],
+                [100, 103, 100, 255],
+                [100, 100, 90, 255],
+            ],
+        );
+        assert_eq!(a.compare_to(&b, 0).unwrap().differing_pixels(), 2);
+        assert_eq!(a.compare_to(&b, 3).unwrap().differing_pixels(), 1);
+        assert_eq!(a.compare_to(&b, 10).unwrap().differing_pixels(), 0);
+    }
+
+    #[test]
+    fn the_diff_image_highlights_changes() {
+        let a = canvas(
+            5,
+            &[
+                [0, 0, 0, 255],
+                [255, 255, 255, 255],
+                [9, 9, 9, 0],
+                [100, 100, 100, 255],
+                [100, 100, 100, 255],
+            ],
+        );
+        let b = canvas(
+            5,
+            &[
+                [0, 0, 0, 255],
+                [255, 255, 255, 255],
+                [9, 9, 9, 0],
+                [102, 100, 100, 255],
+                [200, 100, 100, 255],
+            ],
+        );
+        let diff = a.compare_to(&b, 5).unwrap().diff_image();
+        assert_eq!((diff.width, diff.height), (5, 1));
+        let pixels: Vec<&[u8]> = diff.data.chunks(4).collect();
+        // unchanged pixels are faded towards white, transparent ones are
+        // white
+        assert_eq!(pixels[0], [192, 192, 192, 255]);
+        assert_eq!(pixels[1], [255, 255, 255, 255]);
+        assert_eq!(pixels[2], [255, 255, 255, 255]);
+        assert_eq!(pixels[3], WITHIN_TOLERANCE.to_array());
+        assert_eq!(pixels[4], CHANGED.to_array());
+    }
+
+    #[test]
+    fn transparent_pixels_match_whatever_their_color() {
+        let a = canvas(2, &[[255, 0, 0, 0], [10, 20, 30, 0]]);
+        let b = canvas(2, &[[0, 255, 0, 0], [0, 0, 0, 0]]);
+        let comparison = a.compare_to(&b, 0).unwrap();
+        assert_eq!(comparison.differing_pixels(), 0);
+        assert_eq!(comparison.psnr(), f32::INFINITY);
+
+        // but not a visible pixel
+        let c = canvas(2, &[[0, 255, 0, 1], [0, 0, 0, 0]]);
+        assert_eq!(a.compare_to(&c, 0).unwrap().differing_pixels(), 1);
+    }
+
+    #[test]
+    fn ssim_tracks_structure_rather_than_error() {
+        let image = ramp(|_, _, v| v);
+        // the same squared error, spread as a brightness shift or as noise
+        let brighter = ramp(|_, _, v| v + 10);
+        let noisy = ramp(|x, y, v| if (x + y) % 2 == 0 { v + 10 } else { v - 10 });
+        let shifted = image.compare_to(&brighter, 0).unwrap();
+        let speckled = image.compare_to(&noisy, 0).unwrap();
+        assert!((shifted.psnr() - speckled.psnr()).abs() < 1e-3);
+        assert!(shifted.ssim() > 0.95, "{}", shifted.ssim());
+        assert!(
+            speckled.ssim() < shifted.ssim() - 0.2,
+            "{}",
+            speckled.ssim()
+        );
+
+        let inverted = ramp(|_, _, v| 255 - v);
+        let opposite = image.compare_to(&inverted, 0).unwrap();
+        assert!(opposite.ssim() < 0.0, "{}", opposite.ssim());
+    }
+
+    #[test]
+    fn sizes_must_match() {
+        let small = CanvasSource::try_new(2, 3, Vec::new()).unwrap();
+        let large = CanvasSource::try_new(3, 2, Vec::new()).unwrap();
+        match small.compare_to(&large, 0) {
+            Err(error @ CompareError::Size { .. }) => {
+                assert_eq!(
+                    error.to_string(),
+                    "can't compare a 2x3 image with a 3x2 one"
+                )
+            }
+            Ok(_) => panic!("compared a 2x3 image with a 3x2 one"),
+        }
+    }
+}
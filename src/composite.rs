@@ -0,0 +1,334 @@
+use wasm_bindgen::prelude::*;
+
+use crate::canvas_source::CanvasSource;
+use crate::color::Rgba;
+
+/// Porter-Duff operators, named after their Canvas2D
+/// `globalCompositeOperation` counterparts.
+///
+/// Operators only touch the pixels a draw call covers; pixels outside the
+/// shape are left alone even for operators like `DestinationIn` that would
+/// clear them in a browser canvas.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompositeOp {
+    SourceOver = 0,
+    SourceIn = 1,
+    SourceOut = 2,
+    SourceAtop = 3,
+    DestinationOver = 4,
+    DestinationIn = 5,
+    DestinationOut = 6,
+    DestinationAtop = 7,
+    Xor = 8,
+    Copy = 9,
+    Clear = 10,
+    Lighter = 11,
+}
+
+/// Separable blend modes, applied to the source color before it is
+/// composited.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlendMode {
+    Normal = 0,
+    Multiply = 1,
+    Screen = 2,
+    Overlay = 3,
+    Darken = 4,
+    Lighten = 5,
+    Difference = 6,
+}
+
+impl CompositeOp {
+    /// The (source, destination) weights for this operator, given the source
+    /// and destination alphas.
+    fn factors(self, src_alpha: f32, dst_alpha: f32) -> (f32, f32) {
+        match self {
+            CompositeOp::SourceOver => (1.0, 1.0 - src_alpha),
+            CompositeOp::SourceIn => (dst_alpha, 0.0),
+            CompositeOp::SourceOut => (1.0 - dst_alpha, 0.0),
+            CompositeOp::SourceAtop => (dst_alpha, 1.0 - src_alpha),
+            CompositeOp::DestinationOver => (1.0 - dst_alpha, 1.0),
+            CompositeOp::DestinationIn => (0.0, src_alpha),
+            CompositeOp::DestinationOut => (0.0, 1.0 - src_alpha),
+            CompositeOp::DestinationAtop => (1.0 - dst_alpha, src_alpha),
+            CompositeOp::Xor => (1.0 - dst_alpha, 1.0 - src_alpha),
+            CompositeOp::Copy => (1.0, 0.0),
+            CompositeOp::Clear => (0.0, 0.0),
+            CompositeOp::Lighter => (1.0, 1.0),
+        }
+    }
+}
+
+impl BlendMode {
+    /// B(cb, cs) from the W3C compositing spec, on straight (not
+    /// premultiplied) channel values in 0.0..=1.0.
+    fn blend(self, backdrop: f32, source: f32) -> f32 {
+        match self {
+            BlendMode::Normal => source,
+            BlendMode::Multiply => backdrop * source,
+            BlendMode::Screen => backdrop + source - backdrop * source,
+            BlendMode::Overlay => {
+                // hard-light with the layers swapped
+                if backdrop <= 0.5 {
+                    2.0 * backdrop * source
+                } else {
+                    let doubled = 2.0 * backdrop - 1.0;
+                    doubled + source - doubled * source
+                }
+            }
+            BlendMode::Darken => backdrop.min(source),
+            BlendMode::Lighten => backdrop.max(source),
+            BlendMode::Difference => (backdrop - source).abs(),
+        }
+    }
+}
+
+/// The blend mode and operator a canvas composites its draws with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Compositor {
+    pub op: CompositeOp,
+    pub blend: BlendMode,
+}
+
+impl Default for Compositor {
+    fn default() -> Self {
+        Compositor {
+            op: CompositeOp::SourceOver,
+            blend: BlendMode::Normal,
+        }
+    }
+}
+
+impl Compositor {
+    /// Composite `src` onto the straight-alpha RGBA pixel `dst`.
+    ///
+    /// `coverage` is how much of the pixel the source actually covers (edge
+    /// anti-aliasing, layer opacity, ...); the result is interpolated between
+    /// the untouched destination and the full composite by that amount.
+    pub fn composite(&self, dst: &mut [u8], src: Rgba, coverage: f32) {
+        let coverage = coverage.clamp(0.0, 1.0);
+        if coverage <= 0.0 {
+            return;
+        }
+
+        // the common case of an opaque normal draw is a plain copy
+        let opaque_copy = match self.op {
+            CompositeOp::Copy => self.blend == BlendMode::Normal,
+            CompositeOp::SourceOver => self.blend == BlendMode::Normal && src.a == 255,
+            _ => false,
+        };
+        if opaque_copy && coverage >= 1.0 {
+            dst.copy_from_slice(&src.to_array());
+            return;
+        }
+
+        let src_alpha = src.a as f32 / 255.0;
+        let dst_alpha = dst[3] as f32 / 255.0;
+        let (src_factor, dst_factor) = self.op.factors(src_alpha, dst_alpha);
+
+        // premultiplied result of the operator
+        let mut out = [0.0f32; 4];
+        for channel in 0..3 {
+            let source = src.to_array()[channel] as f32 / 255.0;
+            let backdrop = dst[channel] as f32 / 255.0;
+            // blend modes only apply where there is a backdrop to blend with
+            let blended =
+                (1.0 - dst_alpha) * source + dst_alpha * self.blend.blend(backdrop, source);
+            out[channel] = src_alpha * blended * src_factor + dst_alpha * backdrop * dst_factor;
+        }
+        out[3] = src_alpha * src_factor + dst_alpha * dst_factor;
+
+        // fade between the untouched destination and the result by coverage
+        for channel in 0..3 {
+            let before = dst_alpha * dst[channel] as f32 / 255.0;
+            out[channel] = before + (out[channel] - before) * coverage;
+        }
+        out[3] = (dst_alpha + (out[3] - dst_alpha) * coverage).min(1.0);
+
+        if out[3] <= 0.0 {
+            dst.copy_from_slice(&[0, 0, 0, 0]);
+            return;
+        }
+        for channel in 0..3 {
+            dst[channel] = (out[channel] / out[3] * 255.0).round().clamp(0.0, 255.0) as u8;
+        }
+        dst[3] = (out[3] * 255.0).round() as u8;
+    }
+}
+
+// every draw call on a CanvasSource goes through its compositor, the same way
+// a browser canvas uses globalCompositeOperation
+#[wasm_bindgen]
+impl CanvasSource {
+    pub fn composite_op(&self) -> CompositeOp {
+        self.compositor.op
+    }
+
+    pub fn set_composite_op(&mut self, op: CompositeOp) {
+        self.compositor.op = op;
+    }
+
+    pub fn blend_mode(&self) -> BlendMode {
+        self.compositor.blend
+    }
+
+    pub fn set_blend_mode(&mut self, mode: BlendMode) {
+        self.compositor.blend = mode;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raster::Rasterization;
+
+    /// `src` composited onto `dst` with full coverage.
+    fn composite(op: CompositeOp, blend: BlendMode, dst: [u8; 4], src: u32) -> [u8; 4] {
+        let mut pixel = dst;
+        Compositor { op, blend }.composite(&mut pixel, Rgba::from_u32(src), 1.0);
+        pixel
+    }
+
+    fn over(dst: [u8; 4], src: u32) -> [u8; 4] {
+        composite(CompositeOp::SourceOver, BlendMode::Normal, dst, src)
+    }
+
+    fn blend(mode: BlendMode, dst: [u8; 4], src: u32) -> [u8; 4] {
+        composite(CompositeOp::SourceOver, mode, dst, src)
+    }
+
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+    const CLEAR: [u8; 4] = [0, 0, 0, 0];
+
+    #[test]
+    fn source_over() {
+        assert_eq!(over(CLEAR, 0x0000ff80), [0, 0, 255, 128]);
+        assert_eq!(over(BLUE, 0xff000080), [128, 0, 127, 255]);
+        assert_eq!(over(BLUE, 0xff0000ff), [255, 0, 0, 255]);
+        assert_eq!(over([255, 0, 0, 128], 0x0000ff80), [85, 0, 170, 192]);
+    }
+
+    #[test]
+    fn porter_duff_operators() {
+        let normal = BlendMode::Normal;
+        let half_red = 0xff000080;
+        assert_eq!(
+            composite(CompositeOp::SourceIn, normal, CLEAR, 0xff0000ff),
+            CLEAR
+        );
+        assert_eq!(
+            composite(CompositeOp::SourceIn, normal, [0, 0, 255, 128], 0xff0000ff),
+            [255, 0, 0, 128]
+        );
+        assert_eq!(
+            composite(CompositeOp::SourceOut, normal, BLUE, 0xff0000ff),
+            CLEAR
+        );
+        assert_eq!(
+            composite(CompositeOp::SourceAtop, normal, CLEAR, 0xff0000ff),
+            CLEAR
+        );
+        assert_eq!(
+            composite(CompositeOp::SourceAtop, normal, BLUE, half_red),
+            [128, 0, 127, 255]
+        );
+        assert_eq!(
+            composite(CompositeOp::DestinationOver, normal, BLUE, 0xff0000ff),
+            BLUE
+        );
+        assert_eq!(
+            composite(CompositeOp::DestinationIn, normal, BLUE, half_red),
+            [0, 0, 255, 128]
+        );
+        assert_eq!(
+            composite(CompositeOp::DestinationOut, normal, BLUE, 0xff0000ff),
+            CLEAR
+        );
+        assert_eq!(
+            composite(CompositeOp::DestinationOut, normal, BLUE, half_red),
+            [0, 0, 255, 127]
+        );
+        assert_eq!(
+            composite(CompositeOp::DestinationAtop, normal, BLUE, 0xff0000ff),
+            BLUE
+        );
+        assert_eq!(composite(CompositeOp::Xor, normal, BLUE, 0xff0000ff), CLEAR);
+        assert_eq!(
+            composite(CompositeOp::Copy, normal, BLUE, half_red),
+            [255, 0, 0, 128]
+        );
+        assert_eq!(
+            composite(CompositeOp::Clear, normal, BLUE, 0xff0000ff),
+            CLEAR
+        );
+        assert_eq!(
+            composite(CompositeOp::Lighter, normal, [255, 0, 0, 128], half_red),
+            [255, 0, 0, 255]
+        );
+    }
+
+    #[test]
+    fn blend_modes() {
+        let gray = [128, 128, 128, 255];
+        assert_eq!(
+            blend(BlendMode::Multiply, gray, 0x808080ff),
+            [64, 64, 64, 255]
+        );
+        assert_eq!(
+            blend(BlendMode::Screen, gray, 0x808080ff),
+            [192, 192, 192, 255]
+        );
+        assert_eq!(
+            blend(BlendMode::Difference, gray, 0xffffffff),
+            [127, 127, 127, 255]
+        );
+        assert_eq!(
+            blend(BlendMode::Darken, [200, 50, 0, 255], 0x649600ff),
+            [100, 50, 0, 255]
+        );
+        assert_eq!(
+            blend(BlendMode::Lighten, [200, 50, 0, 255], 0x649600ff),
+            [200, 150, 0, 255]
+        );
+        // dark backdrops multiply, light ones screen
+        assert_eq!(
+            blend(BlendMode::Overlay, [51, 204, 0, 255], 0x999900ff),
+            [61, 214, 0, 255]
+        );
+    }
+
+    #[test]
+    fn blend_modes_need_a_backdrop() {
+        assert_eq!(
+            blend(BlendMode::Multiply, CLEAR, 0xff0000ff),
+            [255, 0, 0, 255]
+        );
+        assert_eq!(
+            blend(BlendMode::Difference, CLEAR, 0x80ff00ff),
+            [128, 255, 0, 255]
+        );
+    }
+
+    #[test]
+    fn coverage_fades_the_result() {
+        let mut pixel = CLEAR;
+        Compositor::default().composite(&mut pixel, Rgba::from_u32(0xff0000ff), 0.5);
+        assert_eq!(pixel, [255, 0, 0, 128]);
+
+        let mut pixel = BLUE;
+        Compositor::default().composite(&mut pixel, Rgba::from_u32(0xff0000ff), 0.0);
+        assert_eq!(pixel, BLUE);
+    }
+
+    #[test]
+    fn canvas_draws_use_the_compositor() {
+        let mut canvas = CanvasSource::try_new(2, 1, Vec::new()).unwrap();
+        canvas.fill_rect(0.0, 0.0, 2.0, 1.0, 0x0000ffff, Rasterization::Aliased);
+        canvas.set_composite_op(CompositeOp::DestinationOut);
+        canvas.fill_rect(0.0, 0.0, 1.0, 1.0, 0x000000ff, Rasterization::Aliased);
+        assert_eq!(canvas.pixel(0, 0), Some(Rgba::TRANSPARENT));
+        assert_eq!(canvas.pixel(1, 0), Some(Rgba::new(0, 0, 255, 255)));
+    }
+}
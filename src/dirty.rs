@@ -0,0 +1,230 @@
+use wasm_bindgen::prelude::*;
+
+use crate::canvas_source::CanvasSource;
+
+// edge length of the tiles dirty regions are bucketed into, in pixels
+const TILE_SIZE: u32 = 32;
+
+/// Pixel rectangle as (x0, y0, x1, y1), end exclusive.
+pub(crate) type Rect = (u32, u32, u32, u32);
+
+fn union(a: Rect, b: Rect) -> Rect {
+    (a.0.min(b.0), a.1.min(b.1), a.2.max(b.2), a.3.max(b.3))
+}
+
+/// Tracks which pixels changed since the last `clear`.
+///
+/// Each tile keeps the exact bounding box of the writes that landed in it,
+/// so marking a single pixel is cheap and the exported rectangles are tight
+/// without keeping an unbounded list of every write.
+pub(crate) struct DirtyRegion {
+    columns: u32,
+    tiles: Vec<Option<Rect>>,
+    bounds: Option<Rect>,
+}
+
+impl DirtyRegion {
+    /// A region for a `width` by `height` canvas, starting fully dirty so
+    /// the first frame uploads everything.
+    pub fn new(width: u32, height: u32) -> DirtyRegion {
+        let columns = width.div_ceil(TILE_SIZE);
+        let rows = height.div_ceil(TILE_SIZE);
+        let mut region = DirtyRegion {
+            columns,
+            tiles: vec![None; (columns * rows) as usize],
+            bounds: None,
+        };
+        region.mark((0, 0, width, height));
+        region
+    }
+
+    /// Mark a rectangle, already clipped to the canvas, as changed.
+    pub fn mark(&mut self, rect: Rect) {
+        let (x0, y0, x1, y1) = rect;
+        if x0 >= x1 || y0 >= y1 {
+            return;
+        }
+        self.bounds = Some(self.bounds.map_or(rect, |b| union(b, rect)));
+
+        for tile_y in y0 / TILE_SIZE..=(y1 - 1) / TILE_SIZE {
+            for tile_x in x0 / TILE_SIZE..=(x1 - 1) / TILE_SIZE {
+                let tile_rect = (
+                    x0.max(tile_x * TILE_SIZE),
+                    y0.max(tile_y * TILE_SIZE),
+                    x1.min((tile_x + 1) * TILE_SIZE),
+                    y1.min((tile_y + 1) * TILE_SIZE),
+                );
+                let tile = &mut self.tiles[(tile_y * self.columns + tile_x) as usize];
+                *tile = Some(tile.map_or(tile_rect, |t| union(t, tile_rect)));
+            }
+        }
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.bounds.is_some()
+    }
+
+    pub fn bounds(&self) -> Option<Rect> {
+        self.bounds
+    }
+
+    /// The dirty area as a short list of rectangles: runs of neighboring
+    /// dirty tiles in a row, stacked with the runs below them when they
+    /// span the same tiles.
+    pub fn rects(&self) -> Vec<Rect> {
+        let mut finished = Vec::new();
+        // runs from the previous tile row that can still grow downwards,
+        // keyed by the tile columns they span
+        let mut open: Vec<(u32, u32, Rect)> = Vec::new();
+
+        for row in self.tiles.chunks(self.columns.max(1) as usize) {
+            let mut runs: Vec<(u32, u32, Rect)> = Vec::new();
+            for (column, tile) in row.iter().enumerate() {
+                let column = column as u32;
+                match (tile, runs.last_mut()) {
+                    (Some(rect), Some(run)) if run.1 == column => {
+                        run.1 = column + 1;
+                        run.2 = union(run.2, *rect);
+                    }
+                    (Some(rect), _) => runs.push((column, column + 1, *rect)),
+                    (None, _) => {}
+                }
+            }
+
+            for run in runs.iter_mut() {
+                if let Some(index) = open.iter().position(|o| o.0 == run.0 && o.1 == run.1) {
+                    run.2 = union(run.2, open.swap_remove(index).2);
+                }
+            }
+            finished.extend(open.drain(..).map(|o| o.2));
+            open = runs;
+        }
+
+        finished.extend(open.into_iter().map(|o| o.2));
+        finished
+    }
+
+    pub fn clear(&mut self) {
+        self.tiles.iter_mut().for_each(|tile| *tile = None);
+        self.bounds = None;
+    }
+}
+
+fn flatten(rects: impl IntoIterator<Item = Rect>) -> Vec<u32> {
+    rects
+        .into_iter()
+        .flat_map(|(x0, y0, x1, y1)| [x0, y0, x1 - x0, y1 - y0])
+        .collect()
+}
+
+// the render loop reads these each frame, uploads just the changed pixels
+// (putImageData's dirty rectangle, or a partial texture write) and then
+// calls clear_dirty
+#[wasm_bindgen]
+impl CanvasSource {
+    pub fn is_dirty(&self) -> bool {
+        self.dirty.is_dirty()
+    }
+
+    /// Rectangles covering every pixel changed since the last
+    /// `clear_dirty`, flattened as `[x, y, width, height, ...]`.
+    pub fn dirty_rects(&self) -> Vec<u32> {
+        flatten(self.dirty.rects())
+    }
+
+    /// The single rectangle bounding every change since the last
+    /// `clear_dirty` as `[x, y, width, height]`, or empty if nothing
+    /// changed.
+    pub fn dirty_bounds(&self) -> Vec<u32> {
+        flatten(self.dirty.bounds())
+    }
+
+    pub fn clear_dirty(&mut self) {
+        self.dirty.clear();
+    }
+}
+
+// removed #[wasm_bindgen] - not sent to js
+impl CanvasSource {
+    /// Changed rectangles as (x, y, width, height), e.g. for
+    /// `wgpu::Queue::write_texture`.
+    pub fn dirty_regions(&self) -> Vec<(u32, u32, u32, u32)> {
+        self.dirty
+            .rects()
+            .into_iter()
+            .map(|(x0, y0, x1, y1)| (x0, y0, x1 - x0, y1 - y0))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raster::Rasterization;
+
+    fn clean(width: u32, height: u32) -> DirtyRegion {
+        let mut region = DirtyRegion::new(width, height);
+        region.clear();
+        region
+    }
+
+    #[test]
+    fn new_regions_start_fully_dirty() {
+        let region = DirtyRegion::new(70, 40);
+        assert_eq!(region.bounds(), Some((0, 0, 70, 40)));
+        assert_eq!(region.rects(), [(0, 0, 70, 40)]);
+    }
+
+    #[test]
+    fn clearing_leaves_nothing_dirty() {
+        let region = clean(70, 40);
+        assert!(!region.is_dirty());
+        assert_eq!(region.bounds(), None);
+        assert!(region.rects().is_empty());
+    }
+
+    #[test]
+    fn marks_keep_their_exact_bounds() {
+        let mut region = clean(100, 100);
+        region.mark((5, 6, 7, 8));
+        region.mark((20, 3, 21, 4));
+        assert_eq!(region.rects(), [(5, 3, 21, 8)]);
+        region.mark((10, 10, 10, 20));
+        assert_eq!(region.bounds(), Some((5, 3, 21, 8)));
+    }
+
+    #[test]
+    fn distant_marks_stay_separate() {
+        let mut region = clean(100, 100);
+        region.mark((1, 1, 2, 2));
+        region.mark((90, 90, 95, 92));
+        let mut rects = region.rects();
+        rects.sort_unstable();
+        assert_eq!(rects, [(1, 1, 2, 2), (90, 90, 95, 92)]);
+        assert_eq!(region.bounds(), Some((1, 1, 95, 92)));
+    }
+
+    #[test]
+    fn runs_spanning_the_same_tiles_are_stacked() {
+        let mut region = clean(100, 100);
+        // a column of marks down the first two tile columns
+        region.mark((0, 0, 40, 10));
+        region.mark((10, 40, 50, 50));
+        region.mark((5, 70, 45, 80));
+        assert_eq!(region.rects(), [(0, 0, 50, 80)]);
+    }
+
+    #[test]
+    fn canvas_edits_are_reported() {
+        let mut canvas = CanvasSource::try_new(64, 64, Vec::new()).unwrap();
+        assert_eq!(canvas.dirty_bounds(), [0, 0, 64, 64]);
+        canvas.clear_dirty();
+        assert!(!canvas.is_dirty());
+        assert!(canvas.dirty_bounds().is_empty());
+
+        canvas.fill_rect(3.0, 4.0, 5.0, 6.0, 0xff0000ff, Rasterization::Aliased);
+        assert!(canvas.is_dirty());
+        assert_eq!(canvas.dirty_rects(), [3, 4, 5, 6]);
+        assert_eq!(canvas.dirty_regions(), [(3, 4, 5, 6)]);
+    }
+}
@@ -0,0 +1,299 @@
+use wasm_bindgen::prelude::*;
+
+use crate::canvas_source::CanvasSource;
+use crate::color::Rgba;
+use crate::paint::Paint;
+
+/// Which neighbors count as touching when a fill spreads.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Connectivity {
+    /// Left, right, up and down only.
+    Four = 0,
+    /// Diagonals too, so fills leak through single-pixel diagonal gaps.
+    Eight = 1,
+}
+
+#[wasm_bindgen]
+impl CanvasSource {
+    /// Paint bucket: fill the area around (x, y) whose color is within
+    /// `tolerance` (0 to 255, see `Rgba::distance`) of the pixel clicked.
+    ///
+    /// With `contiguous` false every matching pixel on the canvas is filled,
+    /// connected or not. Returns how many pixels were filled.
+    pub fn flood_fill(
+        &mut self,
+        x: i32,
+        y: i32,
+        color: u32,
+        tolerance: f32,
+        connectivity: Connectivity,
+        contiguous: bool,
+    ) -> u32 {
+        let paint = Paint::solid(color);
+        self.flood_fill_paint(x, y, &paint, tolerance, connectivity, contiguous)
+    }
+
+    /// `flood_fill` with a pattern (or any other paint) instead of a flat
+    /// color.
+    pub fn flood_fill_paint(
+        &mut self,
+        x: i32,
+        y: i32,
+        paint: &Paint,
+        tolerance: f32,
+        connectivity: Connectivity,
+        contiguous: bool,
+    ) -> u32 {
+        let target = match self.pixel(x, y) {
+            Some(color) => color,
+            None => return 0,
+        };
+        let region = if contiguous {
+            self.connected_region(x as u32, y as u32, target, tolerance, connectivity)
+        } else {
+            self.matching_region(target, tolerance)
+        };
+
+        self.edit(|canvas| canvas.fill_region(&region, paint))
+    }
+}
+
+// removed #[wasm_bindgen] - not sent to js
+impl CanvasSource {
+    fn matches(&self, x: u32, y: u32, target: Rgba, tolerance: f32) -> bool {
+        let idx = self.pixel_index(x, y);
+        Rgba::from_slice(&self.data[idx..idx + 4]).distance(target) <= tolerance
+    }
+
+    /// Every pixel on the canvas within `tolerance` of `target`, one flag
+    /// per pixel.
+    pub(crate) fn matching_region(&self, target: Rgba, tolerance: f32) -> Vec<bool> {
+        self.data
+            .chunks_exact(4)
+            .map(|pixel| Rgba::from_slice(pixel).distance(target) <= tolerance)
+            .collect()
+    }
+
+    /// The pixels reachable from the seed without crossing a pixel further
+    /// than `tolerance` from `target`, one flag per pixel.
+    ///
+    /// Scanline fill with an explicit stack: each popped seed is widened to
+    /// the full run it sits in, then one seed is pushed for every matching
+    /// run directly above and below, so the stack stays small even for huge
+    /// regions.
+    pub(crate) fn connected_region(
+        &self,
+        seed_x: u32,
+        seed_y: u32,
+        target: Rgba,
+        tolerance: f32,
+        connectivity: Connectivity,
+    ) -> Vec<bool> {
+        let (width, height) = (self.width, self.height);
+        let mut filled = vec![false; (width * height) as usize];
+        let mut stack = vec![(seed_x, seed_y)];
+        let open = |filled: &[bool], x: u32, y: u32| {
+            !filled[(y * width + x) as usize] && self.matches(x, y, target, tolerance)
+        };
+
+        while let Some((x, y)) = stack.pop() {
+            if !open(&filled, x, y) {
+                continue;
+            }
+
+            let mut left = x;
+            while left > 0 && open(&filled, left - 1, y) {
+                left -= 1;
+            }
+            let mut right = x;
+            while right + 1 < width && open(&filled, right + 1, y) {
+                right += 1;
+            }
+            let row = (y * width) as usize;
+            filled[row + left as usize..=row + right as usize]
+                .iter_mut()
+                .for_each(|flag| *flag = true);
+
+            // diagonal neighbors reach one pixel past either end of the run
+            let (scan_left, scan_right) = match connectivity {
+                Connectivity::Four => (left, right),
+                Connectivity::Eight => (left.saturating_sub(1), (right + 1).min(width - 1)),
+            };
+            let neighbors = [y.checked_sub(1), Some(y + 1).filter(|&ny| ny < height)];
+            for ny in neighbors.iter().flatten().copied() {
+                let mut in_run = false;
+                for nx in scan_left..=scan_right {
+                    if open(&filled, nx, ny) {
+                        if !in_run {
+                            stack.push((nx, ny));
+                            in_run = true;
+                        }
+                    } else {
+                        in_run = false;
+                    }
+                }
+            }
+        }
+
+        filled
+    }
+
+    /// Composite `paint` onto every flagged pixel, a row run at a time.
+    /// Returns the number of pixels painted.
+    fn fill_region(&mut self, region: &[bool], paint: &Paint) -> u32 {
+        let width = self.width as usize;
+        let mut count = 0;
+        for (y, row) in region.chunks(width.max(1)).enumerate() {
+            let mut x = 0;
+            while x < row.len() {
+                if !row[x] {
+                    x += 1;
+                    continue;
+                }
+                let start = x;
+                while x < row.len() && row[x] {
+                    x += 1;
+                }
+                count += (x - start) as u32;
+                self.paint_span(y as i32, start as i32, x as i32 - 1, paint);
+            }
+        }
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pictures::{canvas_from, picture_with};
+
+    const RED: u32 = 0xff0000ff;
+
+    /// A canvas from a picture: '#' opaque black, '.' opaque white.
+    fn canvas(rows: &[&str]) -> CanvasSource {
+        canvas_from(rows, |c| match c {
+            '#' => [0, 0, 0, 255],
+            _ => [255, 255, 255, 255],
+        })
+    }
+
+    /// The red pixels as 'x', everything else as in `canvas`.
+    fn picture(canvas: &CanvasSource) -> Vec<String> {
+        picture_with(canvas, |p| match p {
+            [255, 0, 0, 255] => 'x',
+            [0, 0, 0, 255] => '#',
+            _ => '.',
+        })
+    }
+
+    #[test]
+    fn fill_stops_at_the_tolerance_edge() {
+        // a ramp of grays 0, 20, 40, 60, 20 from the seed rightwards
+        let data = [0u8, 20, 40, 60, 20]
+            .iter()
+            .flat_map(|&v| [v, v, v, 255])
+            .collect();
+        let mut canvas = CanvasSource::try_new(5, 1, data).unwrap();
+        // 40 gray is 34.6 from black, 60 gray is 52
+        assert_eq!(
+            canvas.flood_fill(0, 0, RED, 35.0, Connectivity::Four, true),
+            3
+        );
+        let row: Vec<u8> = canvas.data.chunks_exact(4).map(|p| p[1]).collect();
+        assert_eq!(row, [0, 0, 0, 60, 20]);
+    }
+
+    #[test]
+    fn zero_tolerance_matches_the_exact_color_only() {
+        let mut canvas = CanvasSource::try_new(2, 1, vec![0, 0, 0, 255, 1, 0, 0, 255]).unwrap();
+        assert_eq!(
+            canvas.flood_fill(0, 0, RED, 0.0, Connectivity::Four, true),
+            1
+        );
+    }
+
+    #[test]
+    fn fill_follows_winding_regions() {
+        let mut canvas = canvas(&[
+            "..#...", //
+            "#.#.#.", //
+            "..#.#.", //
+            ".##.#.", //
+            "....#.",
+        ]);
+        assert_eq!(
+            canvas.flood_fill(0, 0, RED, 0.0, Connectivity::Four, true),
+            20
+        );
+        assert_eq!(
+            picture(&canvas),
+            [
+                "xx#xxx", //
+                "#x#x#x", //
+                "xx#x#x", //
+                "x##x#x", //
+                "xxxx#x",
+            ]
+        );
+    }
+
+    #[test]
+    fn eight_connectivity_leaks_through_diagonals() {
+        let rows = [
+            ".#..", //
+            "#...", //
+            "....",
+        ];
+        let mut four = canvas(&rows);
+        assert_eq!(four.flood_fill(0, 0, RED, 0.0, Connectivity::Four, true), 1);
+        let mut eight = canvas(&rows);
+        assert_eq!(
+            eight.flood_fill(0, 0, RED, 0.0, Connectivity::Eight, true),
+            10
+        );
+        // the black walls are one region too, diagonally
+        let mut walls = canvas(&rows);
+        assert_eq!(
+            walls.flood_fill(1, 0, RED, 0.0, Connectivity::Eight, true),
+            2
+        );
+    }
+
+    #[test]
+    fn non_contiguous_fills_every_match() {
+        let mut canvas = canvas(&[
+            ".#.", //
+            "###", //
+            ".#.",
+        ]);
+        assert_eq!(
+            canvas.flood_fill(0, 0, RED, 0.0, Connectivity::Four, false),
+            4
+        );
+        assert_eq!(picture(&canvas), ["x#x", "###", "x#x"]);
+    }
+
+    #[test]
+    fn patterns_tile_from_the_canvas_origin() {
+        let mut canvas = canvas(&["....", "....", "...."]);
+        let tile = [255, 0, 0, 255, 0, 0, 0, 255];
+        let paint = Paint::try_pattern(&tile, 2, 1).unwrap();
+        canvas.flood_fill_paint(3, 2, &paint, 0.0, Connectivity::Four, true);
+        assert_eq!(picture(&canvas), ["x#x#", "x#x#", "x#x#"]);
+    }
+
+    #[test]
+    fn seeds_off_the_canvas_fill_nothing() {
+        let mut canvas = canvas(&["..", ".."]);
+        assert_eq!(
+            canvas.flood_fill(2, 0, RED, 255.0, Connectivity::Eight, true),
+            0
+        );
+        assert_eq!(
+            canvas.flood_fill(0, -1, RED, 255.0, Connectivity::Eight, false),
+            0
+        );
+        assert!(!canvas.can_undo());
+    }
+}
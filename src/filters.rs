@@ -0,0 +1,739 @@
+use std::fmt;
+
+use wasm_bindgen::prelude::*;
+
+use crate::canvas_source::CanvasSource;
+use crate::color::Rgba;
+use crate::dirty::Rect;
+
+/// What a filter sees past the edges of the canvas.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EdgeMode {
+    /// Repeat the nearest edge pixel.
+    Clamp = 0,
+    /// Continue from the opposite edge, for tiling textures.
+    Wrap = 1,
+    /// Treat everything outside as transparent black.
+    Transparent = 2,
+}
+
+/// A convolution kernel whose weights don't match its size.
+#[derive(Debug)]
+pub struct KernelError {
+    pub width: u32,
+    pub height: u32,
+    pub weights: usize,
+}
+
+impl fmt::Display for KernelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.width == 0 || self.height == 0 {
+            return write!(f, "a {}x{} kernel is empty", self.width, self.height);
+        }
+        write!(
+            f,
+            "expected {} weights for a {}x{} kernel, got {}",
+            self.width as u64 * self.height as u64,
+            self.width,
+            self.height,
+            self.weights
+        )
+    }
+}
+
+impl std::error::Error for KernelError {}
+
+// premultiplied RGBA, 0.0 to 1.0
+type Pixel = [f32; 4];
+
+enum Filter {
+    /// A normalized 1D kernel run horizontally and then vertically.
+    Separable(Vec<f32>),
+    /// A Gaussian with this standard deviation, run like `Separable` with a
+    /// kernel no wider than the canvas it's applied to needs.
+    Gaussian(f32),
+    /// A 2D kernel centered on each pixel. With `preserve_alpha` the kernel
+    /// only sees straight colors and every pixel keeps its alpha, which is
+    /// what kernels that don't sum to one want.
+    Kernel {
+        weights: Vec<f32>,
+        width: u32,
+        height: u32,
+        preserve_alpha: bool,
+    },
+    /// Per-channel gradient magnitude.
+    Sobel,
+}
+
+impl Filter {
+    /// How far the filter reads from each pixel horizontally and
+    /// vertically, on a `width` x `height` canvas.
+    fn reach(&self, width: u32, height: u32) -> (u32, u32) {
+        match self {
+            Filter::Separable(kernel) => {
+                let radius = (kernel.len() / 2) as u32;
+                (radius, radius)
+            }
+            Filter::Gaussian(sigma) => {
+                let radius = gaussian_radius(*sigma, width.max(height));
+                (radius, radius)
+            }
+            Filter::Kernel { width, height, .. } => (*width / 2, *height / 2),
+            Filter::Sobel => (1, 1),
+        }
+    }
+}
+
+/// How far a Gaussian with standard deviation `sigma` is sampled: out to 3
+/// sigma, where it's all but zero, and never further than `max_radius`.
+fn gaussian_radius(sigma: f32, max_radius: u32) -> u32 {
+    (sigma * 3.0).ceil().clamp(0.0, max_radius as f32) as u32
+}
+
+/// The weights of a 1D Gaussian with standard deviation `sigma`, from
+/// `-radius` to `radius` as given by `gaussian_radius` and normalized to sum
+/// to one.
+pub(crate) fn gaussian_kernel(sigma: f32, max_radius: u32) -> Vec<f32> {
+    let radius = gaussian_radius(sigma, max_radius) as i64;
+    if sigma * sigma == 0.0 {
+        // too narrow to reach the next pixel at all
+        return vec![1.0];
+    }
+    let kernel: Vec<f32> = (-radius..=radius)
+        .map(|i| (-(i * i) as f32 / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let total: f32 = kernel.iter().sum();
+    kernel.into_iter().map(|w| w / total).collect()
+}
+
+/// An ordered list of filters applied in one go with `apply_filters`, so a
+/// whole Filters-menu preset is a single undo step.
+#[wasm_bindgen]
+pub struct FilterChain {
+    filters: Vec<Filter>,
+    edges: EdgeMode,
+}
+
+#[wasm_bindgen]
+impl FilterChain {
+    pub fn new() -> FilterChain {
+        FilterChain {
+            filters: Vec::new(),
+            edges: EdgeMode::Clamp,
+        }
+    }
+
+    pub fn edge_mode(&self) -> EdgeMode {
+        self.edges
+    }
+
+    pub fn set_edge_mode(&mut self, edges: EdgeMode) {
+        self.edges = edges;
+    }
+
+    pub fn len(&self) -> usize {
+        self.filters.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.filters.clear();
+    }
+
+    /// Gaussian blur with standard deviation `sigma` pixels, run as two 1D
+    /// passes.
+    pub fn gaussian_blur(&mut self, sigma: f32) {
+        if sigma <= 0.0 || !sigma.is_finite() {
+            return;
+        }
+        self.filters.push(Filter::Gaussian(sigma));
+    }
+
+    /// Average over a `2 * radius + 1` pixel square.
+    pub fn box_blur(&mut self, radius: u32) {
+        if radius == 0 {
+            return;
+        }
+        let size = 2 * radius as usize + 1;
+        self.filters
+            .push(Filter::Separable(vec![1.0 / size as f32; size]));
+    }
+
+    /// Boost each pixel's difference from its four neighbors; 1.0 is a
+    /// strong sharpen.
+    pub fn sharpen(&mut self, amount: f32) {
+        let a = amount;
+        self.filters.push(Filter::Kernel {
+            weights: vec![0.0, -a, 0.0, -a, 1.0 + 4.0 * a, -a, 0.0, -a, 0.0],
+            width: 3,
+            height: 3,
+            preserve_alpha: true,
+        });
+    }
+
+    /// Raised relief lit from the top left.
+    pub fn emboss(&mut self) {
+        self.filters.push(Filter::Kernel {
+            weights: vec![-2.0, -1.0, 0.0, -1.0, 1.0, 1.0, 0.0, 1.0, 2.0],
+            width: 3,
+            height: 3,
+            preserve_alpha: true,
+        });
+    }
+
+    /// Sobel edge detection: bright where the color changes quickly.
+    pub fn sobel(&mut self) {
+        self.filters.push(Filter::Sobel);
+    }
+
+    /// A user-supplied `width` by `height` kernel, row by row, centered on
+    /// each pixel and applied as written (not flipped). Weights are used
+    /// as-is, so divide them by their sum to keep the brightness. With
+    /// `preserve_alpha` the kernel runs on straight colors and pixels keep
+    /// their alpha, otherwise it runs on premultiplied RGBA. Throws unless
+    /// there are exactly `width * height` weights, at least one.
+    pub fn convolve(
+        &mut self,
+        kernel: &[f32],
+        width: u32,
+        height: u32,
+        preserve_alpha: bool,
+    ) -> Result<(), JsError> {
+        Ok(self.try_convolve(kernel, width, height, preserve_alpha)?)
+    }
+}
+
+// removed #[wasm_bindgen] - the Rust-side API for the native binary
+impl FilterChain {
+    pub fn try_convolve(
+        &mut self,
+        kernel: &[f32],
+        width: u32,
+        height: u32,
+        preserve_alpha: bool,
+    ) -> Result<(), KernelError> {
+        if width == 0 || height == 0 || kernel.len() as u64 != width as u64 * height as u64 {
+            return Err(KernelError {
+                width,
+                height,
+                weights: kernel.len(),
+            });
+        }
+        self.filters.push(Filter::Kernel {
+            weights: kernel.to_vec(),
+            width,
+            height,
+            preserve_alpha,
+        });
+        Ok(())
+    }
+}
+
+impl Default for FilterChain {
+    fn default() -> Self {
+        FilterChain::new()
+    }
+}
+
+/// A premultiplied float copy of part of the canvas that filters read from
+/// and write to. Coordinates are the canvas's.
+struct Image {
+    // the copied rectangle, (x0, y0, x1, y1) with an exclusive end
+    window: Rect,
+    canvas_width: u32,
+    canvas_height: u32,
+    pixels: Vec<Pixel>,
+}
+
+impl Image {
+    fn from_canvas(canvas: &CanvasSource, window: Rect) -> Image {
+        let (x0, y0, x1, y1) = window;
+        let mut pixels = Vec::with_capacity((x1 - x0) as usize * (y1 - y0) as usize);
+        for y in y0..y1 {
+            let start = canvas.pixel_index(x0, y);
+            let end = start + (x1 - x0) as usize * 4;
+            pixels.extend(canvas.data[start..end].chunks_exact(4).map(|p| {
+                let alpha = p[3] as f32 / 255.0;
+                [
+                    p[0] as f32 / 255.0 * alpha,
+                    p[1] as f32 / 255.0 * alpha,
+                    p[2] as f32 / 255.0 * alpha,
+                    alpha,
+                ]
+            }));
+        }
+        Image {
+            window,
+            canvas_width: canvas.width,
+            canvas_height: canvas.height,
+            pixels,
+        }
+    }
+
+    /// Another image over the same window.
+    fn with_pixels(&self, pixels: Vec<Pixel>) -> Image {
+        Image {
+            window: self.window,
+            canvas_width: self.canvas_width,
+            canvas_height: self.canvas_height,
+            pixels,
+        }
+    }
+
+    /// The pixel at (x, y), with coordinates off the canvas resolved by
+    /// `edges`. Filters only read as far as the window reaches.
+    fn fetch(&self, x: i32, y: i32, edges: EdgeMode) -> Pixel {
+        let (w, h) = (self.canvas_width as i32, self.canvas_height as i32);
+        let (x, y) = match edges {
+            EdgeMode::Clamp => (x.clamp(0, w - 1), y.clamp(0, h - 1)),
+            EdgeMode::Wrap => (x.rem_euclid(w), y.rem_euclid(h)),
+            EdgeMode::Transparent => {
+                if x < 0 || y < 0 || x >= w || y >= h {
+                    return [0.0; 4];
+                }
+                (x, y)
+            }
+        };
+        self.pixels[self.index(x as u32, y as u32)]
+    }
+
+    /// `fetch` as a straight color. Each sample's color counts in
+    /// proportion to its alpha and `center`, the straight color of the pixel
+    /// being filtered, makes up the rest, so transparent neighbors don't
+    /// pull colors toward black.
+    fn fetch_straight(&self, x: i32, y: i32, edges: EdgeMode, center: Pixel) -> Pixel {
+        let [r, g, b, a] = self.fetch(x, y, edges);
+        let rest = 1.0 - a;
+        [
+            r + rest * center[0],
+            g + rest * center[1],
+            b + rest * center[2],
+            a,
+        ]
+    }
+
+    /// The straight color at (x, y), which must be in the window.
+    fn straight(&self, x: u32, y: u32) -> Pixel {
+        let [r, g, b, a] = self.pixels[self.index(x, y)];
+        if a > 0.0 {
+            [r / a, g / a, b / a, a]
+        } else {
+            [0.0; 4]
+        }
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        let (x0, y0, x1, _) = self.window;
+        (y - y0) as usize * (x1 - x0) as usize + (x - x0) as usize
+    }
+
+    /// Run a 1D `kernel` over the pixels in `area` horizontally and then
+    /// vertically, writing the result to `output`.
+    fn separable(&self, kernel: &[f32], area: Rect, edges: EdgeMode, output: &mut [Pixel]) {
+        let (x0, y0, x1, y1) = area;
+        let radius = (kernel.len() / 2) as i32;
+        // horizontal pass over every row the vertical pass will read,
+        // which for Wrap can be rows from the far side of the canvas
+        let height = self.canvas_height as i32;
+        let mut rows_done = vec![false; (self.window.3 - self.window.1) as usize];
+        let mut horizontal = self.pixels.clone();
+        for y in y0 as i32 - radius..y1 as i32 + radius {
+            let row = match edges {
+                EdgeMode::Clamp => y.clamp(0, height - 1),
+                EdgeMode::Wrap => y.rem_euclid(height),
+                EdgeMode::Transparent if y < 0 || y >= height => continue,
+                EdgeMode::Transparent => y,
+            } as u32;
+            if std::mem::replace(&mut rows_done[(row - self.window.1) as usize], true) {
+                continue;
+            }
+            for x in x0..x1 {
+                let mut sum = [0.0; 4];
+                for (k, weight) in kernel.iter().enumerate() {
+                    let p = self.fetch(x as i32 + k as i32 - radius, row as i32, edges);
+                    (0..4).for_each(|c| sum[c] += weight * p[c]);
+                }
+                horizontal[self.index(x, row)] = sum;
+            }
+        }
+
+        let horizontal = self.with_pixels(horizontal);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let mut sum = [0.0; 4];
+                for (k, weight) in kernel.iter().enumerate() {
+                    let p = horizontal.fetch(x as i32, y as i32 + k as i32 - radius, edges);
+                    (0..4).for_each(|c| sum[c] += weight * p[c]);
+                }
+                output[self.index(x, y)] = sum;
+            }
+        }
+    }
+
+    /// Run `filter` over the pixels in `area`, leaving the rest as they
+    /// were.
+    fn apply(&mut self, filter: &Filter, area: Rect, edges: EdgeMode) {
+        let (x0, y0, x1, y1) = area;
+        let mut output = self.pixels.clone();
+
+        match filter {
+            Filter::Separable(kernel) => self.separable(kernel, area, edges, &mut output),
+            Filter::Gaussian(sigma) => {
+                let longest = self.canvas_width.max(self.canvas_height);
+                self.separable(&gaussian_kernel(*sigma, longest), area, edges, &mut output)
+            }
+            Filter::Kernel {
+                weights,
+                width,
+                height,
+                preserve_alpha,
+            } => {
+                let (ax, ay) = ((width / 2) as i32, (height / 2) as i32);
+                for y in y0..y1 {
+                    for x in x0..x1 {
+                        let center = self.straight(x, y);
+                        let mut sum = [0.0; 4];
+                        for ky in 0..*height as i32 {
+                            for kx in 0..*width as i32 {
+                                let weight = weights[(ky * *width as i32 + kx) as usize];
+                                let (sx, sy) = (x as i32 + kx - ax, y as i32 + ky - ay);
+                                let p = if *preserve_alpha {
+                                    self.fetch_straight(sx, sy, edges, center)
+                                } else {
+                                    self.fetch(sx, sy, edges)
+                                };
+                                (0..4).for_each(|c| sum[c] += weight * p[c]);
+                            }
+                        }
+                        let index = self.index(x, y);
+                        output[index] = if *preserve_alpha {
+                            premultiply(sum, self.pixels[index][3])
+                        } else {
+                            let alpha = sum[3].clamp(0.0, 1.0);
+                            [
+                                sum[0].clamp(0.0, alpha),
+                                sum[1].clamp(0.0, alpha),
+                                sum[2].clamp(0.0, alpha),
+                                alpha,
+                            ]
+                        };
+                    }
+                }
+            }
+            Filter::Sobel => {
+                const GX: [f32; 9] = [-1.0, 0.0, 1.0, -2.0, 0.0, 2.0, -1.0, 0.0, 1.0];
+                const GY: [f32; 9] = [-1.0, -2.0, -1.0, 0.0, 0.0, 0.0, 1.0, 2.0, 1.0];
+                for y in y0..y1 {
+                    for x in x0..x1 {
+                        let center = self.straight(x, y);
+                        let mut gx = [0.0; 4];
+                        let mut gy = [0.0; 4];
+                        for k in 0..9 {
+                            let p = self.fetch_straight(
+                                x as i32 + k as i32 % 3 - 1,
+                                y as i32 + k as i32 / 3 - 1,
+                                edges,
+                                center,
+                            );
+                            (0..3).for_each(|c| {
+                                gx[c] += GX[k] * p[c];
+                                gy[c] += GY[k] * p[c];
+                            });
+                        }
+                        let magnitude = [
+                            gx[0].hypot(gy[0]),
+                            gx[1].hypot(gy[1]),
+                            gx[2].hypot(gy[2]),
+                            0.0,
+                        ];
+                        let index = self.index(x, y);
+                        output[index] = premultiply(magnitude, self.pixels[index][3]);
+                    }
+                }
+            }
+        }
+
+        self.pixels = output;
+    }
+}
+
+/// Clamp a straight color and premultiply it by `alpha`.
+fn premultiply(color: Pixel, alpha: f32) -> Pixel {
+    [
+        color[0].clamp(0.0, 1.0) * alpha,
+        color[1].clamp(0.0, 1.0) * alpha,
+        color[2].clamp(0.0, 1.0) * alpha,
+        alpha,
+    ]
+}
+
+#[wasm_bindgen]
+impl CanvasSource {
+    /// Run every filter in `chain` over the whole canvas as one edit.
+    pub fn apply_filters(&mut self, chain: &FilterChain) {
+        self.apply_filters_to_region(chain, 0, 0, self.width, self.height);
+    }
+
+    /// Run `chain` over a rectangle of the canvas. Pixels outside the
+    /// rectangle (or the selection) are still read by the kernels but never
+    /// changed.
+    pub fn apply_filters_to_region(
+        &mut self,
+        chain: &FilterChain,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+    ) {
+        let region = match self
+            .clip_rect(x, y, width, height)
+            .and_then(|rect| self.selection_clip(rect))
+        {
+            Some(region) => region,
+            None => return,
+        };
+        if chain.filters.is_empty() {
+            return;
+        }
+
+        // only copy what the whole chain reads
+        let (reach_x, reach_y) = chain
+            .filters
+            .iter()
+            .map(|filter| filter.reach(self.width, self.height))
+            .fold((0, 0), |a, b| (a.0 + b.0, a.1 + b.1));
+        let window = self.expand_area(region, reach_x, reach_y, chain.edges);
+        let mut image = Image::from_canvas(self, window);
+        for (i, filter) in chain.filters.iter().enumerate() {
+            // each pass has to cover what the passes after it will read
+            let (reach_x, reach_y) = chain.filters[i + 1..]
+                .iter()
+                .map(|filter| filter.reach(self.width, self.height))
+                .fold((0, 0), |a, b| (a.0 + b.0, a.1 + b.1));
+            let area = self.expand_area(region, reach_x, reach_y, chain.edges);
+            image.apply(filter, area, chain.edges);
+        }
+
+        self.edit(|canvas| {
+            let (x0, y0, x1, y1) = region;
+            canvas.touch(x0 as i32, y0 as i32, x1 as i32, y1 as i32);
+            for y in y0..y1 {
+                for x in x0..x1 {
+                    let [r, g, b, a] = image.pixels[image.index(x, y)];
+                    let byte = |v: f32| (v * 255.0).round().clamp(0.0, 255.0) as u8;
+                    let color = if a > 0.0 {
+                        Rgba::new(byte(r / a), byte(g / a), byte(b / a), byte(a))
+                    } else {
+                        Rgba::TRANSPARENT
+                    };
+                    canvas.replace_pixel(x, y, color);
+                }
+            }
+        })
+    }
+}
+
+// removed #[wasm_bindgen] - not sent to js
+impl CanvasSource {
+    /// Grow `region` by the given reach, clipped to the canvas. Wrapped
+    /// edges read from the far side, so once the area reaches an edge it
+    /// has to span the whole axis.
+    fn expand_area(&self, region: Rect, reach_x: u32, reach_y: u32, edges: EdgeMode) -> Rect {
+        let (x0, y0, x1, y1) = region;
+        let mut area = (
+            x0.saturating_sub(reach_x),
+            y0.saturating_sub(reach_y),
+            x1.saturating_add(reach_x).min(self.width),
+            y1.saturating_add(reach_y).min(self.height),
+        );
+        if edges == EdgeMode::Wrap {
+            if area.0 == 0 || area.2 == self.width {
+                area = (0, area.1, self.width, area.3);
+            }
+            if area.1 == 0 || area.3 == self.height {
+                area = (area.0, 0, area.2, self.height);
+            }
+        }
+        area
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Opaque pixels with varied colors, a few of them translucent.
+    fn noise(width: u32, height: u32) -> CanvasSource {
+        let mut state = 12345u32;
+        let data = (0..width * height * 4)
+            .map(|i| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                let byte = (state >> 16) as u8;
+                if i % 4 == 3 && byte > 64 {
+                    255
+                } else {
+                    byte
+                }
+            })
+            .collect();
+        CanvasSource::try_new(width, height, data).unwrap()
+    }
+
+    /// One row of opaque grays.
+    fn grays(values: &[u8]) -> CanvasSource {
+        let data = values.iter().flat_map(|&v| [v, v, v, 255]).collect();
+        CanvasSource::try_new(values.len() as u32, 1, data).unwrap()
+    }
+
+    fn chain(edges: EdgeMode) -> FilterChain {
+        let mut chain = FilterChain::new();
+        chain.set_edge_mode(edges);
+        chain.gaussian_blur(1.2);
+        chain.sharpen(0.5);
+        chain.box_blur(1);
+        chain
+    }
+
+    #[test]
+    fn regions_match_the_whole_canvas_filtered() {
+        for &edges in &[EdgeMode::Clamp, EdgeMode::Wrap, EdgeMode::Transparent] {
+            let chain = chain(edges);
+            let original = noise(30, 20);
+            let mut whole = noise(30, 20);
+            whole.apply_filters(&chain);
+            let mut region = noise(30, 20);
+            region.apply_filters_to_region(&chain, 8, 5, 10, 9);
+
+            for y in 0..20 {
+                for x in 0..30 {
+                    let inside = (8..18).contains(&x) && (5..14).contains(&y);
+                    let expected = if inside { &whole } else { &original };
+                    assert_eq!(
+                        region.pixel(x, y),
+                        expected.pixel(x, y),
+                        "{:?} at ({}, {})",
+                        edges,
+                        x,
+                        y
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn flat_colors_blur_to_themselves() {
+        let mut canvas = CanvasSource::try_new(9, 7, [40, 120, 200, 255].repeat(63)).unwrap();
+        let original = canvas.data.clone();
+        let mut chain = FilterChain::new();
+        chain.gaussian_blur(2.0);
+        chain.box_blur(3);
+        chain.sharpen(1.0);
+        canvas.apply_filters(&chain);
+        assert_eq!(canvas.data, original);
+    }
+
+    #[test]
+    fn extreme_sigmas_stay_within_the_canvas() {
+        assert_eq!(gaussian_kernel(1e30, 4).len(), 9);
+        assert_eq!(gaussian_kernel(1e-30, 4), [1.0]);
+
+        let mut canvas = CanvasSource::try_new(9, 7, [40, 120, 200, 255].repeat(63)).unwrap();
+        let original = canvas.data.clone();
+        let mut chain = FilterChain::new();
+        chain.gaussian_blur(f32::MAX);
+        canvas.apply_filters(&chain);
+        assert_eq!(canvas.data, original);
+
+        let mut canvas = noise(5, 4);
+        let original = canvas.data.clone();
+        let mut chain = FilterChain::new();
+        chain.gaussian_blur(1e-30);
+        canvas.apply_filters(&chain);
+        assert_eq!(canvas.data, original);
+    }
+
+    #[test]
+    fn edge_modes_decide_what_lies_past_the_edge() {
+        let blurred = |edges: EdgeMode| {
+            let mut canvas = grays(&[0, 0, 90]);
+            let mut chain = FilterChain::new();
+            chain.set_edge_mode(edges);
+            chain.box_blur(1);
+            canvas.apply_filters(&chain);
+            canvas.pixel(0, 0).unwrap().to_array()
+        };
+        assert_eq!(blurred(EdgeMode::Clamp), [0, 0, 0, 255]);
+        assert_eq!(blurred(EdgeMode::Wrap), [30, 30, 30, 255]);
+        // transparent rows above and below as well as to the left
+        assert_eq!(blurred(EdgeMode::Transparent), [0, 0, 0, 57]);
+    }
+
+    #[test]
+    fn transparent_neighbors_dont_darken() {
+        // a lone opaque pixel in a transparent canvas
+        let mut data = vec![0; 3 * 3 * 4];
+        data[16..20].copy_from_slice(&[200, 100, 50, 255]);
+        let mut canvas = CanvasSource::try_new(3, 3, data).unwrap();
+        let mut chain = FilterChain::new();
+        chain.sharpen(1.0);
+        chain.emboss();
+        canvas.apply_filters(&chain);
+        assert_eq!(canvas.pixel(1, 1).unwrap().to_array(), [200, 100, 50, 255]);
+        assert_eq!(canvas.pixel(0, 0).unwrap().a, 0);
+
+        // a blur spreads the pixel out without turning it gray
+        let mut chain = FilterChain::new();
+        chain.box_blur(1);
+        canvas.apply_filters(&chain);
+        assert_eq!(canvas.pixel(0, 0).unwrap().to_array(), [200, 100, 50, 28]);
+    }
+
+    #[test]
+    fn sobel_finds_edges() {
+        let mut canvas = grays(&[0, 0, 255, 255]);
+        let mut chain = FilterChain::new();
+        chain.sobel();
+        canvas.apply_filters(&chain);
+        let row: Vec<u8> = canvas.data.chunks_exact(4).map(|p| p[0]).collect();
+        assert_eq!(row, [0, 255, 255, 0]);
+        assert!(canvas.data.chunks_exact(4).all(|p| p[3] == 255));
+    }
+
+    #[test]
+    fn custom_kernels_run_as_written() {
+        let mut canvas = grays(&[10, 20, 30]);
+        let mut chain = FilterChain::new();
+        // take the pixel to the right
+        chain.try_convolve(&[0.0, 0.0, 1.0], 3, 1, false).unwrap();
+        canvas.apply_filters(&chain);
+        let row: Vec<u8> = canvas.data.chunks_exact(4).map(|p| p[0]).collect();
+        assert_eq!(row, [20, 30, 30]);
+    }
+
+    #[test]
+    fn kernels_need_one_weight_per_cell() {
+        let mut chain = FilterChain::new();
+        assert!(chain.try_convolve(&[], 0, 0, true).is_err());
+        assert!(chain.try_convolve(&[1.0; 8], 3, 3, true).is_err());
+        assert!(chain.try_convolve(&[1.0; 4], 4, 0, true).is_err());
+        assert!(chain.is_empty());
+        assert!(chain.try_convolve(&[1.0; 6], 3, 2, true).is_ok());
+        assert_eq!(chain.len(), 1);
+    }
+
+    #[test]
+    fn a_chain_is_one_undo_step() {
+        let mut canvas = noise(12, 12);
+        let original = canvas.data.clone();
+        canvas.apply_filters(&chain(EdgeMode::Clamp));
+        assert_ne!(canvas.data, original);
+        assert!(canvas.undo());
+        assert_eq!(canvas.data, original);
+        assert!(!canvas.can_undo());
+    }
+}
@@ -0,0 +1,750 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use wasm_bindgen::prelude::*;
+
+use crate::canvas_source::CanvasSource;
+use crate::quantize::{Dither, Palette, QuantizeMethod};
+
+/// Where `GifEncoder` keeps its colors.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GifPalette {
+    /// One palette shared by every frame, picked from all of them. Smaller
+    /// files, and colors don't flicker between frames.
+    Global = 0,
+    /// A palette per frame, for animations whose colors change a lot.
+    PerFrame = 1,
+}
+
+#[derive(Debug)]
+pub enum GifError {
+    /// `encode` was called before any frames were added.
+    NoFrames,
+    /// A frame's size differs from the size the encoder was created with.
+    FrameSize {
+        expected: (u32, u32),
+        actual: (u32, u32),
+    },
+    /// GIF stores sizes in 16 bits, so neither side can be 0 or over 65535.
+    Dimensions(u32, u32),
+    Io(std::io::Error),
+}
+
+impl fmt::Display for GifError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GifError::NoFrames => write!(f, "a GIF needs at least one frame"),
+            GifError::FrameSize { expected, actual } => write!(
+                f,
+                "frame is {}x{}, expected {}x{}",
+                actual.0, actual.1, expected.0, expected.1
+            ),
+            GifError::Dimensions(width, height) => {
+                write!(
+                    f,
+                    "a {}x{} image is too large or empty for GIF",
+                    width, height
+                )
+            }
+            GifError::Io(err) => write!(f, "GIF I/O error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for GifError {}
+
+impl From<std::io::Error> for GifError {
+    fn from(err: std::io::Error) -> Self {
+        GifError::Io(err)
+    }
+}
+
+struct Frame {
+    data: Vec<u8>,
+    // in hundredths of a second, GIF's unit
+    delay: u16,
+}
+
+/// Collects frames and writes them out as an animated GIF89a.
+///
+/// GIF pixels are either opaque or fully transparent, so frames are
+/// thresholded at half alpha when they're added (or made fully opaque when
+/// transparency is turned off).
+#[wasm_bindgen]
+pub struct GifEncoder {
+    width: u32,
+    height: u32,
+    loop_count: Option<u16>,
+    palette_mode: GifPalette,
+    fixed_palette: Option<Palette>,
+    max_colors: usize,
+    method: QuantizeMethod,
+    dither: Dither,
+    transparency: bool,
+    frames: Vec<Frame>,
+}
+
+#[wasm_bindgen]
+impl GifEncoder {
+    /// An encoder for `width` by `height` frames that loops forever, with a
+    /// global 256 color median cut palette, Floyd–Steinberg dithering and
+    /// transparency.
+    pub fn new(width: u32, height: u32) -> GifEncoder {
+        GifEncoder {
+            width,
+            height,
+            loop_count: Some(0),
+            palette_mode: GifPalette::Global,
+            fixed_palette: None,
+            max_colors: 256,
+            method: QuantizeMethod::MedianCut,
+            dither: Dither::FloydSteinberg,
+            transparency: true,
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn loop_count(&self) -> Option<u16> {
+        self.loop_count
+    }
+
+    /// How many times the animation repeats after the first play: `0`
+    /// loops forever, `undefined` (`None`) plays it once.
+    pub fn set_loop_count(&mut self, loop_count: Option<u16>) {
+        self.loop_count = loop_count;
+    }
+
+    pub fn palette_mode(&self) -> GifPalette {
+        self.palette_mode
+    }
+
+    pub fn set_palette_mode(&mut self, palette_mode: GifPalette) {
+        self.palette_mode = palette_mode;
+    }
+
+    /// Use exactly these colors as the global palette instead of picking
+    /// them from the frames. With transparency on, only the first 255 are
+    /// used, the last slot being the transparent one.
+    pub fn set_palette(&mut self, palette: &Palette) {
+        self.fixed_palette = Some(palette.clone());
+        self.palette_mode = GifPalette::Global;
+    }
+
+    /// Go back to picking the palette from the frames.
+    pub fn clear_palette(&mut self) {
+        self.fixed_palette = None;
+    }
+
+    pub fn max_colors(&self) -> usize {
+        self.max_colors
+    }
+
+    /// Colors per palette, 2 to 256 (one fewer is used for colors when
+    /// transparency is on).
+    pub fn set_max_colors(&mut self, max_colors: usize) {
+        self.max_colors = max_colors.clamp(2, 256);
+    }
+
+    pub fn set_quantize_method(&mut self, method: QuantizeMethod) {
+        self.method = method;
+    }
+
+    pub fn set_dither(&mut self, dither: Dither) {
+        self.dither = dither;
+    }
+
+    pub fn transparency(&self) -> bool {
+        self.transparency
+    }
+
+    /// Whether transparent pixels stay transparent. Affects frames added
+    /// afterwards.
+    pub fn set_transparency(&mut self, transparency: bool) {
+        self.transparency = transparency;
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Append a copy of the canvas as the next frame, shown for `delay_ms`
+    /// milliseconds. GIF counts in hundredths of a second, so the delay is
+    /// rounded to the nearest 10ms. Throws if the canvas is the wrong size.
+    pub fn add_frame(&mut self, frame: &CanvasSource, delay_ms: u32) -> Result<(), JsError> {
+        Ok(self.push_frame(frame, delay_ms)?)
+    }
+
+    /// Write the GIF file. Throws if there are no frames or the size
+    /// doesn't fit in a GIF.
+    pub fn finish(&self) -> Result<Vec<u8>, JsError> {
+        Ok(self.encode()?)
+    }
+}
+
+// removed #[wasm_bindgen] - the Rust-side API for the native binary
+impl GifEncoder {
+    pub fn push_frame(&mut self, frame: &CanvasSource, delay_ms: u32) -> Result<(), GifError> {
+        if (frame.width, frame.height) != (self.width, self.height) {
+            return Err(GifError::FrameSize {
+                expected: (self.width, self.height),
+                actual: (frame.width, frame.height),
+            });
+        }
+        let transparency = self.transparency;
+        let data = frame.data[..(self.width * self.height * 4) as usize]
+            .chunks_exact(4)
+            .flat_map(|p| match p[3] {
+                0..=127 if transparency => [0, 0, 0, 0],
+                _ => [p[0], p[1], p[2], 255],
+            })
+            .collect();
+        self.frames.push(Frame {
+            data,
+            delay: (delay_ms.saturating_add(5) / 10).min(u16::MAX as u32) as u16,
+        });
+        Ok(())
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>, GifError> {
+        if self.frames.is_empty() {
+            return Err(GifError::NoFrames);
+        }
+        if self.width == 0 || self.height == 0 || self.width > 65535 || self.height > 65535 {
+            return Err(GifError::Dimensions(self.width, self.height));
+        }
+
+        let has_transparency = |frames: &[Frame]| {
+            frames
+                .iter()
+                .any(|frame| frame.data.chunks_exact(4).any(|p| p[3] == 0))
+        };
+        let global = match self.palette_mode {
+            GifPalette::Global => {
+                let transparent = has_transparency(&self.frames);
+                Some(self.palette_for(&self.frames, transparent))
+            }
+            GifPalette::PerFrame => None,
+        };
+
+        let mut out = Vec::new();
+        out.extend_from_slice(b"GIF89a");
+        out.extend_from_slice(&(self.width as u16).to_le_bytes());
+        out.extend_from_slice(&(self.height as u16).to_le_bytes());
+        match &global {
+            Some((palette, _)) => {
+                // global table present, 8 bits per primary, table size
+                out.push(0x80 | (7 << 4) | (table_bits(palette.len()) - 1));
+            }
+            None => out.push(7 << 4),
+        }
+        // background color index and pixel aspect ratio
+        out.extend_from_slice(&[0, 0]);
+        if let Some((palette, _)) = &global {
+            write_color_table(&mut out, palette);
+        }
+
+        if let Some(loop_count) = self.loop_count {
+            out.extend_from_slice(&[0x21, 0xff, 11]);
+            out.extend_from_slice(b"NETSCAPE2.0");
+            out.extend_from_slice(&[3, 1]);
+            out.extend_from_slice(&loop_count.to_le_bytes());
+            out.push(0);
+        }
+
+        for frame in &self.frames {
+            let local;
+            let (palette, transparent_index) = match &global {
+                Some(global) => global,
+                None => {
+                    let frames = std::slice::from_ref(frame);
+                    local = self.palette_for(frames, has_transparency(frames));
+                    &local
+                }
+            };
+            let transparent_index = *transparent_index;
+
+            // graphic control extension: with transparency each frame
+            // replaces the last one outright (disposal 2, restore to
+            // background) instead of showing it through the holes
+            let packed = match transparent_index {
+                Some(_) => (2 << 2) | 1,
+                None => 1 << 2,
+            };
+            out.extend_from_slice(&[0x21, 0xf9, 4, packed]);
+            out.extend_from_slice(&frame.delay.to_le_bytes());
+            out.extend_from_slice(&[transparent_index.unwrap_or(0), 0]);
+
+            // image descriptor covering the whole canvas
+            out.push(0x2c);
+            out.extend_from_slice(&[0, 0, 0, 0]);
+            out.extend_from_slice(&(self.width as u16).to_le_bytes());
+            out.extend_from_slice(&(self.height as u16).to_le_bytes());
+            if global.is_some() {
+                out.push(0);
+            } else {
+                out.push(0x80 | (table_bits(palette.len()) - 1));
+                write_color_table(&mut out, palette);
+            }
+
+            let indices: Vec<u8> = palette
+                .map_pixels(&frame.data, self.width, self.dither)
+                .into_iter()
+                .map(|index| index.or(transparent_index).unwrap_or(0))
+                .collect();
+            let min_code_size = table_bits(palette.len()).max(2);
+            out.push(min_code_size);
+            write_sub_blocks(&mut out, &lzw_encode(&indices, min_code_size));
+        }
+
+        out.push(0x3b);
+        Ok(out)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save<P: AsRef<std::path::Path>>(&self, path: P) -> Result<(), GifError> {
+        Ok(std::fs::write(path, self.encode()?)?)
+    }
+
+    /// The palette for `frames` and, when they have transparent pixels, the
+    /// index that marks them: one past the last color.
+    fn palette_for(&self, frames: &[Frame], transparent: bool) -> (Palette, Option<u8>) {
+        let max_colors = if transparent {
+            self.max_colors - 1
+        } else {
+            self.max_colors
+        };
+        let mut colors = match &self.fixed_palette {
+            Some(palette) => palette.to_u32s(),
+            None => {
+                let data: Vec<u8> = frames.iter().flat_map(|f| f.data.iter().copied()).collect();
+                Palette::generate(&data, max_colors, self.method).to_u32s()
+            }
+        };
+        colors.truncate(max_colors);
+        if colors.is_empty() {
+            // an all transparent animation still needs a color
+            colors.push(0x000000ff);
+        }
+        let transparent_index = if transparent {
+            colors.push(0x00000000);
+            Some((colors.len() - 1) as u8)
+        } else {
+            None
+        };
+        (
+            Palette::try_new(&colors).expect("no more colors than max_colors"),
+            transparent_index,
+        )
+    }
+}
+
+/// Bits needed to index a palette of `len` colors, at least 1. Color
+/// tables hold 2^bits entries.
+fn table_bits(len: usize) -> u8 {
+    let mut bits = 1;
+    while (1 << bits) < len {
+        bits += 1;
+    }
+    bits
+}
+
+/// Write the palette as RGB triples, padded with black to a power of two.
+fn write_color_table(out: &mut Vec<u8>, palette: &Palette) {
+    for color in palette.colors() {
+        out.extend_from_slice(&[color.r, color.g, color.b]);
+    }
+    let padding = (1 << table_bits(palette.len())) - palette.len();
+    out.extend(std::iter::repeat_n(0, padding * 3));
+}
+
+/// Split `data` into GIF's length-prefixed blocks of up to 255 bytes,
+/// ending with an empty block.
+fn write_sub_blocks(out: &mut Vec<u8>, data: &[u8]) {
+    for block in data.chunks(255) {
+        out.push(block.len() as u8);
+        out.extend_from_slice(block);
+    }
+    out.push(0);
+}
+
+// GIF codes are at most 12 bits
+const MAX_CODE: u16 = 4095;
+
+/// Packs variable-width codes least significant bit first.
+struct BitWriter {
+    out: Vec<u8>,
+    bits: u32,
+    count: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, width: u8) {
+        self.bits |= (code as u32) << self.count;
+        self.count += width as u32;
+        while self.count >= 8 {
+            self.out.push(self.bits as u8);
+            self.bits >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.bits as u8);
+        }
+        self.out
+    }
+}
+
+/// Variable-width LZW as GIF uses it: codes grow a bit whenever the table
+/// outgrows the current width, and a clear code starts over once it's full.
+fn lzw_encode(indices: &[u8], min_code_size: u8) -> Vec<u8> {
+    let clear = 1u16 << min_code_size;
+    let end = clear + 1;
+    let mut writer = BitWriter {
+        out: Vec::new(),
+        bits: 0,
+        count: 0,
+    };
+
+    // (prefix code, next index) -> code
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut width = min_code_size + 1;
+    // the last code handed out
+    let mut last = end;
+    writer.write(clear, width);
+
+    let mut input = indices.iter();
+    if let Some(&first) = input.next() {
+        let mut prefix = first as u16;
+        for &index in input {
+            if let Some(&code) = table.get(&(prefix, index)) {
+                prefix = code;
+                continue;
+            }
+            writer.write(prefix, width);
+
+            last += 1;
+            if last == 1 << width {
+                width += 1;
+            }
+            if last == MAX_CODE {
+                writer.write(clear, width);
+                table.clear();
+                width = min_code_size + 1;
+                last = end;
+            } else {
+                table.insert((prefix, index), last);
+            }
+            prefix = index as u16;
+        }
+        writer.write(prefix, width);
+    }
+    writer.write(end, width);
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One frame as read back: its palette indices and the colors they
+    /// stand for.
+    struct DecodedFrame {
+        delay: u16,
+        transparent: Option<u8>,
+        palette: Vec<[u8; 3]>,
+        indices: Vec<u8>,
+    }
+
+    impl DecodedFrame {
+        fn pixels(&self) -> Vec<u8> {
+            self.indices
+                .iter()
+                .flat_map(|&i| match self.transparent {
+                    Some(t) if t == i => [0, 0, 0, 0],
+                    _ => {
+                        let [r, g, b] = self.palette[i as usize];
+                        [r, g, b, 255]
+                    }
+                })
+                .collect()
+        }
+    }
+
+    struct Decoded {
+        width: u16,
+        height: u16,
+        global: bool,
+        loop_count: Option<u16>,
+        frames: Vec<DecodedFrame>,
+    }
+
+    /// A GIF reader for exactly what `encode` writes, checking the
+    /// structure along the way.
+    fn decode(bytes: &[u8]) -> Decoded {
+        assert_eq!(&bytes[..6], b"GIF89a");
+        let u16_at = |at: usize| u16::from_le_bytes([bytes[at], bytes[at + 1]]);
+        let table = |at: usize, packed: u8| -> Vec<[u8; 3]> {
+            let len = 2usize << (packed & 7);
+            bytes[at..at + len * 3]
+                .chunks_exact(3)
+                .map(|c| [c[0], c[1], c[2]])
+                .collect()
+        };
+        let (width, height) = (u16_at(6), u16_at(8));
+        let mut at = 13;
+        let global = (bytes[10] & 0x80 != 0).then(|| table(at, bytes[10]));
+        at += global.as_ref().map_or(0, |t| t.len() * 3);
+
+        let mut decoded = Decoded {
+            width,
+            height,
+            global: global.is_some(),
+            loop_count: None,
+            frames: Vec::new(),
+        };
+        let mut control = (0, None);
+        loop {
+            match bytes[at] {
+                0x21 if bytes[at + 1] == 0xff => {
+                    assert_eq!(&bytes[at + 3..at + 14], b"NETSCAPE2.0");
+                    decoded.loop_count = Some(u16_at(at + 16));
+                    assert_eq!(bytes[at + 18], 0);
+                    at += 19;
+                }
+                0x21 => {
+                    assert_eq!(&bytes[at + 1..at + 3], [0xf9, 4]);
+                    let transparent = (bytes[at + 3] & 1 != 0).then(|| bytes[at + 6]);
+                    control = (u16_at(at + 4), transparent);
+                    at += 8;
+                }
+                0x2c => {
+                    assert_eq!((u16_at(at + 5), u16_at(at + 7)), (width, height));
+                    let packed = bytes[at + 9];
+                    at += 10;
+                    let palette = if packed & 0x80 != 0 {
+                        let local = table(at, packed);
+                        at += local.len() * 3;
+                        local
+                    } else {
+                        global.clone().expect("a frame without any palette")
+                    };
+                    let min_code_size = bytes[at];
+                    at += 1;
+                    let mut data = Vec::new();
+                    while bytes[at] != 0 {
+                        let len = bytes[at] as usize;
+                        data.extend_from_slice(&bytes[at + 1..at + 1 + len]);
+                        at += 1 + len;
+                    }
+                    at += 1;
+                    let indices = lzw_decode(&data, min_code_size);
+                    assert_eq!(indices.len(), width as usize * height as usize);
+                    decoded.frames.push(DecodedFrame {
+                        delay: control.0,
+                        transparent: control.1,
+                        palette,
+                        indices,
+                    });
+                }
+                0x3b => {
+                    assert_eq!(at, bytes.len() - 1);
+                    return decoded;
+                }
+                other => panic!("unexpected block {:#x}", other),
+            }
+        }
+    }
+
+    /// The usual GIF decoder's side of the LZW scheme, as browsers run it.
+    fn lzw_decode(data: &[u8], min_code_size: u8) -> Vec<u8> {
+        let clear = 1u16 << min_code_size;
+        let end = clear + 1;
+        let reset = || -> Vec<Vec<u8>> { (0..=end).map(|i| vec![i as u8]).collect() };
+        let mut table = reset();
+        let mut width = min_code_size + 1;
+        let mut previous: Option<u16> = None;
+        let mut output = Vec::new();
+        let (mut bits, mut count, mut bytes) = (0u32, 0u8, data.iter());
+
+        loop {
+            while count < width {
+                bits |=
+                    (*bytes.next().expect("ran out of data before the end code") as u32) << count;
+                count += 8;
+            }
+            let code = (bits & ((1 << width) - 1)) as u16;
+            bits >>= width;
+            count -= width;
+
+            if code == clear {
+                table = reset();
+                width = min_code_size + 1;
+                previous = None;
+                continue;
+            }
+            if code == end {
+                return output;
+            }
+            let entry = match previous {
+                None => table[code as usize].clone(),
+                Some(previous) => {
+                    let mut entry = match table.get(code as usize) {
+                        Some(entry) => entry.clone(),
+                        None => {
+                            assert_eq!(code as usize, table.len(), "code out of range");
+                            table[previous as usize].clone()
+                        }
+                    };
+                    if code as usize == table.len() {
+                        entry.push(entry[0]);
+                    }
+                    if table.len() < 4096 {
+                        let mut added = table[previous as usize].clone();
+                        added.push(entry[0]);
+                        table.push(added);
+                        if table.len() == 1 << width && width < 12 {
+                            width += 1;
+                        }
+                    }
+                    entry
+                }
+            };
+            output.extend_from_slice(&entry);
+            previous = Some(code);
+        }
+    }
+
+    fn canvas(width: u32, height: u32, data: Vec<u8>) -> CanvasSource {
+        CanvasSource::try_new(width, height, data).unwrap()
+    }
+
+    #[test]
+    fn lzw_round_trips() {
+        let mut state = 7u32;
+        let mut noise = || {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (state >> 16) as u8
+        };
+        // runs, noise, and enough of it to fill the code table many times
+        let inputs: Vec<(Vec<u8>, u8)> = vec![
+            (vec![], 2),
+            (vec![1], 2),
+            (vec![0; 10_000], 2),
+            ((0..20_000).map(|i| (i % 4) as u8).collect(), 2),
+            ((0..60_000).map(|_| noise()).collect(), 8),
+            ((0..60_000).map(|_| noise() & 7).collect(), 3),
+        ];
+        for (input, min_code_size) in inputs {
+            let encoded = lzw_encode(&input, min_code_size);
+            assert_eq!(lzw_decode(&encoded, min_code_size), input);
+        }
+    }
+
+    #[test]
+    fn frames_round_trip_to_the_same_pixels() {
+        let colors = [
+            [255, 0, 0, 255],
+            [0, 0, 255, 255],
+            [0, 0, 0, 0],
+            [10, 200, 30, 255],
+        ];
+        let first: Vec<u8> = (0..12 * 7).flat_map(|i| colors[i % 4]).collect();
+        let second: Vec<u8> = (0..12 * 7).flat_map(|i| colors[(i / 5) % 4]).collect();
+        let mut encoder = GifEncoder::new(12, 7);
+        encoder
+            .push_frame(&canvas(12, 7, first.clone()), 100)
+            .unwrap();
+        encoder
+            .push_frame(&canvas(12, 7, second.clone()), 33)
+            .unwrap();
+
+        let gif = decode(&encoder.encode().unwrap());
+        assert_eq!((gif.width, gif.height), (12, 7));
+        assert!(gif.global);
+        assert_eq!(gif.loop_count, Some(0));
+        assert_eq!(gif.frames.len(), 2);
+        assert_eq!(gif.frames[0].pixels(), first);
+        assert_eq!(gif.frames[1].pixels(), second);
+        assert_eq!((gif.frames[0].delay, gif.frames[1].delay), (10, 3));
+        assert!(gif.frames[0].transparent.is_some());
+    }
+
+    #[test]
+    fn alpha_is_thresholded_at_half() {
+        let data = [[9, 9, 9, 127], [9, 9, 9, 128]].concat();
+        let mut encoder = GifEncoder::new(2, 1);
+        encoder.push_frame(&canvas(2, 1, data.clone()), 0).unwrap();
+        let gif = decode(&encoder.encode().unwrap());
+        assert_eq!(
+            gif.frames[0].pixels(),
+            [[0, 0, 0, 0], [9, 9, 9, 255]].concat()
+        );
+
+        let mut opaque = GifEncoder::new(2, 1);
+        opaque.set_transparency(false);
+        opaque.set_loop_count(None);
+        opaque.push_frame(&canvas(2, 1, data), 0).unwrap();
+        let gif = decode(&opaque.encode().unwrap());
+        assert_eq!(gif.loop_count, None);
+        assert_eq!(gif.frames[0].transparent, None);
+        assert_eq!(
+            gif.frames[0].pixels(),
+            [[9, 9, 9, 255], [9, 9, 9, 255]].concat()
+        );
+    }
+
+    #[test]
+    fn per_frame_palettes_are_local() {
+        let mut encoder = GifEncoder::new(1, 1);
+        encoder.set_palette_mode(GifPalette::PerFrame);
+        encoder
+            .push_frame(&canvas(1, 1, vec![1, 2, 3, 255]), 0)
+            .unwrap();
+        encoder
+            .push_frame(&canvas(1, 1, vec![4, 5, 6, 255]), 0)
+            .unwrap();
+        let gif = decode(&encoder.encode().unwrap());
+        assert!(!gif.global);
+        assert_eq!(gif.frames[0].pixels(), [1, 2, 3, 255]);
+        assert_eq!(gif.frames[1].pixels(), [4, 5, 6, 255]);
+    }
+
+    #[test]
+    fn fixed_palettes_are_used_as_given() {
+        let mut encoder = GifEncoder::new(2, 1);
+        encoder.set_palette(&Palette::try_new(&[0x000000ff, 0xffffffff]).unwrap());
+        encoder.set_dither(Dither::None);
+        encoder
+            .push_frame(&canvas(2, 1, vec![20, 20, 20, 255, 240, 240, 240, 255]), 0)
+            .unwrap();
+        let gif = decode(&encoder.encode().unwrap());
+        assert_eq!(
+            gif.frames[0].pixels(),
+            [[0, 0, 0, 255], [255, 255, 255, 255]].concat()
+        );
+    }
+
+    #[test]
+    fn bad_sizes_and_missing_frames_are_errors() {
+        let mut encoder = GifEncoder::new(4, 4);
+        assert!(matches!(encoder.encode(), Err(GifError::NoFrames)));
+        assert!(matches!(
+            encoder.push_frame(&canvas(4, 5, Vec::new()), 0),
+            Err(GifError::FrameSize {
+                expected: (4, 4),
+                actual: (4, 5)
+            })
+        ));
+        assert_eq!(encoder.frame_count(), 0);
+
+        let mut huge = GifEncoder::new(70_000, 1);
+        huge.push_frame(&canvas(70_000, 1, Vec::new()), 0).unwrap();
+        assert!(matches!(
+            huge.encode(),
+            Err(GifError::Dimensions(70_000, 1))
+        ));
+    }
+}
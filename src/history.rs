@@ -0,0 +1,510 @@
+use std::collections::VecDeque;
+
+use wasm_bindgen::prelude::*;
+
+use crate::canvas_source::CanvasSource;
+use crate::dirty::Rect;
+
+// edge length of the square tiles history snapshots, in pixels
+const TILE_SIZE: u32 = 64;
+const DEFAULT_DEPTH: usize = 50;
+// bytes of tile snapshots kept across the undo and redo steps, so a few
+// edits to a very large canvas can't use up memory before the depth limit
+const DEFAULT_BUDGET: usize = 256 << 20;
+
+/// One tile's pixels before and after a step, rows packed tightly.
+struct TilePatch {
+    tile: usize,
+    before: Vec<u8>,
+    after: Vec<u8>,
+}
+
+type Step = Vec<TilePatch>;
+
+fn step_bytes(step: &Step) -> usize {
+    step.iter()
+        .map(|patch| patch.before.len() + patch.after.len())
+        .sum()
+}
+
+/// Undo/redo for a pixel buffer that only keeps copies of the tiles each
+/// step actually changed.
+///
+/// Writers call `record` before touching a region; the first time a tile is
+/// touched within a step its current pixels are saved. When the step is
+/// closed, the tiles' new contents are saved alongside them and any tile
+/// that ended up unchanged is dropped. The oldest steps are forgotten once
+/// there are more than `depth` of them or they take up more than `budget`
+/// bytes, though the latest step is always kept.
+pub(crate) struct History {
+    depth: usize,
+    budget: usize,
+    // bytes held by the steps in `undo` and `redo`
+    bytes: usize,
+    undo: VecDeque<Step>,
+    redo: Vec<Step>,
+    pending: Step,
+    // whether each tile already has a snapshot in `pending`
+    recorded: Vec<bool>,
+    // nesting of edit() calls, so only the outermost one closes a step
+    operations: u32,
+    groups: u32,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History {
+            depth: DEFAULT_DEPTH,
+            budget: DEFAULT_BUDGET,
+            bytes: 0,
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            pending: Vec::new(),
+            recorded: Vec::new(),
+            operations: 0,
+            groups: 0,
+        }
+    }
+}
+
+/// Pixel bounds (exclusive end) of a tile, clipped to the canvas.
+fn tile_bounds(tile: usize, width: u32, height: u32) -> Rect {
+    let columns = width.div_ceil(TILE_SIZE);
+    let x0 = (tile as u32 % columns) * TILE_SIZE;
+    let y0 = (tile as u32 / columns) * TILE_SIZE;
+    (
+        x0,
+        y0,
+        (x0 + TILE_SIZE).min(width),
+        (y0 + TILE_SIZE).min(height),
+    )
+}
+
+fn read_tile(data: &[u8], tile: usize, width: u32, height: u32) -> Vec<u8> {
+    let (x0, y0, x1, y1) = tile_bounds(tile, width, height);
+    let run = ((x1 - x0) * 4) as usize;
+    let mut pixels = Vec::with_capacity(run * (y1 - y0) as usize);
+    for y in y0..y1 {
+        let start = ((y * width + x0) * 4) as usize;
+        pixels.extend_from_slice(&data[start..start + run]);
+    }
+    pixels
+}
+
+fn write_tile(data: &mut [u8], tile: usize, width: u32, height: u32, pixels: &[u8]) {
+    let (x0, y0, x1, y1) = tile_bounds(tile, width, height);
+    let run = ((x1 - x0) * 4) as usize;
+    for (row, y) in (y0..y1).enumerate() {
+        let start = ((y * width + x0) * 4) as usize;
+        data[start..start + run].copy_from_slice(&pixels[row * run..(row + 1) * run]);
+    }
+}
+
+impl History {
+    /// Snapshot any tiles in the pixel rectangle (x0, y0)..(x1, y1) that the
+    /// current step hasn't seen yet. The rectangle must already be clipped
+    /// to the canvas.
+    pub fn record(&mut self, data: &[u8], width: u32, height: u32, rect: Rect) {
+        let (x0, y0, x1, y1) = rect;
+        if self.depth == 0 || x0 >= x1 || y0 >= y1 {
+            return;
+        }
+
+        let columns = width.div_ceil(TILE_SIZE);
+        let rows = height.div_ceil(TILE_SIZE);
+        if self.recorded.len() != (columns * rows) as usize {
+            self.recorded = vec![false; (columns * rows) as usize];
+        }
+
+        for tile_y in y0 / TILE_SIZE..=(y1 - 1) / TILE_SIZE {
+            for tile_x in x0 / TILE_SIZE..=(x1 - 1) / TILE_SIZE {
+                let tile = (tile_y * columns + tile_x) as usize;
+                if !self.recorded[tile] {
+                    self.recorded[tile] = true;
+                    self.pending.push(TilePatch {
+                        tile,
+                        before: read_tile(data, tile, width, height),
+                        after: Vec::new(),
+                    });
+                }
+            }
+        }
+    }
+
+    pub fn begin_operation(&mut self) {
+        self.operations += 1;
+    }
+
+    /// Close the outermost operation, which ends the step unless a group
+    /// is holding it open.
+    pub fn end_operation(&mut self, data: &[u8], width: u32, height: u32) {
+        self.operations = self.operations.saturating_sub(1);
+        if self.operations == 0 && self.groups == 0 {
+            self.close_step(data, width, height);
+        }
+    }
+
+    pub fn begin_group(&mut self) {
+        self.groups += 1;
+    }
+
+    pub fn end_group(&mut self, data: &[u8], width: u32, height: u32) {
+        self.groups = self.groups.saturating_sub(1);
+        if self.groups == 0 && self.operations == 0 {
+            self.close_step(data, width, height);
+        }
+    }
+
+    fn close_step(&mut self, data: &[u8], width: u32, height: u32) {
+        let mut step = std::mem::take(&mut self.pending);
+        for patch in step.iter_mut() {
+            self.recorded[patch.tile] = false;
+            patch.after = read_tile(data, patch.tile, width, height);
+        }
+        step.retain(|patch| patch.before != patch.after);
+        if step.is_empty() {
+            return;
+        }
+
+        self.clear_redo();
+        self.bytes += step_bytes(&step);
+        self.undo.push_back(step);
+        self.trim();
+    }
+
+    /// Forget the oldest steps until both the depth and the budget are met.
+    fn trim(&mut self) {
+        while self.undo.len() > self.depth || (self.bytes > self.budget && self.undo.len() > 1) {
+            match self.undo.pop_front() {
+                Some(step) => self.bytes -= step_bytes(&step),
+                None => break,
+            }
+        }
+    }
+
+    fn clear_redo(&mut self) {
+        for step in self.redo.drain(..) {
+            self.bytes -= step_bytes(&step);
+        }
+    }
+
+    /// Whether a group or operation is still recording, in which case undo
+    /// and redo wait for it to end rather than cut it in half.
+    fn recording(&self) -> bool {
+        self.groups > 0 || self.operations > 0
+    }
+
+    /// Revert the latest step, returning the rectangles it rewrote. Does
+    /// nothing while a group is open.
+    pub fn undo(&mut self, data: &mut [u8], width: u32, height: u32) -> Option<Vec<Rect>> {
+        if self.recording() {
+            return None;
+        }
+        self.close_step(data, width, height);
+
+        let step = self.undo.pop_back()?;
+        for patch in step.iter().rev() {
+            write_tile(data, patch.tile, width, height, &patch.before);
+        }
+        let rects = step
+            .iter()
+            .map(|patch| tile_bounds(patch.tile, width, height))
+            .collect();
+        self.redo.push(step);
+        Some(rects)
+    }
+
+    /// Reapply the latest undone step, returning the rectangles it rewrote.
+    /// Does nothing while a group is open.
+    pub fn redo(&mut self, data: &mut [u8], width: u32, height: u32) -> Option<Vec<Rect>> {
+        if self.recording() {
+            return None;
+        }
+        self.close_step(data, width, height);
+
+        let step = self.redo.pop()?;
+        for patch in step.iter() {
+            write_tile(data, patch.tile, width, height, &patch.after);
+        }
+        let rects = step
+            .iter()
+            .map(|patch| tile_bounds(patch.tile, width, height))
+            .collect();
+        self.undo.push_back(step);
+        Some(rects)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.recording() && !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.recording() && !self.redo.is_empty()
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn set_depth(&mut self, depth: usize) {
+        self.depth = depth;
+        self.trim();
+        if depth == 0 {
+            self.clear();
+        }
+    }
+
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    pub fn set_budget(&mut self, bytes: usize) {
+        self.budget = bytes;
+        self.trim();
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.bytes = 0;
+        for patch in self.pending.drain(..) {
+            self.recorded[patch.tile] = false;
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl CanvasSource {
+    /// Revert the most recent edit. Returns false if there is nothing to
+    /// undo, or while a group or brush stroke is still open: end it first.
+    pub fn undo(&mut self) -> bool {
+        match self.history.undo(&mut self.data, self.width, self.height) {
+            Some(rects) => {
+                rects.into_iter().for_each(|rect| self.dirty.mark(rect));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Reapply the most recently undone edit. Returns false if there is
+    /// nothing to redo, or while a group or brush stroke is still open.
+    pub fn redo(&mut self) -> bool {
+        match self.history.redo(&mut self.data, self.width, self.height) {
+            Some(rects) => {
+                rects.into_iter().for_each(|rect| self.dirty.mark(rect));
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.history.can_undo()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.history.can_redo()
+    }
+
+    /// Everything drawn between `begin_group` and `end_group` is undone as
+    /// a single step. Groups nest.
+    pub fn begin_group(&mut self) {
+        self.history.begin_group();
+    }
+
+    pub fn end_group(&mut self) {
+        self.history.end_group(&self.data, self.width, self.height);
+    }
+
+    pub fn history_depth(&self) -> usize {
+        self.history.depth()
+    }
+
+    /// How many undo steps to keep. Zero turns history off entirely.
+    pub fn set_history_depth(&mut self, depth: usize) {
+        self.history.set_depth(depth);
+    }
+
+    pub fn history_budget(&self) -> usize {
+        self.history.budget()
+    }
+
+    /// How many bytes of pixels undo and redo may hold on to. Past this the
+    /// oldest steps are dropped, though the latest edit can always be
+    /// undone.
+    pub fn set_history_budget(&mut self, bytes: usize) {
+        self.history.set_budget(bytes);
+    }
+
+    pub fn clear_history(&mut self) {
+        self.history.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brush::Brush;
+    use crate::raster::Rasterization;
+
+    // spans partial tiles on the right and bottom edges
+    fn canvas() -> CanvasSource {
+        let data = (0..100 * 70 * 4).map(|i| (i % 251) as u8).collect();
+        CanvasSource::try_new(100, 70, data).unwrap()
+    }
+
+    fn fill(canvas: &mut CanvasSource, x: f32, color: u32) {
+        canvas.fill_rect(x, 10.0, 40.0, 55.0, color, Rasterization::AntiAliased);
+    }
+
+    #[test]
+    fn undo_and_redo_restore_exact_bytes() {
+        let mut canvas = canvas();
+        let original = canvas.data.clone();
+        fill(&mut canvas, 50.5, 0x33669980);
+        let drawn = canvas.data.clone();
+        assert_ne!(drawn, original);
+
+        assert!(canvas.undo());
+        assert_eq!(canvas.data, original);
+        assert!(!canvas.can_undo());
+        assert!(canvas.redo());
+        assert_eq!(canvas.data, drawn);
+        assert!(!canvas.can_redo());
+    }
+
+    #[test]
+    fn steps_undo_in_reverse_order() {
+        let mut canvas = canvas();
+        let original = canvas.data.clone();
+        fill(&mut canvas, 0.0, 0xff0000ff);
+        let first = canvas.data.clone();
+        fill(&mut canvas, 20.0, 0x00ff00ff);
+
+        assert!(canvas.undo());
+        assert_eq!(canvas.data, first);
+        assert!(canvas.undo());
+        assert_eq!(canvas.data, original);
+        assert!(!canvas.undo());
+    }
+
+    #[test]
+    fn new_edits_drop_the_redo_steps() {
+        let mut canvas = canvas();
+        fill(&mut canvas, 0.0, 0xff0000ff);
+        canvas.undo();
+        fill(&mut canvas, 20.0, 0x00ff00ff);
+        assert!(!canvas.can_redo());
+        assert!(!canvas.redo());
+    }
+
+    #[test]
+    fn edits_that_change_nothing_add_no_step() {
+        let mut canvas = canvas();
+        let original = canvas.data.clone();
+        fill(&mut canvas, 0.0, 0xff0000ff);
+        // the same opaque fill again leaves every byte as it was
+        fill(&mut canvas, 0.0, 0xff0000ff);
+        assert!(canvas.undo());
+        assert_eq!(canvas.data, original);
+        assert!(!canvas.can_undo());
+    }
+
+    #[test]
+    fn groups_undo_as_one_step() {
+        let mut canvas = canvas();
+        let original = canvas.data.clone();
+        canvas.begin_group();
+        fill(&mut canvas, 0.0, 0xff0000ff);
+        canvas.begin_group();
+        fill(&mut canvas, 70.0, 0x00ff00ff);
+        canvas.end_group();
+        // still inside the outer group
+        assert!(!canvas.can_undo());
+        assert!(!canvas.undo());
+        canvas.end_group();
+
+        assert!(canvas.undo());
+        assert_eq!(canvas.data, original);
+        assert!(!canvas.can_undo());
+    }
+
+    #[test]
+    fn undo_waits_for_the_stroke_to_end() {
+        let mut canvas = canvas();
+        let original = canvas.data.clone();
+        canvas.begin_stroke(&Brush::new(8.0, 0xff0000ff));
+        canvas.stroke_to(10.0, 10.0, 1.0, 0.0);
+        canvas.stroke_to(60.0, 40.0, 1.0, 16.0);
+        assert!(!canvas.undo());
+        canvas.end_stroke();
+
+        assert!(canvas.undo());
+        assert_eq!(canvas.data, original);
+    }
+
+    #[test]
+    fn depth_limits_the_steps_kept() {
+        let mut canvas = canvas();
+        canvas.set_history_depth(2);
+        for (i, color) in [0xff0000ff, 0x00ff00ff, 0x0000ffffu32].iter().enumerate() {
+            fill(&mut canvas, i as f32 * 20.0, *color);
+        }
+        assert!(canvas.undo());
+        assert!(canvas.undo());
+        assert!(!canvas.undo());
+
+        canvas.set_history_depth(0);
+        fill(&mut canvas, 0.0, 0x123456ff);
+        assert!(!canvas.can_undo());
+        assert!(!canvas.can_redo());
+    }
+
+    #[test]
+    fn budget_limits_the_bytes_kept() {
+        let mut canvas = canvas();
+        let original = canvas.data.clone();
+        fill(&mut canvas, 0.0, 0xff0000ff);
+        let first = canvas.data.clone();
+        fill(&mut canvas, 0.0, 0x00ff00ff);
+        // each step keeps two copies of the two tiles it touched, the lower
+        // one cut short by the bottom edge
+        let step = 2 * (64 * 64 + 64 * 6) * 4;
+        assert_eq!(canvas.history.bytes, 2 * step);
+
+        canvas.set_history_budget(step);
+        assert_eq!(canvas.history.bytes, step);
+        assert!(canvas.undo());
+        assert_eq!(canvas.data, first);
+        assert!(!canvas.undo());
+
+        // the latest step stays however small the budget
+        canvas.set_history_budget(0);
+        fill(&mut canvas, 20.0, 0x0000ffff);
+        assert!(canvas.undo());
+        assert_eq!(canvas.data, first);
+        assert_ne!(canvas.data, original);
+        assert_eq!(canvas.history.bytes, step);
+    }
+
+    #[test]
+    fn clearing_forgets_every_step() {
+        let mut canvas = canvas();
+        fill(&mut canvas, 0.0, 0xff0000ff);
+        fill(&mut canvas, 20.0, 0x00ff00ff);
+        canvas.undo();
+        canvas.clear_history();
+        assert!(!canvas.can_undo());
+        assert!(!canvas.can_redo());
+    }
+
+    #[test]
+    fn undo_marks_the_rewritten_tiles_dirty() {
+        let mut canvas = canvas();
+        fill(&mut canvas, 70.0, 0xff0000ff);
+        canvas.clear_dirty();
+        canvas.undo();
+        assert_eq!(canvas.dirty.bounds(), Some((64, 0, 100, 70)));
+    }
+}
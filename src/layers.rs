@@ -0,0 +1,415 @@
+use wasm_bindgen::prelude::*;
+
+use crate::canvas_source::{buffer_len, CanvasSource, SizeError};
+use crate::color::Rgba;
+use crate::composite::{BlendMode, CompositeOp, Compositor};
+
+pub struct Layer {
+    pub source: CanvasSource,
+    pub opacity: f32,
+    pub blend: BlendMode,
+    pub visible: bool,
+    pub locked: bool,
+}
+
+impl Layer {
+    fn new(width: u32, height: u32) -> Layer {
+        Layer {
+            source: CanvasSource::from_parts(width, height, Vec::new()),
+            opacity: 1.0,
+            blend: BlendMode::Normal,
+            visible: true,
+            locked: false,
+        }
+    }
+}
+
+/// A document made of stacked layers, flattened bottom (index 0) to top
+/// into a single RGBA buffer for display.
+///
+/// To draw on a layer from JS, `take_layer` hands over its `CanvasSource`
+/// and `restore_layer` puts it back. Only one layer can be out at a time,
+/// and while it is, layers can't be inserted, removed or moved and it can't
+/// be locked, so its index still means the same layer when it comes back.
+/// Locked layers can't be taken.
+#[wasm_bindgen]
+pub struct LayerStack {
+    width: u32,
+    height: u32,
+    layers: Vec<Layer>,
+    output: CanvasSource,
+    // set whenever a layer changes, so data() only re-flattens when needed
+    dirty: bool,
+    // the index of the layer that's out with take_layer, if any
+    taken: Option<usize>,
+}
+
+#[wasm_bindgen]
+impl LayerStack {
+    /// A new document with a single transparent layer. Throws if it's
+    /// larger than a canvas can be.
+    pub fn new(width: u32, height: u32) -> Result<LayerStack, JsError> {
+        Ok(LayerStack::try_new(width, height)?)
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn layer_count(&self) -> usize {
+        self.layers.len()
+    }
+
+    /// Add a transparent layer on top of the stack, returning its index.
+    pub fn add_layer(&mut self) -> usize {
+        self.layers.push(Layer::new(self.width, self.height));
+        self.dirty = true;
+        self.layers.len() - 1
+    }
+
+    /// Add a transparent layer at `index`, pushing the layers above it up.
+    pub fn insert_layer(&mut self, index: usize) -> bool {
+        if index > self.layers.len() || self.taken.is_some() {
+            return false;
+        }
+        self.layers
+            .insert(index, Layer::new(self.width, self.height));
+        self.dirty = true;
+        true
+    }
+
+    pub fn remove_layer(&mut self, index: usize) -> bool {
+        if index >= self.layers.len() || self.taken.is_some() {
+            return false;
+        }
+        self.layers.remove(index);
+        self.dirty = true;
+        true
+    }
+
+    /// Move the layer at `from` so it ends up at index `to`.
+    pub fn move_layer(&mut self, from: usize, to: usize) -> bool {
+        if from >= self.layers.len() || to >= self.layers.len() || self.taken.is_some() {
+            return false;
+        }
+        let layer = self.layers.remove(from);
+        self.layers.insert(to, layer);
+        self.dirty = true;
+        true
+    }
+
+    pub fn opacity(&self, index: usize) -> f32 {
+        self.layers.get(index).map_or(0.0, |l| l.opacity)
+    }
+
+    /// Set a layer's opacity, clamped to 0..=1. NaN makes it opaque, like a
+    /// new layer.
+    pub fn set_opacity(&mut self, index: usize, opacity: f32) {
+        if let Some(layer) = self.layers.get_mut(index) {
+            layer.opacity = if opacity.is_nan() {
+                1.0
+            } else {
+                opacity.clamp(0.0, 1.0)
+            };
+            self.dirty = true;
+        }
+    }
+
+    pub fn blend_mode(&self, index: usize) -> BlendMode {
+        self.layers
+            .get(index)
+            .map_or(BlendMode::Normal, |l| l.blend)
+    }
+
+    pub fn set_blend_mode(&mut self, index: usize, mode: BlendMode) {
+        if let Some(layer) = self.layers.get_mut(index) {
+            layer.blend = mode;
+            self.dirty = true;
+        }
+    }
+
+    pub fn visible(&self, index: usize) -> bool {
+        self.layers.get(index).is_some_and(|l| l.visible)
+    }
+
+    pub fn set_visible(&mut self, index: usize, visible: bool) {
+        if let Some(layer) = self.layers.get_mut(index) {
+            layer.visible = visible;
+            self.dirty = true;
+        }
+    }
+
+    pub fn locked(&self, index: usize) -> bool {
+        self.layers.get(index).is_some_and(|l| l.locked)
+    }
+
+    /// Lock or unlock a layer. The layer that's out with `take_layer` can't
+    /// be locked until it's restored.
+    pub fn set_locked(&mut self, index: usize, locked: bool) {
+        if locked && self.taken == Some(index) {
+            return;
+        }
+        if let Some(layer) = self.layers.get_mut(index) {
+            layer.locked = locked;
+        }
+    }
+
+    /// Hand the layer's pixels over for editing until `restore_layer` is
+    /// called. A copy stays behind so the document still shows the layer;
+    /// use `data_with_layer` to show the edits as they happen. Returns
+    /// `undefined` for a locked or missing layer, or if a layer is already
+    /// out.
+    pub fn take_layer(&mut self, index: usize) -> Option<CanvasSource> {
+        if self.taken.is_some() {
+            return None;
+        }
+        let (width, height) = (self.width, self.height);
+        let layer = self.layers.get_mut(index).filter(|l| !l.locked)?;
+        let copy = CanvasSource::from_parts(width, height, layer.source.data.clone());
+        self.taken = Some(index);
+        Some(std::mem::replace(&mut layer.source, copy))
+    }
+
+    /// Put a layer's pixels back after editing. Fails unless `index` is the
+    /// layer that's out with `take_layer` and the canvas is the document's
+    /// size.
+    pub fn restore_layer(&mut self, index: usize, source: CanvasSource) -> bool {
+        if self.taken != Some(index) || source.width != self.width || source.height != self.height {
+            return false;
+        }
+        self.layers[index].source = source;
+        self.taken = None;
+        self.dirty = true;
+        true
+    }
+
+    /// Composite every visible layer into the output buffer.
+    pub fn flatten(&mut self) {
+        self.composite_layers(None);
+        self.dirty = false;
+    }
+
+    /// Pointer to the flattened RGBA buffer, re-flattening first if any
+    /// layer changed since the last call.
+    pub fn data(&mut self) -> *const u8 {
+        if self.dirty {
+            self.flatten();
+        }
+        self.output.data.as_ptr()
+    }
+
+    /// `data`, with `source` shown in place of layer `index`: the live view
+    /// of a layer that's out with `take_layer`. Always re-flattens. Returns
+    /// a null pointer if `source` isn't the document's size.
+    pub fn data_with_layer(&mut self, index: usize, source: &CanvasSource) -> *const u8 {
+        if source.width != self.width || source.height != self.height {
+            return std::ptr::null();
+        }
+        self.composite_layers(Some((index, source)));
+        // the output no longer matches the stored layers
+        self.dirty = true;
+        self.output.data.as_ptr()
+    }
+
+    /// A copy of the flattened document as a standalone canvas, e.g. for
+    /// `to_png`.
+    pub fn to_canvas(&mut self) -> CanvasSource {
+        if self.dirty {
+            self.flatten();
+        }
+        CanvasSource::from_parts(self.width, self.height, self.output.data.clone())
+    }
+}
+
+// removed #[wasm_bindgen] - not sent to js
+impl LayerStack {
+    pub fn try_new(width: u32, height: u32) -> Result<LayerStack, SizeError> {
+        if buffer_len(width, height).is_none() {
+            return Err(SizeError { width, height });
+        }
+        Ok(LayerStack {
+            width,
+            height,
+            layers: vec![Layer::new(width, height)],
+            output: CanvasSource::from_parts(width, height, Vec::new()),
+            dirty: true,
+            taken: None,
+        })
+    }
+
+    /// Flatten into the output buffer, with `replacement`'s pixels standing
+    /// in for those of the layer at its index.
+    fn composite_layers(&mut self, replacement: Option<(usize, &CanvasSource)>) {
+        self.output.data.iter_mut().for_each(|byte| *byte = 0);
+
+        for (index, layer) in self.layers.iter().enumerate() {
+            if !layer.visible || layer.opacity <= 0.0 {
+                continue;
+            }
+            let pixels = match replacement {
+                Some((replaced, source)) if replaced == index => &source.data,
+                _ => &layer.source.data,
+            };
+            let compositor = Compositor {
+                op: CompositeOp::SourceOver,
+                blend: layer.blend,
+            };
+            for (dst, src) in self
+                .output
+                .data
+                .chunks_exact_mut(4)
+                .zip(pixels.chunks_exact(4))
+            {
+                if src[3] != 0 {
+                    compositor.composite(dst, Rgba::from_slice(src), layer.opacity);
+                }
+            }
+        }
+    }
+
+    pub fn layer(&self, index: usize) -> Option<&Layer> {
+        self.layers.get(index)
+    }
+
+    /// Mutable access to a layer's pixels, or `None` if it is locked or out
+    /// with `take_layer`.
+    pub fn layer_source_mut(&mut self, index: usize) -> Option<&mut CanvasSource> {
+        if self.taken == Some(index) {
+            return None;
+        }
+        let layer = self.layers.get_mut(index).filter(|l| !l.locked)?;
+        self.dirty = true;
+        Some(&mut layer.source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raster::Rasterization;
+
+    /// A 2x2 document with `colors` filling its layers, bottom first.
+    fn stack(colors: &[u32]) -> LayerStack {
+        let mut stack = LayerStack::try_new(2, 2).unwrap();
+        for (index, &color) in colors.iter().enumerate() {
+            if index > 0 {
+                stack.add_layer();
+            }
+            let source = stack.layer_source_mut(index).unwrap();
+            source.fill_rect(0.0, 0.0, 2.0, 2.0, color, Rasterization::Aliased);
+        }
+        stack
+    }
+
+    fn first_pixel(stack: &mut LayerStack) -> [u8; 4] {
+        stack.to_canvas().pixel(0, 0).unwrap().to_array()
+    }
+
+    #[test]
+    fn layers_flatten_bottom_to_top() {
+        let mut stack = stack(&[0x0000ffff, 0xff0000ff]);
+        assert_eq!(first_pixel(&mut stack), [255, 0, 0, 255]);
+
+        stack.set_opacity(1, 0.5);
+        assert_eq!(first_pixel(&mut stack), [128, 0, 128, 255]);
+
+        stack.set_visible(1, false);
+        assert_eq!(first_pixel(&mut stack), [0, 0, 255, 255]);
+
+        stack.set_visible(1, true);
+        stack.set_opacity(1, 1.0);
+        stack.move_layer(1, 0);
+        assert_eq!(first_pixel(&mut stack), [0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn blend_modes_apply_between_layers() {
+        let mut stack = stack(&[0x808080ff, 0x808080ff]);
+        stack.set_blend_mode(1, BlendMode::Multiply);
+        assert_eq!(first_pixel(&mut stack), [64, 64, 64, 255]);
+        stack.set_blend_mode(1, BlendMode::Screen);
+        assert_eq!(first_pixel(&mut stack), [192, 192, 192, 255]);
+    }
+
+    #[test]
+    fn locked_layers_cant_be_edited() {
+        let mut stack = stack(&[0x0000ffff]);
+        stack.set_locked(0, true);
+        assert!(stack.take_layer(0).is_none());
+        assert!(stack.layer_source_mut(0).is_none());
+        stack.set_locked(0, false);
+        assert!(stack.take_layer(0).is_some());
+        assert!(stack.take_layer(1).is_none());
+    }
+
+    #[test]
+    fn taken_layers_stay_visible_until_restored() {
+        let mut stack = stack(&[0x0000ffff]);
+        let mut taken = stack.take_layer(0).unwrap();
+        assert_eq!(first_pixel(&mut stack), [0, 0, 255, 255]);
+
+        taken.fill_rect(0.0, 0.0, 1.0, 1.0, 0x00ff00ff, Rasterization::Aliased);
+        assert_eq!(first_pixel(&mut stack), [0, 0, 255, 255]);
+        assert!(!stack.data_with_layer(0, &taken).is_null());
+        assert_eq!(stack.output.data[..4], [0, 255, 0, 255]);
+        // the live view doesn't stick once the stored layers are shown again
+        assert_eq!(first_pixel(&mut stack), [0, 0, 255, 255]);
+
+        assert!(stack.restore_layer(0, taken));
+        assert_eq!(first_pixel(&mut stack), [0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn wrong_sized_canvases_are_refused() {
+        let mut stack = stack(&[0x0000ffff]);
+        let wrong = CanvasSource::try_new(3, 2, Vec::new()).unwrap();
+        assert!(stack.data_with_layer(0, &wrong).is_null());
+        assert!(!stack.restore_layer(0, wrong));
+        let right = CanvasSource::try_new(2, 2, Vec::new()).unwrap();
+        assert!(!stack.restore_layer(5, right));
+        assert_eq!(first_pixel(&mut stack), [0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn one_layer_is_out_at_a_time() {
+        let mut stack = stack(&[0x0000ffff, 0xff0000ff]);
+        // nothing to restore yet
+        let stray = CanvasSource::try_new(2, 2, Vec::new()).unwrap();
+        assert!(!stack.restore_layer(0, stray));
+
+        let taken = stack.take_layer(0).unwrap();
+        assert!(stack.take_layer(0).is_none());
+        assert!(stack.take_layer(1).is_none());
+        assert!(stack.layer_source_mut(0).is_none());
+        // the stack keeps its shape while the layer is out
+        assert!(!stack.remove_layer(1));
+        assert!(!stack.move_layer(1, 0));
+        assert!(!stack.insert_layer(0));
+        stack.set_locked(0, true);
+        assert!(!stack.locked(0));
+        assert_eq!(stack.layer_count(), 2);
+
+        // it only goes back where it came from
+        let stray = CanvasSource::try_new(2, 2, Vec::new()).unwrap();
+        assert!(!stack.restore_layer(1, stray));
+        assert!(stack.restore_layer(0, taken));
+        assert!(stack.remove_layer(1));
+    }
+
+    #[test]
+    fn nan_opacity_is_opaque() {
+        let mut stack = stack(&[0x0000ffff, 0xff0000ff]);
+        stack.set_opacity(1, f32::NAN);
+        assert_eq!(stack.opacity(1), 1.0);
+        assert_eq!(first_pixel(&mut stack), [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn oversized_documents_are_refused() {
+        assert!(LayerStack::try_new(u32::MAX, u32::MAX).is_err());
+    }
+}
@@ -1,4 +1,32 @@
+mod adjust;
+mod blit;
+mod brush;
 mod canvas_source;
+mod clipboard;
+mod color;
+mod compare;
+mod composite;
+mod dirty;
+mod fill;
+mod filters;
+mod gif;
+mod history;
+mod layers;
+mod paint;
+mod path;
+#[cfg(test)]
+mod pictures;
+mod raster;
+mod png_codec;
+mod quantize;
+mod resample;
+mod selection;
+mod shapes;
+mod stats;
+mod stroke;
+mod svg;
+mod text;
+mod transform;
 mod universe;
 mod utils;
 // use wasm_bindgen::prelude::*;
@@ -17,6 +45,30 @@ use winit::{
 use utils::set_panic_hook;
 use winit::window::Window;
 
+// the native binary works with canvases directly rather than through js
+pub use adjust::{Adjustment, Channel};
+pub use brush::Brush;
+pub use canvas_source::{CanvasSource, ImageDataError, SizeError};
+pub use color::Rgba;
+pub use compare::{CompareError, Comparison};
+pub use composite::{BlendMode, CompositeOp};
+pub use fill::Connectivity;
+pub use filters::{EdgeMode, FilterChain, KernelError};
+pub use gif::{GifEncoder, GifError, GifPalette};
+pub use layers::{Layer, LayerStack};
+pub use paint::{Paint, Spread};
+pub use path::Path;
+pub use raster::{FillRule, Rasterization};
+pub use png_codec::PngError;
+pub use quantize::{Dither, Palette, PaletteError, QuantizeMethod};
+pub use resample::{AspectMode, ResampleFilter};
+pub use selection::SelectionOp;
+pub use stats::Statistics;
+pub use stroke::{LineCap, LineJoin, StrokeStyle};
+pub use svg::SvgError;
+pub use text::{BitmapFont, FontError, TextAlign, TextStyle};
+pub use transform::Transform;
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug)]
 struct Vertex {
@@ -0,0 +1,462 @@
+use std::f32::consts::TAU;
+
+use wasm_bindgen::prelude::*;
+
+use crate::canvas_source::ImageDataError;
+use crate::color::Rgba;
+
+#[derive(Clone)]
+pub(crate) enum PaintKind {
+    Solid(Rgba),
+    /// An RGBA image repeated across the canvas from its origin.
+    Pattern {
+        width: u32,
+        height: u32,
+        data: Vec<u8>,
+    },
+    Gradient(Gradient),
+}
+
+/// What a gradient does past its first and last stops.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Spread {
+    /// Keep the end colors going.
+    Pad = 0,
+    /// Start over from the first stop.
+    Repeat = 1,
+    /// Run back and forth, mirroring every other repeat.
+    Reflect = 2,
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum GradientShape {
+    Linear {
+        x0: f32,
+        y0: f32,
+        x1: f32,
+        y1: f32,
+    },
+    /// Circles growing from the focal point (fx, fy) out to the circle at
+    /// (cx, cy). The focal point is always inside the outer circle.
+    Radial {
+        cx: f32,
+        cy: f32,
+        radius: f32,
+        fx: f32,
+        fy: f32,
+    },
+    Conic {
+        cx: f32,
+        cy: f32,
+        angle: f32,
+    },
+}
+
+#[derive(Clone)]
+pub(crate) struct Gradient {
+    shape: GradientShape,
+    // (offset, color) sorted by offset; stops at the same offset keep the
+    // order they were added in, for hard edges
+    stops: Vec<(f32, Rgba)>,
+    spread: Spread,
+}
+
+impl Gradient {
+    fn new(shape: GradientShape) -> Gradient {
+        Gradient {
+            shape,
+            stops: Vec::new(),
+            spread: Spread::Pad,
+        }
+    }
+
+    /// Where the point (x, y) falls along the gradient, before spreading:
+    /// 0.0 at the start, 1.0 at the end.
+    fn position(&self, x: f32, y: f32) -> f32 {
+        match self.shape {
+            GradientShape::Linear { x0, y0, x1, y1 } => {
+                let (dx, dy) = (x1 - x0, y1 - y0);
+                let length_squared = dx * dx + dy * dy;
+                if length_squared <= f32::EPSILON {
+                    return 0.0;
+                }
+                ((x - x0) * dx + (y - y0) * dy) / length_squared
+            }
+            GradientShape::Radial {
+                cx,
+                cy,
+                radius,
+                fx,
+                fy,
+            } => {
+                // a radius too small or large to square is as good as none
+                let radius_squared = radius * radius;
+                if !(radius_squared > 0.0 && radius_squared.is_finite()) {
+                    return 1.0;
+                }
+                // solve for the circle centered at f + t (c - f) with radius
+                // t * radius that passes through the point
+                let (dx, dy) = (cx - fx, cy - fy);
+                let (px, py) = (x - fx, y - fy);
+                let a = dx * dx + dy * dy - radius_squared;
+                let b = px * dx + py * dy;
+                let c = px * px + py * py;
+                // a < 0 because the focal point is inside the circle
+                (b - (b * b - a * c).max(0.0).sqrt()) / a
+            }
+            GradientShape::Conic { cx, cy, angle } => {
+                ((y - cy).atan2(x - cx) - angle).rem_euclid(TAU) / TAU
+            }
+        }
+    }
+
+    fn color_at(&self, x: f32, y: f32) -> Rgba {
+        let t = self.position(x, y);
+        // positions that overflowed, or came from NaN coordinates, go to
+        // whichever end they were headed for, or the start if neither
+        let t = if t.is_finite() {
+            t
+        } else if t > 0.0 {
+            1.0
+        } else {
+            0.0
+        };
+        let t = match self.spread {
+            Spread::Pad => t.clamp(0.0, 1.0),
+            Spread::Repeat => t.rem_euclid(1.0),
+            Spread::Reflect => {
+                let t = t.rem_euclid(2.0);
+                if t > 1.0 {
+                    2.0 - t
+                } else {
+                    t
+                }
+            }
+        };
+
+        let (first, last) = match (self.stops.first(), self.stops.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return Rgba::TRANSPARENT,
+        };
+        if t <= first.0 {
+            return first.1;
+        }
+        if t >= last.0 {
+            return last.1;
+        }
+        // the first stop past t; the one before it is at or before t
+        let next = self.stops.partition_point(|stop| stop.0 <= t);
+        let (start, end) = (self.stops[next - 1], self.stops[next]);
+        let f = (t - start.0) / (end.0 - start.0);
+        lerp_premultiplied(start.1, end.1, f)
+    }
+}
+
+/// Mix two colors in premultiplied space, so fading to a transparent stop
+/// doesn't drag its (invisible) color along, the way CSS gradients work.
+fn lerp_premultiplied(from: Rgba, to: Rgba, f: f32) -> Rgba {
+    let premultiply = |c: Rgba| {
+        let a = c.a as f32 / 255.0;
+        [c.r as f32 * a, c.g as f32 * a, c.b as f32 * a, c.a as f32]
+    };
+    let mut mixed = premultiply(from);
+    for (m, t) in mixed.iter_mut().zip(premultiply(to).iter()) {
+        *m += (t - *m) * f;
+    }
+    let alpha = mixed[3];
+    if alpha <= 0.0 {
+        return Rgba::TRANSPARENT;
+    }
+    let channel = |v: f32| (v * 255.0 / alpha).round().clamp(0.0, 255.0) as u8;
+    Rgba::new(
+        channel(mixed[0]),
+        channel(mixed[1]),
+        channel(mixed[2]),
+        alpha.round() as u8,
+    )
+}
+
+/// What a fill puts down: a flat color, a repeating image or a gradient.
+///
+/// Patterns and gradients are positioned in canvas pixel coordinates, not
+/// relative to the shape being filled.
+#[wasm_bindgen]
+#[derive(Clone)]
+pub struct Paint {
+    pub(crate) kind: PaintKind,
+}
+
+#[wasm_bindgen]
+impl Paint {
+    pub fn solid(color: u32) -> Paint {
+        Paint {
+            kind: PaintKind::Solid(Rgba::from_u32(color)),
+        }
+    }
+
+    /// A `width` by `height` RGBA image tiled across the canvas, anchored at
+    /// its top left corner. Throws if the image is empty or `data` is
+    /// shorter than `width * height * 4` bytes.
+    pub fn pattern(data: &[u8], width: u32, height: u32) -> Result<Paint, JsError> {
+        Ok(Paint::try_pattern(data, width, height)?)
+    }
+
+    /// A gradient along the line from (x0, y0) to (x1, y1). Add colors with
+    /// `add_color_stop`.
+    pub fn linear_gradient(x0: f32, y0: f32, x1: f32, y1: f32) -> Paint {
+        Paint {
+            kind: PaintKind::Gradient(Gradient::new(GradientShape::Linear { x0, y0, x1, y1 })),
+        }
+    }
+
+    /// A gradient from the focal point (fx, fy) out to the circle of
+    /// `radius` around (cx, cy). Pass the center as the focal point for a
+    /// plain radial gradient. A focal point outside the circle is moved onto
+    /// its edge, as SVG does.
+    pub fn radial_gradient(cx: f32, cy: f32, radius: f32, fx: f32, fy: f32) -> Paint {
+        let radius = radius.max(0.0);
+        let (dx, dy) = (fx - cx, fy - cy);
+        let distance = (dx * dx + dy * dy).sqrt();
+        // just inside the edge, so every point still has a circle through it
+        let limit = radius * 0.999;
+        let (fx, fy) = if distance > limit {
+            (cx + dx * limit / distance, cy + dy * limit / distance)
+        } else {
+            (fx, fy)
+        };
+        Paint {
+            kind: PaintKind::Gradient(Gradient::new(GradientShape::Radial {
+                cx,
+                cy,
+                radius,
+                fx,
+                fy,
+            })),
+        }
+    }
+
+    /// A gradient sweeping clockwise around (cx, cy), starting at `angle`
+    /// radians from the positive x axis.
+    pub fn conic_gradient(cx: f32, cy: f32, angle: f32) -> Paint {
+        Paint {
+            kind: PaintKind::Gradient(Gradient::new(GradientShape::Conic { cx, cy, angle })),
+        }
+    }
+
+    /// Add a color at `offset` (0.0 to 1.0) along a gradient. Adding two
+    /// stops at the same offset makes a hard edge. Does nothing for solid
+    /// colors and patterns.
+    pub fn add_color_stop(&mut self, offset: f32, color: u32) {
+        if let PaintKind::Gradient(gradient) = &mut self.kind {
+            let offset = offset.clamp(0.0, 1.0);
+            let at = gradient.stops.partition_point(|stop| stop.0 <= offset);
+            gradient.stops.insert(at, (offset, Rgba::from_u32(color)));
+        }
+    }
+
+    /// What a gradient does past its ends. `Pad` by default. Does nothing for
+    /// solid colors and patterns.
+    pub fn set_spread(&mut self, spread: Spread) {
+        if let PaintKind::Gradient(gradient) = &mut self.kind {
+            gradient.spread = spread;
+        }
+    }
+}
+
+// removed #[wasm_bindgen] - the Rust-side API for the native binary
+impl Paint {
+    pub fn try_pattern(data: &[u8], width: u32, height: u32) -> Result<Paint, ImageDataError> {
+        let len = ImageDataError::check_non_empty(data, width, height)?;
+        Ok(Paint {
+            kind: PaintKind::Pattern {
+                width,
+                height,
+                data: data[..len].to_vec(),
+            },
+        })
+    }
+}
+
+impl Paint {
+    /// The color this paint puts down at canvas pixel (x, y).
+    pub(crate) fn color_at(&self, x: i32, y: i32) -> Rgba {
+        match &self.kind {
+            PaintKind::Solid(color) => *color,
+            PaintKind::Pattern {
+                width,
+                height,
+                data,
+            } => {
+                let px = x.rem_euclid(*width as i32) as usize;
+                let py = y.rem_euclid(*height as i32) as usize;
+                let idx = (py * *width as usize + px) * 4;
+                Rgba::from_slice(&data[idx..idx + 4])
+            }
+            // sampled at the pixel's center
+            PaintKind::Gradient(gradient) => gradient.color_at(x as f32 + 0.5, y as f32 + 0.5),
+        }
+    }
+}
+
+impl From<Rgba> for Paint {
+    fn from(color: Rgba) -> Self {
+        Paint {
+            kind: PaintKind::Solid(color),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::canvas_source::ImageDataError;
+
+    #[test]
+    fn patterns_repeat_in_both_directions() {
+        let data: Vec<u8> = (0..6u8).flat_map(|i| [i, 0, 0, 255]).collect();
+        let paint = Paint::try_pattern(&data, 3, 2).unwrap();
+        assert_eq!(paint.color_at(1, 1).r, 4);
+        assert_eq!(paint.color_at(7, 3).r, 4);
+        assert_eq!(paint.color_at(-1, -1).r, 5);
+    }
+
+    #[test]
+    fn patterns_need_whole_non_empty_images() {
+        assert!(matches!(
+            Paint::try_pattern(&[], 0, 0),
+            Err(ImageDataError::Empty { .. })
+        ));
+        assert!(matches!(
+            Paint::try_pattern(&[0; 12], 2, 2),
+            Err(ImageDataError::Length { actual: 12, .. })
+        ));
+        // extra bytes past the image are ignored
+        assert!(Paint::try_pattern(&[0; 20], 2, 2).is_ok());
+    }
+
+    const BLACK: u32 = 0x000000ff;
+    const WHITE: u32 = 0xffffffff;
+
+    /// Black to white from the center of pixel 0 to the center of pixel
+    /// 10 along the x axis.
+    fn ramp(spread: Spread) -> Paint {
+        let mut paint = Paint::linear_gradient(0.5, 0.0, 10.5, 0.0);
+        paint.add_color_stop(0.0, BLACK);
+        paint.add_color_stop(1.0, WHITE);
+        paint.set_spread(spread);
+        paint
+    }
+
+    fn red_at(paint: &Paint, x: i32, y: i32) -> u8 {
+        paint.color_at(x, y).r
+    }
+
+    #[test]
+    fn linear_gradients_run_between_their_ends() {
+        let paint = ramp(Spread::Pad);
+        assert_eq!(paint.color_at(0, 7).to_array(), [0, 0, 0, 255]);
+        assert_eq!(red_at(&paint, 2, -3), 51);
+        assert_eq!(paint.color_at(10, 0).to_array(), [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn spread_modes_continue_past_the_ends() {
+        let pad = ramp(Spread::Pad);
+        assert_eq!((red_at(&pad, -2, 0), red_at(&pad, 12, 0)), (0, 255));
+        let repeat = ramp(Spread::Repeat);
+        assert_eq!((red_at(&repeat, -2, 0), red_at(&repeat, 12, 0)), (204, 51));
+        let reflect = ramp(Spread::Reflect);
+        assert_eq!(
+            (red_at(&reflect, -2, 0), red_at(&reflect, 12, 0)),
+            (51, 204)
+        );
+    }
+
+    #[test]
+    fn stops_at_the_same_offset_make_a_hard_edge() {
+        let mut paint = Paint::linear_gradient(0.5, 0.0, 10.5, 0.0);
+        paint.add_color_stop(0.5, WHITE);
+        paint.add_color_stop(0.5, BLACK);
+        paint.add_color_stop(0.0, BLACK);
+        paint.add_color_stop(1.0, WHITE);
+        assert_eq!(red_at(&paint, 4, 0), 204);
+        assert_eq!(red_at(&paint, 6, 0), 51);
+    }
+
+    #[test]
+    fn fading_to_transparent_keeps_the_color() {
+        let mut paint = Paint::linear_gradient(0.5, 0.0, 10.5, 0.0);
+        paint.add_color_stop(0.0, 0xff0000ff);
+        paint.add_color_stop(1.0, 0x0000ff00);
+        assert_eq!(paint.color_at(4, 0).to_array(), [255, 0, 0, 153]);
+        assert_eq!(paint.color_at(10, 0).a, 0);
+    }
+
+    #[test]
+    fn radial_gradients_grow_from_the_center() {
+        let mut paint = Paint::radial_gradient(0.5, 0.5, 5.0, 0.5, 0.5);
+        paint.add_color_stop(0.0, BLACK);
+        paint.add_color_stop(1.0, WHITE);
+        assert_eq!(red_at(&paint, 0, 0), 0);
+        assert_eq!(red_at(&paint, 0, 3), 153);
+        assert_eq!(red_at(&paint, 3, 0), 153);
+        assert_eq!(red_at(&paint, 3, 4), 255);
+
+        // a focal point past the edge is pulled back inside
+        let mut focal = Paint::radial_gradient(0.5, 0.5, 5.0, 20.5, 0.5);
+        focal.add_color_stop(0.0, BLACK);
+        focal.add_color_stop(1.0, WHITE);
+        assert!(red_at(&focal, 4, 0) < 30);
+        assert_eq!(red_at(&focal, -5, 0), 255);
+    }
+
+    #[test]
+    fn radii_too_small_or_large_to_square_show_the_last_stop() {
+        for &radius in &[1e-30, 1e30, f32::INFINITY] {
+            let mut paint = Paint::radial_gradient(0.5, 0.5, radius, 0.5, 0.5);
+            paint.add_color_stop(0.0, BLACK);
+            paint.add_color_stop(1.0, WHITE);
+            assert_eq!(red_at(&paint, 0, 0), 255, "{}", radius);
+        }
+    }
+
+    #[test]
+    fn positions_that_arent_numbers_dont_panic() {
+        for &spread in &[Spread::Pad, Spread::Repeat, Spread::Reflect] {
+            let mut paint = Paint::linear_gradient(f32::NAN, 0.0, 10.0, 0.0);
+            paint.add_color_stop(0.0, BLACK);
+            paint.add_color_stop(1.0, WHITE);
+            paint.set_spread(spread);
+            assert_eq!(red_at(&paint, 3, 0), 0);
+
+            // infinity over infinity
+            let mut paint = Paint::linear_gradient(0.0, 0.0, 1e30, 0.0);
+            paint.add_color_stop(0.0, BLACK);
+            paint.add_color_stop(1.0, WHITE);
+            paint.set_spread(spread);
+            assert_eq!(red_at(&paint, i32::MAX, 0), 0);
+        }
+    }
+
+    #[test]
+    fn conic_gradients_sweep_clockwise() {
+        let mut paint = Paint::conic_gradient(0.5, 0.5, 0.0);
+        paint.add_color_stop(0.0, BLACK);
+        paint.add_color_stop(1.0, WHITE);
+        assert_eq!(red_at(&paint, 5, 0), 0);
+        assert_eq!(red_at(&paint, 0, 5), 64);
+        assert_eq!(red_at(&paint, -4, 0), 128);
+        assert_eq!(red_at(&paint, 0, -4), 191);
+    }
+
+    #[test]
+    fn gradients_without_stops_are_transparent() {
+        let paint = Paint::linear_gradient(0.0, 0.0, 1.0, 1.0);
+        assert_eq!(paint.color_at(0, 0), Rgba::TRANSPARENT);
+        let mut solid = Paint::solid(0x12345678);
+        solid.add_color_stop(0.5, WHITE);
+        solid.set_spread(Spread::Repeat);
+        assert_eq!(solid.color_at(9, 9).to_u32(), 0x12345678);
+    }
+}
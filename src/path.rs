@@ -0,0 +1,461 @@
+use wasm_bindgen::prelude::*;
+
+use crate::canvas_source::CanvasSource;
+use crate::paint::Paint;
+use crate::raster::{FillRule, Point};
+use crate::transform::Transform;
+
+// how far the straight segments a curve is flattened into may stray from
+// the true curve, in pixels
+const TOLERANCE: f32 = 0.1;
+
+/// A subpath flattened to straight segments.
+pub(crate) struct Polyline {
+    pub(crate) points: Vec<Point>,
+    pub(crate) closed: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Verb {
+    MoveTo(Point),
+    LineTo(Point),
+    QuadTo(Point, Point),
+    CubicTo(Point, Point, Point),
+    Close,
+}
+
+/// A resolution-independent outline built from lines and Bézier curves,
+/// with the same commands as a Canvas2D path.
+///
+/// A path can hold several subpaths, each starting with `move_to`. When
+/// filled, open subpaths are closed with a straight line back to their
+/// start.
+#[wasm_bindgen]
+#[derive(Clone, Debug, Default)]
+pub struct Path {
+    verbs: Vec<Verb>,
+    // start of the current subpath and the current point, once there is one
+    start: Option<Point>,
+    current: Option<Point>,
+}
+
+#[wasm_bindgen]
+impl Path {
+    pub fn new() -> Path {
+        Path::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.verbs.is_empty()
+    }
+
+    /// Start a new subpath at (x, y).
+    pub fn move_to(&mut self, x: f32, y: f32) {
+        self.verbs.push(Verb::MoveTo((x, y)));
+        self.start = Some((x, y));
+        self.current = Some((x, y));
+    }
+
+    /// A straight line to (x, y). Without a current point this starts a
+    /// subpath there instead.
+    pub fn line_to(&mut self, x: f32, y: f32) {
+        if self.ensure_subpath((x, y)) {
+            self.verbs.push(Verb::LineTo((x, y)));
+            self.current = Some((x, y));
+        }
+    }
+
+    /// A quadratic Bézier curve to (x, y) bending towards (cx, cy).
+    pub fn quad_to(&mut self, cx: f32, cy: f32, x: f32, y: f32) {
+        self.ensure_subpath((cx, cy));
+        self.verbs.push(Verb::QuadTo((cx, cy), (x, y)));
+        self.current = Some((x, y));
+    }
+
+    /// A cubic Bézier curve to (x, y) with control points (c1x, c1y) and
+    /// (c2x, c2y).
+    pub fn cubic_to(&mut self, c1x: f32, c1y: f32, c2x: f32, c2y: f32, x: f32, y: f32) {
+        self.ensure_subpath((c1x, c1y));
+        self.verbs
+            .push(Verb::CubicTo((c1x, c1y), (c2x, c2y), (x, y)));
+        self.current = Some((x, y));
+    }
+
+    /// Join the current subpath back to its start. Drawing carries on from
+    /// that start point.
+    pub fn close(&mut self) {
+        if self.current.is_some() {
+            self.verbs.push(Verb::Close);
+            self.current = self.start;
+        }
+    }
+
+    /// Move every point of the path through `transform`.
+    pub fn transform(&mut self, transform: &Transform) {
+        let map = |p: &mut Point| *p = transform.apply(*p);
+        for verb in &mut self.verbs {
+            match verb {
+                Verb::MoveTo(p) | Verb::LineTo(p) => map(p),
+                Verb::QuadTo(c, p) => {
+                    map(c);
+                    map(p);
+                }
+                Verb::CubicTo(c1, c2, p) => {
+                    map(c1);
+                    map(c2);
+                    map(p);
+                }
+                Verb::Close => {}
+            }
+        }
+        self.start = self.start.map(|p| transform.apply(p));
+        self.current = self.current.map(|p| transform.apply(p));
+    }
+
+    /// Bounding box of the flattened path as [x, y, width, height], or an
+    /// empty array for an empty path.
+    pub fn bounds(&self) -> Vec<f32> {
+        let mut points = self.flatten().into_iter().flat_map(|line| line.points);
+        let first = match points.next() {
+            Some(first) => first,
+            None => return Vec::new(),
+        };
+        let (min, max) = points.fold((first, first), |(min, max), (x, y)| {
+            ((min.0.min(x), min.1.min(y)), (max.0.max(x), max.1.max(y)))
+        });
+        vec![min.0, min.1, max.0 - min.0, max.1 - min.1]
+    }
+}
+
+impl Path {
+    /// A path of closed polygons.
+    pub(crate) fn from_contours(contours: &[Vec<Point>]) -> Path {
+        let mut path = Path::new();
+        for contour in contours.iter().filter(|contour| !contour.is_empty()) {
+            path.move_to(contour[0].0, contour[0].1);
+            for &(x, y) in &contour[1..] {
+                path.line_to(x, y);
+            }
+            path.close();
+        }
+        path
+    }
+
+    /// Make sure there's a current point, starting a subpath at `at` if
+    /// not. Returns whether there already was one.
+    fn ensure_subpath(&mut self, at: Point) -> bool {
+        if self.current.is_some() {
+            return true;
+        }
+        self.move_to(at.0, at.1);
+        false
+    }
+
+    /// Every subpath as straight segments, with curves split finely enough
+    /// to stay within a tenth of a pixel of the real thing.
+    pub(crate) fn flatten(&self) -> Vec<Polyline> {
+        let mut lines: Vec<Polyline> = Vec::new();
+        let mut current: Vec<Point> = Vec::new();
+        let mut last = (0.0, 0.0);
+
+        for &verb in &self.verbs {
+            match verb {
+                Verb::MoveTo(p) => {
+                    let done = std::mem::replace(&mut current, vec![p]);
+                    if done.len() > 1 {
+                        lines.push(Polyline {
+                            points: done,
+                            closed: false,
+                        });
+                    }
+                    last = p;
+                }
+                Verb::LineTo(p) => {
+                    current.push(p);
+                    last = p;
+                }
+                Verb::QuadTo(c, p) => {
+                    // Wang's formula: the second derivative is twice the
+                    // control polygon's second difference
+                    let dd = length(add(sub(last, c), sub(p, c)));
+                    let steps = ((dd / (4.0 * TOLERANCE)).sqrt().ceil() as usize).clamp(1, 1000);
+                    for i in 1..=steps {
+                        let t = i as f32 / steps as f32;
+                        let u = 1.0 - t;
+                        current.push((
+                            u * u * last.0 + 2.0 * u * t * c.0 + t * t * p.0,
+                            u * u * last.1 + 2.0 * u * t * c.1 + t * t * p.1,
+                        ));
+                    }
+                    last = p;
+                }
+                Verb::CubicTo(c1, c2, p) => {
+                    // Wang's formula, from the largest second difference of
+                    // the control polygon
+                    let dd = length(add(sub(last, c1), sub(c2, c1)))
+                        .max(length(add(sub(c1, c2), sub(p, c2))));
+                    let steps = ((0.75 * dd / TOLERANCE).sqrt().ceil() as usize).clamp(1, 1000);
+                    for i in 1..=steps {
+                        let t = i as f32 / steps as f32;
+                        let u = 1.0 - t;
+                        let (a, b, c, d) = (u * u * u, 3.0 * u * u * t, 3.0 * u * t * t, t * t * t);
+                        current.push((
+                            a * last.0 + b * c1.0 + c * c2.0 + d * p.0,
+                            a * last.1 + b * c1.1 + c * c2.1 + d * p.1,
+                        ));
+                    }
+                    last = p;
+                }
+                Verb::Close => {
+                    let start = current.first().copied().unwrap_or(last);
+                    let done = std::mem::replace(&mut current, vec![start]);
+                    if done.len() > 1 {
+                        lines.push(Polyline {
+                            points: done,
+                            closed: true,
+                        });
+                    }
+                    last = start;
+                }
+            }
+        }
+        // a lone point left over from a close or move_to draws nothing
+        if current.len() > 1 {
+            lines.push(Polyline {
+                points: current,
+                closed: false,
+            });
+        }
+        lines
+    }
+}
+
+fn add(a: Point, b: Point) -> Point {
+    (a.0 + b.0, a.1 + b.1)
+}
+
+fn sub(a: Point, b: Point) -> Point {
+    (a.0 - b.0, a.1 - b.1)
+}
+
+fn length(v: Point) -> f32 {
+    (v.0 * v.0 + v.1 * v.1).sqrt()
+}
+
+#[wasm_bindgen]
+impl CanvasSource {
+    /// Fill the inside of `path`, anti-aliased by exactly how much of each
+    /// pixel it covers. Coordinates are continuous, as with
+    /// `Rasterization::AntiAliased`.
+    pub fn fill_path(&mut self, path: &Path, color: u32, rule: FillRule) {
+        self.fill_path_paint(path, &Paint::solid(color), rule);
+    }
+
+    /// `fill_path` with a gradient, pattern or any other paint.
+    pub fn fill_path_paint(&mut self, path: &Path, paint: &Paint, rule: FillRule) {
+        let contours: Vec<Vec<Point>> =
+            path.flatten().into_iter().map(|line| line.points).collect();
+        self.edit(|canvas| canvas.fill_contours(&contours, rule, paint))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: u32 = 0xff0000ff;
+
+    fn canvas(width: u32, height: u32) -> CanvasSource {
+        CanvasSource::try_new(width, height, Vec::new()).unwrap()
+    }
+
+    fn alpha(canvas: &CanvasSource, x: i32, y: i32) -> u8 {
+        canvas.pixel(x, y).unwrap().a
+    }
+
+    /// Distance from `p` to the nearest segment of `points`.
+    fn distance_to(points: &[Point], p: Point) -> f32 {
+        points
+            .windows(2)
+            .map(|w| {
+                let (a, b) = (w[0], w[1]);
+                let ab = sub(b, a);
+                let len2 = ab.0 * ab.0 + ab.1 * ab.1;
+                let t = if len2 == 0.0 {
+                    0.0
+                } else {
+                    (((p.0 - a.0) * ab.0 + (p.1 - a.1) * ab.1) / len2).clamp(0.0, 1.0)
+                };
+                length(sub(p, (a.0 + t * ab.0, a.1 + t * ab.1)))
+            })
+            .fold(f32::INFINITY, f32::min)
+    }
+
+    /// The largest gap between the true curve, sampled finely, and its
+    /// flattened polyline.
+    fn deviation(path: &Path, curve: impl Fn(f32) -> Point) -> f32 {
+        let lines = path.flatten();
+        assert_eq!(lines.len(), 1);
+        let points = &lines[0].points;
+        assert_eq!(points[0], curve(0.0));
+        assert_eq!(*points.last().unwrap(), curve(1.0));
+        (0..=2000)
+            .map(|i| distance_to(points, curve(i as f32 / 2000.0)))
+            .fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn curves_stay_within_tolerance() {
+        let mut quad = Path::new();
+        quad.move_to(0.0, 0.0);
+        quad.quad_to(50.0, 100.0, 100.0, 0.0);
+        let error = deviation(&quad, |t| {
+            let u = 1.0 - t;
+            (2.0 * u * t * 50.0 + t * t * 100.0, 2.0 * u * t * 100.0)
+        });
+        assert!(error <= TOLERANCE + 1e-3, "quad is {} off", error);
+
+        let mut cubic = Path::new();
+        cubic.move_to(0.0, 0.0);
+        cubic.cubic_to(0.0, 80.0, 100.0, -40.0, 100.0, 40.0);
+        let error = deviation(&cubic, |t| {
+            let u = 1.0 - t;
+            (
+                3.0 * u * t * t * 100.0 + t * t * t * 100.0,
+                3.0 * u * u * t * 80.0 - 3.0 * u * t * t * 40.0 + t * t * t * 40.0,
+            )
+        });
+        assert!(error <= TOLERANCE + 1e-3, "cubic is {} off", error);
+    }
+
+    #[test]
+    fn flat_curves_are_single_segments() {
+        let mut path = Path::new();
+        path.move_to(0.0, 0.0);
+        path.quad_to(5.0, 0.0, 10.0, 0.0);
+        path.cubic_to(20.0, 0.0, 30.0, 0.0, 40.0, 0.0);
+        assert_eq!(
+            path.flatten()[0].points,
+            vec![(0.0, 0.0), (10.0, 0.0), (40.0, 0.0)]
+        );
+    }
+
+    #[test]
+    fn subpaths_are_split_and_closed() {
+        let mut path = Path::new();
+        path.move_to(0.0, 0.0);
+        path.line_to(4.0, 0.0);
+        path.line_to(4.0, 4.0);
+        path.close();
+        // carries on from the start of the closed subpath
+        path.line_to(0.0, 4.0);
+        path.move_to(9.0, 9.0);
+        path.move_to(6.0, 0.0);
+        path.line_to(8.0, 0.0);
+
+        let lines = path.flatten();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0].points, vec![(0.0, 0.0), (4.0, 0.0), (4.0, 4.0)]);
+        assert!(lines[0].closed);
+        assert_eq!(lines[1].points, vec![(0.0, 0.0), (0.0, 4.0)]);
+        assert!(!lines[1].closed);
+        // the lone move_to to (9, 9) draws nothing
+        assert_eq!(lines[2].points, vec![(6.0, 0.0), (8.0, 0.0)]);
+        assert!(!lines[2].closed);
+    }
+
+    #[test]
+    fn drawing_without_a_current_point_starts_a_subpath() {
+        let mut path = Path::new();
+        path.close();
+        assert!(path.is_empty());
+        path.line_to(3.0, 3.0);
+        path.line_to(5.0, 3.0);
+        assert_eq!(path.flatten()[0].points, vec![(3.0, 3.0), (5.0, 3.0)]);
+
+        let mut curve = Path::new();
+        curve.quad_to(2.0, 2.0, 4.0, 0.0);
+        assert_eq!(curve.flatten()[0].points[0], (2.0, 2.0));
+    }
+
+    #[test]
+    fn bounds_cover_the_flattened_curve() {
+        assert!(Path::new().bounds().is_empty());
+
+        let mut path = Path::new();
+        path.move_to(0.0, 0.0);
+        path.quad_to(5.0, 10.0, 10.0, 0.0);
+        let bounds = path.bounds();
+        assert_eq!(bounds[..3], [0.0, 0.0, 10.0]);
+        // the curve only reaches halfway to its control point
+        assert!((bounds[3] - 5.0).abs() < 0.1, "{:?}", bounds);
+    }
+
+    #[test]
+    fn transform_moves_every_point() {
+        let mut path = Path::new();
+        path.move_to(1.0, 1.0);
+        path.quad_to(2.0, 3.0, 3.0, 1.0);
+        path.close();
+        let mut transform = Transform::identity();
+        transform.translate(10.0, 20.0);
+        transform.scale(2.0, 2.0);
+        path.transform(&transform);
+        // the current point moved too
+        path.line_to(30.0, 30.0);
+
+        let bounds = path.bounds();
+        assert_eq!(bounds, vec![12.0, 22.0, 18.0, 8.0]);
+    }
+
+    #[test]
+    fn fill_rules_decide_holes() {
+        let mut squares = Path::new();
+        for &(x, size) in &[(0.0, 8.0), (2.0, 4.0)] {
+            squares.move_to(x, x);
+            squares.line_to(x + size, x);
+            squares.line_to(x + size, x + size);
+            squares.line_to(x, x + size);
+            squares.close();
+        }
+
+        let mut nonzero = canvas(8, 8);
+        nonzero.fill_path(&squares, RED, FillRule::NonZero);
+        assert_eq!(alpha(&nonzero, 1, 1), 255);
+        assert_eq!(alpha(&nonzero, 4, 4), 255);
+
+        let mut evenodd = canvas(8, 8);
+        evenodd.fill_path(&squares, RED, FillRule::EvenOdd);
+        assert_eq!(alpha(&evenodd, 1, 1), 255);
+        assert_eq!(alpha(&evenodd, 4, 4), 0);
+    }
+
+    #[test]
+    fn open_paths_fill_as_if_closed() {
+        let mut triangle = Path::new();
+        triangle.move_to(0.0, 0.0);
+        triangle.line_to(2.0, 0.0);
+        triangle.line_to(0.0, 2.0);
+
+        let mut canvas = canvas(2, 2);
+        canvas.fill_path(&triangle, RED, FillRule::NonZero);
+        let alphas: Vec<u8> = canvas.data.chunks(4).map(|p| p[3]).collect();
+        assert_eq!(alphas, vec![255, 128, 128, 0]);
+        assert_eq!(canvas.pixel(0, 0).unwrap().to_u32(), RED);
+    }
+
+    #[test]
+    fn a_fill_is_one_undo_step() {
+        let mut path = Path::new();
+        path.move_to(0.5, 0.5);
+        path.cubic_to(6.0, 0.0, 8.0, 8.0, 1.0, 7.0);
+
+        let mut canvas = canvas(8, 8);
+        let before = canvas.data.clone();
+        canvas.fill_path(&path, RED, FillRule::NonZero);
+        assert_ne!(canvas.data, before);
+        assert!(canvas.undo());
+        assert_eq!(canvas.data, before);
+        assert!(!canvas.can_undo());
+    }
+}
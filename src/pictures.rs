@@ -0,0 +1,58 @@
+// pictures of canvases for tests, one string per row and one character per
+// pixel, so expected images can be written out in the test itself
+
+use crate::canvas_source::CanvasSource;
+
+/// A canvas drawn from a picture, with `pixel` giving each character's RGBA
+/// color.
+pub fn canvas_from(rows: &[&str], pixel: impl Fn(char) -> [u8; 4]) -> CanvasSource {
+    let data = rows
+        .iter()
+        .flat_map(|row| row.chars())
+        .flat_map(pixel)
+        .collect();
+    CanvasSource::try_new(rows[0].len() as u32, rows.len() as u32, data).unwrap()
+}
+
+/// The canvas as a picture, with `symbol` picking the character for each
+/// RGBA pixel.
+pub fn picture_with(canvas: &CanvasSource, symbol: impl Fn(&[u8]) -> char) -> Vec<String> {
+    canvas
+        .data
+        .chunks_exact(canvas.width as usize * 4)
+        .map(|row| row.chunks_exact(4).map(&symbol).collect())
+        .collect()
+}
+
+/// '#' for opaque pixels, '+' for translucent ones and '.' for transparent
+/// ones.
+pub fn coverage(canvas: &CanvasSource) -> Vec<String> {
+    picture_with(canvas, |p| match p[3] {
+        255 => '#',
+        0 => '.',
+        _ => '+',
+    })
+}
+
+/// '#' for every pixel painted at all, '.' for untouched ones.
+pub fn painted(canvas: &CanvasSource) -> Vec<String> {
+    picture_with(canvas, |p| if p[3] == 0 { '.' } else { '#' })
+}
+
+/// A canvas from a picture of digits, each an opaque pixel with a red of ten
+/// times the digit, and '.' for transparent pixels.
+pub fn digits(rows: &[&str]) -> CanvasSource {
+    canvas_from(rows, |c| match c.to_digit(10) {
+        Some(d) => [d as u8 * 10, 0, 0, 255],
+        None => [0; 4],
+    })
+}
+
+/// The reverse of `digits`, with '?' for anything it can't draw.
+pub fn digit_picture(canvas: &CanvasSource) -> Vec<String> {
+    picture_with(canvas, |p| match *p {
+        [_, _, _, 0] => '.',
+        [r, 0, 0, 255] if r % 10 == 0 && r < 100 => (b'0' + r / 10) as char,
+        _ => '?',
+    })
+}
@@ -0,0 +1,274 @@
+use std::fmt;
+
+use wasm_bindgen::prelude::*;
+
+use crate::canvas_source::{buffer_len, CanvasSource, SizeError};
+
+#[derive(Debug)]
+pub enum PngError {
+    /// The bytes aren't a PNG we can read: bad signature, corrupt chunks,
+    /// truncated data and so on.
+    Decoding(png::DecodingError),
+    /// An image too large for a canvas.
+    TooLarge(SizeError),
+    Encoding(png::EncodingError),
+    Io(std::io::Error),
+}
+
+impl fmt::Display for PngError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PngError::Decoding(err) => write!(f, "invalid or corrupt PNG: {}", err),
+            PngError::TooLarge(err) => write!(f, "PNG too large: {}", err),
+            PngError::Encoding(err) => write!(f, "couldn't encode PNG: {}", err),
+            PngError::Io(err) => write!(f, "PNG I/O error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for PngError {}
+
+impl From<png::DecodingError> for PngError {
+    fn from(err: png::DecodingError) -> Self {
+        PngError::Decoding(err)
+    }
+}
+
+impl From<png::EncodingError> for PngError {
+    fn from(err: png::EncodingError) -> Self {
+        PngError::Encoding(err)
+    }
+}
+
+impl From<std::io::Error> for PngError {
+    fn from(err: std::io::Error) -> Self {
+        PngError::Io(err)
+    }
+}
+
+#[wasm_bindgen]
+impl CanvasSource {
+    /// Decode a PNG file into a new canvas the size of the image. Throws
+    /// if the bytes aren't a readable PNG or the image is too large for a
+    /// canvas.
+    pub fn from_png(bytes: &[u8]) -> Result<CanvasSource, JsError> {
+        Ok(CanvasSource::decode_png(bytes)?)
+    }
+
+    /// Encode the canvas as an 8-bit RGBA PNG file. Throws for an empty
+    /// (zero width or height) canvas, which PNG can't represent.
+    pub fn to_png(&self) -> Result<Vec<u8>, JsError> {
+        Ok(self.encode_png()?)
+    }
+}
+
+// removed #[wasm_bindgen] - the Rust-side API for the native binary
+impl CanvasSource {
+    /// Decode 8-bit (or lower) grayscale, grayscale + alpha, RGB, RGBA and
+    /// palette PNGs. 16-bit images are reduced to 8 bits per channel.
+    pub fn decode_png(bytes: &[u8]) -> Result<CanvasSource, PngError> {
+        let mut decoder = png::Decoder::new(bytes);
+        // expand palettes, low bit depths and tRNS chunks to plain 8-bit
+        // channels so there are only four layouts to convert below
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info()?;
+        let (width, height) = reader.info().size();
+        if buffer_len(width, height).is_none() {
+            return Err(PngError::TooLarge(SizeError { width, height }));
+        }
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let frame = reader.next_frame(&mut buffer)?;
+        let pixels = &buffer[..frame.buffer_size()];
+
+        let data: Vec<u8> = match frame.color_type {
+            png::ColorType::Rgba => pixels.to_vec(),
+            png::ColorType::Rgb => pixels
+                .chunks_exact(3)
+                .flat_map(|p| [p[0], p[1], p[2], 255])
+                .collect(),
+            png::ColorType::GrayscaleAlpha => pixels
+                .chunks_exact(2)
+                .flat_map(|p| [p[0], p[0], p[0], p[1]])
+                .collect(),
+            png::ColorType::Grayscale => pixels.iter().flat_map(|&v| [v, v, v, 255]).collect(),
+            // normalize_to_color8 always expands palettes
+            png::ColorType::Indexed => unreachable!("palette PNGs are expanded while decoding"),
+        };
+
+        Ok(CanvasSource::from_parts(frame.width, frame.height, data))
+    }
+
+    pub fn encode_png(&self) -> Result<Vec<u8>, PngError> {
+        let mut bytes = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut bytes, self.width, self.height);
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder.write_header()?;
+            writer.write_image_data(&self.data)?;
+            writer.finish()?;
+        }
+        Ok(bytes)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn open_png<P: AsRef<std::path::Path>>(path: P) -> Result<CanvasSource, PngError> {
+        CanvasSource::decode_png(&std::fs::read(path)?)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save_png<P: AsRef<std::path::Path>>(&self, path: P) -> Result<(), PngError> {
+        Ok(std::fs::write(path, self.encode_png()?)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A PNG written straight with the png crate, for layouts `encode_png`
+    /// never produces.
+    fn png_of(
+        width: u32,
+        height: u32,
+        color: png::ColorType,
+        depth: png::BitDepth,
+        configure: impl FnOnce(&mut png::Encoder<&mut Vec<u8>>),
+        data: &[u8],
+    ) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut bytes, width, height);
+            encoder.set_color(color);
+            encoder.set_depth(depth);
+            configure(&mut encoder);
+            let mut writer = encoder.write_header().unwrap();
+            writer.write_image_data(data).unwrap();
+        }
+        bytes
+    }
+
+    #[test]
+    fn round_trip_keeps_every_byte() {
+        // every alpha level, with colors that would be lost if anything
+        // premultiplied along the way
+        let data: Vec<u8> = (0..=255u8).flat_map(|a| [a, 255 - a, 7, a]).collect();
+        let canvas = CanvasSource::try_new(16, 16, data.clone()).unwrap();
+        let decoded = CanvasSource::decode_png(&canvas.encode_png().unwrap()).unwrap();
+        assert_eq!((decoded.width, decoded.height), (16, 16));
+        assert_eq!(decoded.data, data);
+    }
+
+    #[test]
+    fn grayscale_and_rgb_are_expanded() {
+        let gray = png_of(
+            2,
+            1,
+            png::ColorType::Grayscale,
+            png::BitDepth::Eight,
+            |_| {},
+            &[10, 200],
+        );
+        assert_eq!(
+            CanvasSource::decode_png(&gray).unwrap().data,
+            [10, 10, 10, 255, 200, 200, 200, 255]
+        );
+
+        let gray_alpha = png_of(
+            1,
+            1,
+            png::ColorType::GrayscaleAlpha,
+            png::BitDepth::Eight,
+            |_| {},
+            &[60, 70],
+        );
+        assert_eq!(
+            CanvasSource::decode_png(&gray_alpha).unwrap().data,
+            [60, 60, 60, 70]
+        );
+
+        let rgb = png_of(
+            1,
+            1,
+            png::ColorType::Rgb,
+            png::BitDepth::Eight,
+            |_| {},
+            &[1, 2, 3],
+        );
+        assert_eq!(CanvasSource::decode_png(&rgb).unwrap().data, [1, 2, 3, 255]);
+    }
+
+    #[test]
+    fn palettes_and_transparency_are_expanded() {
+        // 2-bit indices 0, 1, 2 packed into one byte
+        let indexed = png_of(
+            3,
+            1,
+            png::ColorType::Indexed,
+            png::BitDepth::Two,
+            |encoder| {
+                encoder.set_palette(vec![1, 2, 3, 4, 5, 6, 7, 8, 9]);
+                encoder.set_trns(vec![255, 0]);
+            },
+            &[0b00_01_10_00],
+        );
+        assert_eq!(
+            CanvasSource::decode_png(&indexed).unwrap().data,
+            [1, 2, 3, 255, 4, 5, 6, 0, 7, 8, 9, 255]
+        );
+    }
+
+    #[test]
+    fn sixteen_bit_channels_keep_the_high_byte() {
+        let deep = png_of(
+            1,
+            1,
+            png::ColorType::Rgba,
+            png::BitDepth::Sixteen,
+            |_| {},
+            &[0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0xff, 0xff],
+        );
+        assert_eq!(
+            CanvasSource::decode_png(&deep).unwrap().data,
+            [0x12, 0x56, 0x9a, 0xff]
+        );
+    }
+
+    #[test]
+    fn bad_bytes_are_errors() {
+        assert!(matches!(
+            CanvasSource::decode_png(b"not a png"),
+            Err(PngError::Decoding(_))
+        ));
+        let bytes = CanvasSource::try_new(4, 4, vec![9; 64])
+            .unwrap()
+            .encode_png()
+            .unwrap();
+        assert!(CanvasSource::decode_png(&bytes[..bytes.len() / 2]).is_err());
+    }
+
+    #[test]
+    fn empty_canvases_cant_be_encoded() {
+        let empty = CanvasSource::try_new(0, 3, Vec::new()).unwrap();
+        assert!(matches!(empty.encode_png(), Err(PngError::Encoding(_))));
+    }
+
+    #[test]
+    fn images_too_large_for_a_canvas_are_refused_before_decoding() {
+        // just the header and an empty data chunk, claiming 10^10 pixels
+        let mut bytes = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut bytes, 100_000, 100_000);
+            encoder.set_depth(png::BitDepth::One);
+            let mut writer = encoder.write_header().unwrap();
+            writer.write_chunk(png::chunk::IDAT, &[]).unwrap();
+        }
+        assert!(matches!(
+            CanvasSource::decode_png(&bytes),
+            Err(PngError::TooLarge(SizeError {
+                width: 100_000,
+                height: 100_000
+            }))
+        ));
+    }
+}
@@ -0,0 +1,644 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use wasm_bindgen::prelude::*;
+
+use crate::canvas_source::CanvasSource;
+use crate::color::Rgba;
+
+/// How `generate_palette` picks its colors.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QuantizeMethod {
+    /// Repeatedly split the box of colors with the widest spread at its
+    /// median. Good all-round results.
+    MedianCut = 0,
+    /// Bucket colors in an octree and merge the rarest branches. Faster on
+    /// photos with lots of distinct colors.
+    Octree = 1,
+}
+
+/// How colors that fall between palette entries are approximated.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dither {
+    /// Plain nearest color, which gives flat bands.
+    None = 0,
+    FloydSteinberg = 1,
+    /// Diffuses only 3/4 of the error, for the crisper, higher contrast
+    /// look of early Macintosh graphics.
+    Atkinson = 2,
+    /// Ordered dithering with an 8x8 Bayer matrix: a regular cross-hatch
+    /// pattern that stays stable between animation frames.
+    Bayer = 3,
+}
+
+// the largest palette an 8-bit index (and a GIF) can address
+const MAX_COLORS: usize = 256;
+
+// octree depth; 6 bits per channel is plenty to tell colors apart while
+// keeping the tree small
+const OCTREE_DEPTH: usize = 6;
+
+const BAYER_8X8: [[u8; 8]; 8] = [
+    [0, 32, 8, 40, 2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44, 4, 36, 14, 46, 6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [3, 35, 11, 43, 1, 33, 9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47, 7, 39, 13, 45, 5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+/// A palette with more colors than fit in a byte.
+#[derive(Debug)]
+pub struct PaletteError {
+    pub colors: usize,
+}
+
+impl fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "a palette holds at most {} colors, got {}",
+            MAX_COLORS, self.colors
+        )
+    }
+}
+
+impl std::error::Error for PaletteError {}
+
+/// Up to 256 colors to reduce an image to.
+#[wasm_bindgen]
+#[derive(Clone, Debug, Default)]
+pub struct Palette {
+    colors: Vec<Rgba>,
+}
+
+#[wasm_bindgen]
+impl Palette {
+    /// A fixed palette from `0xRRGGBBAA` colors. Throws if there are more
+    /// than 256.
+    pub fn new(colors: &[u32]) -> Result<Palette, JsError> {
+        Ok(Palette::try_new(colors)?)
+    }
+
+    pub fn len(&self) -> usize {
+        self.colors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.colors.is_empty()
+    }
+
+    /// The palette as `0xRRGGBBAA` colors.
+    pub fn to_u32s(&self) -> Vec<u32> {
+        self.colors.iter().map(|c| c.to_u32()).collect()
+    }
+}
+
+// removed #[wasm_bindgen] - the Rust-side API for the native binary
+impl Palette {
+    pub fn try_new(colors: &[u32]) -> Result<Palette, PaletteError> {
+        if colors.len() > MAX_COLORS {
+            return Err(PaletteError {
+                colors: colors.len(),
+            });
+        }
+        Ok(Palette {
+            colors: colors.iter().map(|&c| Rgba::from_u32(c)).collect(),
+        })
+    }
+}
+
+impl Palette {
+    pub fn colors(&self) -> &[Rgba] {
+        &self.colors
+    }
+
+    /// Pick up to `max_colors` (at most 256) colors that best represent an
+    /// RGBA image. Fully transparent pixels are ignored.
+    pub(crate) fn generate(data: &[u8], max_colors: usize, method: QuantizeMethod) -> Palette {
+        let max_colors = max_colors.clamp(1, MAX_COLORS);
+        let colors = histogram(data);
+        if colors.len() <= max_colors {
+            return Palette {
+                colors: colors.into_iter().map(|(color, _)| color).collect(),
+            };
+        }
+        let colors = match method {
+            QuantizeMethod::MedianCut => median_cut(colors, max_colors),
+            QuantizeMethod::Octree => octree(&colors, max_colors),
+        };
+        Palette { colors }
+    }
+
+    /// Index of the palette entry closest to `color`.
+    pub fn nearest(&self, color: Rgba) -> u8 {
+        let mut best = (0, f32::MAX);
+        for (i, &entry) in self.colors.iter().enumerate() {
+            let distance = entry.distance(color);
+            if distance < best.1 {
+                best = (i, distance);
+            }
+        }
+        best.0 as u8
+    }
+
+    /// The palette index for every pixel of an RGBA image `width` pixels
+    /// wide, in row order, `None` for fully transparent ones. The palette
+    /// must not be empty.
+    pub(crate) fn map_pixels(&self, data: &[u8], width: u32, dither: Dither) -> Vec<Option<u8>> {
+        let width = width as usize;
+        let height = (data.len() / 4).checked_div(width).unwrap_or(0);
+        let palette = self;
+        let mut indices = vec![None; width * height];
+        // error diffusion spreads over at most the next two rows
+        let mut errors = vec![[0.0f32; 3]; width * 3];
+        // how far ordered dithering nudges a color: about the gap between
+        // neighboring palette entries
+        let spread = 255.0 / (palette.len() as f32).cbrt();
+
+        for y in 0..height {
+            for x in 0..width {
+                let idx = (y * width + x) * 4;
+                let pixel = &data[idx..idx + 4];
+                if pixel[3] == 0 {
+                    continue;
+                }
+
+                let offset = match dither {
+                    Dither::FloydSteinberg | Dither::Atkinson => errors[x],
+                    Dither::Bayer => {
+                        let t = (BAYER_8X8[y % 8][x % 8] as f32 + 0.5) / 64.0 - 0.5;
+                        [t * spread; 3]
+                    }
+                    Dither::None => [0.0; 3],
+                };
+                let wanted = [
+                    pixel[0] as f32 + offset[0],
+                    pixel[1] as f32 + offset[1],
+                    pixel[2] as f32 + offset[2],
+                ];
+                let channel = |v: f32| v.round().clamp(0.0, 255.0) as u8;
+                let index = palette.nearest(Rgba::new(
+                    channel(wanted[0]),
+                    channel(wanted[1]),
+                    channel(wanted[2]),
+                    pixel[3],
+                ));
+                indices[y * width + x] = Some(index);
+
+                let chosen = palette.colors[index as usize].to_array();
+                let error = [
+                    wanted[0] - chosen[0] as f32,
+                    wanted[1] - chosen[1] as f32,
+                    wanted[2] - chosen[2] as f32,
+                ];
+                let taps: &[(isize, usize, f32)] = match dither {
+                    Dither::FloydSteinberg => &[
+                        (1, 0, 7.0 / 16.0),
+                        (-1, 1, 3.0 / 16.0),
+                        (0, 1, 5.0 / 16.0),
+                        (1, 1, 1.0 / 16.0),
+                    ],
+                    Dither::Atkinson => &[
+                        (1, 0, 1.0 / 8.0),
+                        (2, 0, 1.0 / 8.0),
+                        (-1, 1, 1.0 / 8.0),
+                        (0, 1, 1.0 / 8.0),
+                        (1, 1, 1.0 / 8.0),
+                        (0, 2, 1.0 / 8.0),
+                    ],
+                    Dither::None | Dither::Bayer => &[],
+                };
+                for &(dx, dy, weight) in taps {
+                    let tx = x as isize + dx;
+                    if tx < 0 || tx as usize >= width {
+                        continue;
+                    }
+                    let cell = &mut errors[dy * width + tx as usize];
+                    (0..3).for_each(|c| cell[c] += error[c] * weight);
+                }
+            }
+            // move the error rows up one for the next image row
+            errors.copy_within(width.., 0);
+            errors[width * 2..].iter_mut().for_each(|e| *e = [0.0; 3]);
+        }
+        indices
+    }
+}
+
+/// Every distinct visible color with how many pixels use it.
+fn histogram(data: &[u8]) -> Vec<(Rgba, u32)> {
+    let mut counts: HashMap<u32, u32> = HashMap::new();
+    for pixel in data.chunks_exact(4).filter(|p| p[3] > 0) {
+        *counts.entry(Rgba::from_slice(pixel).to_u32()).or_insert(0) += 1;
+    }
+    // sorted so the same image always gives the same palette
+    let mut counts: Vec<(u32, u32)> = counts.into_iter().collect();
+    counts.sort_unstable();
+    counts
+        .into_iter()
+        .map(|(color, count)| (Rgba::from_u32(color), count))
+        .collect()
+}
+
+/// Count-weighted mean of a set of colors.
+fn mean(colors: &[(Rgba, u32)]) -> Rgba {
+    let mut sum = [0u64; 4];
+    let mut total = 0u64;
+    for &(color, count) in colors {
+        for (s, c) in sum.iter_mut().zip(color.to_array().iter()) {
+            *s += *c as u64 * count as u64;
+        }
+        total += count as u64;
+    }
+    let channel = |s: u64| ((s + total / 2) / total.max(1)) as u8;
+    Rgba::new(
+        channel(sum[0]),
+        channel(sum[1]),
+        channel(sum[2]),
+        channel(sum[3]),
+    )
+}
+
+/// A median cut box: a range of colors and its widest channel.
+struct ColorBox {
+    start: usize,
+    end: usize,
+    channel: usize,
+    spread: u8,
+}
+
+impl ColorBox {
+    fn new(colors: &[(Rgba, u32)], start: usize, end: usize) -> ColorBox {
+        let mut min = [255u8; 4];
+        let mut max = [0u8; 4];
+        for (color, _) in &colors[start..end] {
+            for (c, &value) in color.to_array().iter().enumerate() {
+                min[c] = min[c].min(value);
+                max[c] = max[c].max(value);
+            }
+        }
+        let (channel, spread) = (0..4)
+            .map(|c| (c, max[c] - min[c]))
+            .max_by_key(|&(_, spread)| spread)
+            .unwrap();
+        ColorBox {
+            start,
+            end,
+            channel,
+            spread,
+        }
+    }
+}
+
+fn median_cut(mut colors: Vec<(Rgba, u32)>, max_colors: usize) -> Vec<Rgba> {
+    let mut boxes = vec![ColorBox::new(&colors, 0, colors.len())];
+    while boxes.len() < max_colors {
+        // the box with the widest single-channel spread gets split next
+        let (index, widest) = boxes
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, b)| b.spread)
+            .unwrap();
+        // a box of one color has no spread, so this also stops once every
+        // color has a box of its own
+        if widest.spread == 0 {
+            break;
+        }
+
+        let (start, end, channel) = (widest.start, widest.end, widest.channel);
+        let slice = &mut colors[start..end];
+        slice.sort_unstable_by_key(|e| e.0.to_array()[channel]);
+        // split where half the pixels (not half the colors) are on each side
+        let total: u64 = slice.iter().map(|e| e.1 as u64).sum();
+        let mut running = 0;
+        let mut split = 1;
+        for (i, entry) in slice.iter().enumerate() {
+            running += entry.1 as u64;
+            if running * 2 >= total {
+                split = (i + 1).clamp(1, slice.len() - 1);
+                break;
+            }
+        }
+        boxes[index] = ColorBox::new(&colors, start, start + split);
+        boxes.push(ColorBox::new(&colors, start + split, end));
+    }
+
+    boxes
+        .into_iter()
+        .map(|b| mean(&colors[b.start..b.end]))
+        .collect()
+}
+
+#[derive(Default)]
+struct OctreeNode {
+    children: [Option<usize>; 8],
+    sum: [u64; 4],
+    count: u64,
+    leaf: bool,
+}
+
+fn octree(colors: &[(Rgba, u32)], max_colors: usize) -> Vec<Rgba> {
+    let mut nodes = vec![OctreeNode::default()];
+    // nodes with children at each depth, the candidates for merging
+    let mut levels: Vec<Vec<usize>> = vec![Vec::new(); OCTREE_DEPTH];
+    let mut leaves = 0;
+
+    for &(color, count) in colors {
+        let [r, g, b, a] = color.to_array();
+        let mut node = 0;
+        for (depth, level) in levels.iter_mut().enumerate() {
+            let shift = 7 - depth;
+            let child =
+                (((r >> shift) & 1) << 2 | ((g >> shift) & 1) << 1 | ((b >> shift) & 1)) as usize;
+            node = match nodes[node].children[child] {
+                Some(next) => next,
+                None => {
+                    if nodes[node].children.iter().all(Option::is_none) {
+                        level.push(node);
+                    }
+                    nodes.push(OctreeNode::default());
+                    let next = nodes.len() - 1;
+                    nodes[node].children[child] = Some(next);
+                    next
+                }
+            };
+        }
+        let leaf = &mut nodes[node];
+        if !leaf.leaf {
+            leaf.leaf = true;
+            leaves += 1;
+        }
+        for (s, c) in leaf.sum.iter_mut().zip([r, g, b, a].iter()) {
+            *s += *c as u64 * count as u64;
+        }
+        leaf.count += count as u64;
+    }
+
+    // fold the least used deepest branches into their parents until few
+    // enough leaves are left. The root is never folded, which would leave
+    // a single color
+    for depth in (1..OCTREE_DEPTH).rev() {
+        let mut candidates = std::mem::take(&mut levels[depth]);
+        candidates.sort_by_key(|&n| std::cmp::Reverse(subtree_count(&nodes, n)));
+        while leaves > max_colors {
+            let node = match candidates.pop() {
+                Some(node) => node,
+                None => break,
+            };
+            let children: Vec<usize> = nodes[node].children.iter().flatten().copied().collect();
+            for child in children {
+                let (sum, count) = (nodes[child].sum, nodes[child].count);
+                let parent = &mut nodes[node];
+                parent
+                    .sum
+                    .iter_mut()
+                    .zip(sum.iter())
+                    .for_each(|(p, c)| *p += c);
+                parent.count += count;
+                leaves -= 1;
+            }
+            nodes[node].children = [None; 8];
+            nodes[node].leaf = true;
+            leaves += 1;
+        }
+    }
+
+    let mut leaves = Vec::new();
+    let mut stack = vec![0];
+    while let Some(node) = stack.pop() {
+        let node = &nodes[node];
+        if node.leaf {
+            leaves.push((node.sum, node.count));
+        } else {
+            stack.extend(node.children.iter().flatten());
+        }
+    }
+    let color = |(sum, count): ([u64; 4], u64)| {
+        let channel = |s: u64| ((s + count / 2) / count.max(1)) as u8;
+        Rgba::new(
+            channel(sum[0]),
+            channel(sum[1]),
+            channel(sum[2]),
+            channel(sum[3]),
+        )
+    };
+
+    // the root can have up to eight children, more than a small palette
+    // holds: fold the rarest into whichever is closest in color
+    while leaves.len() > max_colors {
+        let rarest = (0..leaves.len()).min_by_key(|&i| leaves[i].1).unwrap();
+        let (sum, count) = leaves.swap_remove(rarest);
+        let nearest = (0..leaves.len())
+            .min_by(|&i, &j| {
+                let distance = |k: usize| color(leaves[k]).distance(color((sum, count)));
+                distance(i).total_cmp(&distance(j))
+            })
+            .unwrap();
+        let leaf = &mut leaves[nearest];
+        leaf.0.iter_mut().zip(sum.iter()).for_each(|(l, s)| *l += s);
+        leaf.1 += count;
+    }
+    leaves.into_iter().map(color).collect()
+}
+
+/// Pixels under an (unmerged) octree node. Only called on nodes whose
+/// children are all leaves.
+fn subtree_count(nodes: &[OctreeNode], node: usize) -> u64 {
+    nodes[node]
+        .children
+        .iter()
+        .flatten()
+        .map(|&child| nodes[child].count)
+        .sum()
+}
+
+#[wasm_bindgen]
+impl CanvasSource {
+    /// Pick up to `max_colors` (at most 256) colors that best represent the
+    /// canvas. Fully transparent pixels are ignored.
+    pub fn generate_palette(&self, max_colors: usize, method: QuantizeMethod) -> Palette {
+        Palette::generate(
+            &self.data[..(self.width * self.height * 4) as usize],
+            max_colors,
+            method,
+        )
+    }
+
+    /// Redraw the canvas using only colors from `palette`. Fully
+    /// transparent pixels are left alone.
+    pub fn apply_palette(&mut self, palette: &Palette, dither: Dither) {
+        if palette.is_empty() {
+            return;
+        }
+        let indices = palette.map_pixels(&self.data, self.width, dither);
+        let region = match self.selection_clip((0, 0, self.width, self.height)) {
+            Some(region) => region,
+            None => return,
+        };
+
+        self.edit(|canvas| {
+            let (x0, y0, x1, y1) = region;
+            canvas.touch(x0 as i32, y0 as i32, x1 as i32, y1 as i32);
+            for y in y0..y1 {
+                for x in x0..x1 {
+                    if let Some(index) = indices[(y * canvas.width + x) as usize] {
+                        canvas.replace_pixel(x, y, palette.colors[index as usize]);
+                    }
+                }
+            }
+        })
+    }
+
+    /// `generate_palette` and `apply_palette` in one step.
+    pub fn reduce_colors(&mut self, max_colors: usize, method: QuantizeMethod, dither: Dither) {
+        let palette = self.generate_palette(max_colors, method);
+        self.apply_palette(&palette, dither);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLACK_AND_WHITE: [u32; 2] = [0x000000ff, 0xffffffff];
+
+    /// Opaque pixels scattered a little around each of `centers`, in turn.
+    fn clusters(centers: &[[u8; 3]], count: usize) -> Vec<u8> {
+        (0..count)
+            .flat_map(|i| {
+                let [r, g, b] = centers[i % centers.len()];
+                let jitter = (i / centers.len() % 5) as u8;
+                [
+                    r.saturating_add(jitter),
+                    g.saturating_sub(jitter),
+                    b.saturating_add(jitter),
+                    255,
+                ]
+            })
+            .collect()
+    }
+
+    fn gray(width: u32, height: u32, value: u8) -> CanvasSource {
+        let data = [value, value, value, 255].repeat((width * height) as usize);
+        CanvasSource::try_new(width, height, data).unwrap()
+    }
+
+    fn white_pixels(canvas: &CanvasSource) -> usize {
+        canvas.data.chunks_exact(4).filter(|p| p[0] == 255).count()
+    }
+
+    #[test]
+    fn few_colors_are_kept_exactly() {
+        let data = [[1, 2, 3, 255], [9, 9, 9, 128], [1, 2, 3, 255], [0, 0, 0, 0]].concat();
+        let mut canvas = CanvasSource::try_new(2, 2, data.clone()).unwrap();
+        for &method in &[QuantizeMethod::MedianCut, QuantizeMethod::Octree] {
+            let palette = canvas.generate_palette(16, method);
+            assert_eq!(palette.to_u32s(), [0x010203ff, 0x09090980]);
+        }
+        canvas.reduce_colors(16, QuantizeMethod::MedianCut, Dither::FloydSteinberg);
+        assert_eq!(canvas.data, data);
+        assert!(!canvas.can_undo());
+    }
+
+    #[test]
+    fn clusters_get_a_color_each() {
+        let centers = [[250, 10, 10], [10, 250, 10], [10, 10, 250], [128, 128, 128]];
+        let data = clusters(&centers, 400);
+        for &method in &[QuantizeMethod::MedianCut, QuantizeMethod::Octree] {
+            let palette = Palette::generate(&data, 4, method);
+            assert_eq!(palette.len(), 4);
+            for &[r, g, b] in &centers {
+                let near = palette.colors()[palette.nearest(Rgba::new(r, g, b, 255)) as usize];
+                assert!(
+                    near.distance(Rgba::new(r, g, b, 255)) < 8.0,
+                    "{:?}: {:?} is far from {:?}",
+                    method,
+                    near,
+                    (r, g, b)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn every_dither_draws_with_palette_colors_only() {
+        let palette = Palette::try_new(&[0x000000ff, 0xff0000ff, 0x00ff00ff, 0xffffffff]).unwrap();
+        let data: Vec<u8> = (0..16 * 16u32)
+            .flat_map(|i| [(i * 7) as u8, (i * 13) as u8, (i * 3) as u8, 255])
+            .collect();
+        for &dither in &[
+            Dither::None,
+            Dither::FloydSteinberg,
+            Dither::Atkinson,
+            Dither::Bayer,
+        ] {
+            let mut canvas = CanvasSource::try_new(16, 16, data.clone()).unwrap();
+            canvas.apply_palette(&palette, dither);
+            for pixel in canvas.data.chunks_exact(4) {
+                assert!(
+                    palette.colors().contains(&Rgba::from_slice(pixel)),
+                    "{:?} drew {:?}",
+                    dither,
+                    pixel
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn dithering_keeps_the_average_tone() {
+        let palette = Palette::try_new(&BLACK_AND_WHITE).unwrap();
+        let mut flat = gray(16, 16, 128);
+        flat.apply_palette(&palette, Dither::None);
+        assert_eq!(white_pixels(&flat), 256);
+
+        for &dither in &[Dither::FloydSteinberg, Dither::Bayer] {
+            let mut canvas = gray(16, 16, 128);
+            canvas.apply_palette(&palette, dither);
+            let white = white_pixels(&canvas);
+            assert!((120..=136).contains(&white), "{:?}: {}", dither, white);
+        }
+        // Atkinson drops a quarter of the error, so it only roughly holds
+        let mut atkinson = gray(16, 16, 64);
+        atkinson.apply_palette(&palette, Dither::Atkinson);
+        assert!((32..=80).contains(&white_pixels(&atkinson)));
+    }
+
+    #[test]
+    fn transparent_pixels_are_left_alone() {
+        let data = [[0, 0, 0, 0], [7, 7, 7, 0], [200, 200, 200, 255]].concat();
+        let mut canvas = CanvasSource::try_new(3, 1, data).unwrap();
+        assert_eq!(canvas.generate_palette(8, QuantizeMethod::Octree).len(), 1);
+        canvas.apply_palette(&Palette::try_new(&BLACK_AND_WHITE).unwrap(), Dither::None);
+        assert_eq!(
+            canvas.data,
+            [[0, 0, 0, 0], [7, 7, 7, 0], [255, 255, 255, 255]].concat()
+        );
+    }
+
+    #[test]
+    fn palettes_hold_at_most_256_colors() {
+        assert_eq!(Palette::try_new(&[0; 256]).unwrap().len(), 256);
+        assert!(matches!(
+            Palette::try_new(&[0; 257]),
+            Err(PaletteError { colors: 257 })
+        ));
+        let many: Vec<u8> = (0..4096u32)
+            .flat_map(|i| i.to_be_bytes())
+            .map(|b| b | 1)
+            .collect();
+        assert!(Palette::generate(&many, 1000, QuantizeMethod::MedianCut).len() <= 256);
+    }
+
+    #[test]
+    fn empty_palettes_change_nothing() {
+        let mut canvas = gray(2, 2, 50);
+        canvas.apply_palette(&Palette::default(), Dither::FloydSteinberg);
+        assert_eq!(canvas.data, gray(2, 2, 50).data);
+    }
+}
@@ -0,0 +1,386 @@
+use wasm_bindgen::prelude::*;
+
+use crate::canvas_source::CanvasSource;
+use crate::paint::Paint;
+
+/// How a shape's edges are turned into pixels.
+///
+/// `Aliased` snaps coordinates to the pixel grid and writes hard-edged
+/// pixels, which is what pixel art wants. `AntiAliased` treats coordinates
+/// as continuous (pixel (x, y) covers the square from x to x + 1) and blends
+/// each edge pixel by how much of it the shape actually covers.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rasterization {
+    Aliased = 0,
+    AntiAliased = 1,
+}
+
+/// Which parts of a shape whose outline crosses itself count as inside.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FillRule {
+    /// Inside wherever the outline winds around the point at all, so
+    /// overlapping subpaths drawn the same way round merge.
+    NonZero = 0,
+    /// Inside where a ray out from the point crosses the outline an odd
+    /// number of times, so overlaps punch holes.
+    EvenOdd = 1,
+}
+
+impl FillRule {
+    /// Turn an accumulated signed winding value into pixel coverage.
+    fn coverage(self, winding: f32) -> f32 {
+        match self {
+            FillRule::NonZero => winding.abs().min(1.0),
+            FillRule::EvenOdd => {
+                let folded = winding.abs() % 2.0;
+                if folded > 1.0 {
+                    2.0 - folded
+                } else {
+                    folded
+                }
+            }
+        }
+    }
+}
+
+pub(crate) type Point = (f32, f32);
+
+/// Exact-area scanline rasterizer.
+///
+/// Every edge deposits the signed area it sweeps into an accumulation buffer;
+/// a running sum along each row then gives the winding at every pixel, with
+/// fractional values along the edges. Only the bounding box of the shape
+/// (clipped to the target) is allocated.
+pub(crate) struct CoverageRaster {
+    left: i32,
+    top: i32,
+    width: usize,
+    height: usize,
+    // one spare column on the right so edges sitting exactly on the right
+    // boundary have somewhere to land
+    stride: usize,
+    cells: Vec<f32>,
+}
+
+impl CoverageRaster {
+    /// Build a raster covering `contours`, clipped to a `clip_width` by
+    /// `clip_height` target. Returns `None` when nothing would be visible.
+    pub fn new(
+        contours: &[Vec<Point>],
+        clip_width: u32,
+        clip_height: u32,
+    ) -> Option<CoverageRaster> {
+        let mut min = (f32::MAX, f32::MAX);
+        let mut max = (f32::MIN, f32::MIN);
+        for &(x, y) in contours.iter().flatten() {
+            if !x.is_finite() || !y.is_finite() {
+                return None;
+            }
+            min = (min.0.min(x), min.1.min(y));
+            max = (max.0.max(x), max.1.max(y));
+        }
+
+        let left = (min.0.floor().max(0.0) as i64).min(clip_width as i64) as i32;
+        let top = (min.1.floor().max(0.0) as i64).min(clip_height as i64) as i32;
+        let right = (max.0.ceil().max(0.0) as i64).min(clip_width as i64) as i32;
+        let bottom = (max.1.ceil().max(0.0) as i64).min(clip_height as i64) as i32;
+        if right <= left || bottom <= top {
+            return None;
+        }
+
+        let width = (right - left) as usize;
+        let height = (bottom - top) as usize;
+        let mut raster = CoverageRaster {
+            left,
+            top,
+            width,
+            height,
+            stride: width + 2,
+            cells: vec![0.0; (width + 2) * height],
+        };
+
+        for contour in contours {
+            for (i, &p0) in contour.iter().enumerate() {
+                let p1 = contour[(i + 1) % contour.len()];
+                raster.add_edge(p0, p1);
+            }
+        }
+
+        Some(raster)
+    }
+
+    fn add_edge(&mut self, p0: Point, p1: Point) {
+        let local = |(x, y): Point| (x - self.left as f32, y - self.top as f32);
+        let (p0, p1) = (local(p0), local(p1));
+        let right = self.width as f32;
+
+        // split the edge where it crosses the left and right sides of the
+        // raster; the parts outside are squashed flat onto the side so they
+        // still contribute their winding to the pixels they pass
+        let mut splits = vec![0.0f32, 1.0];
+        for boundary in [0.0, right] {
+            if (p0.0 < boundary) != (p1.0 < boundary) {
+                splits.push((boundary - p0.0) / (p1.0 - p0.0));
+            }
+        }
+        splits.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        for pair in splits.windows(2) {
+            let at = |t: f32| {
+                let x = p0.0 + (p1.0 - p0.0) * t;
+                (x.max(0.0).min(right), p0.1 + (p1.1 - p0.1) * t)
+            };
+            self.accumulate(at(pair[0]), at(pair[1]));
+        }
+    }
+
+    /// Deposit the area swept by one edge that lies within [0, width].
+    fn accumulate(&mut self, p0: Point, p1: Point) {
+        if (p0.1 - p1.1).abs() <= f32::EPSILON {
+            return;
+        }
+        let (direction, p0, p1) = if p0.1 < p1.1 {
+            (1.0, p0, p1)
+        } else {
+            (-1.0, p1, p0)
+        };
+        let dxdy = (p1.0 - p0.0) / (p1.1 - p0.1);
+
+        let first_row = p0.1.max(0.0) as usize;
+        let last_row = (p1.1.ceil().max(0.0) as usize).min(self.height);
+        let right = self.width as f32;
+        let mut x = p0.0 + (first_row as f32 - p0.1).max(0.0) * dxdy;
+
+        for row in first_row..last_row {
+            let line = row * self.stride;
+            let dy = ((row + 1) as f32).min(p1.1) - (row as f32).max(p0.1);
+            // clamp away float drift so the columns stay inside the row
+            let x_next = (x + dxdy * dy).max(0.0).min(right);
+            let d = dy * direction;
+            let (x0, x1) = if x < x_next { (x, x_next) } else { (x_next, x) };
+            let x0_floor = x0.floor();
+            let x0i = x0_floor as usize;
+            let x1_ceil = x1.ceil();
+            let x1i = x1_ceil as usize;
+
+            if x1i <= x0i + 1 {
+                // the edge stays within one pixel column on this row
+                let mid = 0.5 * (x + x_next) - x0_floor;
+                self.cells[line + x0i] += d - d * mid;
+                self.cells[line + x0i + 1] += d * mid;
+            } else {
+                let s = (x1 - x0).recip();
+                let x0f = x0 - x0_floor;
+                let a0 = 0.5 * s * (1.0 - x0f) * (1.0 - x0f);
+                let x1f = x1 - x1_ceil + 1.0;
+                let am = 0.5 * s * x1f * x1f;
+                self.cells[line + x0i] += d * a0;
+                if x1i == x0i + 2 {
+                    self.cells[line + x0i + 1] += d * (1.0 - a0 - am);
+                } else {
+                    let a1 = s * (1.5 - x0f);
+                    self.cells[line + x0i + 1] += d * (a1 - a0);
+                    for xi in x0i + 2..x1i - 1 {
+                        self.cells[line + xi] += d * s;
+                    }
+                    let a2 = a1 + (x1i - x0i - 3) as f32 * s;
+                    self.cells[line + x1i - 1] += d * (1.0 - a2 - am);
+                }
+                self.cells[line + x1i] += d * am;
+            }
+            x = x_next;
+        }
+    }
+
+    /// Walk every pixel with non-zero coverage, in row order, handing the
+    /// callback target coordinates and a coverage in (0, 1].
+    pub fn for_each_pixel(&self, rule: FillRule, mut emit: impl FnMut(i32, i32, f32)) {
+        for row in 0..self.height {
+            let line = &self.cells[row * self.stride..(row + 1) * self.stride];
+            let mut winding = 0.0;
+            for (column, cell) in line[..self.width].iter().enumerate() {
+                winding += cell;
+                let coverage = rule.coverage(winding);
+                // anything under half a bit of alpha would round away anyway
+                if coverage > 0.5 / 255.0 {
+                    emit(self.left + column as i32, self.top + row as i32, coverage);
+                }
+            }
+        }
+    }
+}
+
+// removed #[wasm_bindgen] - not sent to js
+impl CanvasSource {
+    /// Anti-aliased fill of one or more closed contours.
+    pub(crate) fn fill_contours(&mut self, contours: &[Vec<Point>], rule: FillRule, paint: &Paint) {
+        if let Some(raster) = CoverageRaster::new(contours, self.width, self.height) {
+            raster.for_each_pixel(rule, |x, y, coverage| {
+                self.blend_pixel(x, y, paint.color_at(x, y), coverage)
+            });
+        }
+    }
+}
+
+/// A closed polygon approximating an ellipse, with enough segments that the
+/// chords stay within a tenth of a pixel of the true curve.
+pub(crate) fn ellipse_contour(cx: f32, cy: f32, rx: f32, ry: f32) -> Vec<Point> {
+    let radius = rx.max(ry).max(0.5);
+    let step = 2.0 * (1.0 - 0.1 / radius).max(-1.0).acos();
+    let segments = ((std::f32::consts::TAU / step).ceil() as usize).clamp(8, 1000);
+
+    (0..segments)
+        .map(|i| {
+            let angle = std::f32::consts::TAU * i as f32 / segments as f32;
+            (cx + rx * angle.cos(), cy + ry * angle.sin())
+        })
+        .collect()
+}
+
+/// The outline of a one pixel wide segment from p0 to p1, extended half a
+/// pixel past each end so the end points are fully covered.
+pub(crate) fn line_contour(p0: Point, p1: Point) -> Vec<Point> {
+    let (dx, dy) = (p1.0 - p0.0, p1.1 - p0.1);
+    let length = (dx * dx + dy * dy).sqrt();
+    let (ux, uy) = if length > f32::EPSILON {
+        (0.5 * dx / length, 0.5 * dy / length)
+    } else {
+        (0.5, 0.0)
+    };
+
+    vec![
+        (p0.0 - ux + uy, p0.1 - uy - ux),
+        (p1.0 + ux + uy, p1.1 + uy - ux),
+        (p1.0 + ux - uy, p1.1 + uy + ux),
+        (p0.0 - ux - uy, p0.1 - uy + ux),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shapes::rect_contour;
+
+    /// Coverage of every pixel of a `width` by `height` target.
+    fn coverage(contours: &[Vec<Point>], rule: FillRule, width: u32, height: u32) -> Vec<f32> {
+        let mut out = vec![0.0; (width * height) as usize];
+        if let Some(raster) = CoverageRaster::new(contours, width, height) {
+            raster.for_each_pixel(rule, |x, y, c| {
+                out[(y as u32 * width + x as u32) as usize] = c
+            });
+        }
+        out
+    }
+
+    fn assert_close(actual: &[f32], expected: &[f32]) {
+        assert_eq!(actual.len(), expected.len());
+        for (i, (a, e)) in actual.iter().zip(expected).enumerate() {
+            assert!((a - e).abs() < 1e-4, "pixel {}: {} != {}", i, a, e);
+        }
+    }
+
+    #[test]
+    fn partly_covered_pixels_get_their_area() {
+        let rect = rect_contour(0.5, 0.0, 2.0, 1.0);
+        assert_close(
+            &coverage(&[rect], FillRule::NonZero, 3, 1),
+            &[0.5, 1.0, 0.5],
+        );
+
+        let quarter = rect_contour(0.5, 0.5, 1.0, 1.0);
+        assert_close(
+            &coverage(&[quarter], FillRule::NonZero, 2, 2),
+            &[0.25, 0.25, 0.25, 0.25],
+        );
+    }
+
+    #[test]
+    fn diagonal_edges_split_pixels() {
+        let triangle = vec![(0.0, 0.0), (2.0, 0.0), (0.0, 2.0)];
+        assert_close(
+            &coverage(&[triangle], FillRule::NonZero, 2, 2),
+            &[1.0, 0.5, 0.5, 0.0],
+        );
+    }
+
+    #[test]
+    fn winding_direction_doesnt_matter() {
+        let clockwise = vec![(0.3, 0.2), (2.7, 0.9), (1.1, 2.8)];
+        let counter: Vec<Point> = clockwise.iter().rev().copied().collect();
+        assert_close(
+            &coverage(&[clockwise], FillRule::NonZero, 3, 3),
+            &coverage(&[counter], FillRule::NonZero, 3, 3),
+        );
+    }
+
+    #[test]
+    fn total_coverage_matches_the_area() {
+        let circle = ellipse_contour(8.0, 8.0, 5.0, 5.0);
+        // shoelace formula
+        let area = (0..circle.len())
+            .map(|i| {
+                let ((x0, y0), (x1, y1)) = (circle[i], circle[(i + 1) % circle.len()]);
+                x0 * y1 - x1 * y0
+            })
+            .sum::<f32>()
+            .abs()
+            / 2.0;
+        let total: f32 = coverage(&[circle], FillRule::NonZero, 16, 16).iter().sum();
+        assert!((total - area).abs() < 0.01, "{} vs {}", total, area);
+    }
+
+    #[test]
+    fn fill_rules_treat_overlaps_differently() {
+        let outer = rect_contour(0.0, 0.0, 3.0, 1.0);
+        let inner = rect_contour(1.0, 0.0, 1.0, 1.0);
+        let contours = [outer, inner];
+        assert_close(
+            &coverage(&contours, FillRule::NonZero, 3, 1),
+            &[1.0, 1.0, 1.0],
+        );
+        assert_close(
+            &coverage(&contours, FillRule::EvenOdd, 3, 1),
+            &[1.0, 0.0, 1.0],
+        );
+    }
+
+    #[test]
+    fn shapes_are_clipped_to_the_target() {
+        let rect = rect_contour(-5.0, -5.0, 6.5, 6.0);
+        assert_close(
+            &coverage(&[rect], FillRule::NonZero, 3, 2),
+            &[1.0, 0.5, 0.0, 0.0, 0.0, 0.0],
+        );
+        assert!(CoverageRaster::new(&[rect_contour(5.0, 5.0, 2.0, 2.0)], 3, 3).is_none());
+    }
+
+    #[test]
+    fn non_finite_points_draw_nothing() {
+        let contour = vec![(0.0, 0.0), (f32::NAN, 1.0), (1.0, 1.0)];
+        assert!(CoverageRaster::new(&[contour], 4, 4).is_none());
+        let contour = vec![(0.0, 0.0), (f32::INFINITY, 1.0), (1.0, 1.0)];
+        assert!(CoverageRaster::new(&[contour], 4, 4).is_none());
+    }
+
+    #[test]
+    fn whole_pixel_shapes_match_aliased_ones() {
+        let mut aliased = CanvasSource::try_new(8, 8, Vec::new()).unwrap();
+        let mut smooth = CanvasSource::try_new(8, 8, Vec::new()).unwrap();
+        for (canvas, mode) in [
+            (&mut aliased, Rasterization::Aliased),
+            (&mut smooth, Rasterization::AntiAliased),
+        ] {
+            canvas.fill_rect(1.0, 2.0, 5.0, 3.0, 0xff0000ff, mode);
+            canvas.stroke_rect(0.0, 0.0, 8.0, 8.0, 0x00ff0080, mode);
+        }
+        assert_eq!(aliased.data, smooth.data);
+    }
+
+    #[test]
+    fn huge_ellipses_get_a_bounded_contour() {
+        assert_eq!(ellipse_contour(0.0, 0.0, 1e9, 1e9).len(), 1000);
+        assert_eq!(ellipse_contour(0.0, 0.0, 0.0, 0.0).len(), 8);
+    }
+}
@@ -0,0 +1,454 @@
+use wasm_bindgen::prelude::*;
+
+use crate::canvas_source::{CanvasSource, ImageDataError};
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResampleFilter {
+    Nearest = 0,
+    Bilinear = 1,
+    /// Catmull-Rom cubic.
+    Bicubic = 2,
+    /// Three-lobe Lanczos.
+    Lanczos = 3,
+}
+
+/// How an image whose aspect ratio differs from the canvas is fitted to it.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AspectMode {
+    /// Scale to fit entirely inside the canvas, leaving transparent bars.
+    Fit = 0,
+    /// Scale to cover the whole canvas, cropping the overflow.
+    Fill = 1,
+    /// Scale each axis independently to match the canvas exactly.
+    Stretch = 2,
+}
+
+impl ResampleFilter {
+    /// How far the kernel reaches either side of the sample point, in
+    /// source pixels, when not downscaling.
+    pub(crate) fn support(self) -> f32 {
+        match self {
+            ResampleFilter::Nearest => 0.5,
+            ResampleFilter::Bilinear => 1.0,
+            ResampleFilter::Bicubic => 2.0,
+            ResampleFilter::Lanczos => 3.0,
+        }
+    }
+
+    pub(crate) fn weight(self, x: f32) -> f32 {
+        let x = x.abs();
+        match self {
+            ResampleFilter::Nearest => {
+                if x <= 0.5 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            ResampleFilter::Bilinear => (1.0 - x).max(0.0),
+            ResampleFilter::Bicubic => {
+                // Catmull-Rom, a = -0.5
+                if x < 1.0 {
+                    1.5 * x * x * x - 2.5 * x * x + 1.0
+                } else if x < 2.0 {
+                    -0.5 * x * x * x + 2.5 * x * x - 4.0 * x + 2.0
+                } else {
+                    0.0
+                }
+            }
+            ResampleFilter::Lanczos => {
+                if x < f32::EPSILON {
+                    1.0
+                } else if x < 3.0 {
+                    let pi_x = std::f32::consts::PI * x;
+                    3.0 * pi_x.sin() * (pi_x / 3.0).sin() / (pi_x * pi_x)
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
+
+/// Maps an axis of the output onto an axis of the source: output pixel `i`
+/// samples the source around `(i + 0.5 - offset) / scale`.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct AxisMapping {
+    pub scale: f32,
+    pub offset: f32,
+}
+
+/// The source pixels and normalized weights contributing to one output
+/// pixel along one axis. Empty when the output pixel falls outside the
+/// scaled image.
+struct Taps {
+    first: usize,
+    weights: Vec<f32>,
+}
+
+fn axis_taps(
+    filter: ResampleFilter,
+    mapping: AxisMapping,
+    source_len: usize,
+    output_len: usize,
+) -> Vec<Taps> {
+    // when shrinking, stretch the kernel so every source pixel contributes
+    let widen = (1.0 / mapping.scale).max(1.0);
+    let support = filter.support() * widen;
+
+    (0..output_len)
+        .map(|i| {
+            let center = (i as f32 + 0.5 - mapping.offset) / mapping.scale;
+            if center < 0.0 || center >= source_len as f32 {
+                return Taps {
+                    first: 0,
+                    weights: Vec::new(),
+                };
+            }
+
+            if filter == ResampleFilter::Nearest {
+                return Taps {
+                    first: center as usize,
+                    weights: vec![1.0],
+                };
+            }
+
+            let start = (center - support).floor().max(0.0) as usize;
+            let end = ((center + support).ceil() as usize).min(source_len);
+            let mut weights: Vec<f32> = (start..end)
+                .map(|j| filter.weight((j as f32 + 0.5 - center) / widen))
+                .collect();
+            let total: f32 = weights.iter().sum();
+            if total.abs() > f32::EPSILON {
+                weights.iter_mut().for_each(|w| *w /= total);
+            }
+
+            Taps {
+                first: start,
+                weights,
+            }
+        })
+        .collect()
+}
+
+/// Resample a straight-alpha RGBA image into an `output_width` by
+/// `output_height` buffer. Output pixels that map outside the source are
+/// left transparent.
+///
+/// Filtering happens on premultiplied colors so transparent pixels don't
+/// bleed dark fringes into their neighbors.
+pub(crate) fn resample(
+    source: &[u8],
+    source_size: (u32, u32),
+    filter: ResampleFilter,
+    horizontal: AxisMapping,
+    vertical: AxisMapping,
+    output_size: (u32, u32),
+) -> Vec<u8> {
+    let (source_width, source_height) = (source_size.0 as usize, source_size.1 as usize);
+    let (output_width, output_height) = (output_size.0 as usize, output_size.1 as usize);
+    let columns = axis_taps(filter, horizontal, source_width, output_width);
+    let rows = axis_taps(filter, vertical, source_height, output_height);
+
+    // horizontal pass, only over the source rows something actually reads
+    let first_row = rows
+        .iter()
+        .filter(|t| !t.weights.is_empty())
+        .map(|t| t.first)
+        .min();
+    let last_row = rows
+        .iter()
+        .filter(|t| !t.weights.is_empty())
+        .map(|t| t.first + t.weights.len())
+        .max();
+    let (first_row, last_row) = match (first_row, last_row) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return vec![0; output_width * output_height * 4],
+    };
+
+    let mut intermediate = vec![0.0f32; output_width * (last_row - first_row) * 4];
+    for y in first_row..last_row {
+        let source_row = &source[y * source_width * 4..(y + 1) * source_width * 4];
+        let out_row = &mut intermediate[(y - first_row) * output_width * 4..][..output_width * 4];
+        for (taps, out) in columns.iter().zip(out_row.chunks_exact_mut(4)) {
+            for (k, weight) in taps.weights.iter().enumerate() {
+                let pixel = &source_row[(taps.first + k) * 4..][..4];
+                let alpha = pixel[3] as f32 / 255.0;
+                out[0] += weight * pixel[0] as f32 * alpha;
+                out[1] += weight * pixel[1] as f32 * alpha;
+                out[2] += weight * pixel[2] as f32 * alpha;
+                out[3] += weight * alpha;
+            }
+        }
+    }
+
+    // vertical pass straight into the output, un-premultiplying on the way
+    let mut output = vec![0u8; output_width * output_height * 4];
+    for (y, taps) in rows.iter().enumerate() {
+        if taps.weights.is_empty() {
+            continue;
+        }
+        for x in 0..output_width {
+            if columns[x].weights.is_empty() {
+                continue;
+            }
+            let mut sum = [0.0f32; 4];
+            for (k, weight) in taps.weights.iter().enumerate() {
+                let row = taps.first + k - first_row;
+                let pixel = &intermediate[(row * output_width + x) * 4..][..4];
+                for channel in 0..4 {
+                    sum[channel] += weight * pixel[channel];
+                }
+            }
+
+            let alpha = sum[3].clamp(0.0, 1.0);
+            let out = &mut output[(y * output_width + x) * 4..][..4];
+            if alpha > 0.0 {
+                for channel in 0..3 {
+                    out[channel] = (sum[channel] / alpha).round().clamp(0.0, 255.0) as u8;
+                }
+            }
+            out[3] = (alpha * 255.0).round() as u8;
+        }
+    }
+
+    output
+}
+
+#[wasm_bindgen]
+impl CanvasSource {
+    /// Scale an RGBA image of any size onto the whole canvas, replacing its
+    /// contents. `data` holds `width * height` pixels, four bytes each.
+    /// Throws if it's shorter than that.
+    pub fn scale_to_source(
+        &mut self,
+        data: &[u8],
+        width: u32,
+        height: u32,
+        filter: ResampleFilter,
+        aspect: AspectMode,
+    ) -> Result<(), JsError> {
+        Ok(self.try_scale_to_source(data, width, height, filter, aspect)?)
+    }
+}
+
+// removed #[wasm_bindgen] - the Rust-side API for the native binary
+impl CanvasSource {
+    pub fn try_scale_to_source(
+        &mut self,
+        data: &[u8],
+        width: u32,
+        height: u32,
+        filter: ResampleFilter,
+        aspect: AspectMode,
+    ) -> Result<(), ImageDataError> {
+        ImageDataError::check(data, width, height)?;
+        self.edit(|canvas| {
+            canvas.touch(0, 0, canvas.width as i32, canvas.height as i32);
+            if width == 0 || height == 0 {
+                let blank = vec![0; canvas.data.len()];
+                canvas.replace_all(blank);
+                return;
+            }
+
+            let scale_x = canvas.width as f32 / width as f32;
+            let scale_y = canvas.height as f32 / height as f32;
+            let (scale_x, scale_y) = match aspect {
+                AspectMode::Stretch => (scale_x, scale_y),
+                AspectMode::Fit => (scale_x.min(scale_y), scale_x.min(scale_y)),
+                AspectMode::Fill => (scale_x.max(scale_y), scale_x.max(scale_y)),
+            };
+
+            // center the scaled image; with Fill the offsets go negative and the
+            // overflow is cropped
+            let horizontal = AxisMapping {
+                scale: scale_x,
+                offset: (canvas.width as f32 - width as f32 * scale_x) / 2.0,
+            };
+            let vertical = AxisMapping {
+                scale: scale_y,
+                offset: (canvas.height as f32 - height as f32 * scale_y) / 2.0,
+            };
+
+            let scaled = resample(
+                data,
+                (width, height),
+                filter,
+                horizontal,
+                vertical,
+                (canvas.width, canvas.height),
+            );
+            canvas.replace_all(scaled);
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Rgba;
+
+    const FILTERS: [ResampleFilter; 4] = [
+        ResampleFilter::Nearest,
+        ResampleFilter::Bilinear,
+        ResampleFilter::Bicubic,
+        ResampleFilter::Lanczos,
+    ];
+
+    fn canvas(width: u32, height: u32) -> CanvasSource {
+        CanvasSource::try_new(width, height, Vec::new()).unwrap()
+    }
+
+    fn image(pixels: &[u32]) -> Vec<u8> {
+        pixels
+            .iter()
+            .flat_map(|&p| Rgba::from_u32(p).to_array())
+            .collect()
+    }
+
+    fn scale(
+        canvas: &mut CanvasSource,
+        data: &[u8],
+        size: (u32, u32),
+        filter: ResampleFilter,
+        aspect: AspectMode,
+    ) {
+        canvas
+            .try_scale_to_source(data, size.0, size.1, filter, aspect)
+            .unwrap();
+    }
+
+    #[test]
+    fn same_size_is_a_copy_with_every_filter() {
+        // opaque, so premultiplying loses nothing
+        let data: Vec<u8> = (0..4 * 3 * 4)
+            .map(|i| {
+                if i % 4 == 3 {
+                    255
+                } else {
+                    (i * 37 % 256) as u8
+                }
+            })
+            .collect();
+        for filter in FILTERS {
+            let mut c = canvas(4, 3);
+            scale(&mut c, &data, (4, 3), filter, AspectMode::Stretch);
+            assert_eq!(c.data, data, "{:?}", filter);
+        }
+    }
+
+    #[test]
+    fn nearest_upscaling_repeats_pixels() {
+        let mut c = canvas(4, 2);
+        let data = image(&[0xff0000ff, 0x00ff00ff]);
+        scale(
+            &mut c,
+            &data,
+            (2, 1),
+            ResampleFilter::Nearest,
+            AspectMode::Stretch,
+        );
+        assert_eq!(
+            c.data,
+            image(&[
+                0xff0000ff, 0xff0000ff, 0x00ff00ff, 0x00ff00ff, 0xff0000ff, 0xff0000ff, 0x00ff00ff,
+                0x00ff00ff,
+            ])
+        );
+    }
+
+    #[test]
+    fn downscaling_averages() {
+        let mut c = canvas(1, 1);
+        let data = image(&[0x000000ff, 0xffffffff]);
+        scale(
+            &mut c,
+            &data,
+            (2, 1),
+            ResampleFilter::Bilinear,
+            AspectMode::Stretch,
+        );
+        assert_eq!(c.data, [128, 128, 128, 255]);
+    }
+
+    #[test]
+    fn transparent_pixels_dont_darken_their_neighbors() {
+        let mut c = canvas(1, 1);
+        // the transparent pixel's color is ignored
+        let data = image(&[0xff0000ff, 0x00ff0000]);
+        scale(
+            &mut c,
+            &data,
+            (2, 1),
+            ResampleFilter::Bilinear,
+            AspectMode::Stretch,
+        );
+        assert_eq!(c.data, [255, 0, 0, 128]);
+    }
+
+    #[test]
+    fn fit_leaves_transparent_bars() {
+        let mut c = canvas(3, 1);
+        let data = image(&[0xff0000ff]);
+        scale(
+            &mut c,
+            &data,
+            (1, 1),
+            ResampleFilter::Nearest,
+            AspectMode::Fit,
+        );
+        assert_eq!(c.data, image(&[0, 0xff0000ff, 0]));
+    }
+
+    #[test]
+    fn fill_crops_the_overflow() {
+        let mut c = canvas(1, 1);
+        let data = image(&[0xff0000ff, 0x0000ffff, 0x00ff00ff]);
+        scale(
+            &mut c,
+            &data,
+            (3, 1),
+            ResampleFilter::Nearest,
+            AspectMode::Fill,
+        );
+        assert_eq!(c.data, image(&[0x0000ffff]));
+    }
+
+    #[test]
+    fn short_data_is_an_error() {
+        let mut c = canvas(2, 2);
+        c.cover_in_blood();
+        let before = c.data.clone();
+        let result =
+            c.try_scale_to_source(&[0; 12], 2, 2, ResampleFilter::Nearest, AspectMode::Stretch);
+        assert!(result.is_err());
+        assert_eq!(c.data, before);
+        // sizes whose byte count overflows 32 bits are still just too short
+        assert!(c
+            .try_scale_to_source(
+                &[0; 4],
+                1 << 16,
+                1 << 16,
+                ResampleFilter::Nearest,
+                AspectMode::Fit
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn an_empty_source_clears_the_canvas() {
+        let mut c = canvas(2, 2);
+        c.cover_in_blood();
+        scale(
+            &mut c,
+            &[],
+            (0, 0),
+            ResampleFilter::Bilinear,
+            AspectMode::Fit,
+        );
+        assert!(c.data.iter().all(|&b| b == 0));
+    }
+}
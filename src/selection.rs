@@ -0,0 +1,467 @@
+use wasm_bindgen::prelude::*;
+
+use crate::canvas_source::CanvasSource;
+use crate::color::Rgba;
+use crate::composite::{BlendMode, CompositeOp, Compositor};
+use crate::dirty::Rect;
+use crate::fill::Connectivity;
+use crate::filters::gaussian_kernel;
+use crate::raster::{ellipse_contour, CoverageRaster, FillRule, Point};
+use crate::shapes::rect_contour;
+
+/// How a new selection shape combines with the current selection.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SelectionOp {
+    Replace = 0,
+    Union = 1,
+    Subtract = 2,
+    Intersect = 3,
+}
+
+/// The active selection: how much of each pixel is selected, 0 to 255, and
+/// the box around everything that is. A selection can be empty, e.g. after
+/// subtracting everything, in which case nothing can be edited.
+pub(crate) struct Selection {
+    mask: Vec<u8>,
+    bounds: Rect,
+}
+
+impl Selection {
+    /// Wrap a mask. The bounds of an empty one are all zero.
+    fn from_mask(mask: Vec<u8>, width: u32) -> Selection {
+        let width = width.max(1);
+        let mut bounds: Option<Rect> = None;
+        for (i, _) in mask.iter().enumerate().filter(|(_, &m)| m > 0) {
+            let (x, y) = (i as u32 % width, i as u32 / width);
+            bounds = Some(match bounds {
+                Some((x0, y0, x1, y1)) => (x0.min(x), y0.min(y), x1.max(x + 1), y1.max(y + 1)),
+                None => (x, y, x + 1, y + 1),
+            });
+        }
+        Selection {
+            mask,
+            bounds: bounds.unwrap_or((0, 0, 0, 0)),
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl CanvasSource {
+    /// Select a rectangle, with anti-aliased edges at fractional
+    /// coordinates.
+    pub fn select_rect(&mut self, x: f32, y: f32, width: f32, height: f32, op: SelectionOp) {
+        if width <= 0.0 || height <= 0.0 {
+            return self.combine_selection(vec![0; self.mask_len()], op);
+        }
+        let shape = self.shape_mask(&[rect_contour(x, y, width, height)], FillRule::NonZero);
+        self.combine_selection(shape, op);
+    }
+
+    pub fn select_ellipse(&mut self, cx: f32, cy: f32, rx: f32, ry: f32, op: SelectionOp) {
+        if rx <= 0.0 || ry <= 0.0 {
+            return self.combine_selection(vec![0; self.mask_len()], op);
+        }
+        let shape = self.shape_mask(&[ellipse_contour(cx, cy, rx, ry)], FillRule::NonZero);
+        self.combine_selection(shape, op);
+    }
+
+    /// Select the inside of a polygon or freehand lasso, given as flat
+    /// `[x0, y0, x1, y1, ...]` coordinates. The path is closed
+    /// automatically and self-intersections follow the even-odd rule.
+    pub fn select_polygon(&mut self, points: &[f32], op: SelectionOp) {
+        let contour: Vec<Point> = points.chunks_exact(2).map(|p| (p[0], p[1])).collect();
+        let shape = if contour.len() < 3 {
+            vec![0; self.mask_len()]
+        } else {
+            self.shape_mask(&[contour], FillRule::EvenOdd)
+        };
+        self.combine_selection(shape, op);
+    }
+
+    /// Magic wand: select pixels within `tolerance` of the color at (x, y),
+    /// either the connected area around it or, with `contiguous` false,
+    /// every match on the canvas. Works like `flood_fill`.
+    pub fn select_magic_wand(
+        &mut self,
+        x: i32,
+        y: i32,
+        tolerance: f32,
+        connectivity: Connectivity,
+        contiguous: bool,
+        op: SelectionOp,
+    ) {
+        let target = match self.pixel(x, y) {
+            Some(color) => color,
+            None => return,
+        };
+        let region = if contiguous {
+            self.connected_region(x as u32, y as u32, target, tolerance, connectivity)
+        } else {
+            self.matching_region(target, tolerance)
+        };
+        let shape = region
+            .into_iter()
+            .map(|hit| if hit { 255 } else { 0 })
+            .collect();
+        self.combine_selection(shape, op);
+    }
+
+    pub fn select_all(&mut self) {
+        self.selection = Some(Selection::from_mask(vec![255; self.mask_len()], self.width));
+    }
+
+    /// Drop the selection, so drawing reaches the whole canvas again.
+    pub fn deselect(&mut self) {
+        self.selection = None;
+    }
+
+    /// Swap selected and unselected. Inverting no selection selects
+    /// everything; inverting everything leaves an empty selection.
+    pub fn invert_selection(&mut self) {
+        let mask = match self.selection.take() {
+            Some(selection) => selection.mask.into_iter().map(|m| 255 - m).collect(),
+            None => vec![255; self.mask_len()],
+        };
+        self.selection = Some(Selection::from_mask(mask, self.width));
+    }
+
+    /// Soften the selection's edge so it fades out over roughly `radius`
+    /// pixels either side.
+    pub fn feather_selection(&mut self, radius: f32) {
+        if radius <= 0.0 || !radius.is_finite() {
+            return;
+        }
+        let selection = match self.selection.take() {
+            Some(selection) => selection,
+            None => return,
+        };
+        let mask = feather(&selection.mask, self.width, self.height, radius / 2.0);
+        self.selection = Some(Selection::from_mask(mask, self.width));
+    }
+
+    /// Whether drawing is limited to a selection. True for an empty
+    /// selection too, which blocks all drawing.
+    pub fn has_selection(&self) -> bool {
+        self.selection.is_some()
+    }
+
+    /// `[x, y, width, height]` around the selection, all zero if it's
+    /// empty, or empty if there is none.
+    pub fn selection_bounds(&self) -> Vec<u32> {
+        match &self.selection {
+            Some(Selection {
+                bounds: (x0, y0, x1, y1),
+                ..
+            }) => vec![*x0, *y0, x1 - x0, y1 - y0],
+            None => Vec::new(),
+        }
+    }
+
+    /// How selected each pixel is, one byte (0 to 255) per pixel in row
+    /// order, e.g. for drawing marching ants. Empty if there is no
+    /// selection.
+    pub fn selection_mask(&self) -> Vec<u8> {
+        self.selection
+            .as_ref()
+            .map_or_else(Vec::new, |s| s.mask.clone())
+    }
+}
+
+// removed #[wasm_bindgen] - not sent to js
+impl CanvasSource {
+    fn mask_len(&self) -> usize {
+        (self.width * self.height) as usize
+    }
+
+    fn shape_mask(&self, contours: &[Vec<Point>], rule: FillRule) -> Vec<u8> {
+        let mut mask = vec![0; self.mask_len()];
+        if let Some(raster) = CoverageRaster::new(contours, self.width, self.height) {
+            raster.for_each_pixel(rule, |x, y, coverage| {
+                mask[(y as u32 * self.width + x as u32) as usize] =
+                    (coverage * 255.0).round() as u8;
+            });
+        }
+        mask
+    }
+
+    fn combine_selection(&mut self, shape: Vec<u8>, op: SelectionOp) {
+        // with nothing selected yet every op but intersect starts afresh
+        let current = self.selection.take().map(|s| s.mask);
+        let mask = match (op, current) {
+            // replacing with nothing, like clicking without dragging,
+            // deselects
+            (SelectionOp::Replace, _) if shape.iter().all(|&m| m == 0) => return,
+            (SelectionOp::Replace, _) | (SelectionOp::Union, None) => shape,
+            (SelectionOp::Subtract, None) | (SelectionOp::Intersect, None) => {
+                vec![0; self.mask_len()]
+            }
+            (SelectionOp::Union, Some(current)) => {
+                current.iter().zip(shape).map(|(&a, b)| a.max(b)).collect()
+            }
+            (SelectionOp::Subtract, Some(current)) => current
+                .iter()
+                .zip(shape)
+                .map(|(&a, b)| (a as u32 * (255 - b) as u32 / 255) as u8)
+                .collect(),
+            (SelectionOp::Intersect, Some(current)) => {
+                current.iter().zip(shape).map(|(&a, b)| a.min(b)).collect()
+            }
+        };
+        // an empty result still blocks drawing rather than freeing the
+        // whole canvas
+        self.selection = Some(Selection::from_mask(mask, self.width));
+    }
+
+    /// How much of the pixel at (x, y) the selection lets through, 0.0 to
+    /// 1.0. Without a selection the whole canvas is editable.
+    #[inline]
+    pub(crate) fn selection_coverage(&self, x: u32, y: u32) -> f32 {
+        match &self.selection {
+            Some(selection) => selection.mask[(y * self.width + x) as usize] as f32 / 255.0,
+            None => 1.0,
+        }
+    }
+
+    /// The part of `rect` the selection can affect, or `None` if none of
+    /// it can.
+    pub(crate) fn selection_clip(&self, rect: Rect) -> Option<Rect> {
+        let (x0, y0, x1, y1) = match &self.selection {
+            Some(selection) => {
+                let (sx0, sy0, sx1, sy1) = selection.bounds;
+                (
+                    rect.0.max(sx0),
+                    rect.1.max(sy0),
+                    rect.2.min(sx1),
+                    rect.3.min(sy1),
+                )
+            }
+            None => rect,
+        };
+        Some((x0, y0, x1, y1)).filter(|_| x0 < x1 && y0 < y1)
+    }
+
+    /// Overwrite the pixel at (x, y) with `pixel`, faded into what was
+    /// there by how selected it is. For operations that compute new pixel
+    /// values rather than drawing over them; the caller must already have
+    /// touched the pixel.
+    pub(crate) fn replace_pixel(&mut self, x: u32, y: u32, pixel: Rgba) {
+        let coverage = self.selection_coverage(x, y);
+        let idx = self.pixel_index(x, y);
+        let copy = Compositor {
+            op: CompositeOp::Copy,
+            blend: BlendMode::Normal,
+        };
+        copy.composite(&mut self.data[idx..idx + 4], pixel, coverage);
+    }
+
+    /// Swap in a whole new pixel buffer, or under a selection only the
+    /// selected parts of it. The caller must already have touched the
+    /// canvas.
+    pub(crate) fn replace_all(&mut self, pixels: Vec<u8>) {
+        if self.selection.is_none() {
+            self.data = pixels;
+            return;
+        }
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let idx = self.pixel_index(x, y);
+                self.replace_pixel(x, y, Rgba::from_slice(&pixels[idx..idx + 4]));
+            }
+        }
+    }
+}
+
+/// Gaussian blur of a mask, treating the area past the canvas edges as
+/// continuing the edge pixels so selections touching the border stay solid
+/// there.
+fn feather(mask: &[u8], width: u32, height: u32, sigma: f32) -> Vec<u8> {
+    let kernel = gaussian_kernel(sigma, width.max(height));
+    let radius = (kernel.len() / 2) as i32;
+    let (w, h) = (width as i32, height as i32);
+
+    let mut horizontal = vec![0.0f32; mask.len()];
+    for y in 0..h {
+        for x in 0..w {
+            horizontal[(y * w + x) as usize] = kernel
+                .iter()
+                .enumerate()
+                .map(|(k, weight)| {
+                    let sx = (x + k as i32 - radius).clamp(0, w - 1);
+                    weight * mask[(y * w + sx) as usize] as f32
+                })
+                .sum::<f32>();
+        }
+    }
+
+    let mut output = vec![0u8; mask.len()];
+    for y in 0..h {
+        for x in 0..w {
+            let value = kernel
+                .iter()
+                .enumerate()
+                .map(|(k, weight)| {
+                    let sy = (y + k as i32 - radius).clamp(0, h - 1);
+                    weight * horizontal[(sy * w + x) as usize]
+                })
+                .sum::<f32>();
+            output[(y * w + x) as usize] = value.round().clamp(0.0, 255.0) as u8;
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pictures::coverage;
+    use crate::raster::Rasterization;
+
+    const RED: u32 = 0xff0000ff;
+
+    fn canvas(width: u32, height: u32) -> CanvasSource {
+        CanvasSource::try_new(width, height, Vec::new()).unwrap()
+    }
+
+    /// The selection mask as a picture: '#' fully selected, '+' partly,
+    /// '.' not at all.
+    fn mask_picture(canvas: &CanvasSource) -> Vec<String> {
+        canvas
+            .selection_mask()
+            .chunks_exact(canvas.width as usize)
+            .map(|row| {
+                row.iter()
+                    .map(|&m| match m {
+                        0 => '.',
+                        255 => '#',
+                        _ => '+',
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rectangles_are_anti_aliased() {
+        let mut canvas = canvas(4, 3);
+        assert!(!canvas.has_selection());
+        assert!(canvas.selection_bounds().is_empty());
+        canvas.select_rect(1.0, 0.5, 2.0, 1.0, SelectionOp::Replace);
+        assert_eq!(mask_picture(&canvas), [".++.", ".++.", "...."]);
+        assert_eq!(canvas.selection_mask()[1], 128);
+        assert_eq!(canvas.selection_bounds(), [1, 0, 2, 2]);
+    }
+
+    #[test]
+    fn shapes_combine() {
+        let mut canvas = canvas(4, 2);
+        canvas.select_rect(0.0, 0.0, 2.0, 2.0, SelectionOp::Replace);
+        canvas.select_rect(1.0, 0.0, 2.0, 1.0, SelectionOp::Union);
+        assert_eq!(mask_picture(&canvas), ["###.", "##.."]);
+        canvas.select_rect(0.0, 1.0, 4.0, 1.0, SelectionOp::Subtract);
+        assert_eq!(mask_picture(&canvas), ["###.", "...."]);
+        canvas.select_rect(2.0, 0.0, 2.0, 2.0, SelectionOp::Intersect);
+        assert_eq!(mask_picture(&canvas), ["..#.", "...."]);
+        canvas.invert_selection();
+        assert_eq!(mask_picture(&canvas), ["##.#", "####"]);
+    }
+
+    #[test]
+    fn replacing_with_nothing_deselects() {
+        let mut canvas = canvas(4, 2);
+        canvas.select_rect(0.0, 0.0, 2.0, 2.0, SelectionOp::Replace);
+        canvas.select_rect(1.0, 1.0, 0.0, 0.0, SelectionOp::Replace);
+        assert!(!canvas.has_selection());
+    }
+
+    #[test]
+    fn drawing_stays_inside_the_selection() {
+        let mut canvas = canvas(4, 3);
+        canvas.select_rect(1.0, 0.5, 2.0, 1.0, SelectionOp::Replace);
+        canvas.fill_rect(0.0, 0.0, 4.0, 3.0, RED, Rasterization::Aliased);
+        assert_eq!(coverage(&canvas), [".++.", ".++.", "...."]);
+        assert_eq!(canvas.pixel(1, 0).unwrap().to_array(), [255, 0, 0, 128]);
+
+        canvas.deselect();
+        canvas.fill_rect(0.0, 2.0, 4.0, 1.0, RED, Rasterization::Aliased);
+        assert_eq!(coverage(&canvas)[2], "####");
+    }
+
+    #[test]
+    fn empty_selections_block_every_edit() {
+        let mut canvas = canvas(4, 3);
+        canvas.select_rect(0.0, 0.0, 2.0, 2.0, SelectionOp::Replace);
+        canvas.select_rect(0.0, 0.0, 4.0, 3.0, SelectionOp::Subtract);
+        assert!(canvas.has_selection());
+        assert_eq!(canvas.selection_bounds(), [0, 0, 0, 0]);
+
+        canvas.fill_rect(0.0, 0.0, 4.0, 3.0, RED, Rasterization::AntiAliased);
+        canvas.flood_fill(0, 0, RED, 255.0, Connectivity::Four, true);
+        canvas.cover_in_blood();
+        canvas.flip_horizontal();
+        assert!(canvas.data.iter().all(|&byte| byte == 0));
+        assert!(!canvas.can_undo());
+
+        // intersecting with no selection also leaves nothing selected
+        canvas.deselect();
+        canvas.select_rect(0.0, 0.0, 2.0, 2.0, SelectionOp::Intersect);
+        assert_eq!(canvas.selection_bounds(), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn flips_mirror_only_the_selection() {
+        let data = (1..=4u8).flat_map(|i| [i * 10, 0, 0, 255]).collect();
+        let mut canvas = CanvasSource::try_new(4, 1, data).unwrap();
+        canvas.select_rect(1.0, 0.0, 2.0, 1.0, SelectionOp::Replace);
+        canvas.flip_horizontal();
+        let row: Vec<u8> = canvas.data.chunks_exact(4).map(|p| p[0]).collect();
+        assert_eq!(row, [10, 30, 20, 40]);
+        canvas.flip_vertical();
+        let row: Vec<u8> = canvas.data.chunks_exact(4).map(|p| p[0]).collect();
+        assert_eq!(row, [10, 30, 20, 40]);
+    }
+
+    #[test]
+    fn cover_in_blood_respects_the_selection() {
+        let mut canvas = canvas(3, 2);
+        canvas.select_rect(1.0, 0.0, 2.0, 1.0, SelectionOp::Replace);
+        canvas.cover_in_blood();
+        assert_eq!(coverage(&canvas), [".##", "..."]);
+        assert_eq!(canvas.pixel(2, 0).unwrap().to_array(), [252, 3, 27, 255]);
+    }
+
+    #[test]
+    fn feathering_softens_the_edge() {
+        let mut canvas = canvas(20, 1);
+        canvas.select_rect(0.0, 0.0, 10.0, 1.0, SelectionOp::Replace);
+        canvas.feather_selection(4.0);
+        let mask = canvas.selection_mask();
+        assert_eq!(mask[0], 255);
+        assert_eq!(mask[19], 0);
+        assert!(mask.windows(2).all(|pair| pair[0] >= pair[1]));
+        // symmetric about the old edge
+        assert_eq!(mask[9] as u32 + mask[10] as u32, 255);
+        assert!(mask[10] > 0 && mask[9] < 255);
+    }
+
+    #[test]
+    fn huge_feathers_spread_over_the_canvas() {
+        let mut canvas = canvas(20, 1);
+        canvas.select_rect(0.0, 0.0, 10.0, 1.0, SelectionOp::Replace);
+        canvas.feather_selection(f32::MAX);
+        let mask = canvas.selection_mask();
+        assert!(mask.windows(2).all(|pair| pair[0] >= pair[1]));
+        assert!(mask[19] > 0 && mask[0] < 255);
+    }
+
+    #[test]
+    fn magic_wand_selects_like_a_flood_fill() {
+        let mut data = [0, 0, 0, 255].repeat(4);
+        data[8..12].copy_from_slice(&[255, 255, 255, 255]);
+        let mut canvas = CanvasSource::try_new(4, 1, data).unwrap();
+        canvas.select_magic_wand(0, 0, 0.0, Connectivity::Four, true, SelectionOp::Replace);
+        assert_eq!(mask_picture(&canvas), ["##.."]);
+        canvas.select_magic_wand(0, 0, 0.0, Connectivity::Four, false, SelectionOp::Replace);
+        assert_eq!(mask_picture(&canvas), ["##.#"]);
+    }
+}
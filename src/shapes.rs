@@ -0,0 +1,804 @@
+use wasm_bindgen::prelude::*;
+
+use crate::canvas_source::CanvasSource;
+use crate::paint::Paint;
+use crate::raster::{ellipse_contour, line_contour, FillRule, Point, Rasterization};
+
+// shape coordinates can be negative or past the far edges so shapes can hang
+// off the buffer; everything is clipped before it is written. See
+// `Rasterization` for how the two modes interpret fractional coordinates.
+#[wasm_bindgen]
+impl CanvasSource {
+    pub fn draw_line(
+        &mut self,
+        x0: f32,
+        y0: f32,
+        x1: f32,
+        y1: f32,
+        color: u32,
+        mode: Rasterization,
+    ) {
+        self.draw_line_paint(x0, y0, x1, y1, &Paint::solid(color), mode);
+    }
+
+    /// `draw_line` with a gradient, pattern or any other paint.
+    pub fn draw_line_paint(
+        &mut self,
+        x0: f32,
+        y0: f32,
+        x1: f32,
+        y1: f32,
+        paint: &Paint,
+        mode: Rasterization,
+    ) {
+        self.edit(|canvas| match mode {
+            Rasterization::Aliased => {
+                canvas.line(snap(x0), snap(y0), snap(x1), snap(y1), paint);
+            }
+            Rasterization::AntiAliased => {
+                let contour = line_contour((x0, y0), (x1, y1));
+                canvas.fill_contours(&[contour], FillRule::NonZero, paint);
+            }
+        })
+    }
+
+    pub fn stroke_rect(
+        &mut self,
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+        color: u32,
+        mode: Rasterization,
+    ) {
+        self.stroke_rect_paint(x, y, width, height, &Paint::solid(color), mode);
+    }
+
+    /// `stroke_rect` with a gradient, pattern or any other paint.
+    pub fn stroke_rect_paint(
+        &mut self,
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+        paint: &Paint,
+        mode: Rasterization,
+    ) {
+        self.edit(|canvas| {
+            match mode {
+                Rasterization::Aliased => {
+                    let (left, top, right, bottom) = match snap_rect(x, y, width, height) {
+                        Some(rect) => rect,
+                        None => return,
+                    };
+                    // each pixel once, so translucent paint doesn't build up
+                    // at the corners or on one pixel wide rectangles
+                    canvas.paint_span(top, left, right, paint);
+                    if bottom != top {
+                        canvas.paint_span(bottom, left, right, paint);
+                    }
+                    for row in (top + 1).max(-1)..bottom.min(canvas.height as i32 + 1) {
+                        canvas.paint_pixel(left, row, paint);
+                        if right != left {
+                            canvas.paint_pixel(right, row, paint);
+                        }
+                    }
+                }
+                Rasterization::AntiAliased => {
+                    if width <= 0.0 || height <= 0.0 {
+                        return;
+                    }
+                    // a one pixel band just inside the rectangle's edge, so
+                    // whole-pixel rectangles come out identical in both modes
+                    let mut contours = vec![rect_contour(x, y, width, height)];
+                    if width > 2.0 && height > 2.0 {
+                        contours.push(rect_contour(x + 1.0, y + 1.0, width - 2.0, height - 2.0));
+                    }
+                    canvas.fill_contours(&contours, FillRule::EvenOdd, paint);
+                }
+            }
+        })
+    }
+
+    pub fn fill_rect(
+        &mut self,
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+        color: u32,
+        mode: Rasterization,
+    ) {
+        self.fill_rect_paint(x, y, width, height, &Paint::solid(color), mode);
+    }
+
+    /// `fill_rect` with a gradient, pattern or any other paint.
+    pub fn fill_rect_paint(
+        &mut self,
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+        paint: &Paint,
+        mode: Rasterization,
+    ) {
+        self.edit(|canvas| match mode {
+            Rasterization::Aliased => {
+                let (left, top, right, bottom) = match snap_rect(x, y, width, height) {
+                    Some(rect) => rect,
+                    None => return,
+                };
+                for row in top.max(0)..=bottom.min(canvas.height as i32 - 1) {
+                    canvas.paint_span(row, left, right, paint);
+                }
+            }
+            Rasterization::AntiAliased => {
+                if width <= 0.0 || height <= 0.0 {
+                    return;
+                }
+                let contour = rect_contour(x, y, width, height);
+                canvas.fill_contours(&[contour], FillRule::NonZero, paint);
+            }
+        })
+    }
+
+    pub fn stroke_circle(
+        &mut self,
+        cx: f32,
+        cy: f32,
+        radius: f32,
+        color: u32,
+        mode: Rasterization,
+    ) {
+        self.stroke_circle_paint(cx, cy, radius, &Paint::solid(color), mode);
+    }
+
+    /// `stroke_circle` with a gradient, pattern or any other paint.
+    pub fn stroke_circle_paint(
+        &mut self,
+        cx: f32,
+        cy: f32,
+        radius: f32,
+        paint: &Paint,
+        mode: Rasterization,
+    ) {
+        self.stroke_ellipse_paint(cx, cy, radius, radius, paint, mode);
+    }
+
+    pub fn fill_circle(&mut self, cx: f32, cy: f32, radius: f32, color: u32, mode: Rasterization) {
+        self.fill_circle_paint(cx, cy, radius, &Paint::solid(color), mode);
+    }
+
+    /// `fill_circle` with a gradient, pattern or any other paint.
+    pub fn fill_circle_paint(
+        &mut self,
+        cx: f32,
+        cy: f32,
+        radius: f32,
+        paint: &Paint,
+        mode: Rasterization,
+    ) {
+        self.fill_ellipse_paint(cx, cy, radius, radius, paint, mode);
+    }
+
+    pub fn stroke_ellipse(
+        &mut self,
+        cx: f32,
+        cy: f32,
+        rx: f32,
+        ry: f32,
+        color: u32,
+        mode: Rasterization,
+    ) {
+        self.stroke_ellipse_paint(cx, cy, rx, ry, &Paint::solid(color), mode);
+    }
+
+    /// `stroke_ellipse` with a gradient, pattern or any other paint.
+    pub fn stroke_ellipse_paint(
+        &mut self,
+        cx: f32,
+        cy: f32,
+        rx: f32,
+        ry: f32,
+        paint: &Paint,
+        mode: Rasterization,
+    ) {
+        self.edit(|canvas| {
+            match mode {
+                Rasterization::Aliased => {
+                    let (cx, cy) = (snap(cx) as i64, snap(cy) as i64);
+                    let (rx, ry) = (snap_radius(rx), snap_radius(ry));
+                    let rows = match quadrant_rows(cy, ry, canvas.height) {
+                        Some(rows) => rows,
+                        None => return,
+                    };
+                    for (y, x0, x1) in ellipse_quadrant(rx, ry, rows) {
+                        // the mirror images of points on the axes coincide
+                        let spans = if x0 == 0 {
+                            vec![(cx - x1, cx + x1)]
+                        } else {
+                            vec![(cx - x1, cx - x0), (cx + x0, cx + x1)]
+                        };
+                        let rows = if y == 0 {
+                            vec![cy]
+                        } else {
+                            vec![cy - y, cy + y]
+                        };
+                        for &row in &rows {
+                            for &(left, right) in &spans {
+                                canvas.paint_span(clamp(row), clamp(left), clamp(right), paint);
+                            }
+                        }
+                    }
+                }
+                Rasterization::AntiAliased => {
+                    let (rx, ry) = (rx.max(0.0), ry.max(0.0));
+                    // a one pixel wide ring centered on the ellipse's outline
+                    let mut contours = vec![ellipse_contour(cx, cy, rx + 0.5, ry + 0.5)];
+                    if rx > 0.5 && ry > 0.5 {
+                        contours.push(ellipse_contour(cx, cy, rx - 0.5, ry - 0.5));
+                    }
+                    canvas.fill_contours(&contours, FillRule::EvenOdd, paint);
+                }
+            }
+        })
+    }
+
+    pub fn fill_ellipse(
+        &mut self,
+        cx: f32,
+        cy: f32,
+        rx: f32,
+        ry: f32,
+        color: u32,
+        mode: Rasterization,
+    ) {
+        self.fill_ellipse_paint(cx, cy, rx, ry, &Paint::solid(color), mode);
+    }
+
+    /// `fill_ellipse` with a gradient, pattern or any other paint.
+    pub fn fill_ellipse_paint(
+        &mut self,
+        cx: f32,
+        cy: f32,
+        rx: f32,
+        ry: f32,
+        paint: &Paint,
+        mode: Rasterization,
+    ) {
+        self.edit(|canvas| {
+            match mode {
+                Rasterization::Aliased => {
+                    let (cx, cy) = (snap(cx) as i64, snap(cy) as i64);
+                    let (rx, ry) = (snap_radius(rx), snap_radius(ry));
+                    let rows = match quadrant_rows(cy, ry, canvas.height) {
+                        Some(rows) => rows,
+                        None => return,
+                    };
+
+                    // out to the end of each row of the quadrant, so the fill
+                    // lines up exactly with what stroke_ellipse draws
+                    for (y, _, half) in ellipse_quadrant(rx, ry, rows) {
+                        let (left, right) = (clamp(cx - half), clamp(cx + half));
+                        canvas.paint_span(clamp(cy + y), left, right, paint);
+                        if y != 0 {
+                            canvas.paint_span(clamp(cy - y), left, right, paint);
+                        }
+                    }
+                }
+                Rasterization::AntiAliased => {
+                    if rx <= 0.0 || ry <= 0.0 {
+                        return;
+                    }
+                    let contour = ellipse_contour(cx, cy, rx, ry);
+                    canvas.fill_contours(&[contour], FillRule::NonZero, paint);
+                }
+            }
+        })
+    }
+
+    /// Outline a closed polygon. `points` is a flat list of x, y pairs.
+    pub fn stroke_polygon(&mut self, points: &[f32], color: u32, mode: Rasterization) {
+        self.stroke_polygon_paint(points, &Paint::solid(color), mode);
+    }
+
+    /// `stroke_polygon` with a gradient, pattern or any other paint.
+    pub fn stroke_polygon_paint(&mut self, points: &[f32], paint: &Paint, mode: Rasterization) {
+        self.edit(|canvas| {
+            let vertices = pairs(points);
+            let edges =
+                (0..vertices.len()).map(|i| (vertices[i], vertices[(i + 1) % vertices.len()]));
+
+            match mode {
+                Rasterization::Aliased => {
+                    // neighboring edges share their end pixels and crossing
+                    // edges share more, but each pixel is only painted once
+                    let mut pixels: Vec<(i32, i32)> = edges
+                        .flat_map(|((x0, y0), (x1, y1))| {
+                            canvas.line_pixels(snap(x0), snap(y0), snap(x1), snap(y1))
+                        })
+                        .collect();
+                    pixels.sort_unstable();
+                    pixels.dedup();
+                    for (x, y) in pixels {
+                        canvas.paint_pixel(x, y, paint);
+                    }
+                }
+                Rasterization::AntiAliased => {
+                    // every edge goes into a single non-zero fill, so the corners
+                    // where edges overlap aren't blended twice
+                    let contours: Vec<Vec<Point>> =
+                        edges.map(|(p0, p1)| line_contour(p0, p1)).collect();
+                    canvas.fill_contours(&contours, FillRule::NonZero, paint);
+                }
+            }
+        })
+    }
+
+    /// Fill a polygon using the even-odd rule. `points` is a flat list of
+    /// x, y pairs; the polygon is closed automatically.
+    pub fn fill_polygon(&mut self, points: &[f32], color: u32, mode: Rasterization) {
+        self.fill_polygon_paint(points, &Paint::solid(color), mode);
+    }
+
+    /// `fill_polygon` with a gradient, pattern or any other paint.
+    pub fn fill_polygon_paint(&mut self, points: &[f32], paint: &Paint, mode: Rasterization) {
+        self.edit(|canvas| {
+            let vertices = pairs(points);
+            if vertices.len() < 3 {
+                return;
+            }
+
+            match mode {
+                Rasterization::Aliased => canvas.fill_polygon_aliased(&vertices, paint),
+                Rasterization::AntiAliased => {
+                    canvas.fill_contours(&[vertices], FillRule::EvenOdd, paint);
+                }
+            }
+        })
+    }
+}
+
+// removed #[wasm_bindgen] - not sent to js
+impl CanvasSource {
+    /// Even-odd polygon fill that lights every pixel whose center falls
+    /// inside the polygon.
+    fn fill_polygon_aliased(&mut self, vertices: &[Point], paint: &Paint) {
+        if vertices
+            .iter()
+            .any(|&(x, y)| !x.is_finite() || !y.is_finite())
+        {
+            return;
+        }
+        let vertices: Vec<(f64, f64)> = vertices
+            .iter()
+            .map(|&(x, y)| (x as f64, y as f64))
+            .collect();
+
+        let min_y = vertices.iter().map(|v| v.1).fold(f64::MAX, f64::min);
+        let max_y = vertices.iter().map(|v| v.1).fold(f64::MIN, f64::max);
+        let first_row = (min_y.floor() as i32).max(0);
+        let last_row = (max_y.ceil() as i32).min(self.height as i32 - 1);
+
+        let mut crossings: Vec<f64> = Vec::new();
+        for row in first_row..=last_row {
+            // sample through the pixel centers
+            let sample_y = row as f64 + 0.5;
+            crossings.clear();
+
+            for (i, &(x0, y0)) in vertices.iter().enumerate() {
+                let (x1, y1) = vertices[(i + 1) % vertices.len()];
+                // half-open test so shared vertices are only counted once
+                if (y0 <= sample_y) != (y1 <= sample_y) {
+                    let t = (sample_y - y0) / (y1 - y0);
+                    crossings.push(x0 + t * (x1 - x0));
+                }
+            }
+            crossings.sort_by(f64::total_cmp);
+
+            for pair in crossings.chunks_exact(2) {
+                let start = (pair[0] - 0.5).ceil() as i32;
+                let end = (pair[1] - 0.5).ceil() as i32 - 1;
+                self.paint_span(row, start, end, paint);
+            }
+        }
+    }
+
+    /// Bresenham line between two points, inclusive of both ends.
+    pub(crate) fn line(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, paint: &Paint) {
+        for (x, y) in self.line_pixels(x0, y0, x1, y1) {
+            self.paint_pixel(x, y, paint);
+        }
+    }
+
+    /// The pixels `line` paints, clipped to the buffer.
+    fn line_pixels(&self, x0: i32, y0: i32, x1: i32, y1: i32) -> Vec<(i32, i32)> {
+        let (x0, y0, x1, y1) = match self.clip_line(x0, y0, x1, y1) {
+            Some(clipped) => clipped,
+            None => return Vec::new(),
+        };
+
+        let dx = (x1 - x0).abs();
+        let dy = -(y1 - y0).abs();
+        let step_x = if x0 < x1 { 1 } else { -1 };
+        let step_y = if y0 < y1 { 1 } else { -1 };
+        let mut err = dx + dy;
+        let (mut x, mut y) = (x0, y0);
+        let mut pixels = Vec::new();
+
+        loop {
+            pixels.push((x, y));
+            if x == x1 && y == y1 {
+                return pixels;
+            }
+            let doubled = 2 * err;
+            if doubled >= dy {
+                err += dy;
+                x += step_x;
+            }
+            if doubled <= dx {
+                err += dx;
+                y += step_y;
+            }
+        }
+    }
+
+    /// Liang-Barsky clip of a segment against the buffer, so a line with
+    /// far off-canvas endpoints doesn't walk millions of invisible pixels.
+    fn clip_line(&self, x0: i32, y0: i32, x1: i32, y1: i32) -> Option<(i32, i32, i32, i32)> {
+        if self.width == 0 || self.height == 0 {
+            return None;
+        }
+        if self.contains(x0, y0) && self.contains(x1, y1) {
+            return Some((x0, y0, x1, y1));
+        }
+
+        let (fx0, fy0) = (x0 as f64, y0 as f64);
+        let (dx, dy) = (x1 as f64 - fx0, y1 as f64 - fy0);
+        let (max_x, max_y) = (self.width as f64 - 1.0, self.height as f64 - 1.0);
+        let (mut t0, mut t1) = (0.0f64, 1.0f64);
+
+        for (p, q) in [(-dx, fx0), (dx, max_x - fx0), (-dy, fy0), (dy, max_y - fy0)] {
+            if p == 0.0 {
+                if q < 0.0 {
+                    return None;
+                }
+            } else {
+                let t = q / p;
+                if p < 0.0 {
+                    t0 = t0.max(t);
+                } else {
+                    t1 = t1.min(t);
+                }
+            }
+        }
+        if t0 > t1 {
+            return None;
+        }
+
+        Some((
+            (fx0 + t0 * dx).round() as i32,
+            (fy0 + t0 * dy).round() as i32,
+            (fx0 + t1 * dx).round() as i32,
+            (fy0 + t1 * dy).round() as i32,
+        ))
+    }
+}
+
+/// Runs (y, x0, x1) of the pixels the midpoint ellipse algorithm picks on
+/// one quadrant of an axis-aligned ellipse centered on the origin, x0 to x1
+/// (inclusive) on row y, for each row from `rows.1` down to `rows.0`. Mirror
+/// them to get the full outline. Each row is worked out on its own rather
+/// than by walking the curve from the top, so the cost depends on how many
+/// rows are asked for, not on the size of the ellipse.
+fn ellipse_quadrant(rx: u32, ry: u32, rows: (u32, u32)) -> Vec<(i64, i64, i64)> {
+    if ry == 0 {
+        return vec![(0, 0, rx as i64)];
+    }
+
+    let (rx2, ry2) = (rx as f64 * rx as f64, ry as f64 * ry as f64);
+    let top = ry as i64;
+    // negative inside the ellipse
+    let f = |x: i64, y: f64| ry2 * x as f64 * x as f64 + rx2 * y * y - rx2 * ry2;
+    // how far out the ellipse reaches at height y
+    let reach = |y: f64| rx as f64 * (1.0 - (y / ry as f64).powi(2)).max(0.0).sqrt();
+    // the walk starts in region 1, stepping in x while the slope is shallower
+    // than -1, and moves to region 2, stepping in y, at the first pixel where
+    // this stops being true
+    let shallow = |x: i64, y: i64| ry2 * (x as f64) < rx2 * (y as f64);
+
+    // in region 1 a row ends at the first pixel where the midpoint below the
+    // next one is outside, and the next row starts one pixel further on
+    let outside_below = |y: i64| {
+        let below = y as f64 - 0.5;
+        first(reach(below).ceil() - 1.0, |x| f(x + 1, below) >= 0.0)
+    };
+    let start = |y: i64| {
+        if y == top {
+            0
+        } else {
+            outside_below(y + 1) + 1
+        }
+    };
+    let end = |y: i64| outside_below(y).max(start(y));
+
+    // the row region 2 takes over on, the first whose run gets that far
+    let in_region_1 = |y: i64| shallow(end(y), y);
+    let switch_row = if in_region_1(top) {
+        let (mut low, mut high) = (0, top);
+        while high - low > 1 {
+            let middle = (low + high) / 2;
+            if in_region_1(middle) {
+                high = middle;
+            } else {
+                low = middle;
+            }
+        }
+        low
+    } else {
+        top
+    };
+    let switch = if shallow(start(switch_row), switch_row) {
+        first((rx2 * switch_row as f64 / ry2).ceil(), |x| {
+            !shallow(x, switch_row)
+        })
+    } else {
+        start(switch_row)
+    };
+
+    (rows.0 as i64..=rows.1 as i64)
+        .rev()
+        .map(|y| {
+            if y > switch_row {
+                (y, start(y), end(y))
+            } else if y == switch_row {
+                (y, start(y), switch)
+            } else {
+                // region 2: one pixel per row, stepping out whenever the
+                // midpoint beside it is inside the ellipse
+                let x = first((reach(y as f64) - 0.5).floor() + 1.0, |x| {
+                    ry2 * (x as f64 + 0.5).powi(2) + rx2 * (y as f64).powi(2) > rx2 * ry2
+                });
+                let x = switch.max(x);
+                (y, x, x)
+            }
+        })
+        .collect()
+}
+
+/// The smallest x >= 0 that passes `test`, which stays true from there on,
+/// searching out from `estimate`.
+fn first(estimate: f64, test: impl Fn(i64) -> bool) -> i64 {
+    let mut x = (estimate as i64).max(0);
+    while !test(x) {
+        x += 1;
+    }
+    while x > 0 && test(x - 1) {
+        x -= 1;
+    }
+    x
+}
+
+/// The rows of an ellipse quadrant of radius `ry` (counting out from the
+/// center row, as (first, last)) that land on a canvas `height` pixels
+/// tall either above or below the center row `cy`.
+fn quadrant_rows(cy: i64, ry: u32, height: u32) -> Option<(u32, u32)> {
+    let (ry, height) = (ry as i64, height as i64);
+    let clip = |from: i64, to: i64| (from.max(0), to.min(ry));
+    [clip(-cy, height - 1 - cy), clip(cy - height + 1, cy)]
+        .iter()
+        .filter(|(from, to)| from <= to)
+        .fold(None, |rows, &(from, to)| match rows {
+            None => Some((from, to)),
+            Some((first, last)) => Some((first.min(from), last.max(to))),
+        })
+        .map(|(first, last)| (first as u32, last as u32))
+}
+
+/// A coordinate worked out in 64 bits, pulled back into range for the
+/// pixel helpers, which clip it anyway.
+fn clamp(value: i64) -> i32 {
+    value.clamp(i32::MIN as i64, i32::MAX as i64) as i32
+}
+
+// past the far edge of the largest canvas (2^28 pixels on a side at most)
+const FAR: f32 = (1 << 30) as f32;
+
+/// Snap a continuous coordinate to the pixel that contains it.
+fn snap(value: f32) -> i32 {
+    value.floor() as i32
+}
+
+fn snap_radius(radius: f32) -> u32 {
+    radius.max(0.0).round() as u32
+}
+
+/// Inclusive pixel bounds of the pixels whose centers fall inside the
+/// rectangle, or `None` if it covers no pixel centers at all.
+fn snap_rect(x: f32, y: f32, width: f32, height: f32) -> Option<(i32, i32, i32, i32)> {
+    // edges far off the canvas are pulled in to where the arithmetic on
+    // them can't overflow but they are still well outside any canvas
+    let edge = |value: f32| value.round().clamp(-FAR, FAR) as i32;
+    let (left, top) = (edge(x), edge(y));
+    let right = edge(x + width) - 1;
+    let bottom = edge(y + height) - 1;
+    if right < left || bottom < top {
+        return None;
+    }
+    Some((left, top, right, bottom))
+}
+
+pub(crate) fn rect_contour(x: f32, y: f32, width: f32, height: f32) -> Vec<Point> {
+    vec![
+        (x, y),
+        (x + width, y),
+        (x + width, y + height),
+        (x, y + height),
+    ]
+}
+
+fn pairs(points: &[f32]) -> Vec<Point> {
+    points.chunks_exact(2).map(|p| (p[0], p[1])).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Rgba;
+    use crate::pictures::painted;
+
+    const RED: u32 = 0xff0000ff;
+    // half transparent, so painting a pixel twice shows up in its alpha
+    const GLASS: u32 = 0xff000080;
+
+    fn canvas(width: u32, height: u32) -> CanvasSource {
+        CanvasSource::try_new(width, height, Vec::new()).unwrap()
+    }
+
+    /// Every distinct alpha that was painted.
+    fn painted_alphas(canvas: &CanvasSource) -> Vec<u8> {
+        let mut alphas: Vec<u8> = canvas
+            .data
+            .chunks_exact(4)
+            .map(|p| p[3])
+            .filter(|&a| a > 0)
+            .collect();
+        alphas.sort_unstable();
+        alphas.dedup();
+        alphas
+    }
+
+    #[test]
+    fn lines_include_both_ends() {
+        let mut c = canvas(5, 3);
+        c.draw_line(0.0, 0.0, 4.0, 2.0, RED, Rasterization::Aliased);
+        assert_eq!(painted(&c), ["#....", ".##..", "...##"]);
+    }
+
+    #[test]
+    fn lines_are_clipped_to_the_canvas() {
+        let mut c = canvas(4, 4);
+        c.draw_line(-1000.0, 1.0, 1000.0, 1.0, RED, Rasterization::Aliased);
+        assert_eq!(painted(&c), ["....", "####", "....", "...."]);
+    }
+
+    #[test]
+    fn fill_rect_covers_whole_pixels() {
+        let mut c = canvas(5, 4);
+        c.fill_rect(1.0, 1.0, 3.0, 2.0, RED, Rasterization::Aliased);
+        assert_eq!(painted(&c), [".....", ".###.", ".###.", "....."]);
+        assert_eq!(c.pixel(2, 2), Some(Rgba::from_u32(RED)));
+    }
+
+    #[test]
+    fn stroke_rect_paints_corners_once() {
+        let mut c = canvas(6, 5);
+        c.stroke_rect(1.0, 1.0, 4.0, 3.0, GLASS, Rasterization::Aliased);
+        assert_eq!(
+            painted(&c),
+            ["......", ".####.", ".#..#.", ".####.", "......"]
+        );
+        assert_eq!(painted_alphas(&c), [0x80]);
+
+        // one pixel wide, where the two sides coincide
+        let mut c = canvas(3, 3);
+        c.stroke_rect(1.0, 0.0, 1.0, 3.0, GLASS, Rasterization::Aliased);
+        assert_eq!(painted(&c), [".#.", ".#.", ".#."]);
+        assert_eq!(painted_alphas(&c), [0x80]);
+    }
+
+    #[test]
+    fn stroke_ellipse_paints_axis_points_once() {
+        let mut c = canvas(9, 9);
+        c.stroke_ellipse(4.0, 4.0, 3.0, 2.0, GLASS, Rasterization::Aliased);
+        assert_eq!(painted_alphas(&c), [0x80]);
+        // the outline reaches the ends of both axes
+        for (x, y) in [(1, 4), (7, 4), (4, 2), (4, 6)] {
+            assert_eq!(c.pixel(x, y).unwrap().a, 0x80, "({}, {})", x, y);
+        }
+        assert_eq!(c.pixel(4, 4), Some(Rgba::TRANSPARENT));
+    }
+
+    #[test]
+    fn stroke_polygon_paints_shared_vertices_once() {
+        let mut c = canvas(6, 6);
+        c.stroke_polygon(
+            &[0.0, 0.0, 5.0, 0.0, 5.0, 5.0],
+            GLASS,
+            Rasterization::Aliased,
+        );
+        assert_eq!(painted_alphas(&c), [0x80]);
+        for (x, y) in [(0, 0), (5, 0), (5, 5), (2, 2)] {
+            assert_eq!(c.pixel(x, y).unwrap().a, 0x80, "({}, {})", x, y);
+        }
+    }
+
+    #[test]
+    fn fill_polygon_lights_pixel_centers_inside() {
+        let mut c = canvas(4, 4);
+        c.fill_polygon(&[0.0, 0.0, 4.0, 0.0, 0.0, 4.0], RED, Rasterization::Aliased);
+        assert_eq!(painted(&c), ["###.", "##..", "#...", "...."]);
+    }
+
+    #[test]
+    fn fill_polygon_ignores_non_finite_points() {
+        let mut c = canvas(4, 4);
+        c.fill_polygon(
+            &[0.0, 0.0, f32::NAN, 1.0, 4.0, 4.0],
+            RED,
+            Rasterization::Aliased,
+        );
+        c.fill_polygon(
+            &[0.0, 0.0, f32::INFINITY, 1.0, 4.0, 4.0],
+            RED,
+            Rasterization::Aliased,
+        );
+        assert!(c.data.iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn shapes_off_the_canvas_draw_nothing() {
+        let mut c = canvas(4, 4);
+        c.fill_rect(10.0, 10.0, 5.0, 5.0, RED, Rasterization::Aliased);
+        c.stroke_circle(-20.0, -20.0, 3.0, RED, Rasterization::Aliased);
+        c.fill_circle(50.0, 2.0, 3.0, RED, Rasterization::Aliased);
+        assert!(c.data.iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn far_off_rectangles_do_not_overflow() {
+        let mut c = canvas(4, 3);
+        c.fill_rect(-5e9, 0.0, 1.0, 1.0, RED, Rasterization::Aliased);
+        c.stroke_rect(-5e9, -5e9, 1e10, 1e10, RED, Rasterization::Aliased);
+        assert!(c.data.iter().all(|&b| b == 0));
+        c.fill_rect(-5e9, 1.0, 1e10, 1.0, RED, Rasterization::Aliased);
+        assert_eq!(painted(&c), ["....", "####", "...."]);
+    }
+
+    #[test]
+    fn huge_ellipses_only_work_out_the_rows_on_the_canvas() {
+        // the top of a circle a billion pixels across, along row 0
+        let mut c = canvas(4, 4);
+        c.stroke_circle(2.0, 1e9, 1e9, RED, Rasterization::Aliased);
+        assert_eq!(painted(&c), ["####", "....", "....", "...."]);
+        let mut c = canvas(4, 4);
+        c.fill_circle(2.0, 1e9, 1e9, RED, Rasterization::Aliased);
+        assert_eq!(painted(&c), ["####"; 4]);
+    }
+
+    #[test]
+    fn clipped_ellipses_match_the_whole_outline() {
+        let mut whole = canvas(40, 40);
+        whole.stroke_ellipse(20.0, 20.0, 13.0, 9.0, RED, Rasterization::Aliased);
+        whole.fill_ellipse(20.0, 20.0, 5.0, 11.0, RED, Rasterization::Aliased);
+        // the same shapes seen through a window near their top edges
+        let mut window = canvas(12, 6);
+        window.stroke_ellipse(8.0, 11.0, 13.0, 9.0, RED, Rasterization::Aliased);
+        window.fill_ellipse(8.0, 11.0, 5.0, 11.0, RED, Rasterization::Aliased);
+        let expected: Vec<String> = painted(&whole)[9..15]
+            .iter()
+            .map(|row| row[12..24].to_string())
+            .collect();
+        assert_eq!(painted(&window), expected);
+    }
+}
@@ -0,0 +1,247 @@
+use wasm_bindgen::prelude::*;
+
+use crate::adjust::{luma_tables, Channel};
+use crate::canvas_source::CanvasSource;
+
+/// Histograms and summary numbers for a canvas or part of one, e.g. for a
+/// histogram panel or to pick levels for auto-contrast.
+///
+/// Every pixel counts the same, transparent ones included. The per-channel
+/// arrays are in red, green, blue, alpha order.
+#[wasm_bindgen]
+pub struct Statistics {
+    // 256 bins each for red, green, blue, alpha and luma
+    histograms: Vec<[u32; 256]>,
+    unique_colors: u32,
+}
+
+#[wasm_bindgen]
+impl Statistics {
+    /// How many pixels were measured.
+    pub fn pixel_count(&self) -> u32 {
+        self.histograms[0].iter().sum()
+    }
+
+    /// 256 counts, one per channel value. `Channel::Rgb` gives the
+    /// histogram of the Rec. 601 luma.
+    pub fn histogram(&self, channel: Channel) -> Vec<u32> {
+        self.histograms[Statistics::index(channel)].to_vec()
+    }
+
+    /// The smallest value of each channel, or empty if no pixels were
+    /// measured.
+    pub fn min(&self) -> Vec<u8> {
+        self.per_channel(|histogram| histogram.iter().position(|&count| count > 0))
+            .map(|value| value.unwrap_or(0) as u8)
+            .collect()
+    }
+
+    /// The largest value of each channel, or empty if no pixels were
+    /// measured.
+    pub fn max(&self) -> Vec<u8> {
+        self.per_channel(|histogram| histogram.iter().rposition(|&count| count > 0))
+            .map(|value| value.unwrap_or(0) as u8)
+            .collect()
+    }
+
+    /// The average of each channel, or empty if no pixels were measured.
+    pub fn mean(&self) -> Vec<f32> {
+        self.per_channel(|histogram| moments(histogram).0 as f32)
+            .collect()
+    }
+
+    /// The (population) standard deviation of each channel, or empty if no
+    /// pixels were measured.
+    pub fn stddev(&self) -> Vec<f32> {
+        self.per_channel(|histogram| {
+            let (mean, mean_of_squares) = moments(histogram);
+            (mean_of_squares - mean * mean).max(0.0).sqrt() as f32
+        })
+        .collect()
+    }
+
+    /// How many different colors (alpha included) there are.
+    pub fn unique_colors(&self) -> u32 {
+        self.unique_colors
+    }
+}
+
+impl Statistics {
+    fn index(channel: Channel) -> usize {
+        match channel {
+            Channel::Red => 0,
+            Channel::Green => 1,
+            Channel::Blue => 2,
+            Channel::Alpha => 3,
+            Channel::Rgb => 4,
+        }
+    }
+
+    /// `f` over the red, green, blue and alpha histograms, or nothing if
+    /// they're empty.
+    fn per_channel<'a, T>(
+        &'a self,
+        f: impl Fn(&[u32; 256]) -> T + 'a,
+    ) -> impl Iterator<Item = T> + 'a {
+        let measured = self.pixel_count() > 0;
+        self.histograms[..4].iter().filter(move |_| measured).map(f)
+    }
+}
+
+/// The mean and the mean of the squares of a histogram's values. Summed in
+/// f64 so large canvases don't lose precision.
+fn moments(histogram: &[u32; 256]) -> (f64, f64) {
+    let count: f64 = histogram.iter().map(|&n| n as f64).sum();
+    let (mut sum, mut squares) = (0.0, 0.0);
+    for (value, &n) in histogram.iter().enumerate() {
+        let (value, n) = (value as f64, n as f64);
+        sum += value * n;
+        squares += value * value * n;
+    }
+    (sum / count, squares / count)
+}
+
+#[wasm_bindgen]
+impl CanvasSource {
+    /// Measure the whole canvas.
+    pub fn statistics(&self) -> Statistics {
+        self.statistics_of_region(0, 0, self.width, self.height)
+    }
+
+    /// Measure the part of a rectangle that's on the canvas. The selection
+    /// is ignored.
+    pub fn statistics_of_region(&self, x: i32, y: i32, width: u32, height: u32) -> Statistics {
+        let mut histograms = vec![[0u32; 256]; 5];
+        // packed colors, sorted and deduplicated at the end: four bytes a
+        // pixel, where a hash set of a photo's colors costs several times that
+        let mut colors = Vec::new();
+        if let Some((x0, y0, x1, y1)) = self.clip_rect(x, y, width, height) {
+            let luma = luma_tables();
+            let luma_of = |p: &[u8]| {
+                (luma[0][p[0] as usize] + luma[1][p[1] as usize] + luma[2][p[2] as usize]) >> 8
+            };
+            colors.reserve((x1 - x0) as usize * (y1 - y0) as usize);
+            for row in y0..y1 {
+                let start = self.pixel_index(x0, row);
+                let end = self.pixel_index(x1 - 1, row) + 4;
+                for p in self.data[start..end].chunks_exact(4) {
+                    for (histogram, &value) in histograms.iter_mut().zip(p) {
+                        histogram[value as usize] += 1;
+                    }
+                    histograms[4][luma_of(p) as usize] += 1;
+                    colors.push(u32::from_be_bytes([p[0], p[1], p[2], p[3]]));
+                }
+            }
+        }
+        colors.sort_unstable();
+        colors.dedup();
+        Statistics {
+            histograms,
+            unique_colors: colors.len() as u32,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn canvas(width: u32, pixels: &[[u8; 4]]) -> CanvasSource {
+        let height = pixels.len() as u32 / width;
+        CanvasSource::try_new(width, height, pixels.concat()).unwrap()
+    }
+
+    fn assert_close(actual: &[f32], expected: &[f32]) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-2, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    const MIXED: [[u8; 4]; 4] = [
+        [0, 0, 0, 255],
+        [255, 255, 255, 255],
+        [255, 0, 0, 255],
+        [0, 0, 0, 0],
+    ];
+
+    #[test]
+    fn histograms_count_every_pixel() {
+        let stats = canvas(2, &MIXED).statistics();
+        assert_eq!(stats.pixel_count(), 4);
+
+        let red = stats.histogram(Channel::Red);
+        assert_eq!(red.len(), 256);
+        assert_eq!((red[0], red[255]), (2, 2));
+        assert_eq!(red.iter().sum::<u32>(), 4);
+        let alpha = stats.histogram(Channel::Alpha);
+        assert_eq!((alpha[0], alpha[255]), (1, 3));
+    }
+
+    #[test]
+    fn rgb_is_the_luma_histogram() {
+        let luma = canvas(2, &MIXED).statistics().histogram(Channel::Rgb);
+        let counted: Vec<(usize, u32)> = luma
+            .iter()
+            .enumerate()
+            .filter(|(_, &n)| n > 0)
+            .map(|(value, &n)| (value, n))
+            .collect();
+        // black twice, then red and white
+        assert_eq!(counted, vec![(0, 2), (76, 1), (255, 1)]);
+    }
+
+    #[test]
+    fn summary_numbers_per_channel() {
+        let stats = canvas(2, &MIXED).statistics();
+        assert_eq!(stats.min(), vec![0, 0, 0, 0]);
+        assert_eq!(stats.max(), vec![255, 255, 255, 255]);
+        assert_close(&stats.mean(), &[127.5, 63.75, 63.75, 191.25]);
+        // a quarter of the pixels 255 apart from the rest is √3/4 · 255
+        let skewed = 255.0 * 3f32.sqrt() / 4.0;
+        assert_close(&stats.stddev(), &[127.5, skewed, skewed, skewed]);
+
+        let flat = canvas(3, &[[7, 8, 9, 10]; 6]).statistics();
+        assert_eq!(flat.min(), vec![7, 8, 9, 10]);
+        assert_eq!(flat.max(), vec![7, 8, 9, 10]);
+        assert_eq!(flat.mean(), vec![7.0, 8.0, 9.0, 10.0]);
+        assert_eq!(flat.stddev(), vec![0.0; 4]);
+    }
+
+    #[test]
+    fn unique_colors_include_alpha() {
+        let pixels = [[1, 2, 3, 255], [1, 2, 3, 255], [1, 2, 3, 0], [3, 2, 1, 255]];
+        assert_eq!(canvas(2, &pixels).statistics().unique_colors(), 3);
+        assert_eq!(canvas(2, &MIXED).statistics().unique_colors(), 4);
+    }
+
+    #[test]
+    fn regions_are_clipped_to_the_canvas() {
+        let canvas = canvas(2, &MIXED);
+        let corner = canvas.statistics_of_region(-1, -1, 2, 2);
+        assert_eq!(corner.pixel_count(), 1);
+        assert_eq!(corner.min(), vec![0, 0, 0, 255]);
+
+        let bottom = canvas.statistics_of_region(0, 1, 10, 10);
+        assert_eq!(bottom.pixel_count(), 2);
+        assert_eq!(bottom.max(), vec![255, 0, 0, 255]);
+        assert_eq!(bottom.unique_colors(), 2);
+    }
+
+    #[test]
+    fn empty_regions_have_no_summary() {
+        let canvas = canvas(2, &MIXED);
+        for stats in [
+            canvas.statistics_of_region(5, 5, 2, 2),
+            canvas.statistics_of_region(0, 0, 0, 2),
+        ] {
+            assert_eq!(stats.pixel_count(), 0);
+            assert_eq!(stats.unique_colors(), 0);
+            assert_eq!(stats.histogram(Channel::Rgb), vec![0; 256]);
+            assert!(stats.min().is_empty());
+            assert!(stats.max().is_empty());
+            assert!(stats.mean().is_empty());
+            assert!(stats.stddev().is_empty());
+        }
+    }
+}
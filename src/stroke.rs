@@ -0,0 +1,669 @@
+use std::f32::consts::PI;
+
+use wasm_bindgen::prelude::*;
+
+use crate::canvas_source::CanvasSource;
+use crate::paint::Paint;
+use crate::path::Path;
+use crate::raster::{FillRule, Point};
+
+// the most dashes and gaps a line is cut into; a pattern that makes more is
+// far too fine to see and the line is stroked solid instead
+const MAX_DASH_PIECES: f64 = 100_000.0;
+
+/// How two segments of a stroke meet at a corner.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineJoin {
+    /// Extend the outer edges to a point, falling back to `Bevel` past the
+    /// miter limit.
+    Miter = 0,
+    Round = 1,
+    /// Cut the corner off flat.
+    Bevel = 2,
+}
+
+/// How the open ends of a stroke (and of every dash) are finished.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineCap {
+    /// Stop flat exactly at the end point.
+    Butt = 0,
+    Round = 1,
+    /// Stop flat half the stroke width past the end point.
+    Square = 2,
+}
+
+/// Settings for `stroke_path`, with the same meaning and defaults as the
+/// Canvas2D line properties.
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct StrokeStyle {
+    width: f32,
+    join: LineJoin,
+    miter_limit: f32,
+    cap: LineCap,
+    dash: Vec<f32>,
+    dash_offset: f32,
+}
+
+#[wasm_bindgen]
+impl StrokeStyle {
+    /// A solid stroke `width` pixels wide with miter joins (limit 10) and
+    /// butt caps.
+    pub fn new(width: f32) -> StrokeStyle {
+        StrokeStyle {
+            width: width.max(0.0),
+            join: LineJoin::Miter,
+            miter_limit: 10.0,
+            cap: LineCap::Butt,
+            dash: Vec::new(),
+            dash_offset: 0.0,
+        }
+    }
+
+    pub fn width(&self) -> f32 {
+        self.width
+    }
+
+    pub fn set_width(&mut self, width: f32) {
+        self.width = width.max(0.0);
+    }
+
+    pub fn join(&self) -> LineJoin {
+        self.join
+    }
+
+    pub fn set_join(&mut self, join: LineJoin) {
+        self.join = join;
+    }
+
+    pub fn miter_limit(&self) -> f32 {
+        self.miter_limit
+    }
+
+    /// How long a miter may get, as a multiple of the stroke width, before
+    /// the corner is beveled instead.
+    pub fn set_miter_limit(&mut self, miter_limit: f32) {
+        self.miter_limit = miter_limit.max(1.0);
+    }
+
+    pub fn cap(&self) -> LineCap {
+        self.cap
+    }
+
+    pub fn set_cap(&mut self, cap: LineCap) {
+        self.cap = cap;
+    }
+
+    /// Alternating dash and gap lengths, starting `offset` pixels into the
+    /// pattern. An odd-length list is repeated to make it even, and an empty
+    /// one draws a solid line. Like Canvas2D's `setLineDash`, a list with
+    /// negative or non-finite lengths is ignored. A pattern so fine that it
+    /// would cut a line into more than 100,000 pieces draws it solid.
+    pub fn set_dash(&mut self, dash: &[f32], offset: f32) {
+        if dash
+            .iter()
+            .any(|&length| !length.is_finite() || length < 0.0)
+        {
+            return;
+        }
+        self.dash = dash.to_vec();
+        if self.dash.len() % 2 == 1 {
+            self.dash.extend_from_slice(dash);
+        }
+        self.dash_offset = if offset.is_finite() { offset } else { 0.0 };
+    }
+
+    pub fn dash(&self) -> Vec<f32> {
+        self.dash.clone()
+    }
+
+    pub fn dash_offset(&self) -> f32 {
+        self.dash_offset
+    }
+}
+
+#[wasm_bindgen]
+impl Path {
+    /// The area the stroke would paint, as a path to fill with
+    /// `FillRule::NonZero`. It is made of overlapping pieces (one per
+    /// segment, join and cap), so the even-odd rule would leave gaps.
+    pub fn stroked(&self, style: &StrokeStyle) -> Path {
+        Path::from_contours(&stroke_contours(self, style))
+    }
+}
+
+#[wasm_bindgen]
+impl CanvasSource {
+    /// Draw the outline of `path`, anti-aliased. Coordinates are continuous,
+    /// as with `Rasterization::AntiAliased`.
+    pub fn stroke_path(&mut self, path: &Path, color: u32, style: &StrokeStyle) {
+        self.stroke_path_paint(path, &Paint::solid(color), style);
+    }
+
+    /// `stroke_path` with a gradient, pattern or any other paint.
+    pub fn stroke_path_paint(&mut self, path: &Path, paint: &Paint, style: &StrokeStyle) {
+        let contours = stroke_contours(path, style);
+        self.edit(|canvas| canvas.fill_contours(&contours, FillRule::NonZero, paint))
+    }
+}
+
+/// Outline pieces covering the stroke of `path`, all wound the same way
+/// round so they merge under the non-zero rule.
+pub(crate) fn stroke_contours(path: &Path, style: &StrokeStyle) -> Vec<Vec<Point>> {
+    let mut contours = Vec::new();
+    if style.width <= 0.0 {
+        return contours;
+    }
+    let dashing = !style.dash.is_empty() && style.dash.iter().sum::<f32>() > 0.0;
+    for line in path.flatten() {
+        let mut points = dedup(&line.points);
+        // a closed subpath that already ends back at its start
+        if line.closed
+            && points.len() > 2
+            && length(sub(points[0], points[points.len() - 1])) <= 1e-4
+        {
+            points.pop();
+        }
+        let dashes = if dashing {
+            dash_polyline(&points, line.closed, style)
+        } else {
+            None
+        };
+        match dashes {
+            Some(dashes) => {
+                for dash in dashes {
+                    stroke_polyline(&dash, false, style, &mut contours);
+                }
+            }
+            None => stroke_polyline(&points, line.closed, style, &mut contours),
+        }
+    }
+    contours
+        .into_iter()
+        .map(|contour| {
+            if signed_area(&contour) < 0.0 {
+                contour.into_iter().rev().collect()
+            } else {
+                contour
+            }
+        })
+        .collect()
+}
+
+/// Drop repeated points, which have no direction to stroke along. A line
+/// that collapses to a single point keeps it, so caps can still draw a dot.
+fn dedup(points: &[Point]) -> Vec<Point> {
+    let mut out: Vec<Point> = Vec::with_capacity(points.len());
+    for &p in points {
+        if out.last().is_none_or(|&last| length(sub(p, last)) > 1e-4) {
+            out.push(p);
+        }
+    }
+    out
+}
+
+/// Cut a polyline into the pieces the dash pattern keeps, or `None` if the
+/// pattern is too fine to follow and the line should be stroked solid.
+fn dash_polyline(points: &[Point], closed: bool, style: &StrokeStyle) -> Option<Vec<Vec<Point>>> {
+    let mut points = points.to_vec();
+    if closed && points.len() > 1 {
+        points.push(points[0]);
+    }
+    // distances along the line in f64, so a dash much shorter than the
+    // line's coordinates still moves it along
+    let pattern: Vec<f64> = style.dash.iter().map(|&l| l as f64).collect();
+    let total: f64 = pattern.iter().sum();
+    let lengths: Vec<f64> = points
+        .windows(2)
+        .map(|pair| length(sub(pair[1], pair[0])) as f64)
+        .collect();
+    let line_length: f64 = lengths.iter().sum();
+    if line_length / total * pattern.len() as f64 > MAX_DASH_PIECES {
+        return None;
+    }
+
+    // find where in the pattern the offset lands: the dash or gap at
+    // `index` ends `edge` along the line
+    let offset = (style.dash_offset as f64).rem_euclid(total);
+    let mut index = 0;
+    let mut edge = pattern[0] - offset;
+    while offset > 0.0 && edge <= 0.0 {
+        index = (index + 1) % pattern.len();
+        edge += pattern[index];
+    }
+
+    let mut dashes = Vec::new();
+    // even indices are dashes, odd ones gaps
+    let mut current = if index % 2 == 0 {
+        vec![points[0]]
+    } else {
+        Vec::new()
+    };
+    let mut travelled = 0.0;
+    for (pair, &segment) in points.windows(2).zip(&lengths) {
+        let (from, to) = (pair[0], pair[1]);
+        let end = travelled + segment;
+        // every dash or gap that ends on this segment
+        while edge <= end {
+            let t = if segment > 0.0 {
+                ((edge - travelled) / segment) as f32
+            } else {
+                1.0
+            };
+            let at = (from.0 + (to.0 - from.0) * t, from.1 + (to.1 - from.1) * t);
+            current.push(at);
+            if index % 2 == 0 {
+                dashes.push(std::mem::take(&mut current));
+            }
+            index = (index + 1) % pattern.len();
+            edge += pattern[index];
+        }
+        if index % 2 == 0 {
+            current.push(to);
+        }
+        travelled = end;
+    }
+    if index % 2 == 0 && current.len() > 1 {
+        dashes.push(current);
+    }
+    Some(dashes.iter().map(|dash| dedup(dash)).collect())
+}
+
+fn stroke_polyline(points: &[Point], closed: bool, style: &StrokeStyle, out: &mut Vec<Vec<Point>>) {
+    let half = style.width / 2.0;
+    if points.len() == 1 {
+        // a zero-length line: only caps with some extent show up
+        let p = points[0];
+        match style.cap {
+            LineCap::Butt => {}
+            LineCap::Round => out.push(arc(p, half, 0.0, 2.0 * PI)),
+            LineCap::Square => out.push(vec![
+                (p.0 - half, p.1 - half),
+                (p.0 + half, p.1 - half),
+                (p.0 + half, p.1 + half),
+                (p.0 - half, p.1 + half),
+            ]),
+        }
+        return;
+    }
+
+    let closed = closed && points.len() > 2;
+    let count = points.len();
+    let segments = if closed { count } else { count - 1 };
+    for i in 0..segments {
+        let (a, b) = (points[i], points[(i + 1) % count]);
+        let n = scale(normal(sub(b, a)), half);
+        out.push(vec![add(a, n), add(b, n), sub(b, n), sub(a, n)]);
+    }
+
+    let joins = if closed { 0..count } else { 1..count - 1 };
+    for i in joins {
+        let prev = points[(i + count - 1) % count];
+        let (p, next) = (points[i], points[(i + 1) % count]);
+        join(prev, p, next, style, out);
+    }
+
+    if !closed {
+        cap(points[0], sub(points[0], points[1]), style, out);
+        cap(
+            points[count - 1],
+            sub(points[count - 1], points[count - 2]),
+            style,
+            out,
+        );
+    }
+}
+
+/// The wedge filling the outside of the corner at `p`.
+fn join(prev: Point, p: Point, next: Point, style: &StrokeStyle, out: &mut Vec<Vec<Point>>) {
+    let half = style.width / 2.0;
+    let (d0, d1) = (unit(sub(p, prev)), unit(sub(next, p)));
+    let cross = d0.0 * d1.1 - d0.1 * d1.0;
+    let dot = d0.0 * d1.0 + d0.1 * d1.1;
+    if cross.abs() < 1e-6 && dot > 0.0 {
+        // straight on, the segments already meet
+        return;
+    }
+    // the outer side is the one the path turns away from
+    let side = if cross > 0.0 { -half } else { half };
+    let (n0, n1) = (scale(normal(d0), side), scale(normal(d1), side));
+    let (o0, o1) = (add(p, n0), add(p, n1));
+
+    match style.join {
+        LineJoin::Round => {
+            let start = n0.1.atan2(n0.0);
+            let mut sweep = n1.1.atan2(n1.0) - start;
+            // the short way round, which is the outside of the turn
+            if sweep > PI {
+                sweep -= 2.0 * PI;
+            } else if sweep < -PI {
+                sweep += 2.0 * PI;
+            }
+            if cross.abs() < 1e-6 {
+                // the path doubles back on itself: round off the far end,
+                // the way it was heading
+                let towards = n0.0 * d0.1 - n0.1 * d0.0;
+                sweep = if towards > 0.0 { PI } else { -PI };
+            }
+            let mut wedge = vec![p];
+            wedge.extend(arc(p, half, start, sweep));
+            out.push(wedge);
+        }
+        LineJoin::Miter | LineJoin::Bevel => {
+            // the miter's length over the stroke width is 1 / sin(a / 2),
+            // a being the angle between the segments
+            let sin_half = ((1.0 + dot) / 2.0).max(0.0).sqrt();
+            let miter = style.join == LineJoin::Miter
+                && sin_half > 1e-6
+                && 1.0 / sin_half <= style.miter_limit;
+            if miter {
+                let bisector = unit(add(n0, n1));
+                let tip = add(p, scale(bisector, half / sin_half));
+                out.push(vec![p, o0, tip, o1]);
+            } else {
+                out.push(vec![p, o0, o1]);
+            }
+        }
+    }
+}
+
+/// The cap at `end`, with `outward` pointing away from the line.
+fn cap(end: Point, outward: Point, style: &StrokeStyle, out: &mut Vec<Vec<Point>>) {
+    let half = style.width / 2.0;
+    let d = scale(unit(outward), half);
+    let n = scale(normal(outward), half);
+    match style.cap {
+        LineCap::Butt => {}
+        LineCap::Square => out.push(vec![
+            add(end, n),
+            add(add(end, n), d),
+            add(sub(end, n), d),
+            sub(end, n),
+        ]),
+        LineCap::Round => {
+            let start = n.1.atan2(n.0);
+            // half a turn from one side of the line to the other, through
+            // the outward direction
+            let sweep = if n.0 * d.1 - n.1 * d.0 > 0.0 { PI } else { -PI };
+            out.push(arc(end, half, start, sweep));
+        }
+    }
+}
+
+/// Points along an arc of a circle, from angle `start` through `sweep`
+/// radians, close enough to stay within a tenth of a pixel of it.
+fn arc(center: Point, radius: f32, start: f32, sweep: f32) -> Vec<Point> {
+    let step = 2.0 * (1.0 - 0.1 / radius.max(0.5)).max(-1.0).acos();
+    let segments = ((sweep.abs() / step).ceil() as usize).clamp(1, 1000);
+    (0..=segments)
+        .map(|i| {
+            let angle = start + sweep * i as f32 / segments as f32;
+            (
+                center.0 + radius * angle.cos(),
+                center.1 + radius * angle.sin(),
+            )
+        })
+        .collect()
+}
+
+fn signed_area(contour: &[Point]) -> f32 {
+    let mut area = 0.0;
+    for (i, a) in contour.iter().enumerate() {
+        let b = contour[(i + 1) % contour.len()];
+        area += a.0 * b.1 - b.0 * a.1;
+    }
+    area / 2.0
+}
+
+fn add(a: Point, b: Point) -> Point {
+    (a.0 + b.0, a.1 + b.1)
+}
+
+fn sub(a: Point, b: Point) -> Point {
+    (a.0 - b.0, a.1 - b.1)
+}
+
+fn scale(v: Point, factor: f32) -> Point {
+    (v.0 * factor, v.1 * factor)
+}
+
+fn length(v: Point) -> f32 {
+    (v.0 * v.0 + v.1 * v.1).sqrt()
+}
+
+fn unit(v: Point) -> Point {
+    let length = length(v);
+    if length > 0.0 {
+        scale(v, 1.0 / length)
+    } else {
+        (1.0, 0.0)
+    }
+}
+
+/// The unit vector a quarter turn from `v`.
+fn normal(v: Point) -> Point {
+    let v = unit(v);
+    (-v.1, v.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pictures::coverage;
+
+    const RED: u32 = 0xff0000ff;
+
+    fn line(points: &[Point]) -> Path {
+        let mut path = Path::new();
+        path.move_to(points[0].0, points[0].1);
+        for &(x, y) in &points[1..] {
+            path.line_to(x, y);
+        }
+        path
+    }
+
+    fn stroke(width: u32, height: u32, path: &Path, style: &StrokeStyle) -> CanvasSource {
+        let mut canvas = CanvasSource::try_new(width, height, Vec::new()).unwrap();
+        canvas.stroke_path(path, RED, style);
+        canvas
+    }
+
+    fn alpha(canvas: &CanvasSource, x: i32, y: i32) -> u8 {
+        canvas.pixel(x, y).unwrap().a
+    }
+
+    #[test]
+    fn width_spreads_evenly_either_side() {
+        let canvas = stroke(
+            8,
+            6,
+            &line(&[(1.0, 3.0), (7.0, 3.0)]),
+            &StrokeStyle::new(2.0),
+        );
+        assert_eq!(
+            coverage(&canvas),
+            ["........", "........", ".######.", ".######.", "........", "........"]
+        );
+
+        let thin = stroke(
+            8,
+            2,
+            &line(&[(1.0, 1.0), (7.0, 1.0)]),
+            &StrokeStyle::new(1.0),
+        );
+        assert_eq!(coverage(&thin), [".++++++.", ".++++++."]);
+        assert_eq!(alpha(&thin, 3, 0), 128);
+
+        let none = stroke(
+            8,
+            2,
+            &line(&[(1.0, 1.0), (7.0, 1.0)]),
+            &StrokeStyle::new(0.0),
+        );
+        assert!(none.data.iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn caps_finish_the_ends() {
+        let path = line(&[(2.0, 2.0), (6.0, 2.0)]);
+        let mut style = StrokeStyle::new(2.0);
+
+        style.set_cap(LineCap::Square);
+        assert_eq!(
+            coverage(&stroke(8, 4, &path, &style)),
+            ["........", ".######.", ".######.", "........"]
+        );
+
+        style.set_cap(LineCap::Round);
+        let round = stroke(8, 4, &path, &style);
+        assert_eq!(
+            coverage(&round),
+            ["........", ".+####+.", ".+####+.", "........"]
+        );
+        // a quarter circle of radius one, give or take the tenth of a pixel
+        // the arc may stray along its length
+        let quarter = alpha(&round, 1, 1) as f32 / 255.0;
+        assert!(
+            (quarter - std::f32::consts::FRAC_PI_4).abs() < 0.1 * std::f32::consts::FRAC_PI_2,
+            "{}",
+            quarter
+        );
+    }
+
+    #[test]
+    fn zero_length_lines_draw_only_caps() {
+        let dot = line(&[(2.0, 2.0), (2.0, 2.0)]);
+        let mut style = StrokeStyle::new(2.0);
+        let butt = stroke(4, 4, &dot, &style);
+        assert!(butt.data.iter().all(|&b| b == 0));
+
+        style.set_cap(LineCap::Square);
+        assert_eq!(
+            coverage(&stroke(4, 4, &dot, &style)),
+            ["....", ".##.", ".##.", "...."]
+        );
+
+        style.set_cap(LineCap::Round);
+        assert_eq!(
+            coverage(&stroke(4, 4, &dot, &style)),
+            ["....", ".++.", ".++.", "...."]
+        );
+    }
+
+    #[test]
+    fn joins_fill_the_outside_corner() {
+        // turning from heading right to heading up at (8, 8)
+        let corner = line(&[(2.0, 8.0), (8.0, 8.0), (8.0, 2.0)]);
+        let mut style = StrokeStyle::new(4.0);
+
+        let miter = stroke(12, 12, &corner, &style);
+        assert_eq!(alpha(&miter, 9, 9), 255);
+
+        style.set_join(LineJoin::Bevel);
+        let bevel = stroke(12, 12, &corner, &style);
+        assert_eq!(alpha(&bevel, 8, 8), 255);
+        assert_eq!(alpha(&bevel, 9, 8), 128);
+        assert_eq!(alpha(&bevel, 9, 9), 0);
+
+        style.set_join(LineJoin::Round);
+        let round = stroke(12, 12, &corner, &style);
+        assert_eq!(alpha(&round, 8, 8), 255);
+        let rounded = alpha(&round, 9, 9);
+        assert!(rounded > 0 && rounded < 255, "{}", rounded);
+
+        // a right angle's miter is √2 times the width
+        style.set_join(LineJoin::Miter);
+        style.set_miter_limit(1.4);
+        assert_eq!(stroke(12, 12, &corner, &style).data, bevel.data);
+        style.set_miter_limit(1.5);
+        assert_eq!(stroke(12, 12, &corner, &style).data, miter.data);
+    }
+
+    #[test]
+    fn closed_subpaths_join_all_the_way_round() {
+        let mut square = line(&[(2.0, 2.0), (6.0, 2.0), (6.0, 6.0), (2.0, 6.0)]);
+        square.close();
+        let canvas = stroke(8, 8, &square, &StrokeStyle::new(2.0));
+        assert_eq!(
+            coverage(&canvas),
+            [
+                "........", ".######.", ".######.", ".##..##.", ".##..##.", ".######.", ".######.",
+                "........",
+            ]
+        );
+    }
+
+    #[test]
+    fn dashes_follow_the_pattern() {
+        let path = line(&[(0.0, 1.0), (12.0, 1.0)]);
+        let mut style = StrokeStyle::new(2.0);
+        style.set_dash(&[2.0, 2.0], 0.0);
+        assert_eq!(coverage(&stroke(12, 2, &path, &style))[0], "##..##..##..");
+
+        style.set_dash(&[2.0, 2.0], 1.0);
+        assert_eq!(coverage(&stroke(12, 2, &path, &style))[0], "#..##..##..#");
+
+        // an odd list is repeated: dash 3, gap 1, dash 1 then gap 3
+        style.set_dash(&[3.0, 1.0, 1.0], 0.0);
+        assert_eq!(style.dash(), vec![3.0, 1.0, 1.0, 3.0, 1.0, 1.0]);
+        assert_eq!(coverage(&stroke(12, 2, &path, &style))[0], "###.#...#.##");
+
+        // bad lists are ignored
+        style.set_dash(&[2.0, -1.0], 0.0);
+        assert_eq!(style.dash(), vec![3.0, 1.0, 1.0, 3.0, 1.0, 1.0]);
+        style.set_dash(&[], 0.0);
+        assert_eq!(coverage(&stroke(12, 2, &path, &style))[0], "############");
+    }
+
+    #[test]
+    fn dashes_too_fine_to_see_stroke_solid() {
+        let path = line(&[(0.0, 1.0), (2000.0, 1.0)]);
+        let mut style = StrokeStyle::new(2.0);
+        style.set_dash(&[1e-5], 0.0);
+        assert_eq!(stroke_contours(&path, &style).len(), 1);
+
+        // short dashes far along a line still add up
+        let path = line(&[(0.0, 1.0), (4000.0, 1.0)]);
+        style.set_dash(&[0.05, 0.05], 0.0);
+        assert_eq!(stroke_contours(&path, &style).len(), 40_000);
+    }
+
+    #[test]
+    fn dashes_carry_on_round_corners() {
+        let mut square = line(&[(2.0, 2.0), (6.0, 2.0), (6.0, 6.0), (2.0, 6.0)]);
+        square.close();
+        let mut style = StrokeStyle::new(1.0);
+        style.set_dash(&[6.0, 2.0], 0.0);
+        let contours = stroke_contours(&square, &style);
+        // each dash is two segments and the join between them
+        assert_eq!(contours.len(), 2 * 3);
+    }
+
+    #[test]
+    fn stroked_paths_fill_like_the_stroke() {
+        let path = line(&[(1.0, 6.0), (4.0, 1.5), (7.0, 6.0)]);
+        let mut style = StrokeStyle::new(1.5);
+        style.set_join(LineJoin::Round);
+        style.set_cap(LineCap::Round);
+        let stroked = stroke(8, 8, &path, &style);
+
+        let mut filled = CanvasSource::try_new(8, 8, Vec::new()).unwrap();
+        filled.fill_path(&path.stroked(&style), RED, FillRule::NonZero);
+        assert_eq!(filled.data, stroked.data);
+    }
+
+    #[test]
+    fn a_stroke_is_one_undo_step() {
+        let mut canvas = CanvasSource::try_new(8, 8, Vec::new()).unwrap();
+        let before = canvas.data.clone();
+        let mut style = StrokeStyle::new(3.0);
+        style.set_dash(&[1.0, 1.0], 0.0);
+        canvas.stroke_path(&line(&[(0.0, 0.0), (8.0, 8.0)]), RED, &style);
+        assert_ne!(canvas.data, before);
+        assert!(canvas.undo());
+        assert_eq!(canvas.data, before);
+        assert!(!canvas.can_undo());
+    }
+}
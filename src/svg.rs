@@ -0,0 +1,1656 @@
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::fmt;
+
+use wasm_bindgen::prelude::*;
+
+use crate::canvas_source::CanvasSource;
+use crate::color::Rgba;
+use crate::paint::{Paint, Spread};
+use crate::path::Path;
+use crate::raster::{FillRule, Point};
+use crate::stroke::{stroke_contours, LineCap, LineJoin, StrokeStyle};
+use crate::transform::Transform;
+
+// the size CSS gives an <svg> with neither a size nor a viewBox
+const DEFAULT_SIZE: (f32, f32) = (300.0, 150.0);
+
+// control point distance for a quarter circle of radius 1 drawn as a cubic
+const KAPPA: f32 = 0.552_284_8;
+
+// how deeply elements may nest; parsing, collecting gradients and drawing
+// all recurse through the tree, so this keeps them off the end of the stack
+const MAX_DEPTH: usize = 256;
+
+#[derive(Debug)]
+pub enum SvgError {
+    /// Malformed XML, with the (1-based) line the problem was found on.
+    Syntax { line: usize, message: String },
+    /// The document's root element isn't `<svg>`.
+    NotSvg,
+    /// The image would be empty, larger than a canvas can be, or has a
+    /// size that isn't a number.
+    Size(f32, f32),
+}
+
+impl fmt::Display for SvgError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SvgError::Syntax { line, message } => {
+                write!(f, "invalid SVG (line {}): {}", line, message)
+            }
+            SvgError::NotSvg => write!(f, "the root element isn't <svg>"),
+            SvgError::Size(width, height) => {
+                write!(f, "can't draw an SVG of size {}x{}", width, height)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SvgError {}
+
+#[wasm_bindgen]
+impl CanvasSource {
+    /// Rasterize an SVG document into a new canvas, `scale` canvas pixels
+    /// per SVG pixel. Throws if the text isn't well-formed SVG.
+    ///
+    /// Only a subset is drawn: `path`, `rect`, `circle`, `ellipse`, `line`,
+    /// `polyline` and `polygon` elements, nested in `g`s with transforms,
+    /// filled and stroked with colors or linear and radial gradients.
+    /// Anything else (text, images, `use`, clipping, masks, filters, CSS
+    /// stylesheets) is skipped.
+    pub fn from_svg(text: &str, scale: f32) -> Result<CanvasSource, JsError> {
+        Ok(CanvasSource::decode_svg(text, scale)?)
+    }
+
+    /// Rasterize an SVG document onto this canvas with its top left corner
+    /// at (x, y). Everything it draws is undone as one step.
+    pub fn draw_svg(&mut self, text: &str, x: f32, y: f32, scale: f32) -> Result<(), JsError> {
+        Ok(self.render_svg(text, x, y, scale)?)
+    }
+}
+
+// removed #[wasm_bindgen] - the Rust-side API for the native binary
+impl CanvasSource {
+    pub fn decode_svg(text: &str, scale: f32) -> Result<CanvasSource, SvgError> {
+        let root = parse_document(text)?;
+        let (width, height) = document_size(&root);
+        let (width, height) = (width * scale, height * scale);
+        if !(width >= 1.0 && height >= 1.0 && width.is_finite() && height.is_finite()) {
+            return Err(SvgError::Size(width, height));
+        }
+        // float to int casts saturate, so huge sizes still fail try_new
+        let mut canvas =
+            CanvasSource::try_new(width.ceil() as u32, height.ceil() as u32, Vec::new())
+                .map_err(|_| SvgError::Size(width, height))?;
+        // drawn outside an edit, so a fresh canvas has nothing to undo
+        canvas.draw_document(&root, 0.0, 0.0, scale);
+        Ok(canvas)
+    }
+
+    pub fn render_svg(&mut self, text: &str, x: f32, y: f32, scale: f32) -> Result<(), SvgError> {
+        let root = parse_document(text)?;
+        self.edit(|canvas| canvas.draw_document(&root, x, y, scale));
+        Ok(())
+    }
+
+    fn draw_document(&mut self, root: &Element, x: f32, y: f32, scale: f32) {
+        let mut gradients = HashMap::new();
+        collect_gradients(root, &mut gradients);
+        let (width, height) = document_size(root);
+
+        let mut ctm = Transform::identity();
+        ctm.translate(x, y);
+        ctm.scale(scale, scale);
+        let mut viewport = (width, height);
+        if let Some([min_x, min_y, view_width, view_height]) = root.view_box() {
+            // the default preserveAspectRatio, xMidYMid meet: scale
+            // uniformly to fit and center
+            let fit = (width / view_width).min(height / view_height);
+            ctm.translate(
+                (width - view_width * fit) / 2.0,
+                (height - view_height * fit) / 2.0,
+            );
+            ctm.scale(fit, fit);
+            ctm.translate(-min_x, -min_y);
+            viewport = (view_width, view_height);
+        }
+
+        let renderer = Renderer {
+            gradients,
+            viewport,
+        };
+        // the root's own presentation attributes apply to everything in it
+        let style = Style::default().for_element(root);
+        if style.display {
+            renderer.draw_children(self, root, &style, &ctm);
+        }
+    }
+}
+
+/// The document's size in SVG pixels, from its `width` and `height`, then
+/// its `viewBox`, then the CSS default.
+fn document_size(root: &Element) -> (f32, f32) {
+    let view_box = root.view_box();
+    let width = root.attribute("width").and_then(parse_length);
+    let height = root.attribute("height").and_then(parse_length);
+    match (width, height, view_box) {
+        (Some(width), Some(height), _) => (width, height),
+        (Some(width), None, Some([_, _, w, h])) => (width, width * h / w),
+        (None, Some(height), Some([_, _, w, h])) => (height * w / h, height),
+        (None, None, Some([_, _, w, h])) => (w, h),
+        (width, height, None) => (
+            width.unwrap_or(DEFAULT_SIZE.0),
+            height.unwrap_or(DEFAULT_SIZE.1),
+        ),
+    }
+}
+
+// --- XML ---------------------------------------------------------------
+
+struct Element {
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<Element>,
+}
+
+impl Element {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// A numeric attribute, or `default` if it's missing or not a length.
+    fn length(&self, name: &str, default: f32) -> f32 {
+        self.attribute(name)
+            .and_then(parse_length)
+            .unwrap_or(default)
+    }
+
+    /// A valid `viewBox`: min x, min y, width, height.
+    fn view_box(&self) -> Option<[f32; 4]> {
+        let numbers = parse_numbers(self.attribute("viewBox")?);
+        match numbers[..] {
+            [x, y, width, height] if width > 0.0 && height > 0.0 => Some([x, y, width, height]),
+            _ => None,
+        }
+    }
+
+    /// The `href` (or older `xlink:href`) target id of a `#id` link.
+    fn link(&self) -> Option<&str> {
+        self.attribute("href")
+            .or_else(|| self.attribute("xlink:href"))
+            .and_then(|href| href.strip_prefix('#'))
+    }
+}
+
+/// Parse the document and check that it is an SVG.
+fn parse_document(text: &str) -> Result<Element, SvgError> {
+    let mut reader = XmlReader { text, pos: 0 };
+    let root = reader.document()?;
+    // namespaced documents may spell it svg:svg
+    if root.name != "svg" && !root.name.ends_with(":svg") {
+        return Err(SvgError::NotSvg);
+    }
+    Ok(root)
+}
+
+/// Just enough of an XML parser for SVG files: elements, attributes,
+/// comments, processing instructions, doctypes and CDATA. Text content is
+/// skipped since nothing drawn here uses it.
+struct XmlReader<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> XmlReader<'a> {
+    fn error(&self, message: &str) -> SvgError {
+        SvgError::Syntax {
+            line: self.text[..self.pos].matches('\n').count() + 1,
+            message: message.to_string(),
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    /// Skip past the next `end`, or fail with `message` if there isn't one.
+    fn skip_past(&mut self, end: &str, message: &str) -> Result<(), SvgError> {
+        match self.rest().find(end) {
+            Some(at) => {
+                self.pos += at + end.len();
+                Ok(())
+            }
+            None => Err(self.error(message)),
+        }
+    }
+
+    /// Skip comments, processing instructions, doctypes and CDATA sections
+    /// at the cursor. Returns whether anything was skipped.
+    fn skip_markup(&mut self) -> Result<bool, SvgError> {
+        let rest = self.rest();
+        if rest.starts_with("<!--") {
+            self.skip_past("-->", "unclosed comment")?;
+        } else if rest.starts_with("<?") {
+            self.skip_past("?>", "unclosed processing instruction")?;
+        } else if rest.starts_with("<![CDATA[") {
+            self.skip_past("]]>", "unclosed CDATA section")?;
+        } else if rest.starts_with("<!") {
+            // a doctype, possibly with an internal subset in brackets
+            let end = match rest.find('[') {
+                Some(open) if open < rest.find('>').unwrap_or(usize::MAX) => "]>",
+                _ => ">",
+            };
+            self.skip_past(end, "unclosed doctype")?;
+        } else {
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    fn document(&mut self) -> Result<Element, SvgError> {
+        loop {
+            self.skip_whitespace();
+            if !self.skip_markup()? {
+                break;
+            }
+        }
+        if !self.rest().starts_with('<') {
+            return Err(self.error("expected an element"));
+        }
+        self.element(1)
+    }
+
+    fn name(&mut self) -> Result<String, SvgError> {
+        let rest = self.rest();
+        let end = rest
+            .find(|c: char| c.is_whitespace() || matches!(c, '=' | '>' | '/'))
+            .unwrap_or(rest.len());
+        if end == 0 {
+            return Err(self.error("expected a name"));
+        }
+        self.pos += end;
+        Ok(rest[..end].to_string())
+    }
+
+    /// An element starting at the `<` under the cursor, with everything
+    /// nested in it. `depth` counts it and the elements it's nested in.
+    fn element(&mut self, depth: usize) -> Result<Element, SvgError> {
+        if depth > MAX_DEPTH {
+            return Err(self.error(&format!("elements nested more than {} deep", MAX_DEPTH)));
+        }
+        self.pos += 1;
+        let name = self.name()?;
+        let mut attributes = Vec::new();
+        loop {
+            self.skip_whitespace();
+            let rest = self.rest();
+            if rest.starts_with("/>") {
+                self.pos += 2;
+                return Ok(Element {
+                    name,
+                    attributes,
+                    children: Vec::new(),
+                });
+            }
+            if rest.starts_with('>') {
+                self.pos += 1;
+                break;
+            }
+            if rest.is_empty() {
+                return Err(self.error(&format!("unclosed <{}> tag", name)));
+            }
+
+            let key = self.name()?;
+            self.skip_whitespace();
+            if !self.rest().starts_with('=') {
+                return Err(self.error(&format!("expected = after attribute {}", key)));
+            }
+            self.pos += 1;
+            self.skip_whitespace();
+            let quote = match self.rest().chars().next() {
+                Some(quote @ '"') | Some(quote @ '\'') => quote,
+                _ => return Err(self.error(&format!("attribute {} isn't quoted", key))),
+            };
+            self.pos += 1;
+            let end = match self.rest().find(quote) {
+                Some(end) => end,
+                None => return Err(self.error(&format!("unclosed value for {}", key))),
+            };
+            let value = decode_entities(&self.rest()[..end]);
+            self.pos += end + 1;
+            attributes.push((key, value));
+        }
+
+        let mut children = Vec::new();
+        loop {
+            match self.rest().find('<') {
+                Some(at) => self.pos += at,
+                None => return Err(self.error(&format!("missing </{}>", name))),
+            }
+            if self.skip_markup()? {
+                continue;
+            }
+            if self.rest().starts_with("</") {
+                self.pos += 2;
+                let closing = self.name()?;
+                if closing != name {
+                    return Err(self.error(&format!("</{}> doesn't match <{}>", closing, name)));
+                }
+                self.skip_whitespace();
+                if !self.rest().starts_with('>') {
+                    return Err(self.error("expected >"));
+                }
+                self.pos += 1;
+                return Ok(Element {
+                    name,
+                    attributes,
+                    children,
+                });
+            }
+            children.push(self.element(depth + 1)?);
+        }
+    }
+}
+
+fn decode_entities(value: &str) -> String {
+    if !value.contains('&') {
+        return value.to_string();
+    }
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(at) = rest.find('&') {
+        out.push_str(&rest[..at]);
+        rest = &rest[at..];
+        let end = match rest.find(';') {
+            Some(end) => end,
+            None => break,
+        };
+        let entity = &rest[1..end];
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => {
+                let code = match entity.strip_prefix("#x") {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => entity.strip_prefix('#').and_then(|n| n.parse().ok()),
+                };
+                code.and_then(char::from_u32)
+            }
+        };
+        match decoded {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+// --- attribute values --------------------------------------------------
+
+/// A length in SVG pixels. Absolute units are converted at 96 DPI;
+/// percentages aren't supported.
+fn parse_length(value: &str) -> Option<f32> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| c.is_ascii_alphabetic() && c != 'e' && c != 'E' || c == '%')
+        .unwrap_or(value.len());
+    let number: f32 = value[..split].trim().parse().ok()?;
+    let unit = match &value[split..] {
+        "" | "px" => 1.0,
+        "pt" => 96.0 / 72.0,
+        "pc" => 16.0,
+        "in" => 96.0,
+        "cm" => 96.0 / 2.54,
+        "mm" => 96.0 / 25.4,
+        _ => return None,
+    };
+    Some(number * unit).filter(|length| length.is_finite())
+}
+
+/// A number or a percentage, the latter taken as a fraction of
+/// `reference`.
+fn parse_coordinate(value: &str, reference: f32) -> Option<f32> {
+    match value.trim().strip_suffix('%') {
+        Some(percent) => percent
+            .trim()
+            .parse::<f32>()
+            .ok()
+            .map(|p| p / 100.0 * reference),
+        None => parse_length(value),
+    }
+}
+
+/// Every number in a whitespace or comma separated list, stopping at the
+/// first thing that isn't one.
+fn parse_numbers(value: &str) -> Vec<f32> {
+    let mut scanner = Scanner::new(value);
+    let mut numbers = Vec::new();
+    while let Some(number) = scanner.number() {
+        numbers.push(number);
+    }
+    numbers
+}
+
+/// Reads the compact number syntax shared by path data, point lists and
+/// transforms, where "1-2.5.5" is the three numbers 1, -2.5 and .5.
+struct Scanner<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Scanner<'a> {
+    fn new(text: &'a str) -> Scanner<'a> {
+        Scanner {
+            bytes: text.as_bytes(),
+            pos: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn skip_separators(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r' | b',') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn at_end(&mut self) -> bool {
+        self.skip_separators();
+        self.pos >= self.bytes.len()
+    }
+
+    fn number(&mut self) -> Option<f32> {
+        self.skip_separators();
+        let start = self.pos;
+        let mut end = start;
+        let digits = |end: &mut usize| {
+            let from = *end;
+            while self.bytes.get(*end).is_some_and(u8::is_ascii_digit) {
+                *end += 1;
+            }
+            *end > from
+        };
+        if let Some(b'+' | b'-') = self.bytes.get(end) {
+            end += 1;
+        }
+        let mut any = digits(&mut end);
+        if self.bytes.get(end) == Some(&b'.') {
+            end += 1;
+            any |= digits(&mut end);
+        }
+        if !any {
+            return None;
+        }
+        if let Some(b'e' | b'E') = self.bytes.get(end) {
+            let mut exponent = end + 1;
+            if let Some(b'+' | b'-') = self.bytes.get(exponent) {
+                exponent += 1;
+            }
+            if digits(&mut exponent) {
+                end = exponent;
+            }
+        }
+        let number = std::str::from_utf8(&self.bytes[start..end])
+            .ok()?
+            .parse()
+            .ok()?;
+        self.pos = end;
+        Some(number)
+    }
+
+    /// An arc flag, which may be written with no separator after it.
+    fn flag(&mut self) -> Option<bool> {
+        self.skip_separators();
+        let flag = match self.peek()? {
+            b'0' => false,
+            b'1' => true,
+            _ => return None,
+        };
+        self.pos += 1;
+        Some(flag)
+    }
+}
+
+fn parse_transform(value: &str) -> Transform {
+    let mut transform = Transform::identity();
+    let mut rest = value;
+    while let Some(open) = rest.find('(') {
+        let name = rest[..open].trim_matches(|c: char| c.is_whitespace() || c == ',');
+        let close = match rest[open..].find(')') {
+            Some(close) => open + close,
+            None => break,
+        };
+        let args = parse_numbers(&rest[open + 1..close]);
+        rest = &rest[close + 1..];
+
+        let step = match (name, &args[..]) {
+            ("matrix", &[a, b, c, d, e, f]) => Transform::new(a, b, c, d, e, f),
+            ("translate", &[x]) => Transform::new(1.0, 0.0, 0.0, 1.0, x, 0.0),
+            ("translate", &[x, y]) => Transform::new(1.0, 0.0, 0.0, 1.0, x, y),
+            ("scale", &[s]) => Transform::new(s, 0.0, 0.0, s, 0.0, 0.0),
+            ("scale", &[x, y]) => Transform::new(x, 0.0, 0.0, y, 0.0, 0.0),
+            ("rotate", &[angle]) => {
+                let mut t = Transform::identity();
+                t.rotate(angle.to_radians());
+                t
+            }
+            ("rotate", &[angle, cx, cy]) => {
+                let mut t = Transform::identity();
+                t.translate(cx, cy);
+                t.rotate(angle.to_radians());
+                t.translate(-cx, -cy);
+                t
+            }
+            ("skewX", &[angle]) => {
+                Transform::new(1.0, 0.0, angle.to_radians().tan(), 1.0, 0.0, 0.0)
+            }
+            ("skewY", &[angle]) => {
+                Transform::new(1.0, angle.to_radians().tan(), 0.0, 1.0, 0.0, 0.0)
+            }
+            // an invalid transform list disables the whole attribute
+            _ => return Transform::identity(),
+        };
+        transform.multiply(&step);
+    }
+    transform
+}
+
+fn parse_color(value: &str) -> Option<Rgba> {
+    let value = value.trim();
+    if let Some(hex) = value.strip_prefix('#') {
+        let digits: Vec<u8> = hex
+            .chars()
+            .map(|c| c.to_digit(16).map(|d| d as u8))
+            .collect::<Option<_>>()?;
+        return match digits[..] {
+            [r, g, b] => Some(Rgba::new(r * 17, g * 17, b * 17, 255)),
+            [r, g, b, a] => Some(Rgba::new(r * 17, g * 17, b * 17, a * 17)),
+            [r1, r0, g1, g0, b1, b0] => {
+                Some(Rgba::new(r1 * 16 + r0, g1 * 16 + g0, b1 * 16 + b0, 255))
+            }
+            [r1, r0, g1, g0, b1, b0, a1, a0] => Some(Rgba::new(
+                r1 * 16 + r0,
+                g1 * 16 + g0,
+                b1 * 16 + b0,
+                a1 * 16 + a0,
+            )),
+            _ => None,
+        };
+    }
+
+    let lower = value.to_ascii_lowercase();
+    if let Some(args) = lower
+        .strip_prefix("rgba(")
+        .or_else(|| lower.strip_prefix("rgb("))
+        .and_then(|rest| rest.strip_suffix(')'))
+    {
+        let parts: Vec<&str> = args
+            .split(|c: char| c == ',' || c == '/' || c.is_whitespace())
+            .filter(|part| !part.is_empty())
+            .collect();
+        let channel = |part: &str| -> Option<u8> {
+            let value = match part.strip_suffix('%') {
+                Some(percent) => percent.parse::<f32>().ok()? * 2.55,
+                None => part.parse::<f32>().ok()?,
+            };
+            Some(value.round().clamp(0.0, 255.0) as u8)
+        };
+        let alpha = |part: &str| -> Option<u8> {
+            let value = match part.strip_suffix('%') {
+                Some(percent) => percent.parse::<f32>().ok()? / 100.0,
+                None => part.parse::<f32>().ok()?,
+            };
+            Some((value * 255.0).round().clamp(0.0, 255.0) as u8)
+        };
+        return match parts[..] {
+            [r, g, b] => Some(Rgba::new(channel(r)?, channel(g)?, channel(b)?, 255)),
+            [r, g, b, a] => Some(Rgba::new(channel(r)?, channel(g)?, channel(b)?, alpha(a)?)),
+            _ => None,
+        };
+    }
+
+    // the basic CSS color keywords
+    let packed = match lower.as_str() {
+        "black" => 0x000000ff,
+        "white" => 0xffffffff,
+        "red" => 0xff0000ff,
+        "lime" => 0x00ff00ff,
+        "green" => 0x008000ff,
+        "blue" => 0x0000ffff,
+        "yellow" => 0xffff00ff,
+        "cyan" | "aqua" => 0x00ffffff,
+        "magenta" | "fuchsia" => 0xff00ffff,
+        "gray" | "grey" => 0x808080ff,
+        "silver" => 0xc0c0c0ff,
+        "maroon" => 0x800000ff,
+        "olive" => 0x808000ff,
+        "navy" => 0x000080ff,
+        "purple" => 0x800080ff,
+        "teal" => 0x008080ff,
+        "orange" => 0xffa500ff,
+        "transparent" => 0x00000000,
+        _ => return None,
+    };
+    Some(Rgba::from_u32(packed))
+}
+
+fn parse_opacity(value: &str) -> Option<f32> {
+    let value = value.trim();
+    let opacity = match value.strip_suffix('%') {
+        Some(percent) => percent.trim().parse::<f32>().ok()? / 100.0,
+        None => value.parse().ok()?,
+    };
+    Some(opacity.clamp(0.0, 1.0))
+}
+
+// --- path data ---------------------------------------------------------
+
+/// Build a path from SVG path data. As the spec asks, a syntax error ends
+/// the path there, keeping what came before it.
+fn parse_path_data(data: &str) -> Path {
+    let mut path = Path::new();
+    let mut scanner = Scanner::new(data);
+    let mut command = b'M';
+    let mut current = (0.0, 0.0);
+    let mut start = (0.0, 0.0);
+    // the previous curve's last control point, for the S and T shorthands
+    let mut last_cubic: Option<Point> = None;
+    let mut last_quad: Option<Point> = None;
+    let mut first = true;
+
+    while !scanner.at_end() {
+        if let Some(c) = scanner.peek().filter(u8::is_ascii_alphabetic) {
+            command = c;
+            scanner.pos += 1;
+        } else if first {
+            // path data has to start with a moveto
+            break;
+        }
+        let relative = command.is_ascii_lowercase();
+        let origin = if relative { current } else { (0.0, 0.0) };
+        let point = |scanner: &mut Scanner| -> Option<Point> {
+            let x = scanner.number()?;
+            let y = scanner.number()?;
+            Some((origin.0 + x, origin.1 + y))
+        };
+
+        let ok = match command.to_ascii_uppercase() {
+            b'M' => point(&mut scanner).map(|p| {
+                path.move_to(p.0, p.1);
+                current = p;
+                start = p;
+                // further pairs are implicit linetos
+                command = if relative { b'l' } else { b'L' };
+            }),
+            b'L' => point(&mut scanner).map(|p| {
+                path.line_to(p.0, p.1);
+                current = p;
+            }),
+            b'H' => scanner.number().map(|x| {
+                current.0 = origin.0 + x;
+                path.line_to(current.0, current.1);
+            }),
+            b'V' => scanner.number().map(|y| {
+                current.1 = origin.1 + y;
+                path.line_to(current.0, current.1);
+            }),
+            b'C' => (|| {
+                Some((
+                    point(&mut scanner)?,
+                    point(&mut scanner)?,
+                    point(&mut scanner)?,
+                ))
+            })()
+            .map(|(c1, c2, p)| {
+                path.cubic_to(c1.0, c1.1, c2.0, c2.1, p.0, p.1);
+                last_cubic = Some(c2);
+                current = p;
+            }),
+            b'S' => (|| Some((point(&mut scanner)?, point(&mut scanner)?)))().map(|(c2, p)| {
+                let c1 = reflect(last_cubic, current);
+                path.cubic_to(c1.0, c1.1, c2.0, c2.1, p.0, p.1);
+                last_cubic = Some(c2);
+                current = p;
+            }),
+            b'Q' => (|| Some((point(&mut scanner)?, point(&mut scanner)?)))().map(|(c, p)| {
+                path.quad_to(c.0, c.1, p.0, p.1);
+                last_quad = Some(c);
+                current = p;
+            }),
+            b'T' => point(&mut scanner).map(|p| {
+                let c = reflect(last_quad, current);
+                path.quad_to(c.0, c.1, p.0, p.1);
+                last_quad = Some(c);
+                current = p;
+            }),
+            b'A' => (|| {
+                let rx = scanner.number()?;
+                let ry = scanner.number()?;
+                let rotation = scanner.number()?;
+                let large = scanner.flag()?;
+                let sweep = scanner.flag()?;
+                Some((rx, ry, rotation, large, sweep, point(&mut scanner)?))
+            })()
+            .map(|(rx, ry, rotation, large, sweep, p)| {
+                arc_to(&mut path, current, rx, ry, rotation, large, sweep, p);
+                current = p;
+            }),
+            b'Z' => {
+                path.close();
+                current = start;
+                // Z takes no numbers, so anything else is a new command
+                command = b'\0';
+                Some(())
+            }
+            _ => None,
+        };
+        if ok.is_none() {
+            break;
+        }
+        if !matches!(command.to_ascii_uppercase(), b'C' | b'S') {
+            last_cubic = None;
+        }
+        if !matches!(command.to_ascii_uppercase(), b'Q' | b'T') {
+            last_quad = None;
+        }
+        first = false;
+    }
+    path
+}
+
+/// The first control point of a smooth curve: the previous curve's last
+/// one mirrored through the current point, or the current point itself.
+fn reflect(control: Option<Point>, current: Point) -> Point {
+    match control {
+        Some(c) => (2.0 * current.0 - c.0, 2.0 * current.1 - c.1),
+        None => current,
+    }
+}
+
+/// An elliptical arc from `from` to `to` as cubic curves, following the
+/// conversion in the SVG implementation notes (F.6.5 and F.6.6).
+#[allow(clippy::too_many_arguments)]
+fn arc_to(
+    path: &mut Path,
+    from: Point,
+    rx: f32,
+    ry: f32,
+    rotation: f32,
+    large: bool,
+    sweep: bool,
+    to: Point,
+) {
+    let (mut rx, mut ry) = (rx.abs(), ry.abs());
+    if from == to {
+        return;
+    }
+    if rx == 0.0 || ry == 0.0 {
+        path.line_to(to.0, to.1);
+        return;
+    }
+    let (sin, cos) = rotation.to_radians().sin_cos();
+    let (dx, dy) = ((from.0 - to.0) / 2.0, (from.1 - to.1) / 2.0);
+    let x1 = cos * dx + sin * dy;
+    let y1 = -sin * dx + cos * dy;
+
+    // grow radii that are too small to reach
+    let lambda = (x1 * x1) / (rx * rx) + (y1 * y1) / (ry * ry);
+    if lambda > 1.0 {
+        rx *= lambda.sqrt();
+        ry *= lambda.sqrt();
+    }
+
+    let numerator = rx * rx * ry * ry - rx * rx * y1 * y1 - ry * ry * x1 * x1;
+    let denominator = rx * rx * y1 * y1 + ry * ry * x1 * x1;
+    let mut factor = (numerator / denominator).max(0.0).sqrt();
+    if large == sweep {
+        factor = -factor;
+    }
+    let cx1 = factor * rx * y1 / ry;
+    let cy1 = -factor * ry * x1 / rx;
+    let center = (
+        cos * cx1 - sin * cy1 + (from.0 + to.0) / 2.0,
+        sin * cx1 + cos * cy1 + (from.1 + to.1) / 2.0,
+    );
+
+    let angle = |ux: f32, uy: f32| uy.atan2(ux);
+    let start = angle((x1 - cx1) / rx, (y1 - cy1) / ry);
+    let mut delta = angle((-x1 - cx1) / rx, (-y1 - cy1) / ry) - start;
+    if sweep && delta < 0.0 {
+        delta += 2.0 * PI;
+    } else if !sweep && delta > 0.0 {
+        delta -= 2.0 * PI;
+    }
+
+    // at most a quarter turn per cubic keeps them accurate
+    let pieces = (delta.abs() / (PI / 2.0)).ceil().max(1.0) as usize;
+    let step = delta / pieces as f32;
+    let handle = 4.0 / 3.0 * (step / 4.0).tan();
+    let on_ellipse = |theta: f32| {
+        let (s, c) = theta.sin_cos();
+        (
+            center.0 + rx * c * cos - ry * s * sin,
+            center.1 + rx * c * sin + ry * s * cos,
+        )
+    };
+    let tangent = |theta: f32| {
+        let (s, c) = theta.sin_cos();
+        (-rx * s * cos - ry * c * sin, -rx * s * sin + ry * c * cos)
+    };
+    for i in 0..pieces {
+        let (a, b) = (start + step * i as f32, start + step * (i + 1) as f32);
+        let (p0, p1) = (on_ellipse(a), on_ellipse(b));
+        let (t0, t1) = (tangent(a), tangent(b));
+        let end = if i + 1 == pieces { to } else { p1 };
+        path.cubic_to(
+            p0.0 + handle * t0.0,
+            p0.1 + handle * t0.1,
+            p1.0 - handle * t1.0,
+            p1.1 - handle * t1.1,
+            end.0,
+            end.1,
+        );
+    }
+}
+
+// --- styles and paint --------------------------------------------------
+
+#[derive(Clone)]
+enum PaintSpec {
+    None,
+    Color(Rgba),
+    CurrentColor,
+    /// A gradient by id, and the paint to use if there's no such gradient.
+    Link(String, Box<PaintSpec>),
+}
+
+fn parse_paint(value: &str) -> Option<PaintSpec> {
+    let value = value.trim();
+    if let Some(rest) = value.strip_prefix("url(") {
+        let close = rest.find(')')?;
+        let id = rest[..close]
+            .trim()
+            .trim_matches(|c| c == '"' || c == '\'')
+            .strip_prefix('#')?;
+        let fallback = parse_paint(&rest[close + 1..]).unwrap_or(PaintSpec::None);
+        return Some(PaintSpec::Link(id.to_string(), Box::new(fallback)));
+    }
+    match value {
+        "none" => Some(PaintSpec::None),
+        "currentColor" => Some(PaintSpec::CurrentColor),
+        _ => parse_color(value).map(PaintSpec::Color),
+    }
+}
+
+/// The inherited drawing state at an element.
+#[derive(Clone)]
+struct Style {
+    fill: PaintSpec,
+    fill_opacity: f32,
+    fill_rule: FillRule,
+    stroke: PaintSpec,
+    stroke_opacity: f32,
+    stroke_width: f32,
+    line_cap: LineCap,
+    line_join: LineJoin,
+    miter_limit: f32,
+    dash: Vec<f32>,
+    dash_offset: f32,
+    color: Rgba,
+    // group opacity, multiplied into every descendant's paint rather than
+    // composited as a separate layer
+    opacity: f32,
+    display: bool,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Style {
+            fill: PaintSpec::Color(Rgba::new(0, 0, 0, 255)),
+            fill_opacity: 1.0,
+            fill_rule: FillRule::NonZero,
+            stroke: PaintSpec::None,
+            stroke_opacity: 1.0,
+            stroke_width: 1.0,
+            line_cap: LineCap::Butt,
+            line_join: LineJoin::Miter,
+            miter_limit: 4.0,
+            dash: Vec::new(),
+            dash_offset: 0.0,
+            color: Rgba::new(0, 0, 0, 255),
+            opacity: 1.0,
+            display: true,
+        }
+    }
+}
+
+impl Style {
+    /// The style for `element`, inheriting from its parent's.
+    fn for_element(&self, element: &Element) -> Style {
+        let mut style = self.clone();
+        // opacity isn't inherited: the element sets its own, which then
+        // multiplies with its ancestors'
+        style.opacity = 1.0;
+        for (name, value) in &element.attributes {
+            style.apply(name, value);
+        }
+        // the style attribute wins over presentation attributes
+        if let Some(declarations) = element.attribute("style") {
+            for declaration in declarations.split(';') {
+                if let Some((name, value)) = declaration.split_once(':') {
+                    style.apply(name.trim(), value.trim());
+                }
+            }
+        }
+        style.opacity *= self.opacity;
+        style
+    }
+
+    /// Apply one property. Invalid values are ignored, as CSS does.
+    fn apply(&mut self, name: &str, value: &str) {
+        let value = value.trim();
+        match name {
+            "fill" => {
+                if let Some(paint) = parse_paint(value) {
+                    self.fill = paint;
+                }
+            }
+            "stroke" => {
+                if let Some(paint) = parse_paint(value) {
+                    self.stroke = paint;
+                }
+            }
+            "color" => {
+                if let Some(color) = parse_color(value) {
+                    self.color = color;
+                }
+            }
+            "fill-opacity" => self.fill_opacity = parse_opacity(value).unwrap_or(self.fill_opacity),
+            "stroke-opacity" => {
+                self.stroke_opacity = parse_opacity(value).unwrap_or(self.stroke_opacity)
+            }
+            "opacity" => self.opacity = parse_opacity(value).unwrap_or(self.opacity),
+            "fill-rule" => match value {
+                "nonzero" => self.fill_rule = FillRule::NonZero,
+                "evenodd" => self.fill_rule = FillRule::EvenOdd,
+                _ => {}
+            },
+            "stroke-width" => {
+                if let Some(width) = parse_length(value).filter(|w| *w >= 0.0) {
+                    self.stroke_width = width;
+                }
+            }
+            "stroke-linecap" => match value {
+                "butt" => self.line_cap = LineCap::Butt,
+                "round" => self.line_cap = LineCap::Round,
+                "square" => self.line_cap = LineCap::Square,
+                _ => {}
+            },
+            "stroke-linejoin" => match value {
+                "miter" => self.line_join = LineJoin::Miter,
+                "round" => self.line_join = LineJoin::Round,
+                "bevel" => self.line_join = LineJoin::Bevel,
+                _ => {}
+            },
+            "stroke-miterlimit" => {
+                if let Some(limit) = value.parse::<f32>().ok().filter(|l| *l >= 1.0) {
+                    self.miter_limit = limit;
+                }
+            }
+            "stroke-dasharray" => {
+                self.dash = if value == "none" {
+                    Vec::new()
+                } else {
+                    parse_numbers(value)
+                };
+            }
+            "stroke-dashoffset" => {
+                self.dash_offset = parse_length(value).unwrap_or(self.dash_offset)
+            }
+            "display" => self.display = value != "none",
+            _ => {}
+        }
+    }
+}
+
+struct GradientDef<'a> {
+    element: &'a Element,
+}
+
+/// Every gradient in the document by id. Gradients are never drawn
+/// themselves, wherever they appear.
+fn collect_gradients<'a>(element: &'a Element, out: &mut HashMap<String, GradientDef<'a>>) {
+    for child in &element.children {
+        if matches!(child.name.as_str(), "linearGradient" | "radialGradient") {
+            if let Some(id) = child.attribute("id") {
+                out.insert(id.to_string(), GradientDef { element: child });
+            }
+        }
+        collect_gradients(child, out);
+    }
+}
+
+struct Renderer<'a> {
+    gradients: HashMap<String, GradientDef<'a>>,
+    // size of the viewBox (or document), for userSpaceOnUse percentages
+    viewport: (f32, f32),
+}
+
+impl<'a> Renderer<'a> {
+    fn draw_children(
+        &self,
+        canvas: &mut CanvasSource,
+        parent: &Element,
+        style: &Style,
+        ctm: &Transform,
+    ) {
+        for child in &parent.children {
+            self.draw_element(canvas, child, style, ctm);
+        }
+    }
+
+    fn draw_element(
+        &self,
+        canvas: &mut CanvasSource,
+        element: &Element,
+        parent: &Style,
+        ctm: &Transform,
+    ) {
+        // strip any namespace prefix
+        let name = element.name.rsplit(':').next().unwrap_or("");
+        let style = parent.for_element(element);
+        if !style.display {
+            return;
+        }
+        let mut ctm = *ctm;
+        if let Some(transform) = element.attribute("transform") {
+            ctm.multiply(&parse_transform(transform));
+        }
+
+        let path = match name {
+            // nested <svg>s are drawn as plain groups, without their own
+            // viewport
+            "g" | "a" | "svg" => return self.draw_children(canvas, element, &style, &ctm),
+            "path" => element.attribute("d").map(parse_path_data),
+            "rect" => rect_path(element),
+            "circle" => {
+                let r = element.length("r", 0.0);
+                Some(ellipse_path(
+                    element.length("cx", 0.0),
+                    element.length("cy", 0.0),
+                    r,
+                    r,
+                ))
+            }
+            "ellipse" => Some(ellipse_path(
+                element.length("cx", 0.0),
+                element.length("cy", 0.0),
+                element.length("rx", 0.0),
+                element.length("ry", 0.0),
+            )),
+            "line" => {
+                let mut path = Path::new();
+                path.move_to(element.length("x1", 0.0), element.length("y1", 0.0));
+                path.line_to(element.length("x2", 0.0), element.length("y2", 0.0));
+                Some(path)
+            }
+            "polyline" | "polygon" => element.attribute("points").map(|points| {
+                let numbers = parse_numbers(points);
+                let mut path = Path::new();
+                for pair in numbers.chunks_exact(2) {
+                    path.line_to(pair[0], pair[1]);
+                }
+                if name == "polygon" {
+                    path.close();
+                }
+                path
+            }),
+            // defs, gradients, and everything unsupported
+            _ => None,
+        };
+        if let Some(path) = path.filter(|path| !path.is_empty()) {
+            self.draw_path(canvas, path, &style, &ctm);
+        }
+    }
+
+    fn draw_path(&self, canvas: &mut CanvasSource, mut path: Path, style: &Style, ctm: &Transform) {
+        let bounds = path.bounds();
+        path.transform(ctm);
+
+        if let Some(paint) = self.paint(&style.fill, style, style.fill_opacity, &bounds, ctm) {
+            let contours: Vec<Vec<Point>> =
+                path.flatten().into_iter().map(|line| line.points).collect();
+            canvas.fill_contours(&contours, style.fill_rule, &paint);
+        }
+
+        if let Some(paint) = self.paint(&style.stroke, style, style.stroke_opacity, &bounds, ctm) {
+            // stroked after transforming, with the width scaled by the
+            // transform's average scale; exact unless it stretches one way
+            // more than the other
+            let scale = ctm.determinant().abs().sqrt();
+            let mut stroke = StrokeStyle::new(style.stroke_width * scale);
+            stroke.set_cap(style.line_cap);
+            stroke.set_join(style.line_join);
+            stroke.set_miter_limit(style.miter_limit);
+            let dash: Vec<f32> = style.dash.iter().map(|length| length * scale).collect();
+            stroke.set_dash(&dash, style.dash_offset * scale);
+            let contours = stroke_contours(&path, &stroke);
+            canvas.fill_contours(&contours, FillRule::NonZero, &paint);
+        }
+    }
+
+    /// The paint for a fill or stroke, or `None` when nothing should be
+    /// drawn. `bounds` is the shape's user space bounding box, for
+    /// gradients in `objectBoundingBox` units.
+    fn paint(
+        &self,
+        spec: &PaintSpec,
+        style: &Style,
+        opacity: f32,
+        bounds: &[f32],
+        ctm: &Transform,
+    ) -> Option<Paint> {
+        let opacity = opacity * style.opacity;
+        let faded = |color: Rgba| {
+            let a = (color.a as f32 * opacity).round() as u8;
+            Paint::from(Rgba { a, ..color })
+        };
+        match spec {
+            PaintSpec::None => None,
+            PaintSpec::Color(color) => Some(faded(*color)),
+            PaintSpec::CurrentColor => Some(faded(style.color)),
+            PaintSpec::Link(id, fallback) => match self.gradients.get(id) {
+                Some(gradient) => self.gradient(gradient, opacity, bounds, ctm),
+                None => self.paint(fallback, style, 1.0, bounds, ctm),
+            },
+        }
+    }
+
+    /// Look up a gradient attribute, following `href` links to the
+    /// gradients it inherits from.
+    fn gradient_attribute(&self, gradient: &'a Element, name: &str) -> Option<&'a str> {
+        let mut current = gradient;
+        // bounded, in case of reference cycles
+        for _ in 0..16 {
+            if let Some(value) = current.attribute(name) {
+                return Some(value);
+            }
+            current = self.gradients.get(current.link()?)?.element;
+        }
+        None
+    }
+
+    fn gradient(
+        &self,
+        gradient: &GradientDef<'a>,
+        opacity: f32,
+        bounds: &[f32],
+        ctm: &Transform,
+    ) -> Option<Paint> {
+        let element = gradient.element;
+        let attribute = |name: &str| self.gradient_attribute(element, name);
+        let bounding_box = attribute("gradientUnits") != Some("userSpaceOnUse");
+
+        let mut matrix = *ctm;
+        let (width, height) = if bounding_box {
+            // a box with no area has no gradient, and nothing is drawn
+            let (x, y, w, h) = match *bounds {
+                [x, y, w, h] if w > 0.0 && h > 0.0 => (x, y, w, h),
+                _ => return None,
+            };
+            matrix.translate(x, y);
+            matrix.scale(w, h);
+            (1.0, 1.0)
+        } else {
+            self.viewport
+        };
+        if let Some(transform) = attribute("gradientTransform") {
+            matrix.multiply(&parse_transform(transform));
+        }
+        // percentages are of the box, or of the viewport's diagonal for
+        // lengths that aren't horizontal or vertical
+        let diagonal = ((width * width + height * height) / 2.0).sqrt();
+        let coordinate = |name: &str, default: f32, reference: f32| {
+            attribute(name)
+                .and_then(|value| parse_coordinate(value, reference))
+                .unwrap_or(default * reference)
+        };
+
+        // set for a radial gradient with no size to speak of
+        let mut collapsed = false;
+        let mut paint = if element.name.ends_with("linearGradient") {
+            let p0 = (coordinate("x1", 0.0, width), coordinate("y1", 0.0, height));
+            let p1 = (coordinate("x2", 1.0, width), coordinate("y2", 0.0, height));
+            let (start, end) = map_linear(&matrix, p0, p1);
+            Paint::linear_gradient(start.0, start.1, end.0, end.1)
+        } else {
+            let center = (coordinate("cx", 0.5, width), coordinate("cy", 0.5, height));
+            let radius = coordinate("r", 0.5, diagonal);
+            let focal = (
+                attribute("fx")
+                    .and_then(|v| parse_coordinate(v, width))
+                    .unwrap_or(center.0),
+                attribute("fy")
+                    .and_then(|v| parse_coordinate(v, height))
+                    .unwrap_or(center.1),
+            );
+            // circles only stay circles under uniform scaling; otherwise
+            // this uses the average scale
+            let (c, f) = (matrix.apply(center), matrix.apply(focal));
+            let radius = radius * matrix.determinant().abs().sqrt();
+            collapsed = !(radius.is_finite() && radius > 0.0);
+            Paint::radial_gradient(c.0, c.1, radius, f.0, f.1)
+        };
+
+        paint.set_spread(match attribute("spreadMethod") {
+            Some("reflect") => Spread::Reflect,
+            Some("repeat") => Spread::Repeat,
+            _ => Spread::Pad,
+        });
+
+        // stops come from the first gradient in the href chain that has any
+        let mut source = element;
+        for _ in 0..16 {
+            if source.children.iter().any(|c| c.name.ends_with("stop")) {
+                break;
+            }
+            match source.link().and_then(|id| self.gradients.get(id)) {
+                Some(next) => source = next.element,
+                None => break,
+            }
+        }
+        let mut stops = Vec::new();
+        let mut previous = 0.0f32;
+        for stop in source.children.iter().filter(|c| c.name.ends_with("stop")) {
+            let mut color = Rgba::new(0, 0, 0, 255);
+            let mut stop_opacity = 1.0;
+            for (name, value) in stop.attributes.iter().cloned().chain(
+                stop.attribute("style")
+                    .unwrap_or("")
+                    .split(';')
+                    .filter_map(|d| d.split_once(':'))
+                    .map(|(n, v)| (n.trim().to_string(), v.trim().to_string())),
+            ) {
+                match name.as_str() {
+                    "stop-color" => {
+                        if let Some(c) = parse_color(&value) {
+                            color = c;
+                        }
+                    }
+                    "stop-opacity" => stop_opacity = parse_opacity(&value).unwrap_or(1.0),
+                    _ => {}
+                }
+            }
+            // offsets never go backwards
+            let offset = stop
+                .attribute("offset")
+                .and_then(|v| match v.trim().strip_suffix('%') {
+                    Some(percent) => percent.trim().parse::<f32>().ok().map(|p| p / 100.0),
+                    None => v.trim().parse().ok(),
+                })
+                .unwrap_or(0.0)
+                .clamp(0.0, 1.0)
+                .max(previous);
+            previous = offset;
+            let a = (color.a as f32 * stop_opacity * opacity).round() as u8;
+            stops.push((offset, Rgba { a, ..color }.to_u32()));
+        }
+
+        if collapsed {
+            // the spec paints a zero radius in the last stop's color, and a
+            // radius too small or large to work with is as good as zero
+            return stops.last().map(|&(_, color)| Paint::solid(color));
+        }
+        for (offset, color) in stops {
+            paint.add_color_stop(offset, color);
+        }
+        Some(paint)
+    }
+}
+
+/// Map a linear gradient's end points through `matrix`. Lines of equal
+/// color stay parallel under an affine transform but may no longer be
+/// perpendicular to the mapped end points, so the second point is moved
+/// to keep the color lines where the transform puts them.
+fn map_linear(matrix: &Transform, p0: Point, p1: Point) -> (Point, Point) {
+    let start = matrix.apply(p0);
+    let end = matrix.apply(p1);
+    // direction of the color lines after transforming
+    let (dx, dy) = (p1.0 - p0.0, p1.1 - p0.1);
+    let along = (
+        matrix.a * -dy + matrix.c * dx,
+        matrix.b * -dy + matrix.d * dx,
+    );
+    let length = (along.0 * along.0 + along.1 * along.1).sqrt();
+    if length <= f32::EPSILON {
+        return (start, end);
+    }
+    let normal = (-along.1 / length, along.0 / length);
+    let distance = (end.0 - start.0) * normal.0 + (end.1 - start.1) * normal.1;
+    (
+        start,
+        (start.0 + normal.0 * distance, start.1 + normal.1 * distance),
+    )
+}
+
+fn rect_path(element: &Element) -> Option<Path> {
+    let (x, y) = (element.length("x", 0.0), element.length("y", 0.0));
+    let (width, height) = (element.length("width", 0.0), element.length("height", 0.0));
+    if width <= 0.0 || height <= 0.0 {
+        return None;
+    }
+    // a missing radius copies the other one
+    let rx = element.attribute("rx").and_then(parse_length);
+    let ry = element.attribute("ry").and_then(parse_length);
+    let (rx, ry) = match (rx, ry) {
+        (Some(rx), Some(ry)) => (rx, ry),
+        (Some(r), None) | (None, Some(r)) => (r, r),
+        (None, None) => (0.0, 0.0),
+    };
+    let rx = rx.clamp(0.0, width / 2.0);
+    let ry = ry.clamp(0.0, height / 2.0);
+
+    let mut path = Path::new();
+    if rx == 0.0 || ry == 0.0 {
+        path.move_to(x, y);
+        path.line_to(x + width, y);
+        path.line_to(x + width, y + height);
+        path.line_to(x, y + height);
+        path.close();
+        return Some(path);
+    }
+    let (kx, ky) = (rx * KAPPA, ry * KAPPA);
+    let (right, bottom) = (x + width, y + height);
+    path.move_to(x + rx, y);
+    path.line_to(right - rx, y);
+    path.cubic_to(right - rx + kx, y, right, y + ry - ky, right, y + ry);
+    path.line_to(right, bottom - ry);
+    path.cubic_to(
+        right,
+        bottom - ry + ky,
+        right - rx + kx,
+        bottom,
+        right - rx,
+        bottom,
+    );
+    path.line_to(x + rx, bottom);
+    path.cubic_to(x + rx - kx, bottom, x, bottom - ry + ky, x, bottom - ry);
+    path.line_to(x, y + ry);
+    path.cubic_to(x, y + ry - ky, x + rx - kx, y, x + rx, y);
+    path.close();
+    Some(path)
+}
+
+/// An ellipse as four cubic curves, starting from its rightmost point and
+/// running clockwise like SVG's.
+fn ellipse_path(cx: f32, cy: f32, rx: f32, ry: f32) -> Path {
+    let mut path = Path::new();
+    if rx <= 0.0 || ry <= 0.0 {
+        return path;
+    }
+    let (kx, ky) = (rx * KAPPA, ry * KAPPA);
+    path.move_to(cx + rx, cy);
+    path.cubic_to(cx + rx, cy + ky, cx + kx, cy + ry, cx, cy + ry);
+    path.cubic_to(cx - kx, cy + ry, cx - rx, cy + ky, cx - rx, cy);
+    path.cubic_to(cx - rx, cy - ky, cx - kx, cy - ry, cx, cy - ry);
+    path.cubic_to(cx + kx, cy - ry, cx + rx, cy - ky, cx + rx, cy);
+    path.close();
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pictures::coverage;
+
+    fn decode(text: &str) -> CanvasSource {
+        CanvasSource::decode_svg(text, 1.0).unwrap()
+    }
+
+    fn rgba(canvas: &CanvasSource, x: i32, y: i32) -> [u8; 4] {
+        canvas.pixel(x, y).unwrap().to_array()
+    }
+
+    #[test]
+    fn rects_are_filled() {
+        let canvas = decode(
+            r##"<svg width="4" height="4"><rect x="1" y="1" width="2" height="2" fill="#f00"/></svg>"##,
+        );
+        assert_eq!(coverage(&canvas), ["....", ".##.", ".##.", "...."]);
+        assert_eq!(rgba(&canvas, 1, 1), [255, 0, 0, 255]);
+        // black is the default fill
+        let canvas = decode(r#"<svg width="2" height="1"><rect width="1" height="1"/></svg>"#);
+        assert_eq!(rgba(&canvas, 0, 0), [0, 0, 0, 255]);
+        assert_eq!(rgba(&canvas, 1, 0), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn size_comes_from_the_attributes_then_the_view_box() {
+        let sized = decode(r#"<svg width="5" height="3" viewBox="0 0 10 30"/>"#);
+        assert_eq!((sized.width, sized.height), (5, 3));
+        let viewed = decode(r#"<svg width="20" viewBox="0 0 10 30"/>"#);
+        assert_eq!((viewed.width, viewed.height), (20, 60));
+        let default = decode("<svg/>");
+        assert_eq!((default.width, default.height), (300, 150));
+
+        let scaled = CanvasSource::decode_svg(r#"<svg width="2" height="3"/>"#, 2.5).unwrap();
+        assert_eq!((scaled.width, scaled.height), (5, 8));
+    }
+
+    #[test]
+    fn view_boxes_scale_and_center() {
+        let canvas = decode(
+            r#"<svg width="4" height="4" viewBox="10 10 2 2"><rect x="10" y="10" width="1" height="1"/></svg>"#,
+        );
+        assert_eq!(coverage(&canvas), ["##..", "##..", "....", "...."]);
+
+        // a wide view box in a square image is centered vertically
+        let canvas = decode(
+            r#"<svg width="4" height="4" viewBox="0 0 2 1"><rect width="2" height="1"/></svg>"#,
+        );
+        assert_eq!(coverage(&canvas), ["....", "####", "####", "...."]);
+    }
+
+    #[test]
+    fn the_root_style_applies_to_everything() {
+        let canvas = decode(
+            r##"<svg width="2" height="1" fill="#00f"><rect width="1" height="1"/><g><rect x="1" width="1" height="1"/></g></svg>"##,
+        );
+        assert_eq!(rgba(&canvas, 0, 0), [0, 0, 255, 255]);
+        assert_eq!(rgba(&canvas, 1, 0), [0, 0, 255, 255]);
+
+        let hidden = decode(
+            r#"<svg width="2" height="1" style="display: none"><rect width="2" height="1"/></svg>"#,
+        );
+        assert!(hidden.data.iter().all(|&b| b == 0));
+
+        let group = decode(
+            r#"<svg width="2" height="1"><g display="none"><rect width="1" height="1"/></g><rect x="1" width="1" height="1"/></svg>"#,
+        );
+        assert_eq!(coverage(&group), [".#"]);
+    }
+
+    #[test]
+    fn opacity_is_applied_once_per_element() {
+        // the style attribute replaces the presentation attribute
+        let canvas = decode(
+            r#"<svg width="1" height="1"><rect width="1" height="1" opacity="0.5" style="opacity:0.5"/></svg>"#,
+        );
+        assert_eq!(rgba(&canvas, 0, 0)[3], 128);
+
+        // nested groups multiply
+        let nested = decode(
+            r#"<svg width="1" height="1"><g opacity="0.5"><rect width="1" height="1" opacity="0.5"/></g></svg>"#,
+        );
+        assert_eq!(rgba(&nested, 0, 0)[3], 64);
+
+        let fill = decode(
+            r##"<svg width="1" height="1" opacity="0.5"><rect width="1" height="1" fill="#f00" fill-opacity="0.5"/></svg>"##,
+        );
+        assert_eq!(rgba(&fill, 0, 0), [255, 0, 0, 64]);
+    }
+
+    #[test]
+    fn paths_transforms_and_strokes() {
+        let canvas = decode(
+            r#"<svg width="4" height="4"><path transform="translate(1 1)" d="M0 0h2v2H0z" fill-rule="evenodd"/></svg>"#,
+        );
+        assert_eq!(coverage(&canvas), ["....", ".##.", ".##.", "...."]);
+
+        let holed = decode(
+            r#"<svg width="4" height="4"><path d="M0 0h4v4h-4z M1 1h2v2h-2z" fill-rule="evenodd"/></svg>"#,
+        );
+        assert_eq!(coverage(&holed), ["####", "#..#", "#..#", "####"]);
+
+        let stroked = decode(
+            r##"<svg width="4" height="4"><line x1="0" y1="2" x2="4" y2="2" stroke="#0f0" stroke-width="2"/></svg>"##,
+        );
+        assert_eq!(coverage(&stroked), ["....", "####", "####", "...."]);
+        assert_eq!(rgba(&stroked, 0, 1), [0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn gradients_are_linked_by_id() {
+        let canvas = decode(
+            r##"<svg width="10" height="1">
+                <defs>
+                    <linearGradient id="fade">
+                        <stop offset="0" stop-color="#f00"/>
+                        <stop offset="1" stop-color="#00f"/>
+                    </linearGradient>
+                </defs>
+                <rect width="10" height="1" fill="url(#fade)"/>
+            </svg>"##,
+        );
+        let (left, right) = (rgba(&canvas, 0, 0), rgba(&canvas, 9, 0));
+        assert!(left[0] > 200 && left[2] < 55, "{:?}", left);
+        assert!(right[2] > 200 && right[0] < 55, "{:?}", right);
+
+        // a missing gradient falls back
+        let fallback = decode(
+            r##"<svg width="1" height="1"><rect width="1" height="1" fill="url(#nothing) #0f0"/></svg>"##,
+        );
+        assert_eq!(rgba(&fallback, 0, 0), [0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn radial_gradients_without_a_usable_radius_paint_the_last_stop() {
+        for r in ["0", "1e-30", "1e30", "1e38"] {
+            let text = format!(
+                r##"<svg width="4" height="4">
+                    <radialGradient id="dot" gradientUnits="userSpaceOnUse" r="{}">
+                        <stop offset="0" stop-color="#f00"/>
+                        <stop offset="1" stop-color="#00f"/>
+                    </radialGradient>
+                    <rect width="4" height="4" fill="url(#dot)"/>
+                </svg>"##,
+                r
+            );
+            let canvas = decode(&text);
+            for (x, y) in [(0, 0), (2, 2), (3, 1)] {
+                assert_eq!(rgba(&canvas, x, y), [0, 0, 255, 255], "r={}", r);
+            }
+        }
+    }
+
+    #[test]
+    fn markup_around_the_root_is_skipped() {
+        let canvas = decode(
+            "<?xml version=\"1.0\"?>\n<!DOCTYPE svg [<!ENTITY x \"y\">]>\n<!-- a comment -->\n\
+             <svg:svg width=\"1\" height=\"1\"><![CDATA[<rect/>]]><svg:rect width=\"1\" height=\"1\" \
+             fill=\"&#x23;f00\"/></svg:svg>",
+        );
+        assert_eq!(rgba(&canvas, 0, 0), [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn bad_sizes_are_refused() {
+        let size = |text: &str, scale: f32| {
+            matches!(
+                CanvasSource::decode_svg(text, scale),
+                Err(SvgError::Size(..))
+            )
+        };
+        assert!(size(r#"<svg width="0" height="10"/>"#, 1.0));
+        assert!(size(r#"<svg width="10" height="10"/>"#, 0.0));
+        assert!(size(r#"<svg width="10" height="10"/>"#, f32::NAN));
+        assert!(size(r#"<svg width="100000" height="100000"/>"#, 1.0));
+        assert!(size(r#"<svg width="1e30" height="1e30"/>"#, 1.0));
+    }
+
+    #[test]
+    fn only_svg_documents_are_drawn() {
+        assert!(matches!(
+            CanvasSource::decode_svg("<html/>", 1.0),
+            Err(SvgError::NotSvg)
+        ));
+    }
+
+    #[test]
+    fn syntax_errors_point_at_the_line() {
+        let line = |text: &str| match CanvasSource::decode_svg(text, 1.0) {
+            Err(SvgError::Syntax { line, .. }) => line,
+            other => panic!("{:?} isn't a syntax error", other.map(|_| ())),
+        };
+        assert_eq!(line(""), 1);
+        assert_eq!(line("<svg>\n<rect width=1/>\n</svg>"), 2);
+        assert_eq!(line("<svg>\n<g>\n</svg>"), 3);
+        assert_eq!(line("<svg>\n\n<!-- open"), 3);
+        assert_eq!(line("<svg width=\"1\""), 1);
+        assert_eq!(line("<svg>\n<rect/>"), 2);
+    }
+
+    #[test]
+    fn nesting_is_limited() {
+        let nested = |depth: usize| {
+            let mut text = r#"<svg width="1" height="1">"#.to_string();
+            text += &"<g>".repeat(depth - 2);
+            text += r#"<rect width="1" height="1"/>"#;
+            text += &"</g>".repeat(depth - 2);
+            text + "</svg>"
+        };
+        assert_eq!(rgba(&decode(&nested(MAX_DEPTH)), 0, 0), [0, 0, 0, 255]);
+        assert!(matches!(
+            CanvasSource::decode_svg(&nested(MAX_DEPTH + 1), 1.0),
+            Err(SvgError::Syntax { line: 1, .. })
+        ));
+        // deep enough to overflow the stack without the limit
+        let deep = "<svg>".to_string() + &"<g>".repeat(1_000_000);
+        assert!(CanvasSource::decode_svg(&deep, 1.0).is_err());
+    }
+
+    #[test]
+    fn rendering_onto_a_canvas_is_one_undo_step() {
+        let mut canvas = CanvasSource::try_new(4, 4, Vec::new()).unwrap();
+        let text = r#"<svg width="1" height="1"><rect width="1" height="1"/></svg>"#;
+        canvas.render_svg(text, 2.0, 1.0, 2.0).unwrap();
+        assert_eq!(coverage(&canvas), ["....", "..##", "..##", "...."]);
+        assert!(canvas.undo());
+        assert!(canvas.data.iter().all(|&b| b == 0));
+        assert!(!canvas.can_undo());
+
+        assert!(canvas.render_svg("<svg>", 0.0, 0.0, 1.0).is_err());
+        assert!(!canvas.can_undo());
+    }
+}
@@ -0,0 +1,757 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use wasm_bindgen::prelude::*;
+
+use crate::canvas_source::CanvasSource;
+use crate::paint::Paint;
+
+// the public domain X11 misc-fixed 4x6 font, converted to PSF2 with its
+// unicode table (918 glyphs covering Latin, Greek, Cyrillic, box drawing
+// and more)
+const DEFAULT_FONT: &[u8] = include_bytes!("fonts/fixed-4x6.psf");
+
+const PSF1_MAGIC: [u8; 2] = [0x36, 0x04];
+const PSF2_MAGIC: [u8; 4] = [0x72, 0xb5, 0x4a, 0x86];
+// the largest size or offset a BDF font may give, in pixels: far past any
+// real bitmap font, and small enough that laying out glyphs can't overflow
+const MAX_BDF_METRIC: i32 = 1024;
+
+#[derive(Debug)]
+pub enum FontError {
+    /// A malformed BDF file, with the (1-based) line the problem was found
+    /// on.
+    Bdf {
+        line: usize,
+        message: String,
+    },
+    Psf(String),
+}
+
+impl fmt::Display for FontError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FontError::Bdf { line, message } => {
+                write!(f, "invalid BDF font (line {}): {}", line, message)
+            }
+            FontError::Psf(message) => write!(f, "invalid PSF font: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for FontError {}
+
+struct Glyph {
+    width: u32,
+    height: u32,
+    // offset of the bitmap from the pen position, in pixels right and down
+    // from the top of the line
+    left: i32,
+    top: i32,
+    advance: i32,
+    bits: Vec<bool>,
+}
+
+/// A monochrome bitmap font, loaded from BDF or PSF or the built-in
+/// default.
+#[wasm_bindgen]
+pub struct BitmapFont {
+    glyphs: HashMap<char, Glyph>,
+    line_height: u32,
+    // drawn in place of characters the font doesn't have
+    fallback: Option<char>,
+}
+
+/// How the lines of a text block line up with each other.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextAlign {
+    Left = 0,
+    Center = 1,
+    Right = 2,
+}
+
+/// Color and layout for `draw_text`.
+#[wasm_bindgen]
+#[derive(Clone)]
+pub struct TextStyle {
+    paint: Paint,
+    align: TextAlign,
+    max_width: u32,
+    line_spacing: i32,
+}
+
+#[wasm_bindgen]
+impl TextStyle {
+    /// Left aligned, unwrapped text in `color`.
+    pub fn new(color: u32) -> TextStyle {
+        TextStyle {
+            paint: Paint::solid(color),
+            align: TextAlign::Left,
+            max_width: 0,
+            line_spacing: 0,
+        }
+    }
+
+    pub fn set_color(&mut self, color: u32) {
+        self.paint = Paint::solid(color);
+    }
+
+    pub fn set_paint(&mut self, paint: &Paint) {
+        self.paint = paint.clone();
+    }
+
+    pub fn align(&self) -> TextAlign {
+        self.align
+    }
+
+    pub fn set_align(&mut self, align: TextAlign) {
+        self.align = align;
+    }
+
+    pub fn max_width(&self) -> u32 {
+        self.max_width
+    }
+
+    /// Wrap lines longer than `max_width` pixels, at spaces where possible.
+    /// Zero turns wrapping off.
+    pub fn set_max_width(&mut self, max_width: u32) {
+        self.max_width = max_width;
+    }
+
+    pub fn line_spacing(&self) -> i32 {
+        self.line_spacing
+    }
+
+    /// Extra pixels between lines, on top of the font's line height.
+    pub fn set_line_spacing(&mut self, line_spacing: i32) {
+        self.line_spacing = line_spacing;
+    }
+}
+
+#[wasm_bindgen]
+impl BitmapFont {
+    /// The built-in 4x6 pixel font.
+    pub fn default_font() -> BitmapFont {
+        BitmapFont::parse_psf(DEFAULT_FONT).expect("the embedded font is valid")
+    }
+
+    /// Load a font from the text of a BDF file. Throws if it is malformed.
+    pub fn from_bdf(text: &str) -> Result<BitmapFont, JsError> {
+        Ok(BitmapFont::parse_bdf(text)?)
+    }
+
+    /// Load a PSF (version 1 or 2) console font. Throws if it is malformed.
+    pub fn from_psf(bytes: &[u8]) -> Result<BitmapFont, JsError> {
+        Ok(BitmapFont::parse_psf(bytes)?)
+    }
+
+    pub fn line_height(&self) -> u32 {
+        self.line_height
+    }
+
+    pub fn has_glyph(&self, c: char) -> bool {
+        self.glyphs.contains_key(&c)
+    }
+
+    /// Size of `text` laid out with `style`, as `[width, height]`.
+    pub fn measure_text(&self, text: &str, style: &TextStyle) -> Vec<u32> {
+        let lines = self.layout(text, style.max_width);
+        let width = lines.iter().map(|line| line.1).max().unwrap_or(0);
+        vec![width.max(0) as u32, self.block_height(lines.len(), style)]
+    }
+}
+
+// removed #[wasm_bindgen] - the Rust-side API for the native binary
+impl BitmapFont {
+    /// Parse a BDF font. Encodings are taken to be Unicode code points,
+    /// which also covers ISO 8859-1 fonts.
+    pub fn parse_bdf(text: &str) -> Result<BitmapFont, FontError> {
+        let mut lines = text.lines().enumerate().map(|(i, line)| (i + 1, line));
+        let mut bounding_box: Option<[i32; 4]> = None;
+        let mut ascent = None;
+        let mut descent = None;
+        let mut default_char = None;
+        // glyphs as (code point, advance, bbx, rows), positioned once the
+        // font's ascent is known
+        let mut parsed = Vec::new();
+        // truncation errors point at the end of the file
+        let end = text.lines().count();
+
+        while let Some((number, line)) = lines.next() {
+            let mut words = line.split_whitespace();
+            let keyword = match words.next() {
+                Some(keyword) => keyword,
+                None => continue,
+            };
+            let error = |message: &str| FontError::Bdf {
+                line: number,
+                message: message.to_string(),
+            };
+            let mut numbers = || -> Result<Vec<i32>, FontError> {
+                words
+                    .by_ref()
+                    .map(|w| w.parse().map_err(|_| error("expected a number")))
+                    .collect()
+            };
+
+            match keyword {
+                "FONTBOUNDINGBOX" => bounding_box = Some(four(&numbers()?, &error)?),
+                "FONT_ASCENT" => ascent = first_metric(&numbers()?, &error)?,
+                "FONT_DESCENT" => descent = first_metric(&numbers()?, &error)?,
+                "DEFAULT_CHAR" => default_char = numbers()?.first().copied(),
+                "STARTCHAR" => {
+                    let mut encoding = None;
+                    let mut advance = None;
+                    let mut bbx = None;
+                    loop {
+                        let (number, line) = lines.next().ok_or_else(|| FontError::Bdf {
+                            line: end,
+                            message: "unterminated STARTCHAR".to_string(),
+                        })?;
+                        let error = |message: &str| FontError::Bdf {
+                            line: number,
+                            message: message.to_string(),
+                        };
+                        let mut words = line.split_whitespace();
+                        let keyword = words.next().unwrap_or("");
+                        let numbers: Vec<i32> = words
+                            .map(|w| w.parse().map_err(|_| error("expected a number")))
+                            .collect::<Result<_, _>>()?;
+                        match keyword {
+                            "ENCODING" => encoding = numbers.first().copied(),
+                            "DWIDTH" => advance = first_metric(&numbers, &error)?,
+                            "BBX" => bbx = Some(four(&numbers, &error)?),
+                            "BITMAP" => break,
+                            "ENDCHAR" => return Err(error("ENDCHAR before BITMAP")),
+                            _ => {}
+                        }
+                    }
+
+                    let bbx = bbx
+                        .or(bounding_box)
+                        .ok_or_else(|| error("glyph without a BBX"))?;
+                    let mut rows = Vec::new();
+                    for _ in 0..bbx[1].max(0) {
+                        let (number, line) = lines.next().ok_or_else(|| FontError::Bdf {
+                            line: end,
+                            message: "truncated BITMAP".to_string(),
+                        })?;
+                        let row = hex_row(line.trim()).ok_or_else(|| FontError::Bdf {
+                            line: number,
+                            message: "bad BITMAP row".to_string(),
+                        })?;
+                        rows.push(row);
+                    }
+
+                    let c = encoding
+                        .filter(|&e| e >= 0)
+                        .and_then(|e| char::from_u32(e as u32));
+                    if let Some(c) = c {
+                        parsed.push((c, advance.unwrap_or(bbx[0]), bbx, rows));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let bounding_box = bounding_box.unwrap_or([0, 0, 0, 0]);
+        let ascent = ascent.unwrap_or(bounding_box[1] + bounding_box[3]);
+        let descent = descent.unwrap_or(-bounding_box[3]);
+
+        let mut glyphs = HashMap::new();
+        for (c, advance, [width, height, x_offset, y_offset], rows) in parsed {
+            let (width, height) = (width.max(0) as u32, height.max(0) as u32);
+            let mut bits = Vec::with_capacity((width * height) as usize);
+            for row in rows.iter() {
+                bits.extend((0..width as usize).map(|x| {
+                    row.get(x / 8)
+                        .is_some_and(|byte| byte & (0x80 >> (x % 8)) != 0)
+                }));
+            }
+            glyphs.insert(
+                c,
+                Glyph {
+                    width,
+                    height,
+                    left: x_offset,
+                    top: ascent - (y_offset + height as i32),
+                    advance,
+                    bits,
+                },
+            );
+        }
+
+        let default_char = default_char
+            .and_then(|c| char::from_u32(c as u32))
+            .filter(|c| glyphs.contains_key(c));
+        Ok(BitmapFont::with_glyphs(
+            glyphs,
+            (ascent + descent).max(0) as u32,
+            default_char,
+        ))
+    }
+
+    /// Parse a PSF1 or PSF2 console font. Fonts without a unicode table
+    /// map glyph n to code point n.
+    pub fn parse_psf(bytes: &[u8]) -> Result<BitmapFont, FontError> {
+        let error = |message: &str| FontError::Psf(message.to_string());
+        let read_u32 = |offset: usize| -> Result<u32, FontError> {
+            bytes
+                .get(offset..offset + 4)
+                .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .ok_or_else(|| error("truncated header"))
+        };
+
+        let (header_size, count, glyph_size, width, height, table) =
+            if bytes.starts_with(&PSF2_MAGIC) {
+                let flags = read_u32(12)?;
+                (
+                    read_u32(8)? as usize,
+                    read_u32(16)? as usize,
+                    read_u32(20)? as usize,
+                    read_u32(28)?,
+                    read_u32(24)?,
+                    flags & 1 != 0,
+                )
+            } else if bytes.starts_with(&PSF1_MAGIC) && bytes.len() >= 4 {
+                let mode = bytes[2];
+                let count = if mode & 0x01 != 0 { 512 } else { 256 };
+                (
+                    4,
+                    count,
+                    bytes[3] as usize,
+                    8,
+                    bytes[3] as u32,
+                    mode & 0x06 != 0,
+                )
+            } else {
+                return Err(error("not a PSF file"));
+            };
+
+        let row_size = width.div_ceil(8) as usize;
+        if width == 0 || height == 0 || glyph_size < row_size * height as usize {
+            return Err(error("bad glyph size"));
+        }
+        let table_start = count
+            .checked_mul(glyph_size)
+            .and_then(|size| size.checked_add(header_size))
+            .filter(|&end| end <= bytes.len())
+            .ok_or_else(|| error("truncated glyph data"))?;
+
+        let glyph = |index: usize| {
+            let start = header_size + index * glyph_size;
+            let data = &bytes[start..start + glyph_size];
+            let bits = (0..height as usize)
+                .flat_map(|y| {
+                    (0..width as usize)
+                        .map(move |x| data[y * row_size + x / 8] & (0x80 >> (x % 8)) != 0)
+                })
+                .collect();
+            Glyph {
+                width,
+                height,
+                left: 0,
+                top: 0,
+                advance: width as i32,
+                bits,
+            }
+        };
+
+        let mut glyphs = HashMap::new();
+        if !table {
+            for index in 0..count {
+                if let Some(c) = char::from_u32(index as u32) {
+                    glyphs.insert(c, glyph(index));
+                }
+            }
+        } else if bytes.starts_with(&PSF2_MAGIC) {
+            // per glyph: UTF-8 characters, then optional 0xfe-prefixed
+            // sequences we don't render, then 0xff
+            let mut entries = bytes[table_start..].split(|&b| b == 0xff);
+            for index in 0..count {
+                let entry = entries
+                    .next()
+                    .ok_or_else(|| error("truncated unicode table"))?;
+                let singles = entry.split(|&b| b == 0xfe).next().unwrap_or(&[]);
+                let singles = std::str::from_utf8(singles)
+                    .map_err(|_| error("bad UTF-8 in unicode table"))?;
+                for c in singles.chars() {
+                    glyphs.insert(c, glyph(index));
+                }
+            }
+        } else {
+            // the same layout in little-endian u16s, 0xfffe and 0xffff
+            let mut entries = bytes[table_start..]
+                .chunks_exact(2)
+                .map(|pair| u16::from_le_bytes([pair[0], pair[1]]));
+            for index in 0..count {
+                let mut in_sequence = false;
+                loop {
+                    match entries.next() {
+                        None => return Err(error("truncated unicode table")),
+                        Some(0xffff) => break,
+                        Some(0xfffe) => in_sequence = true,
+                        Some(code) if !in_sequence => {
+                            if let Some(c) = char::from_u32(code as u32) {
+                                glyphs.insert(c, glyph(index));
+                            }
+                        }
+                        Some(_) => {}
+                    }
+                }
+            }
+        }
+
+        Ok(BitmapFont::with_glyphs(glyphs, height, None))
+    }
+
+    fn with_glyphs(
+        glyphs: HashMap<char, Glyph>,
+        line_height: u32,
+        default_char: Option<char>,
+    ) -> BitmapFont {
+        let fallback = default_char.or_else(|| {
+            ['\u{fffd}', '?']
+                .iter()
+                .copied()
+                .find(|c| glyphs.contains_key(c))
+        });
+        BitmapFont {
+            glyphs,
+            line_height,
+            fallback,
+        }
+    }
+
+    fn glyph(&self, c: char) -> Option<&Glyph> {
+        self.glyphs
+            .get(&c)
+            .or_else(|| self.fallback.and_then(|f| self.glyphs.get(&f)))
+    }
+
+    fn advance(&self, c: char) -> i32 {
+        self.glyph(c).map_or(0, |g| g.advance)
+    }
+
+    fn block_height(&self, lines: usize, style: &TextStyle) -> u32 {
+        let pitch = self.line_height as i32 + style.line_spacing;
+        (pitch * lines as i32 - style.line_spacing).max(0) as u32
+    }
+
+    /// Break `text` into lines at newlines and, when `max_width` isn't
+    /// zero, greedily at spaces so no line is wider than `max_width`. Words
+    /// too long for a line on their own are broken between characters.
+    /// Returns each line with its width in pixels.
+    fn layout(&self, text: &str, max_width: u32) -> Vec<(Vec<char>, i32)> {
+        let width_of = |chars: &[char]| chars.iter().map(|&c| self.advance(c)).sum::<i32>();
+        let mut lines = Vec::new();
+
+        for paragraph in text.split('\n') {
+            let mut line: Vec<char> = Vec::new();
+            let mut line_width = 0;
+            // index just past the last space on the line, where it can be
+            // broken
+            let mut break_at = None;
+
+            for c in paragraph.trim_end_matches('\r').chars() {
+                let advance = self.advance(c);
+                if max_width > 0 && !line.is_empty() && line_width + advance > max_width as i32 {
+                    if c.is_whitespace() {
+                        lines.push(trimmed(line, &width_of));
+                        line = Vec::new();
+                        line_width = 0;
+                        break_at = None;
+                        continue;
+                    }
+                    let rest = match break_at {
+                        Some(index) => line.split_off(index),
+                        None => Vec::new(),
+                    };
+                    lines.push(trimmed(line, &width_of));
+                    line_width = width_of(&rest);
+                    line = rest;
+                    break_at = None;
+                }
+                line.push(c);
+                line_width += advance;
+                if c.is_whitespace() {
+                    break_at = Some(line.len());
+                }
+            }
+            lines.push(trimmed(line, &width_of));
+        }
+        lines
+    }
+}
+
+impl Default for BitmapFont {
+    fn default() -> Self {
+        BitmapFont::default_font()
+    }
+}
+
+/// The four metrics of a bounding box: width, height and x and y offsets.
+fn four(numbers: &[i32], error: &impl Fn(&str) -> FontError) -> Result<[i32; 4], FontError> {
+    match *numbers {
+        [a, b, c, d, ..] => {
+            for &n in &[a, b, c, d] {
+                metric(n, error)?;
+            }
+            Ok([a, b, c, d])
+        }
+        _ => Err(error("expected four numbers")),
+    }
+}
+
+fn first_metric(
+    numbers: &[i32],
+    error: &impl Fn(&str) -> FontError,
+) -> Result<Option<i32>, FontError> {
+    numbers.first().map(|&n| metric(n, error)).transpose()
+}
+
+fn metric(n: i32, error: &impl Fn(&str) -> FontError) -> Result<i32, FontError> {
+    if (-MAX_BDF_METRIC..=MAX_BDF_METRIC).contains(&n) {
+        Ok(n)
+    } else {
+        Err(error("size or offset out of range"))
+    }
+}
+
+fn hex_row(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Drop trailing whitespace so it doesn't throw off alignment.
+fn trimmed(mut line: Vec<char>, width_of: &impl Fn(&[char]) -> i32) -> (Vec<char>, i32) {
+    while line.last().is_some_and(|c| c.is_whitespace()) {
+        line.pop();
+    }
+    let width = width_of(&line);
+    (line, width)
+}
+
+#[wasm_bindgen]
+impl CanvasSource {
+    /// Draw `text` with its top left corner at (x, y). Lines are aligned
+    /// within the style's `max_width` when it is set, or else within the
+    /// widest line.
+    pub fn draw_text(&mut self, font: &BitmapFont, text: &str, x: i32, y: i32, style: &TextStyle) {
+        let lines = font.layout(text, style.max_width);
+        let block_width = if style.max_width > 0 {
+            style.max_width as i32
+        } else {
+            lines.iter().map(|line| line.1).max().unwrap_or(0)
+        };
+        let pitch = font.line_height as i32 + style.line_spacing;
+
+        self.edit(|canvas| {
+            for (row, (line, width)) in lines.iter().enumerate() {
+                let mut pen = x + match style.align {
+                    TextAlign::Left => 0,
+                    TextAlign::Center => (block_width - width) / 2,
+                    TextAlign::Right => block_width - width,
+                };
+                let top = y + row as i32 * pitch;
+                for &c in line {
+                    if let Some(glyph) = font.glyph(c) {
+                        canvas.draw_glyph(glyph, pen, top, &style.paint);
+                        pen += glyph.advance;
+                    }
+                }
+            }
+        })
+    }
+}
+
+// removed #[wasm_bindgen] - not sent to js
+impl CanvasSource {
+    fn draw_glyph(&mut self, glyph: &Glyph, pen: i32, top: i32, paint: &Paint) {
+        let left = pen + glyph.left;
+        let top = top + glyph.top;
+        for gy in 0..glyph.height {
+            for gx in 0..glyph.width {
+                if glyph.bits[(gy * glyph.width + gx) as usize] {
+                    let (px, py) = (left + gx as i32, top + gy as i32);
+                    self.put_pixel(px, py, paint.color_at(px, py));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pictures::painted;
+
+    // a 3x3 'A' sitting on the baseline and a '_' hanging below it
+    const BDF: &str = "STARTFONT 2.1
+FONTBOUNDINGBOX 3 4 0 -1
+FONT_ASCENT 3
+FONT_DESCENT 1
+CHARS 2
+STARTCHAR A
+ENCODING 65
+DWIDTH 4 0
+BBX 3 3 0 0
+BITMAP
+40
+A0
+E0
+ENDCHAR
+STARTCHAR underscore
+ENCODING 95
+DWIDTH 4 0
+BBX 3 1 0 -1
+BITMAP
+E0
+ENDCHAR
+ENDFONT
+";
+
+    fn canvas(width: u32, height: u32) -> CanvasSource {
+        CanvasSource::try_new(width, height, Vec::new()).unwrap()
+    }
+
+    /// A PSF2 font of 3x2 glyphs, with `table` as its unicode table if
+    /// given.
+    fn psf2(glyphs: &[[u8; 2]], table: Option<&[u8]>) -> Vec<u8> {
+        let mut bytes = PSF2_MAGIC.to_vec();
+        let header = [0, 32, table.is_some() as u32, glyphs.len() as u32, 2, 2, 3];
+        bytes.extend(header.iter().flat_map(|n| n.to_le_bytes()));
+        bytes.extend(glyphs.iter().flatten());
+        bytes.extend(table.unwrap_or(&[]));
+        bytes
+    }
+
+    #[test]
+    fn bdf_glyphs_sit_on_the_baseline() {
+        let font = BitmapFont::parse_bdf(BDF).unwrap();
+        assert_eq!(font.line_height(), 4);
+        assert!(font.has_glyph('A') && font.has_glyph('_'));
+        assert!(!font.has_glyph('B'));
+
+        let mut canvas = canvas(8, 4);
+        canvas.draw_text(&font, "A_", 0, 0, &TextStyle::new(0xff0000ff));
+        assert_eq!(
+            painted(&canvas),
+            [".#......", "#.#.....", "###.....", "....###."]
+        );
+        assert_eq!(canvas.pixel(1, 0).unwrap().to_array(), [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn bdf_errors_point_at_the_line() {
+        let line_of = |text: &str| match BitmapFont::parse_bdf(text) {
+            Err(FontError::Bdf { line, .. }) => line,
+            other => panic!("expected a BDF error, got {:?}", other.err()),
+        };
+        assert_eq!(line_of(&BDF.replace("A0", "A")), 12);
+        assert_eq!(line_of(&BDF.replace("BBX 3 3 0 0", "BBX 3 3 0")), 9);
+        assert_eq!(line_of(&BDF.replace("DWIDTH 4 0", "DWIDTH four 0")), 8);
+        // sizes and offsets no real font would have
+        assert_eq!(line_of(&BDF.replace("BBX 3 3 0 0", "BBX 3 3000000 0 0")), 9);
+        assert_eq!(
+            line_of(&BDF.replace("BBX 3 1 0 -1", "BBX 3 1 0 -2147483648")),
+            18
+        );
+        assert_eq!(
+            line_of(&BDF.replace("FONT_ASCENT 3", "FONT_ASCENT 2147483647")),
+            3
+        );
+        assert_eq!(
+            line_of(&BDF.replace("FONTBOUNDINGBOX 3 4", "FONTBOUNDINGBOX 65536 4")),
+            2
+        );
+        // cut off inside the last bitmap
+        assert_eq!(
+            line_of(&BDF[..BDF.find("E0\nENDCHAR\nENDFONT").unwrap()]),
+            19
+        );
+    }
+
+    #[test]
+    fn psf2_fonts_use_their_unicode_table() {
+        let glyphs = [[0xe0, 0x00], [0xa0, 0x40]];
+        let plain = BitmapFont::parse_psf(&psf2(&glyphs, None)).unwrap();
+        assert!(plain.has_glyph('\u{1}'));
+        assert!(!plain.has_glyph('A'));
+
+        let font = BitmapFont::parse_psf(&psf2(&glyphs, Some(b"A\xffBC\xfe\xff"))).unwrap();
+        assert!(font.has_glyph('A') && font.has_glyph('B') && font.has_glyph('C'));
+        assert_eq!(font.line_height(), 2);
+        let mut canvas = canvas(6, 2);
+        canvas.draw_text(&font, "AC", 0, 0, &TextStyle::new(0xffffffff));
+        assert_eq!(painted(&canvas), ["####.#", "....#."]);
+    }
+
+    #[test]
+    fn psf1_fonts_map_glyphs_to_code_points() {
+        let mut bytes = vec![PSF1_MAGIC[0], PSF1_MAGIC[1], 0, 2];
+        bytes.resize(4 + 256 * 2, 0);
+        bytes[4 + 65 * 2] = 0x81;
+        let font = BitmapFont::parse_psf(&bytes).unwrap();
+        assert_eq!(font.line_height(), 2);
+        let mut canvas = canvas(8, 2);
+        canvas.draw_text(&font, "A", 0, 0, &TextStyle::new(0xffffffff));
+        assert_eq!(painted(&canvas), ["#......#", "........"]);
+    }
+
+    #[test]
+    fn bad_psf_files_are_errors() {
+        let font = psf2(&[[0xe0, 0x00]], Some(b"A\xff"));
+        assert!(BitmapFont::parse_psf(b"hello").is_err());
+        assert!(BitmapFont::parse_psf(&font[..20]).is_err());
+        assert!(BitmapFont::parse_psf(&font[..33]).is_err());
+        // three glyphs but only two table entries, the last one empty
+        let short = psf2(&[[0xe0, 0x00]; 3], Some(b"A\xff"));
+        assert!(BitmapFont::parse_psf(&short).is_err());
+        assert!(BitmapFont::parse_psf(&psf2(&[[0xff, 0x00]], Some(b"\xc3\xff"))).is_err());
+    }
+
+    #[test]
+    fn the_default_font_has_a_fallback() {
+        let font = BitmapFont::default_font();
+        let style = TextStyle::new(0xffffffff);
+        assert_eq!(font.line_height(), 6);
+        assert!(!font.has_glyph('\u{e000}'));
+        assert_eq!(font.measure_text("\u{e000}", &style), [4, 6]);
+    }
+
+    #[test]
+    fn text_wraps_at_spaces() {
+        let font = BitmapFont::default_font();
+        let mut style = TextStyle::new(0xffffffff);
+        assert_eq!(font.measure_text("ab cd", &style), [20, 6]);
+        style.set_max_width(12);
+        assert_eq!(font.measure_text("ab cd", &style), [8, 12]);
+        // too long for a line, so broken between characters
+        assert_eq!(font.measure_text("abcdef", &style), [12, 12]);
+        style.set_line_spacing(2);
+        assert_eq!(font.measure_text("ab cd\nef", &style), [8, 22]);
+    }
+
+    #[test]
+    fn lines_align_within_the_block() {
+        let font = BitmapFont::parse_bdf(BDF).unwrap();
+        let mut style = TextStyle::new(0xffffffff);
+        style.set_max_width(8);
+        style.set_align(TextAlign::Right);
+        let mut canvas = canvas(8, 4);
+        canvas.draw_text(&font, "_", 0, 0, &style);
+        assert_eq!(painted(&canvas)[3], "....###.");
+
+        style.set_align(TextAlign::Center);
+        let mut canvas = self::canvas(8, 4);
+        canvas.draw_text(&font, "_", 0, 0, &style);
+        assert_eq!(painted(&canvas)[3], "..###...");
+    }
+}
@@ -0,0 +1,544 @@
+use wasm_bindgen::prelude::*;
+
+use crate::canvas_source::CanvasSource;
+use crate::color::Rgba;
+use crate::dirty::Rect;
+use crate::raster::Point;
+use crate::resample::ResampleFilter;
+
+/// A 2x3 affine matrix in the Canvas2D / SVG layout, mapping (x, y) to
+/// (a * x + c * y + e, b * x + d * y + f).
+///
+/// Like the Canvas2D context methods, `translate`, `scale`, `rotate` and
+/// `shear` apply in the matrix's local space: the last one called is the
+/// first to act on a point.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub a: f32,
+    pub b: f32,
+    pub c: f32,
+    pub d: f32,
+    pub e: f32,
+    pub f: f32,
+}
+
+#[wasm_bindgen]
+impl Transform {
+    pub fn new(a: f32, b: f32, c: f32, d: f32, e: f32, f: f32) -> Transform {
+        Transform { a, b, c, d, e, f }
+    }
+
+    pub fn identity() -> Transform {
+        Transform::new(1.0, 0.0, 0.0, 1.0, 0.0, 0.0)
+    }
+
+    /// Combine with `other` so that `other` acts on points first, the same
+    /// as Canvas2D's `transform()`.
+    pub fn multiply(&mut self, other: &Transform) {
+        let m = *self;
+        *self = Transform {
+            a: m.a * other.a + m.c * other.b,
+            b: m.b * other.a + m.d * other.b,
+            c: m.a * other.c + m.c * other.d,
+            d: m.b * other.c + m.d * other.d,
+            e: m.a * other.e + m.c * other.f + m.e,
+            f: m.b * other.e + m.d * other.f + m.f,
+        };
+    }
+
+    pub fn translate(&mut self, x: f32, y: f32) {
+        self.multiply(&Transform::new(1.0, 0.0, 0.0, 1.0, x, y));
+    }
+
+    /// Scale by (x, y); negative factors flip.
+    pub fn scale(&mut self, x: f32, y: f32) {
+        self.multiply(&Transform::new(x, 0.0, 0.0, y, 0.0, 0.0));
+    }
+
+    /// Rotate clockwise (on screen, y pointing down) by `radians`.
+    pub fn rotate(&mut self, radians: f32) {
+        let (sin, cos) = radians.sin_cos();
+        self.multiply(&Transform::new(cos, sin, -sin, cos, 0.0, 0.0));
+    }
+
+    /// Shear x by `x` times y, and y by `y` times x.
+    pub fn shear(&mut self, x: f32, y: f32) {
+        self.multiply(&Transform::new(1.0, y, x, 1.0, 0.0, 0.0));
+    }
+
+    pub fn determinant(&self) -> f32 {
+        self.a * self.d - self.b * self.c
+    }
+
+    /// The transform that undoes this one, or `undefined` if it squashes
+    /// everything onto a line or point.
+    pub fn inverse(&self) -> Option<Transform> {
+        let det = self.determinant();
+        if det.abs() < f32::EPSILON || !det.is_finite() {
+            return None;
+        }
+        Some(Transform {
+            a: self.d / det,
+            b: -self.b / det,
+            c: -self.c / det,
+            d: self.a / det,
+            e: (self.c * self.f - self.d * self.e) / det,
+            f: (self.b * self.e - self.a * self.f) / det,
+        })
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform::identity()
+    }
+}
+
+impl Transform {
+    pub fn apply(&self, (x, y): Point) -> Point {
+        (
+            self.a * x + self.c * y + self.e,
+            self.b * x + self.d * y + self.f,
+        )
+    }
+
+    /// Pixel bounds (x0, y0, x1, y1), end exclusive, of the rectangle
+    /// (x, y, width, height) after transforming.
+    pub(crate) fn bounds(&self, x: f32, y: f32, width: f32, height: f32) -> (i32, i32, i32, i32) {
+        let corners = [
+            self.apply((x, y)),
+            self.apply((x + width, y)),
+            self.apply((x, y + height)),
+            self.apply((x + width, y + height)),
+        ];
+        let min_x = corners.iter().map(|p| p.0).fold(f32::MAX, f32::min);
+        let min_y = corners.iter().map(|p| p.1).fold(f32::MAX, f32::min);
+        let max_x = corners.iter().map(|p| p.0).fold(f32::MIN, f32::max);
+        let max_y = corners.iter().map(|p| p.1).fold(f32::MIN, f32::max);
+        // a hair of slack so exact edges like 10.0000001 don't add a row
+        (
+            (min_x + 1e-3).floor() as i32,
+            (min_y + 1e-3).floor() as i32,
+            (max_x - 1e-3).ceil() as i32,
+            (max_y - 1e-3).ceil() as i32,
+        )
+    }
+}
+
+/// Straight-alpha RGBA pixels to sample from while transforming.
+struct Source<'a> {
+    data: &'a [u8],
+    width: u32,
+    height: u32,
+}
+
+impl Source<'_> {
+    /// Filtered, premultiplied color around the continuous point (x, y),
+    /// where pixel (i, j) covers the square from (i, j) to (i + 1, j + 1).
+    /// Everything outside the image counts as transparent, which gives
+    /// transformed edges a soft, anti-aliased falloff.
+    fn sample(&self, x: f32, y: f32, filter: ResampleFilter) -> [f32; 4] {
+        let pixel = |i: i32, j: i32| -> [f32; 4] {
+            if i < 0 || j < 0 || i >= self.width as i32 || j >= self.height as i32 {
+                return [0.0; 4];
+            }
+            let idx = ((j as u32 * self.width + i as u32) * 4) as usize;
+            let p = &self.data[idx..idx + 4];
+            let alpha = p[3] as f32 / 255.0;
+            [
+                p[0] as f32 * alpha,
+                p[1] as f32 * alpha,
+                p[2] as f32 * alpha,
+                alpha,
+            ]
+        };
+
+        if filter == ResampleFilter::Nearest {
+            return pixel(x.floor() as i32, y.floor() as i32);
+        }
+
+        let support = filter.support();
+        let (cx, cy) = (x - 0.5, y - 0.5);
+        let taps = |center: f32| {
+            let first = (center - support).ceil() as i32;
+            let last = (center + support).floor() as i32;
+            (first..=last).map(move |i| (i, filter.weight(center - i as f32)))
+        };
+        let mut sum = [0.0; 4];
+        let mut total = 0.0;
+        for (j, wy) in taps(cy) {
+            for (i, wx) in taps(cx) {
+                let weight = wx * wy;
+                let p = pixel(i, j);
+                (0..4).for_each(|c| sum[c] += weight * p[c]);
+                total += weight;
+            }
+        }
+        if total.abs() > f32::EPSILON {
+            sum.iter_mut().for_each(|v| *v /= total);
+        }
+        sum
+    }
+}
+
+/// Back to a straight-alpha color.
+fn unpremultiply(p: [f32; 4]) -> Rgba {
+    let alpha = p[3].clamp(0.0, 1.0);
+    if alpha <= 0.0 {
+        return Rgba::TRANSPARENT;
+    }
+    let channel = |v: f32| (v / alpha).round().clamp(0.0, 255.0) as u8;
+    Rgba::new(
+        channel(p[0]),
+        channel(p[1]),
+        channel(p[2]),
+        (alpha * 255.0).round() as u8,
+    )
+}
+
+#[wasm_bindgen]
+impl CanvasSource {
+    /// Transform the whole canvas in place. Whatever ends up outside the
+    /// canvas is cut off and uncovered areas become transparent.
+    pub fn transform(&mut self, transform: &Transform, filter: ResampleFilter) {
+        self.transform_region(transform, filter, 0, 0, self.width, self.height);
+    }
+
+    /// Lift the rectangle at (x, y), transform it and composite it back
+    /// onto the canvas, leaving transparency where it used to be. The
+    /// matrix is in canvas coordinates, so rotating about the region's
+    /// center means translating there, rotating, and translating back.
+    ///
+    /// Under a selection only the selected pixels are lifted, but the
+    /// transformed result can land anywhere.
+    pub fn transform_region(
+        &mut self,
+        transform: &Transform,
+        filter: ResampleFilter,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+    ) {
+        let (x0, y0, x1, y1) = match self.clip_rect(x, y, width, height) {
+            Some(region) => region,
+            None => return,
+        };
+        // a matrix that flattens everything would just erase the region
+        if transform.inverse().is_none() {
+            return;
+        }
+
+        let lifted = self.lift((x0, y0, x1, y1));
+        // the lifted pixels' (0, 0) sits at (x0, y0)
+        let mut placement = *transform;
+        placement.translate(x0 as f32, y0 as f32);
+
+        self.edit(|canvas| {
+            canvas.clear_rect((x0, y0, x1, y1));
+            canvas.draw_transformed(&lifted, &placement, filter);
+        })
+    }
+
+    /// A transformed copy of the canvas. With `grow` the copy is resized to
+    /// hold the whole transformed image (and shifted so its top left corner
+    /// lands at the origin); otherwise it keeps this canvas's size.
+    /// Returns `undefined` if the grown copy would be too large for a
+    /// canvas.
+    pub fn transformed(
+        &self,
+        transform: &Transform,
+        filter: ResampleFilter,
+        grow: bool,
+    ) -> Option<CanvasSource> {
+        let mut transform = *transform;
+        let (width, height) = if grow {
+            let (x0, y0, x1, y1) =
+                transform.bounds(0.0, 0.0, self.width as f32, self.height as f32);
+            let mut shift = Transform::identity();
+            shift.translate(-x0 as f32, -y0 as f32);
+            shift.multiply(&transform);
+            transform = shift;
+            let span =
+                |from: i32, to: i32| (to as i64 - from as i64).clamp(0, u32::MAX as i64) as u32;
+            (span(x0, x1), span(y0, y1))
+        } else {
+            (self.width, self.height)
+        };
+
+        let mut output = CanvasSource::try_new(width, height, Vec::new()).ok()?;
+        let inverse = match transform.inverse() {
+            Some(inverse) => inverse,
+            None => return Some(output),
+        };
+        let source = Source {
+            data: &self.data,
+            width: self.width,
+            height: self.height,
+        };
+        for py in 0..height {
+            for px in 0..width {
+                let (sx, sy) = inverse.apply((px as f32 + 0.5, py as f32 + 0.5));
+                let color = unpremultiply(source.sample(sx, sy, filter));
+                let idx = output.pixel_index(px, py);
+                output.data[idx..idx + 4].copy_from_slice(&color.to_array());
+            }
+        }
+        Some(output)
+    }
+
+    /// A copy of the rectangle at (x, y) as a new canvas. Parts outside
+    /// this canvas come out transparent. Returns `undefined` if the
+    /// rectangle is too large for a canvas.
+    pub fn crop(&self, x: i32, y: i32, width: u32, height: u32) -> Option<CanvasSource> {
+        let mut output = CanvasSource::try_new(width, height, Vec::new()).ok()?;
+        if let Some((x0, y0, x1, y1)) = self.clip_rect(x, y, width, height) {
+            let run = ((x1 - x0) * 4) as usize;
+            for row in y0..y1 {
+                let from = self.pixel_index(x0, row);
+                let to = output.pixel_index((x0 as i32 - x) as u32, (row as i32 - y) as u32);
+                output.data[to..to + run].copy_from_slice(&self.data[from..from + run]);
+            }
+        }
+        Some(output)
+    }
+
+    /// Mirror the canvas left to right, or under a selection the selected
+    /// pixels across the middle of the selection's bounds.
+    pub fn flip_horizontal(&mut self) {
+        if self.selection.is_some() {
+            return self.flip_selection(true);
+        }
+        self.edit(|canvas| {
+            canvas.touch(0, 0, canvas.width as i32, canvas.height as i32);
+            let row_size = (canvas.width * 4) as usize;
+            for row in canvas.data.chunks_exact_mut(row_size.max(1)) {
+                let pixels = row.len() / 4;
+                for i in 0..pixels / 2 {
+                    for c in 0..4 {
+                        row.swap(i * 4 + c, (pixels - 1 - i) * 4 + c);
+                    }
+                }
+            }
+        })
+    }
+
+    /// Mirror the canvas top to bottom, or under a selection the selected
+    /// pixels across the middle of the selection's bounds.
+    pub fn flip_vertical(&mut self) {
+        if self.selection.is_some() {
+            return self.flip_selection(false);
+        }
+        self.edit(|canvas| {
+            canvas.touch(0, 0, canvas.width as i32, canvas.height as i32);
+            let row_size = (canvas.width * 4) as usize;
+            let height = canvas.height as usize;
+            for i in 0..height / 2 {
+                let (top, bottom) = canvas.data.split_at_mut((height - 1 - i) * row_size);
+                top[i * row_size..(i + 1) * row_size].swap_with_slice(&mut bottom[..row_size]);
+            }
+        })
+    }
+}
+
+// removed #[wasm_bindgen] - not sent to js
+impl CanvasSource {
+    /// A copy of the rectangle `rect`, with each pixel's alpha scaled by
+    /// how selected it is.
+    pub(crate) fn lift(&self, rect: Rect) -> CanvasSource {
+        let (x0, y0, x1, y1) = rect;
+        let mut lifted = self
+            .crop(x0 as i32, y0 as i32, x1 - x0, y1 - y0)
+            .expect("a region of the canvas fits in a canvas");
+        if self.selection.is_some() {
+            for (i, pixel) in lifted.data.chunks_exact_mut(4).enumerate() {
+                let (lx, ly) = (i as u32 % lifted.width, i as u32 / lifted.width);
+                let selected = self.selection_coverage(x0 + lx, y0 + ly);
+                pixel[3] = (pixel[3] as f32 * selected).round() as u8;
+            }
+        }
+        lifted
+    }
+
+    /// Lift the selected pixels and put them back mirrored within the
+    /// selection's bounds, like `transform_region` with a flip.
+    fn flip_selection(&mut self, horizontal: bool) {
+        let (x0, y0, x1, y1) = match self.selection_clip((0, 0, self.width, self.height)) {
+            Some(rect) => rect,
+            None => return,
+        };
+        let lifted = self.lift((x0, y0, x1, y1));
+        let mut placement = Transform::identity();
+        if horizontal {
+            placement.translate(x1 as f32, y0 as f32);
+            placement.scale(-1.0, 1.0);
+        } else {
+            placement.translate(x0 as f32, y1 as f32);
+            placement.scale(1.0, -1.0);
+        }
+
+        self.edit(|canvas| {
+            canvas.clear_rect((x0, y0, x1, y1));
+            // pixel centers land on pixel centers, so nothing is resampled
+            canvas.draw_transformed(&lifted, &placement, ResampleFilter::Nearest);
+        })
+    }
+
+    /// Clear the selected part of `rect` to transparent.
+    pub(crate) fn clear_rect(&mut self, rect: Rect) {
+        let (x0, y0, x1, y1) = rect;
+        self.touch(x0 as i32, y0 as i32, x1 as i32, y1 as i32);
+        for row in y0..y1 {
+            for column in x0..x1 {
+                self.replace_pixel(column, row, Rgba::TRANSPARENT);
+            }
+        }
+    }
+
+    /// Composite `image` onto the canvas, its pixel (i, j) covering the
+    /// square from (i, j) to (i + 1, j + 1) before `transform` moves it
+    /// into canvas coordinates. Ignores the selection.
+    pub(crate) fn draw_transformed(
+        &mut self,
+        image: &CanvasSource,
+        transform: &Transform,
+        filter: ResampleFilter,
+    ) {
+        let inverse = match transform.inverse() {
+            Some(inverse) => inverse,
+            None => return,
+        };
+        let source = Source {
+            data: &image.data,
+            width: image.width,
+            height: image.height,
+        };
+        let (x0, y0, x1, y1) = transform.bounds(0.0, 0.0, image.width as f32, image.height as f32);
+
+        let selection = self.selection.take();
+        for py in y0.max(0)..y1.min(self.height as i32) {
+            for px in x0.max(0)..x1.min(self.width as i32) {
+                let (sx, sy) = inverse.apply((px as f32 + 0.5, py as f32 + 0.5));
+                let color = source.sample(sx, sy, filter);
+                if color[3] > 0.0 {
+                    self.put_pixel(px, py, unpremultiply(color));
+                }
+            }
+        }
+        self.selection = selection;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pictures::{digit_picture, digits};
+
+    fn close(a: Point, b: Point) -> bool {
+        (a.0 - b.0).abs() < 1e-4 && (a.1 - b.1).abs() < 1e-4
+    }
+
+    #[test]
+    fn the_last_call_acts_first() {
+        let mut transform = Transform::identity();
+        transform.translate(10.0, 0.0);
+        transform.scale(2.0, 3.0);
+        assert_eq!(transform.apply((1.0, 1.0)), (12.0, 3.0));
+
+        let mut rotation = Transform::identity();
+        rotation.rotate(std::f32::consts::FRAC_PI_2);
+        assert!(close(rotation.apply((1.0, 0.0)), (0.0, 1.0)));
+
+        let mut shear = Transform::identity();
+        shear.shear(1.0, 0.0);
+        assert_eq!(shear.apply((0.0, 2.0)), (2.0, 2.0));
+    }
+
+    #[test]
+    fn inverses_undo_the_transform() {
+        let mut transform = Transform::identity();
+        transform.translate(3.0, -7.0);
+        transform.rotate(0.3);
+        transform.scale(2.0, 0.5);
+        let inverse = transform.inverse().unwrap();
+        let point = (4.0, 9.0);
+        assert!(close(inverse.apply(transform.apply(point)), point));
+
+        let mut flat = Transform::identity();
+        flat.scale(1.0, 0.0);
+        assert!(flat.inverse().is_none());
+        assert!(Transform::new(f32::NAN, 0.0, 0.0, 1.0, 0.0, 0.0)
+            .inverse()
+            .is_none());
+    }
+
+    #[test]
+    fn bounds_cover_the_transformed_rectangle() {
+        let mut transform = Transform::identity();
+        transform.translate(0.5, 10.0);
+        assert_eq!(transform.bounds(0.0, 0.0, 2.0, 3.0), (0, 10, 3, 13));
+        transform.scale(-1.0, 1.0);
+        assert_eq!(transform.bounds(0.0, 0.0, 2.0, 3.0), (-2, 10, 1, 13));
+    }
+
+    #[test]
+    fn flips_mirror_the_canvas() {
+        let mut canvas = digits(&["12.", "345"]);
+        canvas.flip_horizontal();
+        assert_eq!(digit_picture(&canvas), [".21", "543"]);
+        canvas.flip_vertical();
+        assert_eq!(digit_picture(&canvas), ["543", ".21"]);
+        assert!(canvas.undo());
+        assert_eq!(digit_picture(&canvas), [".21", "543"]);
+    }
+
+    #[test]
+    fn crops_pad_with_transparency() {
+        let canvas = digits(&["12", "34"]);
+        let cropped = canvas.crop(-1, 1, 3, 2).unwrap();
+        assert_eq!(digit_picture(&cropped), [".34", "..."]);
+        assert_eq!(digit_picture(&canvas.crop(5, 5, 1, 1).unwrap()), ["."]);
+        assert!(canvas.crop(0, 0, u32::MAX, u32::MAX).is_none());
+    }
+
+    #[test]
+    fn regions_move_whole_pixels_exactly() {
+        let mut canvas = digits(&["12..", "34..", "...."]);
+        let mut shift = Transform::identity();
+        shift.translate(2.0, 1.0);
+        canvas.transform_region(&shift, ResampleFilter::Bilinear, 0, 0, 2, 2);
+        assert_eq!(digit_picture(&canvas), ["....", "..12", "..34"]);
+
+        assert!(canvas.undo());
+        assert_eq!(digit_picture(&canvas), ["12..", "34..", "...."]);
+        assert!(!canvas.can_undo());
+    }
+
+    #[test]
+    fn flattening_matrices_are_ignored() {
+        let mut canvas = digits(&["12", "34"]);
+        canvas.transform(
+            &Transform::new(0.0, 0.0, 0.0, 0.0, 1.0, 1.0),
+            ResampleFilter::Nearest,
+        );
+        assert_eq!(digit_picture(&canvas), ["12", "34"]);
+        assert!(!canvas.can_undo());
+    }
+
+    #[test]
+    fn grown_copies_hold_the_whole_image() {
+        let canvas = digits(&["123"]);
+        let mut quarter_turn = Transform::identity();
+        quarter_turn.rotate(std::f32::consts::FRAC_PI_2);
+        let turned = canvas
+            .transformed(&quarter_turn, ResampleFilter::Nearest, true)
+            .unwrap();
+        assert_eq!(digit_picture(&turned), ["1", "2", "3"]);
+
+        let same_size = canvas
+            .transformed(&quarter_turn, ResampleFilter::Nearest, false)
+            .unwrap();
+        assert_eq!(digit_picture(&same_size), ["..."]);
+    }
+}
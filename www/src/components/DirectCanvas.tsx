@@ -1,5 +1,5 @@
 import { useEffect, useRef, useState } from "react";
-import { CanvasSource } from "rust-canvas-prototype";
+import { Brush, CanvasSource } from "rust-canvas-prototype";
 import { memory } from "../../../pkg/rust_canvas_prototype_bg.wasm";
 import { useFPS } from "../hooks/useFPS";
 import styles from "./DirectCanvas.module.css";
@@ -13,6 +13,11 @@ const getRenderLoop = (
 		const loop = () => {
 			recordFPS ? recordFPS() : null;
 			// debugger;
+			// nothing changed since the last frame, so nothing to upload
+			if (!source.is_dirty()) {
+				return;
+			}
+
 			const sourceDataPtr = source.data();
 
 			const width = source.width();
@@ -25,7 +30,12 @@ const getRenderLoop = (
 					source.width() * source.height() * 4
 				), width, height);
 
-			ctx.putImageData(pixelData, 0, 0)
+			// only push the rectangles rust reports as changed
+			const rects = source.dirty_rects();
+			for (let i = 0; i < rects.length; i += 4) {
+				ctx.putImageData(pixelData, 0, 0, rects[i], rects[i + 1], rects[i + 2], rects[i + 3]);
+			}
+			source.clear_dirty();
 		};
 
 		return loop;
@@ -44,6 +54,7 @@ export function DirectCanvas() {
 	// undefined on init, null when paused
 	const [animationId, setAnimationId] = useState<number>(0);
 	const canvasElement = useRef<HTMLCanvasElement>(null);
+	const [brush] = useState(() => Brush.new(8, 0x000000ff));
 
 	const initialized = source && ctx;
 
@@ -77,6 +88,18 @@ export function DirectCanvas() {
 		}
 	}, [source, ctx, animationId]);
 
+	// map a pointer event from css pixels to canvas pixels and hand it to
+	// the brush engine
+	const strokeTo = (event: React.PointerEvent<HTMLCanvasElement>) => {
+		const canvas = event.currentTarget;
+		const bounds = canvas.getBoundingClientRect();
+		const x = (event.clientX - bounds.left) * (canvas.width / bounds.width);
+		const y = (event.clientY - bounds.top) * (canvas.height / bounds.height);
+		// mice report 0.5 while a button is held
+		const pressure = event.pointerType === "mouse" ? 1 : event.pressure;
+		source?.stroke_to(x, y, pressure, event.timeStamp);
+	};
+
 	return (
 		<div className={styles.Container}>
 			<div className={styles.Dashboard}>
@@ -84,8 +107,24 @@ export function DirectCanvas() {
 			</div>
 			<span className={styles.Controls}>
 				<button onClick={() => source?.cover_in_blood()}>Splatter</button>
+				<button onClick={() => source?.undo()}>Undo</button>
+				<button onClick={() => source?.redo()}>Redo</button>
 			</span>
-			<canvas ref={canvasElement}></canvas>
+			<canvas
+				ref={canvasElement}
+				onPointerDown={(event) => {
+					event.currentTarget.setPointerCapture(event.pointerId);
+					source?.begin_stroke(brush);
+					strokeTo(event);
+				}}
+				onPointerMove={(event) => {
+					if (source?.is_stroking()) {
+						strokeTo(event);
+					}
+				}}
+				onPointerUp={() => source?.end_stroke()}
+				onPointerCancel={() => source?.end_stroke()}
+			></canvas>
 		</div>
 	)
 }
\ No newline at end of file